@@ -0,0 +1,154 @@
+use interface::Document;
+use layout::{BoxType, Dimensions, LayoutBox, LayoutInfo, Rect};
+use style::{BoxSizing, Position};
+
+use std::cmp::max;
+use std::rc::Rc;
+
+use app_units::Au;
+
+impl<'a> LayoutBox<'a> {
+    /// Lay out the absolutely positioned boxes in the subtree after the flow.
+    /// `containing_block` is the padding box of the nearest positioned ancestor, or the viewport,
+    /// relative to the content box of this box.
+    pub fn layout_absolute_boxes(
+        &mut self,
+        containing_block: Rect,
+        viewport: Dimensions,
+        document: &Rc<Document>,
+    ) {
+        // Positioned boxes are the containing blocks of their absolutely positioned descendants.
+        let positioned = match self.box_type {
+            BoxType::TextNode(_) | BoxType::AnonymousBlock => false,
+            _ => self.style.map_or(false, |style| style.position() != Position::Static),
+        };
+        let containing_block = if positioned {
+            let padding_box = self.dimensions.padding_box();
+            Rect {
+                x: -self.dimensions.padding.left,
+                y: -self.dimensions.padding.top,
+                width: padding_box.width,
+                height: padding_box.height,
+            }
+        } else {
+            containing_block
+        };
+
+        for child in &mut self.children {
+            if child.box_type == BoxType::Absolute {
+                child.layout_absolute(containing_block, viewport, document);
+            }
+            let d = child.dimensions;
+            child.layout_absolute_boxes(
+                Rect {
+                    x: containing_block.x - d.content.x,
+                    y: containing_block.y - d.content.y,
+                    width: containing_block.width,
+                    height: containing_block.height,
+                },
+                viewport,
+                document,
+            );
+        }
+    }
+
+    /// Lay out an absolutely positioned box in `containing_block`, relative to the content box of
+    /// the parent. The position in the flow, which is used if the offsets are `auto`, is already
+    /// noted in the box.
+    /// ref. https://www.w3.org/TR/CSS2/visudet.html#abs-non-replaced-width
+    fn layout_absolute(
+        &mut self,
+        containing_block: Rect,
+        viewport: Dimensions,
+        document: &Rc<Document>,
+    ) {
+        let style = self.get_style_node();
+        let (static_x, static_y) = (self.dimensions.content.x, self.dimensions.content.y);
+
+        let mut cb: Dimensions = Default::default();
+        cb.content.width = containing_block.width;
+        cb.content.height = containing_block.height;
+        let (cb_width, cb_height) = (
+            containing_block.width.to_f64_px(),
+            containing_block.height.to_f64_px(),
+        );
+
+        self.assign_padding(cb);
+        self.assign_border_width();
+        self.assign_margin();
+
+        let offset = |name: &str, len: f64| style.offset(name, Some(len)).map(Au::from_f64_px);
+        let (left, right) = (offset("left", cb_width), offset("right", cb_width));
+        let (top, bottom) = (offset("top", cb_height), offset("bottom", cb_height));
+
+        let d = self.dimensions;
+        let left_edges = d.margin.left + d.border.left + d.padding.left;
+        let right_edges = d.margin.right + d.border.right + d.padding.right;
+        let top_edges = d.margin.top + d.border.top + d.padding.top;
+        let bottom_edges = d.margin.bottom + d.border.bottom + d.padding.bottom;
+
+        if let LayoutInfo::Image(_) = self.info {
+            self.calculate_replaced_inline_width_height(cb, document);
+        } else {
+            // Under `box-sizing: border-box`, `width` includes the padding and border.
+            let box_edges = d.padding.left + d.padding.right + d.border.left + d.border.right;
+            let width = style
+                .value("width")
+                .and_then(|width| width[0].maybe_percent_to_px(cb_width))
+                .map(|width| match style.box_sizing() {
+                    BoxSizing::BorderBox => max(Au::from_f64_px(width) - box_edges, Au(0)),
+                    BoxSizing::ContentBox => Au::from_f64_px(width),
+                });
+            // With both `top` and `bottom`, the height of `auto` fills the containing block.
+            let height = self.specified_height(Some(containing_block.height))
+                .or_else(|| match (top, bottom) {
+                    (Some(top), Some(bottom)) => Some(max(
+                        containing_block.height - top - bottom - top_edges - bottom_edges,
+                        Au(0),
+                    )),
+                    _ => None,
+                });
+
+            match (width, left, right) {
+                (Some(width), _, _) => {
+                    self.dimensions.content.width = width;
+                    self.layout_block_children(Au(0), height, viewport, document);
+                }
+                // With both `left` and `right`, the width of `auto` fills the containing block.
+                (None, Some(left), Some(right)) => {
+                    self.dimensions.content.width = max(
+                        containing_block.width - left - right - left_edges - right_edges,
+                        Au(0),
+                    );
+                    self.layout_block_children(Au(0), height, viewport, document);
+                }
+                (None, _, _) => {
+                    let available = containing_block.width - left.unwrap_or(Au(0))
+                        - right.unwrap_or(Au(0)) - left_edges
+                        - right_edges;
+                    self.dimensions.content.width = max(available, Au(0));
+                    self.layout_children_shrink_to_fit(height, viewport, document);
+                }
+            }
+            self.calculate_block_height(height);
+        }
+
+        // `left` wins over `right`, and `top` wins over `bottom`. Without them, the box stays
+        // where it would be in the flow.
+        let (width, height) = (self.dimensions.content.width, self.dimensions.content.height);
+        self.dimensions.content.x = match (left, right) {
+            (Some(left), _) => containing_block.x + left + left_edges,
+            (None, Some(right)) => {
+                containing_block.x + containing_block.width - right - right_edges - width
+            }
+            (None, None) => static_x + left_edges,
+        };
+        self.dimensions.content.y = match (top, bottom) {
+            (Some(top), _) => containing_block.y + top + top_edges,
+            (None, Some(bottom)) => {
+                containing_block.y + containing_block.height - bottom - bottom_edges - height
+            }
+            (None, None) => static_y + top_edges,
+        };
+    }
+}
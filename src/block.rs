@@ -1,8 +1,11 @@
 use css::{Unit, Value};
 use float::Floats;
+use interface::Document;
 use layout::{BoxType, Dimensions, LayoutBox};
+use style::BoxSizing;
 
-use std::cmp::max;
+use std::cmp::{max, min};
+use std::rc::Rc;
 
 use app_units::Au;
 
@@ -13,8 +16,9 @@ impl<'a> LayoutBox<'a> {
         floats: &mut Floats,
         last_margin_bottom: Au,
         containing_block: Dimensions,
-        _saved_block: Dimensions,
+        containing_height: Option<Au>,
         viewport: Dimensions,
+        document: &Rc<Document>,
     ) {
         self.floats = floats.clone();
 
@@ -31,7 +35,7 @@ impl<'a> LayoutBox<'a> {
             border.clone(),
         );
 
-        self.calculate_block_position(
+        let first_child_margin_top = self.calculate_block_position(
             last_margin_bottom,
             containing_block,
             margin,
@@ -43,11 +47,14 @@ impl<'a> LayoutBox<'a> {
             self.floats.translate(self.dimensions.offset());
         }
 
-        self.layout_block_children(viewport);
+        let height = self.specified_height(containing_height);
+        self.layout_block_children(first_child_margin_top, height, viewport, document);
 
         // Parent height can depend on child height, so `calculate_height` must be called after the
         // children are laid out.
-        self.calculate_block_height();
+        self.calculate_block_height(height);
+        let height = self.clamp_height(self.dimensions.content.height, containing_height);
+        self.dimensions.content.height = height;
     }
 
     /// Calculate the width of a block-level non-replaced element in normal flow.
@@ -63,9 +70,68 @@ impl<'a> LayoutBox<'a> {
         let style = self.get_style_node();
         let cb_width = containing_block.content.width.to_f64_px();
 
+        // Under `box-sizing: border-box`, `width`, `min-width` and `max-width` include the padding
+        // and border.
+        let edges = match style.box_sizing() {
+            BoxSizing::BorderBox => sum([&border.3, &border.1, &padding.3, &padding.1]
+                .iter()
+                .map(|v| v.maybe_percent_to_px(cb_width).unwrap_or(0.0))),
+            BoxSizing::ContentBox => 0.0,
+        };
+        let content_width = |name: &str| {
+            style
+                .value(name)
+                .and_then(|v| v[0].maybe_percent_to_px(cb_width))
+                .map(|w| Value::Length((w - edges).max(0.0), Unit::Px))
+        };
+
         // `width` has initial value `auto`.
+        let width = content_width("width").unwrap_or(Value::Keyword("auto".to_string()));
+        self.calculate_used_block_width(
+            containing_block,
+            margin.clone(),
+            padding.clone(),
+            border.clone(),
+            width,
+        );
+
+        // If the width is out of the range, calculate it again with `max-width` or `min-width`.
+        // ref. https://www.w3.org/TR/CSS2/visudet.html#min-max-widths
+        if let Some(max_width) = content_width("max-width") {
+            if self.dimensions.content.width.to_f64_px() > max_width.to_px().unwrap() {
+                self.calculate_used_block_width(
+                    containing_block,
+                    margin.clone(),
+                    padding.clone(),
+                    border.clone(),
+                    max_width,
+                );
+            }
+        }
+        if let Some(min_width) = content_width("min-width") {
+            if self.dimensions.content.width.to_f64_px() < min_width.to_px().unwrap() {
+                self.calculate_used_block_width(
+                    containing_block,
+                    margin,
+                    padding,
+                    border,
+                    min_width,
+                );
+            }
+        }
+    }
+
+    /// Calculate the used width and horizontal margins for the given content `width`.
+    fn calculate_used_block_width(
+        &mut self,
+        containing_block: Dimensions,
+        margin: (Value, Value, Value, Value),
+        padding: (Value, Value, Value, Value),
+        border: (Value, Value, Value, Value),
+        mut width: Value,
+    ) {
+        let cb_width = containing_block.content.width.to_f64_px();
         let auto = Value::Keyword("auto".to_string());
-        let mut width = style.value("width").unwrap_or(vec![auto.clone()])[0].clone();
 
         let mut margin_left = margin.3;
         let mut margin_right = margin.1;
@@ -178,6 +244,7 @@ impl<'a> LayoutBox<'a> {
     /// Finish calculating the block's edge sizes, and position it within its containing block.
     /// http://www.w3.org/TR/CSS2/visudet.html#normal-block
     /// Sets the vertical margin/padding/border dimensions, and the `x`, `y` values.
+    /// Returns the margin that the first child should collapse with (see `layout_block_children`).
     pub fn calculate_block_position(
         &mut self,
         last_margin_bottom: Au,
@@ -185,24 +252,28 @@ impl<'a> LayoutBox<'a> {
         margin: (Value, Value, Value, Value),
         padding: (Value, Value, Value, Value),
         border: (Value, Value, Value, Value),
-    ) {
-        let style = self.get_style_node();
+    ) -> Au {
         let cb_width = containing_block.content.width.to_f64_px();
-        let d = &mut self.dimensions;
 
-        // margin, border, and padding have initial value 0.
-        let zero = Value::Length(0.0, Unit::Px);
+        // The top margin collapses with the previous sibling's bottom margin, and with the top
+        // margins of the first in-flow children if there is no border or padding between them.
+        // ref. https://www.w3.org/TR/CSS2/box.html#collapsing-margins
+        let margin_top = Au::from_f64_px(margin.0.maybe_percent_to_px(cb_width).unwrap_or(0f64));
+        let children_margin_tops = if padding.0.maybe_percent_to_px(cb_width) == Some(0.0)
+            && border.0.to_px() == Some(0.0)
+        {
+            self.first_children_margin_tops(self.dimensions.content.width)
+        } else {
+            vec![]
+        };
+        let mut adjoining_margins = vec![last_margin_bottom, margin_top];
+        adjoining_margins.extend(children_margin_tops.iter().cloned());
 
-        d.margin.top = Au::from_f64_px(margin.0.maybe_percent_to_px(cb_width).unwrap_or(0f64));
-        d.margin.bottom = Au::from_f64_px(margin.2.maybe_percent_to_px(cb_width).unwrap_or(0f64));
+        let d = &mut self.dimensions;
 
-        // Margin collapse
-        // TODO: Is this implementation correct?
-        if last_margin_bottom >= d.margin.top {
-            d.margin.top = Au(0);
-        } else {
-            d.margin.top = d.margin.top - last_margin_bottom;
-        }
+        // `last_margin_bottom` is already included in the previous sibling's margin box.
+        d.margin.top = collapse_margins(&adjoining_margins) - last_margin_bottom;
+        d.margin.bottom = Au::from_f64_px(margin.2.maybe_percent_to_px(cb_width).unwrap_or(0f64));
 
         d.border.top = Au::from_f64_px(border.0.maybe_percent_to_px(cb_width).unwrap());
         d.border.bottom = Au::from_f64_px(border.2.maybe_percent_to_px(cb_width).unwrap());
@@ -210,21 +281,50 @@ impl<'a> LayoutBox<'a> {
         d.padding.top = Au::from_f64_px(padding.0.maybe_percent_to_px(cb_width).unwrap());
         d.padding.bottom = Au::from_f64_px(padding.2.maybe_percent_to_px(cb_width).unwrap());
 
-        self.z_index = style.lookup("z-index", "z-index", &vec![zero])[0]
-            .clone()
-            .to_num() as i32;
-
         d.content.x = d.margin.left + d.border.left + d.padding.left;
 
         // Position the box below all the previous boxes in the container.
         d.content.y = containing_block.content.height + d.margin.top + d.border.top + d.padding.top;
+
+        // The first child collapses with this margin, so that its own top margin, which is already
+        // included in this box's margin, becomes zero.
+        collapse_margins(&children_margin_tops)
+    }
+
+    /// Return the top margins of the first in-flow block children (and their first children, and
+    /// so on) whose margins are adjoining.
+    fn first_children_margin_tops(&self, width: Au) -> Vec<Au> {
+        let child = match self.children.first() {
+            Some(child) if child.box_type == BoxType::BlockNode => child,
+            _ => return vec![],
+        };
+        let style = child.get_style_node();
+        let cb_width = width.to_f64_px();
+        let (margin, padding, border) = (style.margin(), style.padding(), style.border_width());
+
+        let mut margins = vec![
+            Au::from_f64_px(margin.0.maybe_percent_to_px(cb_width).unwrap_or(0f64)),
+        ];
+        if padding.0.maybe_percent_to_px(cb_width) == Some(0.0) && border.0.to_px() == Some(0.0) {
+            // TODO: The child's width is not known yet, so percentages are resolved against ours.
+            margins.extend(child.first_children_margin_tops(width));
+        }
+        margins
     }
 
     /// Lay out the block's children within its content area.
     /// Sets `self.dimensions.height` to the total content height.
-    pub fn layout_block_children(&mut self, viewport: Dimensions) {
+    /// `first_child_margin_top` is the margin the first child's top margin collapses with.
+    /// `height` is the content height if it doesn't depend on the children.
+    pub fn layout_block_children(
+        &mut self,
+        first_child_margin_top: Au,
+        height: Option<Au>,
+        viewport: Dimensions,
+        document: &Rc<Document>,
+    ) {
         let d = &mut self.dimensions;
-        let mut last_margin_bottom = Au(0);
+        let mut last_margin_bottom = first_child_margin_top;
         let mut floats = &mut self.floats;
 
         // TODO: Consider a better way to position children.
@@ -240,9 +340,9 @@ impl<'a> LayoutBox<'a> {
                 floats.ceiling = max(floats.ceiling, d.content.height);
             }
 
-            child.layout(&mut floats, last_margin_bottom, *d, *d, viewport);
+            child.layout(&mut floats, last_margin_bottom, *d, height, viewport, document);
 
-            if child.box_type != BoxType::Float {
+            if child.box_type != BoxType::Float && child.box_type != BoxType::Absolute {
                 last_margin_bottom = child.dimensions.margin.bottom;
                 // Increment the height so each child is laid out below the previous one.
                 d.content.height += child.dimensions.margin_box().height;
@@ -250,21 +350,181 @@ impl<'a> LayoutBox<'a> {
         }
     }
 
+    /// The content height given by `height`, if it doesn't depend on the content. Percentages are
+    /// resolved against `containing_height`, and behave as `auto` if it's None.
+    pub fn specified_height(&self, containing_height: Option<Au>) -> Option<Au> {
+        self.content_height("height", containing_height)
+            .map(|h| self.clamp_height(h, containing_height))
+    }
+
+    /// Clamp `height` to `min-height` and `max-height`. `min-height` wins if they conflict.
+    /// ref. https://www.w3.org/TR/CSS2/visudet.html#min-max-heights
+    pub fn clamp_height(&self, height: Au, containing_height: Option<Au>) -> Au {
+        let mut height = height;
+        if let Some(max_height) = self.content_height("max-height", containing_height) {
+            height = min(height, max_height);
+        }
+        if let Some(min_height) = self.content_height("min-height", containing_height) {
+            height = max(height, min_height);
+        }
+        height
+    }
+
+    /// The content height given by the property `name`, if it's a length or a percentage of a
+    /// definite `containing_height`.
+    fn content_height(&self, name: &str, containing_height: Option<Au>) -> Option<Au> {
+        let style = self.get_style_node();
+        let height = match style.value(name) {
+            Some(val) => val[0].clone(),
+            None => return None,
+        };
+        let px = match height {
+            Value::Length(f, Unit::Percent) => {
+                containing_height.map(|h| h.to_f64_px() * f / 100.0)
+            }
+            ref height => height.to_px(),
+        };
+
+        // Under `box-sizing: border-box`, heights include the padding and border.
+        let edges = match style.box_sizing() {
+            BoxSizing::BorderBox => {
+                let d = &self.dimensions;
+                d.padding.top + d.padding.bottom + d.border.top + d.border.bottom
+            }
+            BoxSizing::ContentBox => Au(0),
+        };
+        px.map(|h| max(Au::from_f64_px(h) - edges, Au(0)))
+    }
+
     /// Height of a block-level non-replaced element in normal flow with overflow visible.
-    pub fn calculate_block_height(&mut self) {
+    /// `height` is the result of `specified_height`.
+    pub fn calculate_block_height(&mut self, height: Option<Au>) {
         // If the height is set to an explicit length, use that exact length.
         // Otherwise, just keep the value set by `layout_block_children`.
-        if let Some(val) = self.get_style_node().value("height") {
-            if let Value::Length(h, Unit::Px) = val[0] {
-                self.dimensions.content.height = Au::from_f64_px(h);
-            }
+        if let Some(height) = height {
+            self.dimensions.content.height = height;
         }
     }
 }
 
+/// Collapse adjoining margins into one: the largest positive margin plus the most negative one.
+fn collapse_margins(margins: &[Au]) -> Au {
+    let max_positive = margins.iter().fold(Au(0), |acc, &m| max(acc, m));
+    let min_negative = margins.iter().fold(Au(0), |acc, &m| min(acc, m));
+    max_positive + min_negative
+}
+
 fn sum<I>(iter: I) -> f64
 where
     I: Iterator<Item = f64>,
 {
     iter.fold(0., |a, b| a + b)
 }
+
+#[test]
+fn test_margin_collapse() {
+    use layout::with_layout_tree;
+
+    let html_src = "<html><body><div id='a'></div><div id='b'></div><div id='c'></div>\
+                    <div id='p'><div id='q'></div></div></body></html>";
+    let css_src = "div { height: 10px; }
+                   #a { margin-bottom: 20px; }
+                   #b { margin-top: 30px; margin-bottom: 20px; }
+                   #c { margin-top: -5px; }
+                   #p { margin-top: 10px; height: auto; }
+                   #q { margin-top: 25px; }";
+    with_layout_tree(html_src, css_src, 100.0, |layout_tree| {
+        let body = &layout_tree.children[0];
+        let y = |b: &LayoutBox| b.dimensions.content.y;
+        // Sibling margins collapse to the larger one.
+        assert_eq!(y(&body.children[0]), Au::from_f64_px(0.0));
+        assert_eq!(y(&body.children[1]), Au::from_f64_px(40.0));
+        // 20px and -5px collapse to 15px.
+        assert_eq!(y(&body.children[2]), Au::from_f64_px(65.0));
+        // The parent's margin collapses with its first child's.
+        let parent = &body.children[3];
+        assert_eq!(parent.dimensions.margin.top, Au::from_f64_px(25.0));
+        assert_eq!(y(parent), Au::from_f64_px(100.0));
+        assert_eq!(y(&parent.children[0]), Au::from_f64_px(0.0));
+    });
+}
+
+#[test]
+fn test_percent_length() {
+    use layout::{self, with_layout_tree_in};
+
+    let html_src = "<html><body><div id='p'><div id='a'></div></div>\
+                    <div id='q'><div id='b'></div></div></body></html>";
+    let css_src = "#p { width: 400px; height: 100px; }
+                   #a { width: 50%; height: 50%; }
+                   #q { width: 400px; }
+                   #b { height: 50%; }";
+    let mut viewport: layout::Dimensions = ::std::default::Default::default();
+    viewport.content.width = Au::from_f64_px(800.0);
+    viewport.content.height = Au::from_f64_px(600.0);
+    let document = Rc::new(Document::new());
+    with_layout_tree_in(html_src, css_src, viewport, &document, |layout_tree| {
+        let body = &layout_tree.children[0];
+        let a = &body.children[0].children[0];
+        assert_eq!(a.dimensions.content.width, Au::from_f64_px(200.0));
+        assert_eq!(a.dimensions.content.height, Au::from_f64_px(50.0));
+        // The containing block's height depends on the content, so the percent height is `auto`.
+        let b = &body.children[1].children[0];
+        assert_eq!(b.dimensions.content.width, Au::from_f64_px(400.0));
+        assert_eq!(b.dimensions.content.height, Au::from_f64_px(0.0));
+    });
+}
+
+#[test]
+fn test_box_sizing() {
+    use layout::with_layout_tree;
+    use layout::Rect;
+
+    let html_src = "<html><body><div id='a'></div><div id='b'></div><div id='c'></div></body>\
+                    </html>";
+    let css_src = "div { width: 100px; height: 50px; padding: 10px; border: 5px solid #000; }
+                   #b { box-sizing: border-box; }
+                   #c { box-sizing: border-box; width: auto; }";
+    with_layout_tree(html_src, css_src, 300.0, |layout_tree| {
+        let body = &layout_tree.children[0];
+        let px = Au::from_f64_px;
+        let size = |r: Rect| (r.width, r.height);
+        // content-box: the declared size is the content size.
+        let a = &body.children[0].dimensions;
+        assert_eq!(size(a.content), (px(100.0), px(50.0)));
+        assert_eq!(size(a.border_box()), (px(130.0), px(80.0)));
+        // border-box: the declared size is the border box size.
+        let b = &body.children[1].dimensions;
+        assert_eq!(size(b.content), (px(70.0), px(20.0)));
+        assert_eq!(size(b.border_box()), (px(100.0), px(50.0)));
+        // An auto width still fills the containing block.
+        let c = &body.children[2].dimensions;
+        assert_eq!(c.border_box().width, px(300.0));
+    });
+}
+
+#[test]
+fn test_min_max_size() {
+    use layout::with_layout_tree;
+
+    let html_src = "<html><body><div id='a'></div><div id='b'></div><div id='c'></div>\
+                    <div id='d'></div><div id='e'></div></body></html>";
+    let css_src = "#a { width: 500px; max-width: 300px; }
+                   #b { width: 100px; min-width: 200px; max-width: 150px; }
+                   #c { max-width: 50%; height: 10px; min-height: 20px; }
+                   #d { height: 100px; max-height: 30px; }
+                   #e { max-width: none; min-width: 0; }";
+    with_layout_tree(html_src, css_src, 800.0, |layout_tree| {
+        let body = &layout_tree.children[0];
+        let px = Au::from_f64_px;
+        let content = |i: usize| body.children[i].dimensions.content;
+        assert_eq!(content(0).width, px(300.0));
+        // `min-width` wins over `max-width`.
+        assert_eq!(content(1).width, px(200.0));
+        // Percentages are resolved against the containing block.
+        assert_eq!(content(2).width, px(400.0));
+        assert_eq!(content(2).height, px(20.0));
+        assert_eq!(content(3).height, px(30.0));
+        assert_eq!(content(4).width, px(800.0));
+    });
+}
@@ -31,6 +31,7 @@ pub struct SimpleSelector {
 pub struct Declaration {
     pub name: String,
     pub values: Vec<Value>,
+    pub important: bool,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -39,6 +40,11 @@ pub enum Value {
     Length(f64, Unit),
     Num(f64),
     Color(Color),
+    Gradient(GradientSpec),
+    Url(String),
+    Str(String),
+    // A comma separating the items of a list, e.g. the shadows of `text-shadow`.
+    Comma,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -47,6 +53,7 @@ pub enum Unit {
     Pt,
     Percent,
     Em,
+    Rem,
 }
 
 #[derive(Debug, Clone, PartialEq, Default)]
@@ -66,9 +73,45 @@ pub enum TextDecoration {
     // Blink,
 }
 
-macro_rules! color { ($name:ident, $r:expr, $g:expr, $b:expr) => {
-    pub const $name: Color = Color { r: $r, g: $g, b: $b, a: 0xff };
-}}
+#[derive(Debug, Clone, PartialEq)]
+pub struct GradientSpec {
+    pub direction: GradientDirection,
+    pub stops: Vec<ColorStop>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GradientDirection {
+    ToTop,
+    ToBottom,
+    ToLeft,
+    ToRight,
+    // Degrees clockwise from the top.
+    Angle(f64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ColorStop {
+    pub color: Color,
+    // Position in the range 0.0-1.0. None means it's placed evenly between the neighbors.
+    pub position: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BorderStyle {
+    None,
+    Solid,
+    Dashed,
+    Dotted,
+}
+
+macro_rules! color {
+    ($name:ident, $r:expr, $g:expr, $b:expr) => {
+        color!($name, $r, $g, $b, 0xff);
+    };
+    ($name:ident, $r:expr, $g:expr, $b:expr, $a:expr) => {
+        pub const $name: Color = Color { r: $r, g: $g, b: $b, a: $a };
+    };
+}
 
 color!(BLACK, 0x00, 0x00, 0x00);
 color!(SILVER, 0xc0, 0xc0, 0xc0);
@@ -86,9 +129,176 @@ color!(NAVY, 0x00, 0x00, 0x80);
 color!(BLUE, 0x00, 0x00, 0xff);
 color!(TEAL, 0x00, 0x80, 0x80);
 color!(AQUA, 0x00, 0xff, 0xff);
+color!(TRANSPARENT, 0x00, 0x00, 0x00, 0x00);
 
 impl Copy for Color {}
 
+/// CSS3 extended color keywords, sorted by name for binary search.
+/// ref. https://www.w3.org/TR/css-color-3/#svg-color
+#[cfg_attr(rustfmt, rustfmt_skip)]
+const NAMED_COLORS: &'static [(&'static str, Color)] = &[
+    ("aliceblue", Color { r: 0xf0, g: 0xf8, b: 0xff, a: 0xff }),
+    ("antiquewhite", Color { r: 0xfa, g: 0xeb, b: 0xd7, a: 0xff }),
+    ("aqua", Color { r: 0x00, g: 0xff, b: 0xff, a: 0xff }),
+    ("aquamarine", Color { r: 0x7f, g: 0xff, b: 0xd4, a: 0xff }),
+    ("azure", Color { r: 0xf0, g: 0xff, b: 0xff, a: 0xff }),
+    ("beige", Color { r: 0xf5, g: 0xf5, b: 0xdc, a: 0xff }),
+    ("bisque", Color { r: 0xff, g: 0xe4, b: 0xc4, a: 0xff }),
+    ("black", Color { r: 0x00, g: 0x00, b: 0x00, a: 0xff }),
+    ("blanchedalmond", Color { r: 0xff, g: 0xeb, b: 0xcd, a: 0xff }),
+    ("blue", Color { r: 0x00, g: 0x00, b: 0xff, a: 0xff }),
+    ("blueviolet", Color { r: 0x8a, g: 0x2b, b: 0xe2, a: 0xff }),
+    ("brown", Color { r: 0xa5, g: 0x2a, b: 0x2a, a: 0xff }),
+    ("burlywood", Color { r: 0xde, g: 0xb8, b: 0x87, a: 0xff }),
+    ("cadetblue", Color { r: 0x5f, g: 0x9e, b: 0xa0, a: 0xff }),
+    ("chartreuse", Color { r: 0x7f, g: 0xff, b: 0x00, a: 0xff }),
+    ("chocolate", Color { r: 0xd2, g: 0x69, b: 0x1e, a: 0xff }),
+    ("coral", Color { r: 0xff, g: 0x7f, b: 0x50, a: 0xff }),
+    ("cornflowerblue", Color { r: 0x64, g: 0x95, b: 0xed, a: 0xff }),
+    ("cornsilk", Color { r: 0xff, g: 0xf8, b: 0xdc, a: 0xff }),
+    ("crimson", Color { r: 0xdc, g: 0x14, b: 0x3c, a: 0xff }),
+    ("cyan", Color { r: 0x00, g: 0xff, b: 0xff, a: 0xff }),
+    ("darkblue", Color { r: 0x00, g: 0x00, b: 0x8b, a: 0xff }),
+    ("darkcyan", Color { r: 0x00, g: 0x8b, b: 0x8b, a: 0xff }),
+    ("darkgoldenrod", Color { r: 0xb8, g: 0x86, b: 0x0b, a: 0xff }),
+    ("darkgray", Color { r: 0xa9, g: 0xa9, b: 0xa9, a: 0xff }),
+    ("darkgreen", Color { r: 0x00, g: 0x64, b: 0x00, a: 0xff }),
+    ("darkgrey", Color { r: 0xa9, g: 0xa9, b: 0xa9, a: 0xff }),
+    ("darkkhaki", Color { r: 0xbd, g: 0xb7, b: 0x6b, a: 0xff }),
+    ("darkmagenta", Color { r: 0x8b, g: 0x00, b: 0x8b, a: 0xff }),
+    ("darkolivegreen", Color { r: 0x55, g: 0x6b, b: 0x2f, a: 0xff }),
+    ("darkorange", Color { r: 0xff, g: 0x8c, b: 0x00, a: 0xff }),
+    ("darkorchid", Color { r: 0x99, g: 0x32, b: 0xcc, a: 0xff }),
+    ("darkred", Color { r: 0x8b, g: 0x00, b: 0x00, a: 0xff }),
+    ("darksalmon", Color { r: 0xe9, g: 0x96, b: 0x7a, a: 0xff }),
+    ("darkseagreen", Color { r: 0x8f, g: 0xbc, b: 0x8f, a: 0xff }),
+    ("darkslateblue", Color { r: 0x48, g: 0x3d, b: 0x8b, a: 0xff }),
+    ("darkslategray", Color { r: 0x2f, g: 0x4f, b: 0x4f, a: 0xff }),
+    ("darkslategrey", Color { r: 0x2f, g: 0x4f, b: 0x4f, a: 0xff }),
+    ("darkturquoise", Color { r: 0x00, g: 0xce, b: 0xd1, a: 0xff }),
+    ("darkviolet", Color { r: 0x94, g: 0x00, b: 0xd3, a: 0xff }),
+    ("deeppink", Color { r: 0xff, g: 0x14, b: 0x93, a: 0xff }),
+    ("deepskyblue", Color { r: 0x00, g: 0xbf, b: 0xff, a: 0xff }),
+    ("dimgray", Color { r: 0x69, g: 0x69, b: 0x69, a: 0xff }),
+    ("dimgrey", Color { r: 0x69, g: 0x69, b: 0x69, a: 0xff }),
+    ("dodgerblue", Color { r: 0x1e, g: 0x90, b: 0xff, a: 0xff }),
+    ("firebrick", Color { r: 0xb2, g: 0x22, b: 0x22, a: 0xff }),
+    ("floralwhite", Color { r: 0xff, g: 0xfa, b: 0xf0, a: 0xff }),
+    ("forestgreen", Color { r: 0x22, g: 0x8b, b: 0x22, a: 0xff }),
+    ("fuchsia", Color { r: 0xff, g: 0x00, b: 0xff, a: 0xff }),
+    ("gainsboro", Color { r: 0xdc, g: 0xdc, b: 0xdc, a: 0xff }),
+    ("ghostwhite", Color { r: 0xf8, g: 0xf8, b: 0xff, a: 0xff }),
+    ("gold", Color { r: 0xff, g: 0xd7, b: 0x00, a: 0xff }),
+    ("goldenrod", Color { r: 0xda, g: 0xa5, b: 0x20, a: 0xff }),
+    ("gray", Color { r: 0x80, g: 0x80, b: 0x80, a: 0xff }),
+    ("green", Color { r: 0x00, g: 0x80, b: 0x00, a: 0xff }),
+    ("greenyellow", Color { r: 0xad, g: 0xff, b: 0x2f, a: 0xff }),
+    ("grey", Color { r: 0x80, g: 0x80, b: 0x80, a: 0xff }),
+    ("honeydew", Color { r: 0xf0, g: 0xff, b: 0xf0, a: 0xff }),
+    ("hotpink", Color { r: 0xff, g: 0x69, b: 0xb4, a: 0xff }),
+    ("indianred", Color { r: 0xcd, g: 0x5c, b: 0x5c, a: 0xff }),
+    ("indigo", Color { r: 0x4b, g: 0x00, b: 0x82, a: 0xff }),
+    ("ivory", Color { r: 0xff, g: 0xff, b: 0xf0, a: 0xff }),
+    ("khaki", Color { r: 0xf0, g: 0xe6, b: 0x8c, a: 0xff }),
+    ("lavender", Color { r: 0xe6, g: 0xe6, b: 0xfa, a: 0xff }),
+    ("lavenderblush", Color { r: 0xff, g: 0xf0, b: 0xf5, a: 0xff }),
+    ("lawngreen", Color { r: 0x7c, g: 0xfc, b: 0x00, a: 0xff }),
+    ("lemonchiffon", Color { r: 0xff, g: 0xfa, b: 0xcd, a: 0xff }),
+    ("lightblue", Color { r: 0xad, g: 0xd8, b: 0xe6, a: 0xff }),
+    ("lightcoral", Color { r: 0xf0, g: 0x80, b: 0x80, a: 0xff }),
+    ("lightcyan", Color { r: 0xe0, g: 0xff, b: 0xff, a: 0xff }),
+    ("lightgoldenrodyellow", Color { r: 0xfa, g: 0xfa, b: 0xd2, a: 0xff }),
+    ("lightgray", Color { r: 0xd3, g: 0xd3, b: 0xd3, a: 0xff }),
+    ("lightgreen", Color { r: 0x90, g: 0xee, b: 0x90, a: 0xff }),
+    ("lightgrey", Color { r: 0xd3, g: 0xd3, b: 0xd3, a: 0xff }),
+    ("lightpink", Color { r: 0xff, g: 0xb6, b: 0xc1, a: 0xff }),
+    ("lightsalmon", Color { r: 0xff, g: 0xa0, b: 0x7a, a: 0xff }),
+    ("lightseagreen", Color { r: 0x20, g: 0xb2, b: 0xaa, a: 0xff }),
+    ("lightskyblue", Color { r: 0x87, g: 0xce, b: 0xfa, a: 0xff }),
+    ("lightslategray", Color { r: 0x77, g: 0x88, b: 0x99, a: 0xff }),
+    ("lightslategrey", Color { r: 0x77, g: 0x88, b: 0x99, a: 0xff }),
+    ("lightsteelblue", Color { r: 0xb0, g: 0xc4, b: 0xde, a: 0xff }),
+    ("lightyellow", Color { r: 0xff, g: 0xff, b: 0xe0, a: 0xff }),
+    ("lime", Color { r: 0x00, g: 0xff, b: 0x00, a: 0xff }),
+    ("limegreen", Color { r: 0x32, g: 0xcd, b: 0x32, a: 0xff }),
+    ("linen", Color { r: 0xfa, g: 0xf0, b: 0xe6, a: 0xff }),
+    ("magenta", Color { r: 0xff, g: 0x00, b: 0xff, a: 0xff }),
+    ("maroon", Color { r: 0x80, g: 0x00, b: 0x00, a: 0xff }),
+    ("mediumaquamarine", Color { r: 0x66, g: 0xcd, b: 0xaa, a: 0xff }),
+    ("mediumblue", Color { r: 0x00, g: 0x00, b: 0xcd, a: 0xff }),
+    ("mediumorchid", Color { r: 0xba, g: 0x55, b: 0xd3, a: 0xff }),
+    ("mediumpurple", Color { r: 0x93, g: 0x70, b: 0xdb, a: 0xff }),
+    ("mediumseagreen", Color { r: 0x3c, g: 0xb3, b: 0x71, a: 0xff }),
+    ("mediumslateblue", Color { r: 0x7b, g: 0x68, b: 0xee, a: 0xff }),
+    ("mediumspringgreen", Color { r: 0x00, g: 0xfa, b: 0x9a, a: 0xff }),
+    ("mediumturquoise", Color { r: 0x48, g: 0xd1, b: 0xcc, a: 0xff }),
+    ("mediumvioletred", Color { r: 0xc7, g: 0x15, b: 0x85, a: 0xff }),
+    ("midnightblue", Color { r: 0x19, g: 0x19, b: 0x70, a: 0xff }),
+    ("mintcream", Color { r: 0xf5, g: 0xff, b: 0xfa, a: 0xff }),
+    ("mistyrose", Color { r: 0xff, g: 0xe4, b: 0xe1, a: 0xff }),
+    ("moccasin", Color { r: 0xff, g: 0xe4, b: 0xb5, a: 0xff }),
+    ("navajowhite", Color { r: 0xff, g: 0xde, b: 0xad, a: 0xff }),
+    ("navy", Color { r: 0x00, g: 0x00, b: 0x80, a: 0xff }),
+    ("oldlace", Color { r: 0xfd, g: 0xf5, b: 0xe6, a: 0xff }),
+    ("olive", Color { r: 0x80, g: 0x80, b: 0x00, a: 0xff }),
+    ("olivedrab", Color { r: 0x6b, g: 0x8e, b: 0x23, a: 0xff }),
+    ("orange", Color { r: 0xff, g: 0xa5, b: 0x00, a: 0xff }),
+    ("orangered", Color { r: 0xff, g: 0x45, b: 0x00, a: 0xff }),
+    ("orchid", Color { r: 0xda, g: 0x70, b: 0xd6, a: 0xff }),
+    ("palegoldenrod", Color { r: 0xee, g: 0xe8, b: 0xaa, a: 0xff }),
+    ("palegreen", Color { r: 0x98, g: 0xfb, b: 0x98, a: 0xff }),
+    ("paleturquoise", Color { r: 0xaf, g: 0xee, b: 0xee, a: 0xff }),
+    ("palevioletred", Color { r: 0xdb, g: 0x70, b: 0x93, a: 0xff }),
+    ("papayawhip", Color { r: 0xff, g: 0xef, b: 0xd5, a: 0xff }),
+    ("peachpuff", Color { r: 0xff, g: 0xda, b: 0xb9, a: 0xff }),
+    ("peru", Color { r: 0xcd, g: 0x85, b: 0x3f, a: 0xff }),
+    ("pink", Color { r: 0xff, g: 0xc0, b: 0xcb, a: 0xff }),
+    ("plum", Color { r: 0xdd, g: 0xa0, b: 0xdd, a: 0xff }),
+    ("powderblue", Color { r: 0xb0, g: 0xe0, b: 0xe6, a: 0xff }),
+    ("purple", Color { r: 0x80, g: 0x00, b: 0x80, a: 0xff }),
+    ("rebeccapurple", Color { r: 0x66, g: 0x33, b: 0x99, a: 0xff }),
+    ("red", Color { r: 0xff, g: 0x00, b: 0x00, a: 0xff }),
+    ("rosybrown", Color { r: 0xbc, g: 0x8f, b: 0x8f, a: 0xff }),
+    ("royalblue", Color { r: 0x41, g: 0x69, b: 0xe1, a: 0xff }),
+    ("saddlebrown", Color { r: 0x8b, g: 0x45, b: 0x13, a: 0xff }),
+    ("salmon", Color { r: 0xfa, g: 0x80, b: 0x72, a: 0xff }),
+    ("sandybrown", Color { r: 0xf4, g: 0xa4, b: 0x60, a: 0xff }),
+    ("seagreen", Color { r: 0x2e, g: 0x8b, b: 0x57, a: 0xff }),
+    ("seashell", Color { r: 0xff, g: 0xf5, b: 0xee, a: 0xff }),
+    ("sienna", Color { r: 0xa0, g: 0x52, b: 0x2d, a: 0xff }),
+    ("silver", Color { r: 0xc0, g: 0xc0, b: 0xc0, a: 0xff }),
+    ("skyblue", Color { r: 0x87, g: 0xce, b: 0xeb, a: 0xff }),
+    ("slateblue", Color { r: 0x6a, g: 0x5a, b: 0xcd, a: 0xff }),
+    ("slategray", Color { r: 0x70, g: 0x80, b: 0x90, a: 0xff }),
+    ("slategrey", Color { r: 0x70, g: 0x80, b: 0x90, a: 0xff }),
+    ("snow", Color { r: 0xff, g: 0xfa, b: 0xfa, a: 0xff }),
+    ("springgreen", Color { r: 0x00, g: 0xff, b: 0x7f, a: 0xff }),
+    ("steelblue", Color { r: 0x46, g: 0x82, b: 0xb4, a: 0xff }),
+    ("tan", Color { r: 0xd2, g: 0xb4, b: 0x8c, a: 0xff }),
+    ("teal", Color { r: 0x00, g: 0x80, b: 0x80, a: 0xff }),
+    ("thistle", Color { r: 0xd8, g: 0xbf, b: 0xd8, a: 0xff }),
+    ("tomato", Color { r: 0xff, g: 0x63, b: 0x47, a: 0xff }),
+    ("turquoise", Color { r: 0x40, g: 0xe0, b: 0xd0, a: 0xff }),
+    ("violet", Color { r: 0xee, g: 0x82, b: 0xee, a: 0xff }),
+    ("wheat", Color { r: 0xf5, g: 0xde, b: 0xb3, a: 0xff }),
+    ("white", Color { r: 0xff, g: 0xff, b: 0xff, a: 0xff }),
+    ("whitesmoke", Color { r: 0xf5, g: 0xf5, b: 0xf5, a: 0xff }),
+    ("yellow", Color { r: 0xff, g: 0xff, b: 0x00, a: 0xff }),
+    ("yellowgreen", Color { r: 0x9a, g: 0xcd, b: 0x32, a: 0xff }),
+];
+
+/// Return the color named `name` (ASCII case-insensitive), or None if `name` is unknown.
+pub fn named_color(name: &str) -> Option<Color> {
+    let name = name.to_ascii_lowercase();
+    if name == "transparent" {
+        return Some(TRANSPARENT);
+    }
+    NAMED_COLORS
+        .binary_search_by(|&(n, _)| n.cmp(name.as_str()))
+        .ok()
+        .map(|i| NAMED_COLORS[i].1)
+}
+
 impl Value {
     pub fn to_px(&self) -> Option<f64> {
         match *self {
@@ -125,23 +335,35 @@ impl Value {
     pub fn to_color(&self) -> Option<Color> {
         match *self {
             Value::Color(color) => Some(color),
-            Value::Keyword(ref color_name) => match color_name.as_str() {
-                "black" => Some(BLACK),
-                "silver" => Some(SILVER),
-                "gray" => Some(GRAY),
-                "white" => Some(WHITE),
-                "red" => Some(RED),
-                "maroon" => Some(MAROON),
-                "purple" => Some(PURPLE),
-                "fuchsia" => Some(FUCHSIA),
-                "green" => Some(GREEN),
-                "lime" => Some(LIME),
-                "olive" => Some(OLIVE),
-                "yellow" => Some(YELLOW),
-                "navy" => Some(NAVY),
-                "blue" => Some(BLUE),
-                "teal" => Some(TEAL),
-                "aqua" => Some(AQUA),
+            Value::Keyword(ref color_name) => named_color(color_name),
+            _ => None,
+        }
+    }
+
+    pub fn to_url(&self) -> Option<&String> {
+        match *self {
+            Value::Url(ref url) => Some(url),
+            _ => None,
+        }
+    }
+
+    pub fn to_gradient(&self) -> Option<GradientSpec> {
+        match *self {
+            Value::Gradient(ref gradient) => Some(gradient.clone()),
+            _ => None,
+        }
+    }
+
+    pub fn to_border_style(&self) -> Option<BorderStyle> {
+        match *self {
+            Value::Keyword(ref name) => match name.to_lowercase().as_str() {
+                "none" | "hidden" => Some(BorderStyle::None),
+                "dashed" => Some(BorderStyle::Dashed),
+                "dotted" => Some(BorderStyle::Dotted),
+                // TODO: Other styles are painted as solid for now.
+                "solid" | "double" | "groove" | "ridge" | "inset" | "outset" => {
+                    Some(BorderStyle::Solid)
+                }
                 _ => None,
             },
             _ => None,
@@ -162,6 +384,72 @@ impl Value {
     }
 }
 
+impl GradientSpec {
+    /// Return the color stops with their positions fixed up.
+    /// ref. https://www.w3.org/TR/css-images-3/#color-stop-syntax
+    pub fn resolved_stops(&self) -> Vec<(f64, Color)> {
+        let len = self.stops.len();
+        let mut positions: Vec<Option<f64>> = self.stops.iter().map(|s| s.position).collect();
+
+        // The first and the last stops default to 0% and 100%.
+        if len > 0 {
+            positions[0].get_or_insert(0.0);
+            positions[len - 1].get_or_insert(1.0);
+        }
+
+        // A position can't be smaller than the one before it.
+        let mut last = 0.0f64;
+        for position in positions.iter_mut() {
+            if let Some(ref mut p) = *position {
+                *p = p.max(last);
+                last = *p;
+            }
+        }
+
+        // Stops without a position are spaced evenly between the positioned ones.
+        let mut i = 0;
+        while i < len {
+            if positions[i].is_some() {
+                i += 1;
+                continue;
+            }
+            let start = i - 1; // The first stop always has a position.
+            let end = (i..len).find(|&j| positions[j].is_some()).unwrap();
+            let (from, to) = (positions[start].unwrap(), positions[end].unwrap());
+            for j in i..end {
+                positions[j] = Some(from + (to - from) * (j - start) as f64 / (end - start) as f64);
+            }
+            i = end;
+        }
+
+        positions
+            .into_iter()
+            .zip(self.stops.iter())
+            .map(|(p, s)| (p.unwrap(), s.color))
+            .collect()
+    }
+}
+
+impl fmt::Display for GradientSpec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(match self.direction {
+            GradientDirection::ToTop => write!(f, "linear-gradient(to top"),
+            GradientDirection::ToBottom => write!(f, "linear-gradient(to bottom"),
+            GradientDirection::ToLeft => write!(f, "linear-gradient(to left"),
+            GradientDirection::ToRight => write!(f, "linear-gradient(to right"),
+            GradientDirection::Angle(angle) => write!(f, "linear-gradient({}deg", angle),
+        });
+        for stop in &self.stops {
+            let c = stop.color;
+            try!(write!(f, ", rgba({}, {}, {}, {})", c.r, c.g, c.b, c.a));
+            if let Some(position) = stop.position {
+                try!(write!(f, " {}%", position * 100.0));
+            }
+        }
+        write!(f, ")")
+    }
+}
+
 const DPI: f64 = 96.0;
 
 // TODO: any other better way?
@@ -203,11 +491,17 @@ impl Selector {
 }
 
 pub fn parse(source: String) -> Stylesheet {
-    Stylesheet {
-        rules: Parser::new(source).parse_rules(),
-    }
+    parse_with_imports(source).1
+}
+
+/// Parse a stylesheet, and return the URLs of its `@import` rules in order too.
+pub fn parse_with_imports(source: String) -> (Vec<String>, Stylesheet) {
+    let mut imports = vec![];
+    let rules = Parser::new(source).parse_rules(&mut imports);
+    (imports, Stylesheet { rules: rules })
 }
 
+/// Parse the declarations of a `style` attribute. Malformed declarations are skipped.
 pub fn parse_attr_style(source: String) -> Vec<Declaration> {
     let mut decls = Vec::new();
     let mut parser = Parser::new(source);
@@ -216,12 +510,19 @@ pub fn parse_attr_style(source: String) -> Vec<Declaration> {
         if parser.eof() {
             break;
         }
-        decls.push(parser.parse_declaration());
+        // A stray `}` doesn't end anything here.
+        if parser.next_char() == '}' {
+            parser.consume_char();
+            continue;
+        }
+        if let Some(decl) = parser.parse_declaration() {
+            decls.push(decl);
+        }
     }
     decls
 }
 
-pub fn parse_value(source: String) -> Value {
+pub fn parse_value(source: String) -> Option<Value> {
     Parser::new(source).parse_value()
 }
 
@@ -241,12 +542,11 @@ fn valid_alpha_percent_char(c: char) -> bool {
 }
 
 fn valid_hex_char(c: char) -> bool {
-    // TODO: other char codes?
-    match c.to_ascii_lowercase() {
-        'a' | 'b' | 'c' | 'd' | 'e' | 'f' => true,
-        c if c.is_numeric() => true,
-        _ => false,
-    }
+    c.is_digit(16)
+}
+
+fn is_number_start(c: char) -> bool {
+    c.is_digit(10) || c == '.' || c == '-' || c == '+'
 }
 
 #[derive(Clone, Debug)]
@@ -263,65 +563,142 @@ impl Parser {
         }
     }
 
-    fn parse_rules(&mut self) -> Vec<Rule> {
+    /// Parse the rules, and push the URLs of `@import` to `imports`. Other at-rules are skipped.
+    fn parse_rules(&mut self, imports: &mut Vec<String>) -> Vec<Rule> {
         let mut rules = vec![];
+        // @import is only valid before the other rules, except @charset.
+        let mut imports_allowed = true;
         loop {
             self.consume_whitespace();
             if self.eof() {
                 break;
             }
-            rules.push(self.parse_rule());
+            if self.next_char() == '@' {
+                assert_eq!(self.consume_char(), '@');
+                match self.parse_identifier().as_str() {
+                    "charset" => {}
+                    "import" if imports_allowed => {
+                        // TODO: Support media queries.
+                        if let Some(url) = self.parse_import_url() {
+                            imports.push(url);
+                        }
+                    }
+                    _ => imports_allowed = false,
+                }
+                self.skip_at_rule();
+                continue;
+            }
+            imports_allowed = false;
+            match self.parse_rule() {
+                Some(rule) => rules.push(rule),
+                // Rules with selectors that aren't supported are skipped whole.
+                None => self.skip_rule(),
+            }
         }
         rules
     }
 
-    fn parse_rule(&mut self) -> Rule {
-        Rule {
-            selectors: self.parse_selectors(),
-            declarations: self.parse_declarations(),
+    /// Parse the URL of `@import`, given as `url(...)` or a string.
+    fn parse_import_url(&mut self) -> Option<String> {
+        self.consume_whitespace();
+        if self.eof() {
+            return None;
+        }
+        match self.parse_value() {
+            Some(Value::Url(url)) | Some(Value::Str(url)) => Some(url),
+            _ => None,
+        }
+    }
+
+    /// Skip the rest of an at-rule: up to `;`, or to the end of its block.
+    fn skip_at_rule(&mut self) {
+        self.skip_to_block_end(true);
+    }
+
+    /// Skip the rest of a rule up to the end of its block.
+    fn skip_rule(&mut self) {
+        self.skip_to_block_end(false);
+    }
+
+    /// Skip up to the end of the next block, or to a `;` before it if `semicolon_ends`. Nested
+    /// blocks and strings are skipped whole.
+    fn skip_to_block_end(&mut self, semicolon_ends: bool) {
+        let mut depth = 0;
+        while !self.eof() {
+            match self.consume_char() {
+                ';' if semicolon_ends && depth == 0 => break,
+                '{' => depth += 1,
+                '}' if depth <= 1 => break,
+                '}' => depth -= 1,
+                quote @ '"' | quote @ '\'' => {
+                    self.consume_while(|c| c != quote);
+                    if !self.eof() {
+                        self.consume_char();
+                    }
+                }
+                _ => {}
+            }
         }
     }
 
-    fn parse_selectors(&mut self) -> Vec<Selector> {
+    /// None if a selector isn't supported, e.g. one with a pseudo-class or an attribute.
+    fn parse_rule(&mut self) -> Option<Rule> {
+        let selectors = self.parse_selectors()?;
+        Some(Rule {
+            selectors: selectors,
+            declarations: self.parse_declarations(),
+        })
+    }
+
+    /// Parse the selectors up to the `{` of the declarations. None if one isn't supported.
+    fn parse_selectors(&mut self) -> Option<Vec<Selector>> {
         let mut selectors = Vec::new();
         loop {
-            selectors.push(self.parse_selector());
+            selectors.push(self.parse_selector()?);
             self.consume_whitespace();
+            if self.eof() {
+                return None;
+            }
             match self.next_char() {
                 ',' => {
                     self.consume_char();
                     self.consume_whitespace();
                 }
                 '{' => break,
-                c => panic!("Unexpected character {} in selector list", c),
+                _ => return None,
             }
         }
         // Return selectors with highest specificity first, for use in matching.
         selectors.sort_by(|a, b| b.specificity().cmp(&a.specificity()));
-        selectors
+        Some(selectors)
     }
 
-    fn parse_selector(&mut self) -> Selector {
-        let s1 = self.parse_simple_selector();
+    fn parse_selector(&mut self) -> Option<Selector> {
+        let s1 = self.parse_simple_selector()?;
         self.consume_whitespace();
+        if self.eof() {
+            return None;
+        }
         match self.next_char() {
             // Descendant
-            c if c.is_alphanumeric() || c == '#' || c == '.' => {
-                let s2 = self.parse_selector();
-                return Selector::Descendant(s1, Box::new(s2));
+            c if c.is_alphanumeric() || c == '#' || c == '.' || c == '*' => {
+                let s2 = self.parse_selector()?;
+                return Some(Selector::Descendant(s1, Box::new(s2)));
             }
             '>' => {
-                assert_eq!(self.consume_char(), '>');
+                self.consume_char();
                 self.consume_whitespace();
-                let s2 = self.parse_selector();
-                return Selector::Child(s1, Box::new(s2));
+                let s2 = self.parse_selector()?;
+                return Some(Selector::Child(s1, Box::new(s2)));
             }
             _ => {}
         }
-        Selector::Simple(s1)
+        Some(Selector::Simple(s1))
     }
 
-    fn parse_simple_selector(&mut self) -> SimpleSelector {
+    /// None if there's no simple selector here.
+    fn parse_simple_selector(&mut self) -> Option<SimpleSelector> {
+        let start = self.pos;
         let mut selector = SimpleSelector {
             tag_name: None,
             id: None,
@@ -347,158 +724,399 @@ impl Parser {
                 _ => break,
             }
         }
-        selector
+        if self.pos == start {
+            None
+        } else {
+            Some(selector)
+        }
     }
 
+    /// Parse the block of declarations after the selectors, which end at its `{`.
     fn parse_declarations(&mut self) -> Vec<Declaration> {
-        assert_eq!(self.consume_char(), '{');
+        self.consume_char();
         let mut declarations = Vec::new();
         loop {
             self.consume_whitespace();
+            if self.eof() {
+                break;
+            }
             if self.next_char() == '}' {
                 self.consume_char();
                 break;
             }
-            declarations.push(self.parse_declaration());
+            if let Some(declaration) = self.parse_declaration() {
+                declarations.push(declaration);
+            }
         }
         declarations
     }
 
-    fn parse_declaration(&mut self) -> Declaration {
+    /// Parse a declaration. None if it's malformed or invalid, in which case it's skipped.
+    fn parse_declaration(&mut self) -> Option<Declaration> {
         let property_name = self.parse_identifier();
         self.consume_whitespace();
-        assert_eq!(self.consume_char(), ':');
+        if property_name.is_empty() || self.eof() || self.next_char() != ':' {
+            self.consume_while(|c| c != ';' && c != '}');
+            if !self.eof() && self.next_char() == ';' {
+                self.consume_char();
+            }
+            return None;
+        }
+        self.consume_char();
         self.consume_whitespace();
         let values = self.parse_values();
         self.consume_whitespace();
 
-        Declaration {
+        values.map(|(values, important)| Declaration {
             name: property_name,
             values: values,
-        }
+            important: important,
+        })
     }
 
     // Methods for parsing values:
 
-    fn parse_values(&mut self) -> Vec<Value> {
+    /// Returns the values and whether they're followed by `!important`.
+    fn parse_values(&mut self) -> Option<(Vec<Value>, bool)> {
         let mut values = vec![];
+        let mut important = false;
+        let mut valid = true;
         loop {
             self.consume_whitespace();
-            if self.eof() {
+            if self.eof() || self.next_char() == '}' {
                 break;
             }
             if self.next_char() == ';' {
                 assert_eq!(self.consume_char(), ';');
                 break;
             }
-            values.push(self.parse_value());
+            // Commas separate layers (e.g. multiple shadows). Keep them so getters can split.
+            if self.next_char() == ',' {
+                assert_eq!(self.consume_char(), ',');
+                values.push(Value::Comma);
+                continue;
+            }
+            if self.next_char() == '!' {
+                assert_eq!(self.consume_char(), '!');
+                self.consume_whitespace();
+                if self.parse_identifier() == "important" {
+                    important = true;
+                } else {
+                    valid = false;
+                    self.consume_while(|c| c != ';' && c != '}');
+                }
+                continue;
+            }
+            match self.parse_value() {
+                Some(value) => values.push(value),
+                None => {
+                    // Skip the rest of the declaration.
+                    valid = false;
+                    self.consume_while(|c| c != ';' && c != '}');
+                }
+            }
+        }
+        if valid && !values.is_empty() {
+            Some((values, important))
+        } else {
+            None
         }
-        values
     }
 
-    fn parse_value(&mut self) -> Value {
+    fn parse_value(&mut self) -> Option<Value> {
         match self.next_char() {
-            '0'...'9' => self.parse_length(),
+            '0'...'9' | '.' => self.parse_length(),
+            '-' | '+' if self.starts_with_number_after_sign() => self.parse_length(),
             '#' => self.parse_color(),
+            '"' | '\'' => self.parse_string(),
             _ => {
                 let ident = self.parse_identifier();
                 match ident.as_str() {
-                    "rgb" => self.parse_rgb_color(),
-                    "rgba" => self.parse_rgba_color(),
-                    _ => Value::Keyword(ident),
+                    "" => None,
+                    "rgb" | "rgba" => self.parse_rgba_color(),
+                    "linear-gradient" => self.parse_linear_gradient(),
+                    "url" => self.parse_url(),
+                    _ => Some(Value::Keyword(ident)),
                 }
             }
         }
     }
 
-    fn parse_length(&mut self) -> Value {
-        let num = self.parse_float();
+    fn starts_with_number_after_sign(&self) -> bool {
+        match self.input[self.pos + 1..].chars().next() {
+            Some('0'...'9') | Some('.') => true,
+            _ => false,
+        }
+    }
+
+    /// None if the number is malformed or the unit is unknown.
+    fn parse_length(&mut self) -> Option<Value> {
+        let sign = match self.next_char() {
+            '-' => {
+                self.consume_char();
+                -1.0
+            }
+            '+' => {
+                self.consume_char();
+                1.0
+            }
+            _ => 1.0,
+        };
+        let num = sign * self.parse_float()?;
         if !self.eof() && valid_alpha_percent_char(self.next_char()) {
-            Value::Length(num, self.parse_unit())
+            Some(Value::Length(num, self.parse_unit()?))
         } else {
-            Value::Num(num)
+            Some(Value::Num(num))
         }
     }
 
-    fn parse_float(&mut self) -> f64 {
+    fn parse_float(&mut self) -> Option<f64> {
         let s = self.consume_while(|c| match c {
             '0'...'9' | '.' => true,
             _ => false,
         });
-        s.parse().unwrap()
+        s.parse().ok()
     }
 
-    fn parse_unit(&mut self) -> Unit {
+    fn parse_unit(&mut self) -> Option<Unit> {
         match &*self.parse_identifier_percent() {
-            "px" => Unit::Px,
-            "pt" => Unit::Pt,
-            "%" => Unit::Percent,
-            "em" => Unit::Em,
-            _ => panic!("unrecognized unit"),
-        }
-    }
-
-    fn parse_rgb_color(&mut self) -> Value {
-        assert_eq!(self.consume_char_ignore_whitescape(), '(');
-        let r = self.parse_float();
-        assert_eq!(self.consume_char_ignore_whitescape(), ',');
-        let g = self.parse_float();
-        assert_eq!(self.consume_char_ignore_whitescape(), ',');
-        let b = self.parse_float();
-        assert_eq!(self.consume_char_ignore_whitescape(), ')');
-        Value::Color(Color {
-            r: r as u8,
-            g: g as u8,
-            b: b as u8,
-            a: 255,
-        })
+            "px" => Some(Unit::Px),
+            "pt" => Some(Unit::Pt),
+            "%" => Some(Unit::Percent),
+            "em" => Some(Unit::Em),
+            "rem" => Some(Unit::Rem),
+            _ => None,
+        }
     }
 
-    fn parse_rgba_color(&mut self) -> Value {
-        assert_eq!(self.consume_char_ignore_whitescape(), '(');
-        let r = self.parse_float();
-        assert_eq!(self.consume_char_ignore_whitescape(), ',');
-        let g = self.parse_float();
-        assert_eq!(self.consume_char_ignore_whitescape(), ',');
-        let b = self.parse_float();
-        assert_eq!(self.consume_char_ignore_whitescape(), ',');
-        let a = self.parse_float();
-        assert_eq!(self.consume_char_ignore_whitescape(), ')');
-        Value::Color(Color {
-            r: r as u8,
-            g: g as u8,
-            b: b as u8,
-            a: (255.0 * a) as u8,
-        })
+    /// Parses the arguments of `rgb()` and `rgba()`. Both accept three channels and an optional
+    /// alpha. Channels are integers (0-255) or percentages, and the alpha is a float (0.0-1.0)
+    /// or a percentage. Out-of-range values are clamped.
+    fn parse_rgba_color(&mut self) -> Option<Value> {
+        if self.consume_char_ignore_whitescape() != Some('(') {
+            return None;
+        }
+
+        let mut args = vec![];
+        loop {
+            args.push(self.parse_color_arg()?);
+            match self.consume_char_ignore_whitescape() {
+                Some(',') => {}
+                Some(')') => break,
+                _ => return None,
+            }
+        }
+
+        let channel = |(f, percent): (f64, bool)| {
+            let f = if percent { f * 255.0 / 100.0 } else { f };
+            f.max(0.0).min(255.0).round() as u8
+        };
+        let alpha = |(f, percent): (f64, bool)| {
+            let f = if percent { f / 100.0 } else { f };
+            (255.0 * f.max(0.0).min(1.0)) as u8
+        };
+
+        match args.len() {
+            3 | 4 => Some(Value::Color(Color {
+                r: channel(args[0]),
+                g: channel(args[1]),
+                b: channel(args[2]),
+                a: args.get(3).map_or(255, |&a| alpha(a)),
+            })),
+            _ => None,
+        }
+    }
+
+    /// Parses a quoted string such as a font family name with spaces.
+    fn parse_string(&mut self) -> Option<Value> {
+        let quote = self.consume_char();
+        let string = self.consume_while(|c| c != quote);
+        if self.eof() {
+            return None;
+        }
+        assert_eq!(self.consume_char(), quote);
+        Some(Value::Str(string))
+    }
+
+    /// Parses the argument of `url()`, which may be quoted.
+    fn parse_url(&mut self) -> Option<Value> {
+        if self.consume_char_ignore_whitescape() != Some('(') {
+            return None;
+        }
+        self.consume_whitespace();
+        if self.eof() {
+            return None;
+        }
+
+        let url = match self.next_char() {
+            quote @ '"' | quote @ '\'' => {
+                self.consume_char();
+                let url = self.consume_while(|c| c != quote);
+                if self.eof() {
+                    return None;
+                }
+                assert_eq!(self.consume_char(), quote);
+                url
+            }
+            _ => self.consume_while(|c| c != ')' && !c.is_whitespace()),
+        };
+
+        if self.consume_char_ignore_whitescape() != Some(')') {
+            return None;
+        }
+        Some(Value::Url(url))
+    }
+
+    /// Parses the arguments of `linear-gradient()`: an optional angle or `to <side>` followed by
+    /// two or more color stops with optional percentage positions.
+    fn parse_linear_gradient(&mut self) -> Option<Value> {
+        if self.consume_char_ignore_whitescape() != Some('(') {
+            return None;
+        }
+
+        let mut direction = GradientDirection::ToBottom;
+        self.consume_whitespace();
+        let start = self.pos;
+        if !self.eof() && is_number_start(self.next_char()) {
+            direction = GradientDirection::Angle(self.parse_angle()?);
+            if self.consume_char_ignore_whitescape() != Some(',') {
+                return None;
+            }
+        } else if self.parse_identifier() != "to" {
+            // Not a direction (e.g. `tomato`). Rewind and parse it as a color stop.
+            self.pos = start;
+        } else {
+            self.consume_whitespace();
+            direction = match self.parse_identifier().as_str() {
+                "top" => GradientDirection::ToTop,
+                "bottom" => GradientDirection::ToBottom,
+                "left" => GradientDirection::ToLeft,
+                "right" => GradientDirection::ToRight,
+                _ => return None,
+            };
+            if self.consume_char_ignore_whitescape() != Some(',') {
+                return None;
+            }
+        }
+
+        let mut stops = vec![];
+        loop {
+            self.consume_whitespace();
+            if self.eof() {
+                return None;
+            }
+            let color = self.parse_value()?.to_color()?;
+            self.consume_whitespace();
+            if self.eof() {
+                return None;
+            }
+            let position = match self.next_char() {
+                '0'...'9' | '.' => match self.parse_length() {
+                    Some(Value::Length(f, Unit::Percent)) => Some(f / 100.0),
+                    Some(Value::Num(f)) if f == 0.0 => Some(0.0),
+                    // TODO: Support other units.
+                    _ => return None,
+                },
+                _ => None,
+            };
+            stops.push(ColorStop {
+                color: color,
+                position: position,
+            });
+            match self.consume_char_ignore_whitescape() {
+                Some(',') => {}
+                Some(')') => break,
+                _ => return None,
+            }
+        }
+
+        if stops.len() < 2 {
+            return None;
+        }
+
+        Some(Value::Gradient(GradientSpec {
+            direction: direction,
+            stops: stops,
+        }))
+    }
+
+    /// Parses an angle in `deg`, `grad`, `rad` or `turn` into degrees. Zero may omit the unit.
+    fn parse_angle(&mut self) -> Option<f64> {
+        let sign = match self.next_char() {
+            '-' => {
+                self.consume_char();
+                -1.0
+            }
+            '+' => {
+                self.consume_char();
+                1.0
+            }
+            _ => 1.0,
+        };
+        let num: f64 = self.consume_while(|c| match c {
+            '0'...'9' | '.' => true,
+            _ => false,
+        }).parse()
+            .ok()?;
+        let num = sign * num;
+        match self.parse_identifier().as_str() {
+            "deg" => Some(num),
+            "grad" => Some(num * 0.9),
+            "rad" => Some(num.to_degrees()),
+            "turn" => Some(num * 360.0),
+            "" if num == 0.0 => Some(0.0),
+            _ => None,
+        }
+    }
+
+    /// Returns the number and whether it's followed by '%'.
+    fn parse_color_arg(&mut self) -> Option<(f64, bool)> {
+        let sign = if !self.eof() && self.next_char() == '-' {
+            self.consume_char();
+            -1.0
+        } else {
+            1.0
+        };
+        let num = self.consume_while(|c| match c {
+            '0'...'9' | '.' => true,
+            _ => false,
+        });
+        let num: f64 = num.parse().ok()?;
+        let percent = !self.eof() && self.next_char() == '%';
+        if percent {
+            self.consume_char();
+        }
+        Some((sign * num, percent))
     }
 
-    fn parse_color(&mut self) -> Value {
+    /// Parses `#rgb`, `#rgba`, `#rrggbb` and `#rrggbbaa`. Returns None for any other form.
+    fn parse_color(&mut self) -> Option<Value> {
         assert_eq!(self.consume_char(), '#');
         let hex_str = self.parse_hex_num();
-        let (r, g, b) = match hex_str.len() {
-            3 => {
-                let r = u8::from_str_radix(&hex_str[0..1], 16).unwrap();
-                let g = u8::from_str_radix(&hex_str[1..2], 16).unwrap();
-                let b = u8::from_str_radix(&hex_str[2..3], 16).unwrap();
-                (r * 16 + r, g * 16 + g, b * 16 + b)
-            }
-            6 => (
-                u8::from_str_radix(&hex_str[0..2], 16).unwrap(),
-                u8::from_str_radix(&hex_str[2..4], 16).unwrap(),
-                u8::from_str_radix(&hex_str[4..6], 16).unwrap(),
-            ),
-            _ => panic!(),
+        if !hex_str.chars().all(valid_hex_char) {
+            return None;
+        }
+
+        let nibble = |i: usize| u8::from_str_radix(&hex_str[i..i + 1], 16).unwrap() * 0x11;
+        let pair = |i: usize| u8::from_str_radix(&hex_str[i..i + 2], 16).unwrap();
+        let (r, g, b, a) = match hex_str.len() {
+            3 => (nibble(0), nibble(1), nibble(2), 255),
+            4 => (nibble(0), nibble(1), nibble(2), nibble(3)),
+            6 => (pair(0), pair(2), pair(4), 255),
+            8 => (pair(0), pair(2), pair(4), pair(6)),
+            _ => return None,
         };
-        Value::Color(Color {
+        Some(Value::Color(Color {
             r: r,
             g: g,
             b: b,
-            a: 255,
-        })
+            a: a,
+        }))
     }
 
     fn parse_hex_num(&mut self) -> String {
-        self.consume_while(valid_hex_char)
+        // Consume all identifier characters so that malformed colors can be detected.
+        self.consume_while(valid_ident_char)
     }
 
     // fn parse_hex_pair(&mut self) -> u8 {
@@ -515,11 +1133,14 @@ impl Parser {
         self.consume_while(valid_ident_percent_char).to_lowercase()
     }
 
-    fn consume_char_ignore_whitescape(&mut self) -> char {
+    fn consume_char_ignore_whitescape(&mut self) -> Option<char> {
         self.consume_whitespace();
+        if self.eof() {
+            return None;
+        }
         let c = self.consume_char();
         self.consume_whitespace();
-        c
+        Some(c)
     }
 
     fn consume_whitespace(&mut self) {
@@ -617,10 +1238,15 @@ impl fmt::Display for Stylesheet {
                             &Value::Length(ref f, Unit::Pt) => format!("{}pt", f),
                             &Value::Length(ref f, Unit::Percent) => format!("{}%", f),
                             &Value::Length(ref f, Unit::Em) => format!("{}em", f),
+                            &Value::Length(ref f, Unit::Rem) => format!("{}rem", f),
                             &Value::Num(ref f) => format!("{}", f),
                             &Value::Color(ref color) => {
                                 format!("rgba({}, {}, {}, {})", color.r, color.g, color.b, color.a)
                             }
+                            &Value::Gradient(ref gradient) => format!("{}", gradient),
+                            &Value::Url(ref url) => format!("url({})", url),
+                            &Value::Str(ref string) => format!("\"{}\"", string),
+                            &Value::Comma => ",".to_string(),
                         }
                     ))
                 }
@@ -707,22 +1333,27 @@ fn test1() {
                 Declaration {
                     name: "width".to_string(),
                     values: vec![Value::Length(70.0, Unit::Percent)],
+                    important: false,
                 },
                 Declaration {
                     name: "height".to_string(),
                     values: vec![Value::Length(50.0, Unit::Px)],
+                    important: false,
                 },
                 Declaration {
                     name: "font-weight".to_string(),
                     values: vec![Value::Keyword("bold".to_string())],
+                    important: false,
                 },
                 Declaration {
                     name: "z-index".to_string(),
                     values: vec![Value::Num(2.0)],
+                    important: false,
                 },
                 Declaration {
                     name: "font-size".to_string(),
                     values: vec![Value::Length(10.0, Unit::Pt)],
+                    important: false,
                 },
                 Declaration {
                     name: "color".to_string(),
@@ -734,6 +1365,7 @@ fn test1() {
                             a: 0xff,
                         }),
                     ],
+                    important: false,
                 },
                 Declaration {
                     name: "background-color".to_string(),
@@ -745,6 +1377,7 @@ fn test1() {
                             a: 0xff,
                         }),
                     ],
+                    important: false,
                 },
             ],
         },
@@ -763,15 +1396,44 @@ fn test2() {
             Declaration {
                 name: "color".to_string(),
                 values: vec![Value::Keyword("black".to_string())],
+                important: false,
             },
             Declaration {
                 name: "background".to_string(),
                 values: vec![Value::Keyword("white".to_string())],
+                important: false,
             },
         ]
     );
 }
 
+#[test]
+fn test_malformed_attr_style() {
+    let src = "color red; background: white; : blue; width: 10px; }; ; height: 5px";
+    let decls = parse_attr_style(src.to_string());
+
+    // Only the malformed declarations are dropped.
+    let names: Vec<&str> = decls.iter().map(|decl| decl.name.as_str()).collect();
+    assert_eq!(names, vec!["background", "width", "height"]);
+}
+
+#[test]
+fn test_invalid_lengths() {
+    // Declarations with an unknown unit or a malformed number are dropped.
+    let src = "width: 10vh; height: 1.2.3px; margin: 1px 2vh; padding: 4px";
+    let decls = parse_attr_style(src.to_string());
+    let names: Vec<&str> = decls.iter().map(|decl| decl.name.as_str()).collect();
+    assert_eq!(names, vec!["padding"]);
+
+    let stylesheet = parse(format!("p {{ {} }} div {{ width: 10px }}", src));
+    let names: Vec<Vec<&str>> = stylesheet
+        .rules
+        .iter()
+        .map(|rule| rule.declarations.iter().map(|decl| decl.name.as_str()).collect())
+        .collect();
+    assert_eq!(names, vec![vec!["padding"], vec!["width"]]);
+}
+
 #[test]
 fn test_rgb_rgba() {
     let src = "color: rgb(1, 2, 3); background: rgba(250, 1, 250, 0.3); ";
@@ -790,6 +1452,7 @@ fn test_rgb_rgba() {
                         a: 255,
                     }),
                 ],
+                important: false,
             },
             Declaration {
                 name: "background".to_string(),
@@ -801,7 +1464,277 @@ fn test_rgb_rgba() {
                         a: (255.0 * 0.3) as u8,
                     }),
                 ],
+                important: false,
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_hex_color() {
+    let src = "a: #abc; b: #abcd; c: #a1b2c3; d: #a1b2c3d4; e: #abcde; f: #xyz; g: #fff;";
+    let decls = parse_attr_style(src.to_string());
+
+    let color = |r, g, b, a| {
+        vec![
+            Value::Color(Color {
+                r: r,
+                g: g,
+                b: b,
+                a: a,
+            }),
+        ]
+    };
+    assert_eq!(
+        decls,
+        vec![
+            Declaration {
+                name: "a".to_string(),
+                values: color(0xaa, 0xbb, 0xcc, 0xff),
+                important: false,
+            },
+            Declaration {
+                name: "b".to_string(),
+                values: color(0xaa, 0xbb, 0xcc, 0xdd),
+                important: false,
+            },
+            Declaration {
+                name: "c".to_string(),
+                values: color(0xa1, 0xb2, 0xc3, 0xff),
+                important: false,
+            },
+            Declaration {
+                name: "d".to_string(),
+                values: color(0xa1, 0xb2, 0xc3, 0xd4),
+                important: false,
+            },
+            // "e" and "f" are malformed and ignored.
+            Declaration {
+                name: "g".to_string(),
+                values: color(0xff, 0xff, 0xff, 0xff),
+                important: false,
             },
         ]
     );
 }
+
+#[test]
+fn test_named_color() {
+    let rgba = |name: &str| named_color(name).map(|c| (c.r, c.g, c.b, c.a));
+    assert_eq!(rgba("red"), Some((0xff, 0x00, 0x00, 0xff)));
+    assert_eq!(rgba("Red"), Some((0xff, 0x00, 0x00, 0xff)));
+    assert_eq!(rgba("RED"), Some((0xff, 0x00, 0x00, 0xff)));
+    assert_eq!(rgba("rebeccapurple"), Some((0x66, 0x33, 0x99, 0xff)));
+    assert_eq!(rgba("aliceblue"), Some((0xf0, 0xf8, 0xff, 0xff)));
+    assert_eq!(rgba("yellowgreen"), Some((0x9a, 0xcd, 0x32, 0xff)));
+    assert_eq!(rgba("grey"), rgba("gray"));
+    assert_eq!(rgba("transparent"), Some((0x00, 0x00, 0x00, 0x00)));
+    assert_eq!(rgba("notacolor"), None);
+    assert_eq!(
+        Value::Keyword("cornflowerblue".to_string()).to_color(),
+        Some(Color {
+            r: 0x64,
+            g: 0x95,
+            b: 0xed,
+            a: 0xff,
+        })
+    );
+}
+
+#[test]
+fn test_rgb_rgba_variants() {
+    let src = "a: rgb( 255 ,0,  128 ); b: rgb(100%, 50%, 0%); c: rgba(0, 0, 0, 0.5); \
+               d: rgba(10%, 20%, 30%, 50%); e: rgb(300, -20, 1.5); f: rgb(1, 2); g: rgb(1, 2, 3, 1)";
+    let decls = parse_attr_style(src.to_string());
+
+    let color = |r, g, b, a| {
+        vec![
+            Value::Color(Color {
+                r: r,
+                g: g,
+                b: b,
+                a: a,
+            }),
+        ]
+    };
+    assert_eq!(
+        decls,
+        vec![
+            Declaration {
+                name: "a".to_string(),
+                values: color(255, 0, 128, 255),
+                important: false,
+            },
+            Declaration {
+                name: "b".to_string(),
+                values: color(255, 128, 0, 255),
+                important: false,
+            },
+            Declaration {
+                name: "c".to_string(),
+                values: color(0, 0, 0, 127),
+                important: false,
+            },
+            Declaration {
+                name: "d".to_string(),
+                values: color(26, 51, 77, 127),
+                important: false,
+            },
+            Declaration {
+                name: "e".to_string(),
+                values: color(255, 0, 2, 255),
+                important: false,
+            },
+            // "f" is malformed and ignored.
+            Declaration {
+                name: "g".to_string(),
+                values: color(1, 2, 3, 255),
+                important: false,
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_linear_gradient() {
+    let src = "a: linear-gradient(to right, #fff, #000); \
+               b: linear-gradient(red 10%, lime, blue 90%, white); \
+               c: linear-gradient(to nowhere, red, blue); d: linear-gradient(red)";
+    let decls = parse_attr_style(src.to_string());
+
+    assert_eq!(decls.len(), 2);
+    assert_eq!(
+        decls[0],
+        Declaration {
+            name: "a".to_string(),
+            values: vec![
+                Value::Gradient(GradientSpec {
+                    direction: GradientDirection::ToRight,
+                    stops: vec![
+                        ColorStop {
+                            color: WHITE,
+                            position: None,
+                        },
+                        ColorStop {
+                            color: BLACK,
+                            position: None,
+                        },
+                    ],
+                }),
+            ],
+            important: false,
+        }
+    );
+
+    let gradient = decls[1].values[0].to_gradient().unwrap();
+    assert_eq!(gradient.direction, GradientDirection::ToBottom);
+    assert_eq!(
+        gradient.resolved_stops(),
+        vec![(0.1, RED), (0.5, LIME), (0.9, BLUE), (1.0, WHITE)]
+    );
+}
+
+#[test]
+fn test_linear_gradient_angle() {
+    let src = "a: linear-gradient(45deg, red, blue 80%); b: linear-gradient(-0.25turn, red, blue); \
+               c: linear-gradient(0, red, blue); d: linear-gradient(45, red, blue)";
+    let decls = parse_attr_style(src.to_string());
+
+    let directions: Vec<GradientDirection> = decls
+        .iter()
+        .map(|decl| decl.values[0].to_gradient().unwrap().direction)
+        .collect();
+    // The angle of d has no unit.
+    assert_eq!(
+        directions,
+        vec![
+            GradientDirection::Angle(45.0),
+            GradientDirection::Angle(-90.0),
+            GradientDirection::Angle(0.0),
+        ]
+    );
+    assert_eq!(
+        decls[0].values[0].to_gradient().unwrap().resolved_stops(),
+        vec![(0.0, RED), (0.8, BLUE)]
+    );
+    assert_eq!(
+        format!("{}", decls[0].values[0].to_gradient().unwrap()),
+        "linear-gradient(45deg, rgba(255, 0, 0, 255), rgba(0, 0, 255, 255) 80%)"
+    );
+}
+
+#[test]
+fn test_important() {
+    let decls = parse_attr_style("color: red !important; width: 1px".to_string());
+    assert_eq!(
+        decls,
+        vec![
+            Declaration {
+                name: "color".to_string(),
+                values: vec![Value::Keyword("red".to_string())],
+                important: true,
+            },
+            Declaration {
+                name: "width".to_string(),
+                values: vec![Value::Length(1.0, Unit::Px)],
+                important: false,
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_url() {
+    let decls = parse_attr_style(
+        "a: url(foo.png); b: url( \"b a r.png\" ) no-repeat; c: url('baz.png'); d: url(x"
+            .to_string(),
+    );
+    let values: Vec<Vec<Value>> = decls.into_iter().map(|d| d.values).collect();
+    assert_eq!(
+        values,
+        vec![
+            vec![Value::Url("foo.png".to_string())],
+            vec![
+                Value::Url("b a r.png".to_string()),
+                Value::Keyword("no-repeat".to_string()),
+            ],
+            vec![Value::Url("baz.png".to_string())],
+        ]
+    );
+}
+
+#[test]
+fn test_import() {
+    let (imports, stylesheet) = parse_with_imports(
+        "@charset \"utf-8\"; @import url(a.css); @import \"b.css\" screen; @import 'c.css';
+         @media print { p { color: red; } } @import url(late.css); p { color: blue; }
+         @import 'later.css';"
+            .to_string(),
+    );
+    // Imports after another rule are ignored, and so are the rules in @media for now.
+    assert_eq!(imports, vec!["a.css", "b.css", "c.css"]);
+    assert_eq!(stylesheet, parse("p { color: blue; }".to_string()));
+
+    let (imports, _) = parse_with_imports("p { color: blue; } @import url(a.css);".to_string());
+    assert!(imports.is_empty());
+}
+
+#[test]
+fn test_font_family() {
+    let decls = parse_attr_style(
+        "font-family: \"Times New Roman\", 'Noto Serif', Georgia, serif; x: \"oops".to_string(),
+    );
+    let values: Vec<Vec<Value>> = decls.into_iter().map(|d| d.values).collect();
+    assert_eq!(
+        values,
+        vec![vec![
+            Value::Str("Times New Roman".to_string()),
+            Value::Comma,
+            Value::Str("Noto Serif".to_string()),
+            Value::Comma,
+            Value::Keyword("georgia".to_string()),
+            Value::Comma,
+            Value::Keyword("serif".to_string()),
+        ]]
+    );
+}
@@ -23,6 +23,11 @@ thread_local!(
         rule_img(&mut rules);
         rule_b(&mut rules);
         rule_button(&mut rules);
+        rule_ul(&mut rules);
+        rule_ol(&mut rules);
+        rule_li(&mut rules);
+        rule_hr(&mut rules);
+        rule_script_style(&mut rules);
         RefCell::new(rules)
     }
 );
@@ -35,7 +40,8 @@ macro_rules! tag_name { ($name:expr) => {
 macro_rules! decl { ($name:expr, $( $val:expr ),*) => {
     Declaration {
         name: $name.to_string(),
-        values: vec![$($val)*],
+        values: vec![$($val),*],
+        important: false,
     }
 }}
 
@@ -161,3 +167,50 @@ fn rule_button(rules: &mut Vec<Rule>) {
         declarations: vec![decl!("display", keyword!("inline"))],
     });
 }
+
+fn rule_ul(rules: &mut Vec<Rule>) {
+    rules.push(Rule {
+        selectors: vec![tag_name!("ul")],
+        declarations: vec![
+            decl!("padding-left", len_px!(40f64)),
+            decl!("list-style-type", keyword!("disc")),
+        ],
+    });
+}
+
+fn rule_ol(rules: &mut Vec<Rule>) {
+    rules.push(Rule {
+        selectors: vec![tag_name!("ol")],
+        declarations: vec![
+            decl!("padding-left", len_px!(40f64)),
+            decl!("list-style-type", keyword!("decimal")),
+        ],
+    });
+}
+
+fn rule_li(rules: &mut Vec<Rule>) {
+    rules.push(Rule {
+        selectors: vec![tag_name!("li")],
+        declarations: vec![decl!("display", keyword!("list-item"))],
+    });
+}
+
+fn rule_hr(rules: &mut Vec<Rule>) {
+    rules.push(Rule {
+        selectors: vec![tag_name!("hr")],
+        declarations: vec![
+            decl!("height", len_px!(0f64)),
+            decl!("margin-top", len_px!(8f64)),
+            decl!("margin-bottom", len_px!(8f64)),
+            decl!("border", len_px!(1f64), keyword!("solid"), color!(GRAY)),
+        ],
+    });
+}
+
+// The contents of <script> and <style> are not page text.
+fn rule_script_style(rules: &mut Vec<Rule>) {
+    rules.push(Rule {
+        selectors: vec![tag_name!("script"), tag_name!("style")],
+        declarations: vec![decl!("display", keyword!("none"))],
+    });
+}
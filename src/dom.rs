@@ -1,5 +1,4 @@
 use std::collections::{HashMap, HashSet};
-use std::path::{Path, PathBuf};
 use std::{fmt, iter};
 use css;
 
@@ -28,6 +27,15 @@ pub enum LayoutType {
     Button,
 }
 
+/// A stylesheet of a document.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StyleSource {
+    // The contents of <style>.
+    Inline(String),
+    // The href of <link rel="stylesheet">.
+    Link(String),
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct ElementData {
     pub tag_name: String,
@@ -95,17 +103,63 @@ impl Node {
             }
             _ => self.children
                 .iter()
-                .find(|child| child.find_first_node_by_tag_name(expected).is_some()),
+                .filter_map(|child| child.find_first_node_by_tag_name(expected))
+                .next(),
+        }
+    }
+
+    /// The text of the first <title>, with whitespace trimmed. None if it's missing or empty.
+    pub fn title(&self) -> Option<String> {
+        fn text(node: &Node) -> String {
+            match node.data {
+                NodeType::Text(ref text) => text.clone(),
+                NodeType::Element(_) => node.children.iter().map(text).collect(),
+            }
+        }
+        self.find_first_node_by_tag_name("title")
+            .map(|title| text(title).trim().to_string())
+            .and_then(|title| if title.is_empty() { None } else { Some(title) })
+    }
+
+    /// The stylesheets of the document in document order: the contents of <style>, and the hrefs
+    /// of <link rel="stylesheet">. Those for other media than the screen are left out.
+    pub fn style_sources(&self) -> Vec<StyleSource> {
+        fn collect(node: &Node, sources: &mut Vec<StyleSource>) {
+            match node.data {
+                NodeType::Element(ref e) if e.tag_name.eq_ignore_ascii_case("style") => {
+                    if !e.for_screen() {
+                        return;
+                    }
+                    for child in &node.children {
+                        if let NodeType::Text(ref text) = child.data {
+                            sources.push(StyleSource::Inline(text.clone()));
+                        }
+                    }
+                }
+                NodeType::Element(ref e) if e.tag_name.eq_ignore_ascii_case("link") => {
+                    if let Some(href) = e.attrs.get("href") {
+                        if e.is_stylesheet_link() && e.for_screen() {
+                            sources.push(StyleSource::Link(href.clone()));
+                        }
+                    }
+                }
+                NodeType::Element(_) => for child in &node.children {
+                    collect(child, sources);
+                },
+                NodeType::Text(_) => {}
+            }
         }
+        let mut sources = vec![];
+        collect(self, &mut sources);
+        sources
     }
 
-    pub fn find_stylesheet_path(&self) -> Option<PathBuf> {
-        self.find_first_node_by_tag_name("link")
-            .and_then(|&Node { ref data, .. }| match data {
-                &NodeType::Element(ElementData { ref attrs, .. }) => attrs
-                    .get("href")
-                    .and_then(|filename| Some(Path::new(filename).to_path_buf())),
-                &NodeType::Text(_) => None,
+    /// The href of the first <base>, which relative URLs in the document resolve against.
+    pub fn base_href(&self) -> Option<&String> {
+        self.find_first_node_by_tag_name("base")
+            .and_then(|base| match base.data {
+                NodeType::Element(ElementData { ref attrs, .. }) => attrs.get("href"),
+                NodeType::Text(_) => None,
             })
     }
 
@@ -116,6 +170,14 @@ impl Node {
         }
     }
 
+    /// The text shown instead of an image that can't be loaded.
+    pub fn alt_text(&self) -> Option<&String> {
+        match self.data {
+            NodeType::Element(ElementData { ref attrs, .. }) => attrs.get("alt"),
+            NodeType::Text(_) => None,
+        }
+    }
+
     pub fn anker_url(&self) -> Option<&String> {
         match self.data {
             NodeType::Element(ElementData { ref attrs, .. }) => attrs.get("href"),
@@ -127,7 +189,7 @@ impl Node {
         match self.data {
             NodeType::Element(ElementData { ref attrs, .. }) => attrs
                 .get(name)
-                .and_then(|val| Some(css::parse_value(val.clone()))),
+                .and_then(|val| css::parse_value(val.clone())),
             NodeType::Text(_) => None,
         }
     }
@@ -140,9 +202,52 @@ impl ElementData {
         self.attrs.get("id")
     }
 
+    /// The advisory text shown as a tooltip.
+    pub fn title(&self) -> Option<&String> {
+        self.attrs.get("title")
+    }
+
+    /// The name referred to by URL fragments: the id, or the name of <a>.
+    pub fn fragment_name(&self) -> Option<&String> {
+        self.id().or_else(|| {
+            if self.tag_name == "a" {
+                self.attrs.get("name")
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Whether `rel` of <link> has `stylesheet`. Alternative stylesheets aren't applied.
+    fn is_stylesheet_link(&self) -> bool {
+        let rel = self.attrs.get("rel").map_or("", |rel| rel.as_str());
+        let has = |kind: &str| rel.split_whitespace().any(|r| r.eq_ignore_ascii_case(kind));
+        has("stylesheet") && !has("alternate")
+    }
+
+    /// Whether the `media` of <style> or <link> includes the screen. It does without `media`.
+    /// Only the media types of the queries are looked at.
+    fn for_screen(&self) -> bool {
+        match self.attrs.get("media") {
+            Some(media) => media.split(',').any(|query| {
+                let mut words = query
+                    .split_whitespace()
+                    .skip_while(|word| word.eq_ignore_ascii_case("only"));
+                match words.next() {
+                    Some(medium) => {
+                        medium.starts_with('(') || medium.eq_ignore_ascii_case("all")
+                            || medium.eq_ignore_ascii_case("screen")
+                    }
+                    None => true,
+                }
+            }),
+            None => true,
+        }
+    }
+
     pub fn classes(&self) -> HashSet<&str> {
         match self.attrs.get("class") {
-            Some(classlist) => classlist.split(' ').collect(),
+            Some(classlist) => classlist.split_whitespace().collect(),
             None => HashSet::new(),
         }
     }
@@ -213,3 +318,29 @@ fn test_id() {
         None
     )
 }
+
+#[test]
+fn test_style_sources() {
+    use html;
+
+    let html_tree = html::parse(
+        "<html><head><link rel='stylesheet' href='a.css'><style>p {}</style>\
+         <link rel='icon' href='a.ico'><link rel='Alternate StyleSheet' href='b.css'>\
+         <link rel='stylesheet' href='print.css' media='print'>\
+         <link rel='stylesheet' href='c.css' media='print, only screen and (color)'></head>\
+         <body><style media='print'>p { color: red; }</style><link rel='stylesheet' href='d.css'>\
+         <link rel='stylesheet' href='http://cdn.example.com/e.css'></body></html>"
+            .to_string(),
+    );
+    // The hrefs are kept as written, to be resolved against the base URL of the document.
+    assert_eq!(
+        html_tree.style_sources(),
+        vec![
+            StyleSource::Link("a.css".to_string()),
+            StyleSource::Inline("p {}".to_string()),
+            StyleSource::Link("c.css".to_string()),
+            StyleSource::Link("d.css".to_string()),
+            StyleSource::Link("http://cdn.example.com/e.css".to_string()),
+        ]
+    );
+}
@@ -1,9 +1,12 @@
 use layout::{BoxType, Dimensions, EdgeSizes, LayoutBox, LayoutInfo, Rect};
 use inline::get_image;
+use interface::Document;
 use style;
+use style::BoxSizing;
 use css::Value;
 
 use std::cmp::{max, min};
+use std::rc::Rc;
 
 use app_units::Au;
 
@@ -149,19 +152,21 @@ impl<'a> LayoutBox<'a> {
         floats: &mut Floats,
         _last_margin_bottom: Au,
         containing_block: Dimensions,
-        _saved_block: Dimensions,
+        _containing_height: Option<Au>,
         viewport: Dimensions,
+        document: &Rc<Document>,
     ) {
         // TODO: Implement correctly ASAP!
         // Replaced Inline Element (<img>)
         match self.info {
             LayoutInfo::Image(ref mut pixbuf) => {
-                let (width, height) = get_image(self.style.unwrap(), pixbuf, containing_block);
+                let style = self.style.unwrap();
+                let (width, height) = get_image(style, pixbuf, containing_block, document);
                 self.dimensions.content.width = width;
                 self.dimensions.content.height = height;
             }
             LayoutInfo::Generic => {
-                self.assign_padding();
+                self.assign_padding(containing_block);
                 self.assign_border_width();
                 self.assign_margin();
 
@@ -170,7 +175,7 @@ impl<'a> LayoutBox<'a> {
                 if width_not_specified {
                     let children = self.children.clone();
                     let floats = self.floats.clone();
-                    self.layout_float_children(viewport);
+                    self.layout_float_children(viewport, document);
 
                     self.dimensions.content.width = Au(0);
                     for child in &self.children {
@@ -195,12 +200,13 @@ impl<'a> LayoutBox<'a> {
                     self.dimensions.content.height = Au(0);
                     self.floats = floats;
                     self.children = children;
-                    self.layout_float_children(viewport);
+                    self.layout_float_children(viewport, document);
                 } else {
-                    self.layout_float_children(viewport);
+                    self.layout_float_children(viewport, document);
                 }
 
-                self.calculate_block_height();
+                let height = self.specified_height(None);
+                self.calculate_block_height(height);
             }
             _ => unimplemented!(),
         };
@@ -213,8 +219,8 @@ impl<'a> LayoutBox<'a> {
         ));
     }
 
-    pub fn layout_float_children(&mut self, viewport: Dimensions) {
-        self.layout_block_children(viewport);
+    pub fn layout_float_children(&mut self, viewport: Dimensions, document: &Rc<Document>) {
+        self.layout_block_children(Au(0), None, viewport, document);
         // The height of float children in a float element is noticed.
         self.dimensions.content.height = max(
             self.dimensions.content.height,
@@ -271,7 +277,12 @@ impl<'a> LayoutBox<'a> {
             width_not_specified = true;
             d.content.width = containing_block.content.width;
         } else if let Some(width) = width.maybe_percent_to_px(cb_width) {
-            d.content.width = Au::from_f64_px(width)
+            d.content.width = Au::from_f64_px(width);
+            // Under `box-sizing: border-box`, `width` includes the padding and border.
+            if style.box_sizing() == BoxSizing::BorderBox {
+                let edges = d.padding.left + d.padding.right + d.border.left + d.border.right;
+                d.content.width = max(d.content.width - edges, Au(0));
+            }
         }
 
         width_not_specified
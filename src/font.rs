@@ -5,7 +5,8 @@ use pangocairo;
 use css::px2pt;
 
 use std::cell::RefCell;
-use pango::{ContextExt, LayoutExt};
+use std::collections::HashSet;
+use pango::{ContextExt, FontFamilyExt, LayoutExt};
 
 use app_units::Au;
 
@@ -18,14 +19,31 @@ thread_local!(
     };
     pub static FONT_DESC: RefCell<pango::FontDescription> = {
         RefCell::new(pango::FontDescription::from_string("sans-serif normal 16"))
-    }
+    };
+    /// The lowercased names of the font families installed in the system.
+    static AVAILABLE_FAMILIES: HashSet<String> = PANGO_LAYOUT.with(|layout| {
+        layout
+            .borrow()
+            .get_context()
+            .unwrap()
+            .list_families()
+            .iter()
+            .filter_map(|family| family.get_name())
+            .map(|name| name.to_lowercase())
+            .collect()
+    })
 );
 
-#[derive(Clone, Copy, Debug, PartialEq)]
+pub const DEFAULT_FONT_FAMILY: &str = "Sans";
+
+#[derive(Clone, Debug, PartialEq)]
 pub struct Font {
     pub size: Au,
     pub weight: FontWeight,
     pub slant: FontSlant,
+    pub family: String,
+    /// The extra space after each character.
+    pub letter_spacing: Au,
 }
 
 #[derive(Clone, Copy, Debug, PartialEq)]
@@ -41,11 +59,13 @@ pub enum FontSlant {
 }
 
 impl Font {
-    pub fn new(size: Au, weight: FontWeight, slant: FontSlant) -> Font {
+    pub fn new(size: Au, weight: FontWeight, slant: FontSlant, family: String) -> Font {
         Font {
             size: size,
             weight: weight,
             slant: slant,
+            family: family,
+            letter_spacing: Au(0),
         }
     }
 
@@ -54,30 +74,73 @@ impl Font {
             size: Au(0),
             weight: FontWeight::Normal,
             slant: FontSlant::Normal,
+            family: DEFAULT_FONT_FAMILY.to_string(),
+            letter_spacing: Au(0),
+        }
+    }
+
+    pub fn set_font_description(&self, font_desc: &mut pango::FontDescription) {
+        font_desc.set_family(self.family.as_str());
+        font_desc.set_size(pango::units_from_double(px2pt(self.size.to_f64_px())));
+        font_desc.set_style(self.slant.to_pango_font_slant());
+        font_desc.set_weight(self.weight.to_pango_font_weight());
+    }
+
+    /// Set the attributes of the text in `layout` that aren't in the font description.
+    pub fn set_layout_attributes(&self, layout: &pango::Layout) {
+        let attrs = pango::AttrList::new();
+        if self.letter_spacing != Au(0) {
+            let spacing = pango::units_from_double(self.letter_spacing.to_f64_px());
+            if let Some(attr) = pango::Attribute::new_letter_spacing(spacing) {
+                attrs.insert(attr);
+            }
         }
+        layout.set_attributes(Some(&attrs));
     }
 
     pub fn text_width(&self, text: &str) -> f64 {
         FONT_DESC.with(|font_desc| {
             let mut font_desc = font_desc.borrow_mut();
-            font_desc.set_size(pango::units_from_double(px2pt(self.size.to_f64_px())));
-            font_desc.set_style(self.slant.to_pango_font_slant());
-            font_desc.set_weight(self.weight.to_pango_font_weight());
+            self.set_font_description(&mut font_desc);
             PANGO_LAYOUT.with(|layout| {
                 let layout = layout.borrow_mut();
                 layout.set_text(text);
                 layout.set_font_description(Some(&*font_desc));
+                self.set_layout_attributes(&layout);
                 pango::units_to_double(layout.get_size().0)
             })
         })
     }
 
+    /// Return the byte index in `text` of the character boundary nearest to `x`.
+    pub fn index_at(&self, text: &str, x: f64) -> usize {
+        if x <= 0.0 {
+            return 0;
+        }
+        FONT_DESC.with(|font_desc| {
+            let mut font_desc = font_desc.borrow_mut();
+            self.set_font_description(&mut font_desc);
+            PANGO_LAYOUT.with(|layout| {
+                let layout = layout.borrow_mut();
+                layout.set_text(text);
+                layout.set_font_description(Some(&*font_desc));
+                self.set_layout_attributes(&layout);
+                let (_, index, trailing) = layout.xy_to_index(pango::units_from_double(x), 0);
+                // `trailing` is the number of characters to skip when `x` is on the trailing
+                // half of the character.
+                let index = index as usize;
+                text[index..]
+                    .char_indices()
+                    .nth(trailing as usize)
+                    .map_or(text.len(), |(pos, _)| index + pos)
+            })
+        })
+    }
+
     pub fn get_ascent_descent(&self) -> (Au, Au) {
         FONT_DESC.with(|font_desc| {
             let mut font_desc = font_desc.borrow_mut();
-            font_desc.set_size(pango::units_from_double(px2pt(self.size.to_f64_px())));
-            font_desc.set_style(self.slant.to_pango_font_slant());
-            font_desc.set_weight(self.weight.to_pango_font_weight());
+            self.set_font_description(&mut font_desc);
             PANGO_LAYOUT.with(|layout| {
                 let ctx = layout.borrow_mut().get_context().unwrap();
                 let metrics =
@@ -91,45 +154,97 @@ impl Font {
         })
     }
 
-    pub fn compute_max_chars(&self, s: &str, max_width: f64) -> usize {
+    /// Return the length of the longest prefix of `s` that fits in `max_width` and ends at a
+    /// break opportunity. If no such prefix exists, return 0, or break in the middle of the first
+    /// word if `line_is_empty`, since it doesn't fit in any line.
+    pub fn compute_max_chars(&self, s: &str, max_width: f64, line_is_empty: bool) -> usize {
         // TODO: Inefficient!
-        // TODO: This code doesn't allow other than alphabets.
-        if max_width < 0f64 {
-            return 0;
-        }
-
-        let mut buf = "".to_string();
+        // TODO: Follow the line breaking rules of each language.
         let mut last_splittable_pos = None;
-        let mut last_pos = 0;
         for (pos, c) in s.char_indices() {
-            buf.push(c);
-
-            if c.is_whitespace() || c.is_ascii_punctuation() {
+            let end = pos + c.len_utf8();
+            if c.is_whitespace() {
+                // Whitespace at the end of a line may overflow.
+                last_splittable_pos = Some(end);
+                continue;
+            }
+            if !c.is_ascii() && pos > 0 {
+                // Lines can be broken between multi-byte characters (e.g. Japanese).
                 last_splittable_pos = Some(pos);
             }
-
-            let text_width = self.text_width(buf.as_str());
-            if text_width > max_width {
-                if let Some(pos) = last_splittable_pos {
-                    return pos + 1; // '1' means whitespace or punctuation.
-                } else {
-                    if pos == 0 {
-                        break;
-                    }
-                    if pos - last_pos > 1 {
-                        // if c is multi-byte character
-                        return pos;
-                    }
-                }
+            if self.text_width(&s[0..end]) > max_width {
+                return match last_splittable_pos {
+                    Some(pos) => pos,
+                    // Break before `c`, but put at least one character in the line.
+                    None if line_is_empty => if pos == 0 { end } else { pos },
+                    None => 0,
+                };
+            }
+            if c.is_ascii_punctuation() {
+                last_splittable_pos = Some(end);
             }
-
-            last_pos = pos;
         }
+        s.len()
+    }
+}
 
-        if s.is_empty() {
-            0
-        } else {
-            1
+/// Return the first family in `families` that is a generic family or installed in the system.
+/// Generic families are mapped to the fontconfig aliases.
+pub fn resolve_font_family(families: &[String]) -> String {
+    for family in families {
+        match family.to_lowercase().as_str() {
+            "serif" => return "Serif".to_string(),
+            "sans-serif" | "system-ui" => return "Sans".to_string(),
+            "monospace" => return "Monospace".to_string(),
+            name => if AVAILABLE_FAMILIES.with(|available| available.contains(name)) {
+                return family.clone();
+            },
         }
     }
+    DEFAULT_FONT_FAMILY.to_string()
+}
+
+#[test]
+fn test_resolve_font_family() {
+    let families = |names: &[&str]| -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    };
+
+    assert_eq!(
+        resolve_font_family(&families(&["No Such Family", "monospace", "serif"])),
+        "Monospace"
+    );
+    assert_eq!(resolve_font_family(&families(&["No Such Family"])), DEFAULT_FONT_FAMILY);
+    assert_eq!(resolve_font_family(&[]), DEFAULT_FONT_FAMILY);
+
+    // The resolved family reaches the font description used for measuring and rendering.
+    let font = Font::new(
+        Au::from_f64_px(16.0),
+        FontWeight::Normal,
+        FontSlant::Normal,
+        resolve_font_family(&families(&["Unknown Family", "Serif"])),
+    );
+    let mut font_desc = pango::FontDescription::new();
+    font.set_font_description(&mut font_desc);
+    assert_eq!(
+        font_desc.get_family().map(|family| family.to_string()),
+        Some("Serif".to_string())
+    );
+}
+
+#[test]
+fn test_letter_spacing() {
+    let mut font = Font::new(
+        Au::from_f64_px(16.0),
+        FontWeight::Normal,
+        FontSlant::Normal,
+        DEFAULT_FONT_FAMILY.to_string(),
+    );
+    let width = font.text_width("spacing");
+
+    // Every character is followed by the spacing.
+    font.letter_spacing = Au::from_f64_px(2.0);
+    assert!((font.text_width("spacing") - (width + 7.0 * 2.0)).abs() < 1.0);
+    font.letter_spacing = Au::from_f64_px(-1.0);
+    assert!(font.text_width("spacing") < width);
 }
@@ -1,19 +1,12 @@
 use dom;
 
 use std::collections::HashMap;
-use std::cell::RefCell;
-use std::path::PathBuf;
 use std::cmp::max;
 use std::str::from_utf8;
 
-thread_local!(
-    pub static CUR_DIR: RefCell<PathBuf> = {
-        RefCell::new(PathBuf::new())
-    };
-);
-
-pub fn parse(source: String, file_path: PathBuf) -> dom::Node {
-    CUR_DIR.with(|cur_dir| *cur_dir.borrow_mut() = file_path.parent().unwrap().to_path_buf());
+/// Parse an HTML document. The values of attributes are kept as written, so URLs in `src` and
+/// `href` are resolved later against the base URL of the document.
+pub fn parse(source: String) -> dom::Node {
     let mut nodes = match Parser::new(source).parse_nodes() {
         Ok(nodes) => nodes,
         Err(_) => panic!("unknown error"),
@@ -27,6 +20,12 @@ pub fn parse(source: String, file_path: PathBuf) -> dom::Node {
     }
 }
 
+/// Whether the content of the element is raw text, which is not parsed as markup.
+fn is_raw_text_tag(tag_name: &str) -> bool {
+    let tag_name = tag_name.to_ascii_lowercase();
+    tag_name == "script" || tag_name == "style"
+}
+
 fn is_not_to_close_tag(tag_name: &str) -> bool {
     if tag_name == "br" || tag_name == "img" || tag_name == "hr" || tag_name == "meta"
         || tag_name == "input" || tag_name == "embed" || tag_name == "area"
@@ -128,6 +127,21 @@ impl Parser {
             return Ok(dom::Node::elem(tag_name, attrs, vec![]));
         }
 
+        if is_raw_text_tag(tag_name.as_str()) {
+            let text = self.parse_raw_text(tag_name.as_str());
+            // Closing tag, which may have whitespace before `>`.
+            if !self.eof() {
+                self.consume_while(|c| c != '>')?;
+                self.consume_char()?;
+            }
+            let children = if text.is_empty() {
+                vec![]
+            } else {
+                vec![dom::Node::text(text)]
+            };
+            return Ok(dom::Node::elem(tag_name, attrs, children));
+        }
+
         // Contents.
         let children = self.parse_nodes()?;
 
@@ -143,6 +157,29 @@ impl Parser {
         Ok(dom::Node::elem(tag_name, attrs, children))
     }
 
+    /// Consume the text up to the end tag of `tag_name`, or to the end of the input. Only an end
+    /// tag ends the text, so `</scripts>` or `"<b>"` in a script don't.
+    fn parse_raw_text(&mut self, tag_name: &str) -> String {
+        let end_tag = format!("</{}", tag_name.to_ascii_lowercase());
+        let rest = self.input[self.pos..].to_ascii_lowercase();
+        let mut from = 0;
+        let end = loop {
+            match rest[from..].find(end_tag.as_str()) {
+                Some(i) => {
+                    let after = from + i + end_tag.len();
+                    match rest[after..].chars().next() {
+                        Some(c) if c != '>' && c != '/' && !c.is_whitespace() => from = after,
+                        _ => break from + i,
+                    }
+                }
+                None => break rest.len(),
+            }
+        };
+        let text = self.input[self.pos..self.pos + end].to_string();
+        self.pos += end;
+        text
+    }
+
     fn parse_tag_name(&mut self) -> Result<String, ()> {
         self.consume_while(|c| c.is_alphanumeric())
     }
@@ -155,9 +192,8 @@ impl Parser {
                 break;
             }
             match self.parse_attr() {
-                Ok(x) => {
-                    let (name, value) = url_conv(x);
-                    attributes.insert(name, value);
+                Ok((name, value)) => {
+                    attributes.insert(name, decode_entities(value.as_str()));
                 }
                 Err(()) => {}
             }
@@ -190,7 +226,7 @@ impl Parser {
 
     fn parse_text(&mut self) -> Result<dom::Node, ()> {
         let mut last = '*'; // any char except space
-        Ok(dom::Node::text(
+        Ok(dom::Node::text(decode_entities(
             self.consume_while(|c| c != '<')?
                 .chars()
                 .fold("".to_string(), |mut s, c| {
@@ -199,8 +235,9 @@ impl Parser {
                     }
                     last = c;
                     s
-                }),
-        ))
+                })
+                .as_str(),
+        )))
     }
 
     fn consume_whitespace(&mut self) -> Result<(), ()> {
@@ -239,27 +276,289 @@ impl Parser {
     }
 }
 
-fn url_conv(attr: (String, String)) -> (String, String) {
-    match attr.0.to_lowercase().as_str() {
-        "src" | "href" => {
-            if attr.1.len() > 0 && attr.1.chars().next().unwrap() == '#' {
-                (attr.0.clone(), attr.1.clone())
-            } else {
-                (
-                    attr.0.clone(),
-                    CUR_DIR.with(|dir| dir.borrow().join(attr.1).to_str().unwrap().to_string()),
-                )
+/// Replace character references like `&amp;`, `&#38;` and `&#x26;` with the characters. Unknown
+/// and unterminated ones are left as they are.
+pub fn decode_entities(s: &str) -> String {
+    let mut decoded = "".to_string();
+    let mut rest = s;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[0..start]);
+        rest = &rest[start..];
+
+        let c = rest.find(';').and_then(|end| {
+            let c = match &rest[1..end] {
+                name if name.starts_with("#x") || name.starts_with("#X") => {
+                    u32::from_str_radix(&name[2..], 16)
+                        .ok()
+                        .map(numeric_reference)
+                }
+                name if name.starts_with('#') => {
+                    name[1..].parse::<u32>().ok().map(numeric_reference)
+                }
+                name => named_entity(name),
+            };
+            c.map(|c| (c, end))
+        });
+        match c {
+            Some((c, end)) => {
+                decoded.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
             }
         }
-        _ => (attr.0, attr.1),
     }
+    decoded.push_str(rest);
+    decoded
+}
+
+/// The character of a numeric reference. NUL, surrogates and values out of range are replaced
+/// with U+FFFD.
+fn numeric_reference(code: u32) -> char {
+    match code {
+        0 => '\u{fffd}',
+        code => ::std::char::from_u32(code).unwrap_or('\u{fffd}'),
+    }
+}
+
+/// The character of a named reference, from the commonly used ones of HTML5.
+fn named_entity(name: &str) -> Option<char> {
+    let c = match name {
+        "amp" => '&',
+        "lt" => '<',
+        "gt" => '>',
+        "quot" => '"',
+        "apos" => '\'',
+        "nbsp" => '\u{a0}',
+        "iexcl" => '¡',
+        "cent" => '¢',
+        "pound" => '£',
+        "curren" => '¤',
+        "yen" => '¥',
+        "brvbar" => '¦',
+        "sect" => '§',
+        "uml" => '¨',
+        "copy" => '©',
+        "ordf" => 'ª',
+        "laquo" => '«',
+        "not" => '¬',
+        "shy" => '\u{ad}',
+        "reg" => '®',
+        "macr" => '¯',
+        "deg" => '°',
+        "plusmn" => '±',
+        "sup2" => '²',
+        "sup3" => '³',
+        "acute" => '´',
+        "micro" => 'µ',
+        "para" => '¶',
+        "middot" => '·',
+        "cedil" => '¸',
+        "sup1" => '¹',
+        "ordm" => 'º',
+        "raquo" => '»',
+        "frac14" => '¼',
+        "frac12" => '½',
+        "frac34" => '¾',
+        "iquest" => '¿',
+        "Agrave" => 'À',
+        "Aacute" => 'Á',
+        "Acirc" => 'Â',
+        "Atilde" => 'Ã',
+        "Auml" => 'Ä',
+        "Aring" => 'Å',
+        "AElig" => 'Æ',
+        "Ccedil" => 'Ç',
+        "Egrave" => 'È',
+        "Eacute" => 'É',
+        "Ecirc" => 'Ê',
+        "Euml" => 'Ë',
+        "Igrave" => 'Ì',
+        "Iacute" => 'Í',
+        "Icirc" => 'Î',
+        "Iuml" => 'Ï',
+        "ETH" => 'Ð',
+        "Ntilde" => 'Ñ',
+        "Ograve" => 'Ò',
+        "Oacute" => 'Ó',
+        "Ocirc" => 'Ô',
+        "Otilde" => 'Õ',
+        "Ouml" => 'Ö',
+        "times" => '×',
+        "Oslash" => 'Ø',
+        "Ugrave" => 'Ù',
+        "Uacute" => 'Ú',
+        "Ucirc" => 'Û',
+        "Uuml" => 'Ü',
+        "Yacute" => 'Ý',
+        "THORN" => 'Þ',
+        "szlig" => 'ß',
+        "agrave" => 'à',
+        "aacute" => 'á',
+        "acirc" => 'â',
+        "atilde" => 'ã',
+        "auml" => 'ä',
+        "aring" => 'å',
+        "aelig" => 'æ',
+        "ccedil" => 'ç',
+        "egrave" => 'è',
+        "eacute" => 'é',
+        "ecirc" => 'ê',
+        "euml" => 'ë',
+        "igrave" => 'ì',
+        "iacute" => 'í',
+        "icirc" => 'î',
+        "iuml" => 'ï',
+        "eth" => 'ð',
+        "ntilde" => 'ñ',
+        "ograve" => 'ò',
+        "oacute" => 'ó',
+        "ocirc" => 'ô',
+        "otilde" => 'õ',
+        "ouml" => 'ö',
+        "divide" => '÷',
+        "oslash" => 'ø',
+        "ugrave" => 'ù',
+        "uacute" => 'ú',
+        "ucirc" => 'û',
+        "uuml" => 'ü',
+        "yacute" => 'ý',
+        "thorn" => 'þ',
+        "yuml" => 'ÿ',
+        "OElig" => 'Œ',
+        "oelig" => 'œ',
+        "Scaron" => 'Š',
+        "scaron" => 'š',
+        "Yuml" => 'Ÿ',
+        "fnof" => 'ƒ',
+        "circ" => 'ˆ',
+        "tilde" => '˜',
+        "Alpha" => 'Α',
+        "Beta" => 'Β',
+        "Gamma" => 'Γ',
+        "Delta" => 'Δ',
+        "Epsilon" => 'Ε',
+        "Zeta" => 'Ζ',
+        "Eta" => 'Η',
+        "Theta" => 'Θ',
+        "Iota" => 'Ι',
+        "Kappa" => 'Κ',
+        "Lambda" => 'Λ',
+        "Mu" => 'Μ',
+        "Nu" => 'Ν',
+        "Xi" => 'Ξ',
+        "Omicron" => 'Ο',
+        "Pi" => 'Π',
+        "Rho" => 'Ρ',
+        "Sigma" => 'Σ',
+        "Tau" => 'Τ',
+        "Upsilon" => 'Υ',
+        "Phi" => 'Φ',
+        "Chi" => 'Χ',
+        "Psi" => 'Ψ',
+        "Omega" => 'Ω',
+        "alpha" => 'α',
+        "beta" => 'β',
+        "gamma" => 'γ',
+        "delta" => 'δ',
+        "epsilon" => 'ε',
+        "zeta" => 'ζ',
+        "eta" => 'η',
+        "theta" => 'θ',
+        "iota" => 'ι',
+        "kappa" => 'κ',
+        "lambda" => 'λ',
+        "mu" => 'μ',
+        "nu" => 'ν',
+        "xi" => 'ξ',
+        "omicron" => 'ο',
+        "pi" => 'π',
+        "rho" => 'ρ',
+        "sigmaf" => 'ς',
+        "sigma" => 'σ',
+        "tau" => 'τ',
+        "upsilon" => 'υ',
+        "phi" => 'φ',
+        "chi" => 'χ',
+        "psi" => 'ψ',
+        "omega" => 'ω',
+        "thetasym" => 'ϑ',
+        "upsih" => 'ϒ',
+        "piv" => 'ϖ',
+        "ensp" => '\u{2002}',
+        "emsp" => '\u{2003}',
+        "thinsp" => '\u{2009}',
+        "zwnj" => '\u{200c}',
+        "zwj" => '\u{200d}',
+        "ndash" => '–',
+        "mdash" => '—',
+        "lsquo" => '‘',
+        "rsquo" => '’',
+        "sbquo" => '‚',
+        "ldquo" => '“',
+        "rdquo" => '”',
+        "bdquo" => '„',
+        "dagger" => '†',
+        "Dagger" => '‡',
+        "bull" => '•',
+        "hellip" => '…',
+        "permil" => '‰',
+        "prime" => '′',
+        "Prime" => '″',
+        "lsaquo" => '‹',
+        "rsaquo" => '›',
+        "euro" => '€',
+        "trade" => '™',
+        "larr" => '←',
+        "uarr" => '↑',
+        "rarr" => '→',
+        "darr" => '↓',
+        "harr" => '↔',
+        "lArr" => '⇐',
+        "rArr" => '⇒',
+        "hArr" => '⇔',
+        "forall" => '∀',
+        "part" => '∂',
+        "exist" => '∃',
+        "empty" => '∅',
+        "nabla" => '∇',
+        "isin" => '∈',
+        "notin" => '∉',
+        "prod" => '∏',
+        "sum" => '∑',
+        "minus" => '−',
+        "radic" => '√',
+        "infin" => '∞',
+        "and" => '∧',
+        "or" => '∨',
+        "cap" => '∩',
+        "cup" => '∪',
+        "int" => '∫',
+        "there4" => '∴',
+        "sim" => '∼',
+        "asymp" => '≈',
+        "ne" => '≠',
+        "equiv" => '≡',
+        "le" => '≤',
+        "ge" => '≥',
+        "sub" => '⊂',
+        "sup" => '⊃',
+        "loz" => '◊',
+        "spades" => '♠',
+        "clubs" => '♣',
+        "hearts" => '♥',
+        "diams" => '♦',
+        _ => return None,
+    };
+    Some(c)
 }
 
 #[test]
 fn test1() {
-    use std::path::Path;
     let src = "<html><head></head><body><div id=\"x\">test</div><p>paragrapgh</p><span>aa</span>\n  space<img src='a.png'></body></html>";
-    let dom_node = parse(src.to_string(), Path::new("./a/a.html").to_path_buf());
+    let dom_node = parse(src.to_string());
     assert_eq!(
         dom_node,
         dom::Node::elem(
@@ -295,7 +594,7 @@ fn test1() {
                             "img".to_string(),
                             {
                                 let mut h = HashMap::new();
-                                h.insert("src".to_string(), "./a/a.png".to_string());
+                                h.insert("src".to_string(), "a.png".to_string());
                                 h
                             },
                             vec![],
@@ -309,11 +608,93 @@ fn test1() {
 
 #[test]
 fn test_empty_source() {
-    use std::path::Path;
     let src = "";
-    let dom_node = parse(src.to_string(), Path::new("a.html").to_path_buf());
+    let dom_node = parse(src.to_string());
     assert_eq!(
         dom_node,
         dom::Node::elem("html".to_string(), HashMap::new(), vec![])
     );
 }
+
+#[test]
+fn test_raw_text() {
+    let src = "<html><head><style>p > b { color: red; }</style></head><body>\
+               <script>if (a < b && c) { s = \"<b>&amp;</scripts>\"; }</SCRIPT >\
+               <p>after</p></body></html>";
+    let dom_node = parse(src.to_string());
+
+    // The contents are kept verbatim as a text node.
+    let style = dom_node.find_first_node_by_tag_name("style").unwrap();
+    assert_eq!(
+        style.children,
+        vec![dom::Node::text("p > b { color: red; }".to_string())]
+    );
+    let script = dom_node.find_first_node_by_tag_name("script").unwrap();
+    assert_eq!(
+        script.children,
+        vec![
+            dom::Node::text("if (a < b && c) { s = \"<b>&amp;</scripts>\"; }".to_string()),
+        ]
+    );
+    // The parsing continues after the end tag.
+    assert!(dom_node.find_first_node_by_tag_name("p").is_some());
+    assert!(dom_node.find_first_node_by_tag_name("b").is_none());
+}
+
+#[test]
+fn test_entities() {
+    assert_eq!(decode_entities("a &amp; b &lt;c&gt;"), "a & b <c>");
+    assert_eq!(decode_entities("&#65;&#x42;&quot;"), "AB\"");
+    assert_eq!(decode_entities("&unknown; & &amp"), "&unknown; & &amp");
+}
+
+#[test]
+fn test_entity_forms() {
+    // Named.
+    assert_eq!(decode_entities("&copy; 2018 &mdash; &hellip;"), "\u{a9} 2018 \u{2014} \u{2026}");
+    assert_eq!(decode_entities("&eta;&rho;&tau;&Xi;&sigmaf;&piv;"), "ηρτΞςϖ");
+    // Decimal.
+    assert_eq!(decode_entities("&#169;&#8226;"), "\u{a9}\u{2022}");
+    // Hex, in either case.
+    assert_eq!(decode_entities("&#x2022;&#X2022;&#xA9;"), "\u{2022}\u{2022}\u{a9}");
+    // Invalid code points are replaced.
+    assert_eq!(decode_entities("&#0;&#xD800;&#x110000;"), "\u{fffd}\u{fffd}\u{fffd}");
+    // Unterminated references are left verbatim.
+    assert_eq!(decode_entities("&copy 2018"), "&copy 2018");
+    assert_eq!(decode_entities("a &#169"), "a &#169");
+}
+
+#[test]
+fn test_entities_in_text() {
+    let src = "<html><body><p>&lt;b&gt; &amp; &quot;&#169;&#x2022;&quot;</p></body></html>";
+    let dom_node = parse(src.to_string());
+    let p = dom_node.find_first_node_by_tag_name("p").unwrap();
+    assert_eq!(
+        p.children,
+        vec![dom::Node::text("<b> & \"\u{a9}\u{2022}\"".to_string())]
+    );
+}
+
+#[test]
+fn test_attribute_entities() {
+    let src = "<html><body><span title='Tom &amp; Jerry &#x2022;'>a</span></body></html>";
+    let dom_node = parse(src.to_string());
+    let span = dom_node.find_first_node_by_tag_name("span").unwrap();
+    match span.data {
+        dom::NodeType::Element(ref e) => {
+            assert_eq!(e.title(), Some(&"Tom & Jerry \u{2022}".to_string()))
+        }
+        _ => panic!(),
+    }
+}
+
+#[test]
+fn test_title() {
+    let src = "<html><head><title> Tom &amp; Jerry </title></head><body>a</body></html>";
+    let dom_node = parse(src.to_string());
+    assert_eq!(dom_node.title(), Some("Tom & Jerry".to_string()));
+
+    let src = "<html><head><title></title></head><body>a</body></html>";
+    let dom_node = parse(src.to_string());
+    assert_eq!(dom_node.title(), None);
+}
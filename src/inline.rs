@@ -1,7 +1,7 @@
 use css::Value;
-use style::StyledNode;
+use style::{BoxSizing, StyledNode, TextAlign};
 use dom::NodeType;
-use font::Font;
+use font::{resolve_font_family, Font};
 use layout::{BoxType, Dimensions, LayoutBox, LayoutInfo, Text};
 use float::Floats;
 
@@ -77,7 +77,7 @@ impl<'a> LineMaker<'a> {
         }
     }
 
-    pub fn run(&mut self, max_width: Au, containing_block: Dimensions) {
+    pub fn run(&mut self, max_width: Au, containing_block: Dimensions, document: &Rc<Document>) {
         while let Some(layoutbox) = self.work_list.pop_front() {
             if let BoxType::TextNode(ref text_info) = layoutbox.box_type {
                 self.pending.range = text_info.range.clone()
@@ -95,10 +95,18 @@ impl<'a> LineMaker<'a> {
                         .width;
                 },
                 BoxType::InlineBlockNode => {
-                    self.run_on_inline_block_node(layoutbox, max_width_considered_float)
+                    self.run_on_inline_block_node(layoutbox, max_width_considered_float, document)
                 }
-                BoxType::InlineNode => {
-                    self.run_on_inline_node(layoutbox, max_width_considered_float, containing_block)
+                BoxType::InlineNode => self.run_on_inline_node(
+                    layoutbox,
+                    max_width_considered_float,
+                    containing_block,
+                    document,
+                ),
+                // Absolutely positioned boxes take up no space, but note where they would be.
+                BoxType::Absolute => {
+                    self.end += 1;
+                    self.new_boxes.push(layoutbox);
                 }
                 _ => unimplemented!(),
             }
@@ -132,29 +140,47 @@ impl<'a> LineMaker<'a> {
 
     pub fn assign_position(&mut self, max_width: Au) {
         self.cur_height = Au(0);
+        let lines_len = self.lines.len();
 
-        for line in &self.lines {
+        for (i, line) in self.lines.iter().enumerate() {
             self.cur_width = Au(0);
 
-            for new_box in &mut self.new_boxes[line.range.clone()] {
-                let (left_floats_width, max_width_considered_float) = {
-                    let available_area =
-                        self.floats
-                            .available_area(max_width, self.cur_height, Au(1)); // magic number '1': Anything is ok if Au(x) > 0.
-                    (available_area.x, available_area.width)
-                };
-                // TODO: Refine
-                let style = new_box.get_style_node();
-                let text_align = style.text_align();
-                let init_width = match text_align {
-                    Value::Keyword(ref k) => match k.as_str() {
-                        "center" => (max_width_considered_float - line.width) / 2,
-                        "right" => max_width_considered_float - line.width,
-                        "left" | _ => Au(0),
-                    },
-                    _ => Au(0),
-                } + left_floats_width;
+            let (left_floats_width, max_width_considered_float) = {
+                let available_area =
+                    self.floats
+                        .available_area(max_width, self.cur_height, Au(1)); // magic number '1': Anything is ok if Au(x) > 0.
+                (available_area.x, available_area.width)
+            };
+
+            let boxes = &mut self.new_boxes[line.range.clone()];
+            let text_align = match boxes.first() {
+                Some(new_box) => new_box.get_style_node().text_align(),
+                None => TextAlign::Left,
+            };
+            let extra_space = max_width_considered_float - line.width;
+
+            // `word_spacing` is added after each word but the last one in the line.
+            let (init_width, word_spacing) = match text_align {
+                TextAlign::Center => (extra_space / 2, Au(0)),
+                TextAlign::Right => (extra_space, Au(0)),
+                // The last line is not justified.
+                TextAlign::Justify if i != lines_len - 1 && extra_space > Au(0) => {
+                    let gaps = boxes[..boxes.len() - 1]
+                        .iter()
+                        .filter(|b| b.ends_with_whitespace())
+                        .count();
+                    if gaps > 0 {
+                        (Au(0), extra_space / gaps as i32)
+                    } else {
+                        (Au(0), Au(0))
+                    }
+                }
+                TextAlign::Left | TextAlign::Justify => (Au(0), Au(0)),
+            };
+            let init_width = init_width + left_floats_width;
 
+            let boxes_len = boxes.len();
+            for (j, new_box) in boxes.iter_mut().enumerate() {
                 new_box.dimensions.content.x = init_width + self.cur_width
                     + new_box.dimensions.padding.left
                     + new_box.dimensions.border.left
@@ -166,6 +192,9 @@ impl<'a> LineMaker<'a> {
                     self.cur_height + (line.metrics.above_baseline - ascent);
 
                 self.cur_width += new_box.dimensions.margin_box().width;
+                if j != boxes_len - 1 && new_box.ends_with_whitespace() {
+                    self.cur_width += word_spacing;
+                }
             }
             self.cur_height += line.metrics.calculate_line_height();
         }
@@ -176,17 +205,19 @@ impl<'a> LineMaker<'a> {
         mut layoutbox: LayoutBox<'a>,
         max_width: Au,
         containing_block: Dimensions,
+        document: &Rc<Document>,
     ) {
         fn layout_text<'a>(
             mut layoutbox: LayoutBox<'a>,
             linemaker: &mut LineMaker<'a>,
             max_width: Au,
             containing_block: Dimensions,
+            document: &Rc<Document>,
         ) {
             linemaker.work_list = VecDeque::from(layoutbox.children.clone());
             layoutbox.children.clear();
 
-            layoutbox.assign_padding();
+            layoutbox.assign_padding(containing_block);
             layoutbox.assign_border_width();
 
             let start = linemaker.end;
@@ -197,6 +228,7 @@ impl<'a> LineMaker<'a> {
                 max_width
                     - (layoutbox.dimensions.padding.right + layoutbox.dimensions.border.right),
                 containing_block,
+                document,
             );
             linemaker.cur_width +=
                 layoutbox.dimensions.padding.right + layoutbox.dimensions.border.right;
@@ -249,7 +281,7 @@ impl<'a> LineMaker<'a> {
             LayoutInfo::Generic | LayoutInfo::Anker => {
                 let mut linemaker = self.clone();
 
-                layout_text(layoutbox, &mut linemaker, max_width, containing_block);
+                layout_text(layoutbox, &mut linemaker, max_width, containing_block, document);
 
                 self.new_boxes = linemaker.new_boxes;
                 self.lines = linemaker.lines;
@@ -263,7 +295,7 @@ impl<'a> LineMaker<'a> {
                 // Replaced Inline Element (<img>)
                 let width;
                 let height;
-                layoutbox.layout_inline(&mut self.floats, containing_block);
+                layoutbox.layout_inline(&mut self.floats, containing_block, document);
                 width = layoutbox.dimensions.border_box().width;
                 height = layoutbox.dimensions.border_box().height;
 
@@ -286,17 +318,16 @@ impl<'a> LineMaker<'a> {
                 use gtk::Button;
                 use gtk::BinExt;
                 use gtk::WidgetExt;
-                use window::BUTTONS;
                 // println!("d {:?}", d);
 
                 let button = match &mut layoutbox.info {
                     &mut LayoutInfo::Button(ref mut btn, ref id) => {
-                        let button = BUTTONS.with(|b| {
-                            b.borrow_mut()
-                                .entry(*id)
-                                .or_insert_with(|| Button::new_with_label(btn_text.as_str()))
-                                .clone()
-                        });
+                        let button = document
+                            .buttons
+                            .borrow_mut()
+                            .entry(*id)
+                            .or_insert_with(|| Button::new_with_label(btn_text.as_str()))
+                            .clone();
                         *btn = Some(button.clone());
                         button
                     }
@@ -317,6 +348,7 @@ impl<'a> LineMaker<'a> {
                     &mut linemaker,
                     max_width,
                     containing_block,
+                    document,
                 );
 
                 let font = get_font(&linemaker);
@@ -333,8 +365,7 @@ impl<'a> LineMaker<'a> {
                 button.set_valign(gtk::Align::Baseline);
                 let width = Au::from_f64_px(label.get_allocated_width() as f64 + 10.0);
 
-                let mut d = Au::from_f64_px(button_height as f64) - font.size;
-                println!("height: {} {:?}", button_height, d);
+                let d = Au::from_f64_px(button_height as f64) - font.size;
 
                 layoutbox.dimensions.content.width = width;
                 layoutbox.dimensions.content.height = Au::from_f64_px(button_height as f64);
@@ -393,40 +424,43 @@ impl<'a> LineMaker<'a> {
         }
     }
 
-    fn run_on_inline_block_node(&mut self, mut layoutbox: LayoutBox<'a>, max_width: Au) {
+    fn run_on_inline_block_node(
+        &mut self,
+        mut layoutbox: LayoutBox<'a>,
+        max_width: Au,
+        document: &Rc<Document>,
+    ) {
+        // An inline-block that doesn't fit in the rest of the line goes to the next line, so it's
+        // as wide as a whole line at most.
         let mut containing_block: Dimensions = ::std::default::Default::default();
-        containing_block.content.width = max_width - self.cur_width;
+        containing_block.content.width = max_width;
         layoutbox.layout(
             &mut self.floats,
             Au(0),
             containing_block,
+            None,
             containing_block,
-            containing_block,
+            document,
         );
 
-        let box_width = layoutbox.dimensions.margin_box().width;
+        let margin_box = layoutbox.dimensions.margin_box();
+        let d = layoutbox.dimensions;
+        // The box sits on the baseline of its last line.
+        let above_baseline =
+            d.margin.top + d.border.top + d.padding.top + layoutbox.inline_block_ascent();
+        let under_baseline = max(margin_box.height - above_baseline, Au(0));
 
-        if self.cur_width + box_width > max_width {
+        if self.cur_width + margin_box.width > max_width && self.cur_width > Au(0) {
             self.flush_cur_line();
-            self.end += 1;
-
-            self.cur_width = box_width;
-            self.cur_metrics.above_baseline = max(
-                self.cur_metrics.above_baseline,
-                layoutbox.dimensions.margin_box().height,
-            );
-
-            self.new_boxes.push(layoutbox);
-        } else {
-            self.end += 1;
-            self.cur_width += box_width;
-            self.cur_metrics.above_baseline = max(
-                self.cur_metrics.above_baseline,
-                layoutbox.dimensions.margin_box().height,
-            );
-
-            self.new_boxes.push(layoutbox);
+            self.cur_metrics.reset();
+            self.cur_width = Au(0);
         }
+        self.end += 1;
+        self.cur_width += margin_box.width;
+        self.cur_metrics.above_baseline = max(self.cur_metrics.above_baseline, above_baseline);
+        self.cur_metrics.under_baseline = max(self.cur_metrics.under_baseline, under_baseline);
+
+        self.new_boxes.push(layoutbox);
     }
 
     fn run_on_text_node(&mut self, layoutbox: LayoutBox<'a>, max_width: Au) {
@@ -437,43 +471,67 @@ impl<'a> LineMaker<'a> {
         } else {
             return;
         };
+        // Justified text is laid out word by word, so that spaces between words can be widened.
+        let text = match style.text_align() {
+            TextAlign::Justify => match text.find(' ') {
+                Some(pos) => &text[0..pos + 1],
+                None => text,
+            },
+            _ => text,
+        };
 
         let font_size = style.font_size();
         let line_height = style.line_height();
         let font_weight = style.font_weight();
         let font_slant = style.font_style();
+        let font_family = resolve_font_family(&style.font_family());
 
-        let my_font = Font::new(font_size, font_weight, font_slant);
+        let mut my_font = Font::new(font_size, font_weight, font_slant, font_family);
+        my_font.letter_spacing = style.letter_spacing();
         let text_width = Au::from_f64_px(my_font.text_width(text));
         let (ascent, descent) = my_font.get_ascent_descent();
 
+        let line_is_empty = self.start == self.end;
+        let max_chars = if self.cur_width + text_width > max_width {
+            let remaining_width = max_width - self.cur_width;
+            my_font.compute_max_chars(text, remaining_width.to_f64_px(), line_is_empty)
+        } else {
+            text.len()
+        };
+
+        if max_chars == 0 {
+            // Not even the first word fits in the rest of the line. Move it to the next line.
+            // Trailing whitespace at the end of a line doesn't take up space.
+            if let Some(last_box) = self.new_boxes[self.start..self.end].last_mut() {
+                last_box.trim_trailing_whitespace();
+            }
+            self.flush_cur_line();
+            self.cur_width = Au(0);
+            self.cur_metrics.reset();
+            return;
+        }
+
         let mut new_layoutbox = layoutbox.clone();
 
         self.end += 1;
 
-        self.cur_metrics.above_baseline = max(
-            self.cur_metrics.above_baseline,
-            ascent + (line_height - (ascent + descent)) / 2,
-        );
+        // The leading is split above and below the text, so that the line is exactly
+        // `line_height` tall.
+        let above_baseline = ascent + (line_height - (ascent + descent)) / 2;
+        self.cur_metrics.above_baseline = max(self.cur_metrics.above_baseline, above_baseline);
         self.cur_metrics.under_baseline = max(
             self.cur_metrics.under_baseline,
-            (line_height - (ascent + descent)) / 2 + descent,
+            line_height - above_baseline,
         );
 
-        if self.cur_width + text_width > max_width {
-            let remaining_width = max_width - self.cur_width; // Is this correc?
-            let max_chars = my_font.compute_max_chars(text, remaining_width.to_f64_px());
-
+        if max_chars < text.len() {
+            // Trailing whitespace at the end of a line doesn't take up space.
             new_layoutbox.dimensions.content.width =
-                Au::from_f64_px(my_font.text_width(&text[0..max_chars]));
+                Au::from_f64_px(my_font.text_width(text[0..max_chars].trim_right()));
             new_layoutbox.dimensions.content.height = ascent + descent;
 
             new_layoutbox.set_text_info(
-                Font {
-                    size: font_size,
-                    weight: font_weight,
-                    slant: font_slant,
-                },
+                my_font.clone(),
                 self.pending.range.start..self.pending.range.start + max_chars,
             );
             self.new_boxes.push(new_layoutbox.clone());
@@ -489,16 +547,12 @@ impl<'a> LineMaker<'a> {
             new_layoutbox.dimensions.content.height = ascent + descent;
 
             new_layoutbox.set_text_info(
-                Font {
-                    size: font_size,
-                    weight: font_weight,
-                    slant: font_slant,
-                },
+                my_font.clone(),
                 self.pending.range.start..text.len() + self.pending.range.start,
             );
             self.new_boxes.push(new_layoutbox.clone());
 
-            self.pending.range = 0..0;
+            self.pending.range = self.pending.range.start + text.len()..self.pending.range.end;
 
             self.cur_width += text_width;
         }
@@ -507,12 +561,17 @@ impl<'a> LineMaker<'a> {
 
 impl<'a> LayoutBox<'a> {
     /// Lay out a inline-level element and its descendants.
-    pub fn layout_inline(&mut self, _floats: &mut Floats, containing_block: Dimensions) {
+    pub fn layout_inline(
+        &mut self,
+        _floats: &mut Floats,
+        containing_block: Dimensions,
+        document: &Rc<Document>,
+    ) {
         match self.info {
             LayoutInfo::Image(_) => {
-                self.calculate_replaced_inline_width_height(containing_block);
+                self.calculate_replaced_inline_width_height(containing_block, document);
 
-                self.assign_padding();
+                self.assign_padding(containing_block);
                 self.assign_border_width();
                 self.assign_margin();
             }
@@ -521,11 +580,17 @@ impl<'a> LayoutBox<'a> {
     }
 
     /// Calculate the width of a inline-level replaced(<img>) element in normal flow.
-    pub fn calculate_replaced_inline_width_height(&mut self, containing_block: Dimensions) {
+    pub fn calculate_replaced_inline_width_height(
+        &mut self,
+        containing_block: Dimensions,
+        document: &Rc<Document>,
+    ) {
         // Replaced Inline Element (<img>)
         let style = self.get_style_node();
         let (width, height) = match &mut self.info {
-            &mut LayoutInfo::Image(ref mut pixbuf) => get_image(style, pixbuf, containing_block),
+            &mut LayoutInfo::Image(ref mut pixbuf) => {
+                get_image(style, pixbuf, containing_block, document)
+            }
             _ => unimplemented!(),
         };
 
@@ -549,131 +614,574 @@ impl<'a> LayoutBox<'a> {
         }
     }
 
+    fn ends_with_whitespace(&self) -> bool {
+        match self.box_type {
+            BoxType::TextNode(Text { ref range, .. }) => match self.get_style_node().node.data {
+                NodeType::Text(ref text) => text[range.clone()].ends_with(' '),
+                _ => false,
+            },
+            _ => self.children
+                .last()
+                .map_or(false, |child| child.ends_with_whitespace()),
+        }
+    }
+
+    /// Shrink the text box at the end of a line so that its trailing whitespace doesn't take up
+    /// space.
+    fn trim_trailing_whitespace(&mut self) {
+        let width = match self.box_type {
+            BoxType::TextNode(Text { ref font, ref range }) => {
+                match self.get_style_node().node.data {
+                    NodeType::Text(ref text) => font.text_width(text[range.clone()].trim_right()),
+                    _ => return,
+                }
+            }
+            _ => return,
+        };
+        self.dimensions.content.width = Au::from_f64_px(width);
+    }
+
     pub fn content_inline_ascent(&mut self) -> Au {
         let height = self.dimensions.content.height;
+        match self.box_type {
+            BoxType::InlineBlockNode => return self.inline_block_ascent(),
+            BoxType::Absolute => return Au(0),
+            _ => {}
+        }
         match self.get_first_text_node() {
             Some(node) => match node.box_type {
-                BoxType::TextNode(Text { font, .. }) => font.get_ascent_descent().0,
+                BoxType::TextNode(Text { ref font, .. }) => font.get_ascent_descent().0,
                 _ => unreachable!(),
             },
             None => height,
         }
     }
+
+    /// The distance from the top of the content box of an inline-block to its baseline, which is
+    /// the baseline of its last line box, or the bottom margin edge if it has no line boxes.
+    /// ref. https://www.w3.org/TR/CSS2/visudet.html#propdef-vertical-align
+    fn inline_block_ascent(&self) -> Au {
+        let d = &self.dimensions;
+        self.last_baseline().unwrap_or(
+            d.content.height + d.padding.bottom + d.border.bottom + d.margin.bottom,
+        )
+    }
+
+    /// The baseline of the last line box in the descendants, relative to the top of the content
+    /// box. Floats are out of flow and don't have the baseline.
+    fn last_baseline(&self) -> Option<Au> {
+        self.children
+            .iter()
+            .rev()
+            .filter(|child| child.box_type != BoxType::Float && child.box_type != BoxType::Absolute)
+            .filter_map(|child| {
+                let baseline = match child.box_type {
+                    BoxType::TextNode(Text { ref font, .. }) => Some(font.get_ascent_descent().0),
+                    BoxType::InlineBlockNode => Some(child.inline_block_ascent()),
+                    _ => child.last_baseline(),
+                };
+                baseline.map(|baseline| child.dimensions.content.y + baseline)
+            })
+            .next()
+    }
 }
 
+/// The size of <img>: the size of the image, scaled to `width` and `height` in CSS or the
+/// attributes. Given only one of them, the aspect ratio is kept. Until the image arrives, the box
+/// has the specified size. An image that failed to load makes room for the broken image glyph
+/// and the alt text, unless the size is specified.
 pub fn get_image<'a>(
     style: &'a StyledNode<'a>,
     pixbuf: &mut Option<gdk_pixbuf::Pixbuf>,
     containing_block: Dimensions,
+    document: &Rc<Document>,
 ) -> (Au, Au) {
     let cb_width = containing_block.content.width.to_f64_px();
     let cb_height = containing_block.content.height.to_f64_px();
 
-    let pixbuf = match pixbuf {
-        &mut Some(ref pixbuf) => pixbuf.clone(),
-        &mut None => {
-            *pixbuf = Some(style.get_pixbuf());
-            pixbuf.clone().unwrap()
+    if pixbuf.is_none() {
+        *pixbuf = style.image(document);
+    }
+
+    // `width` and `height` in CSS win over the attributes. `auto` falls back to them.
+    let specified_px = |name: &str, cb_length: f64| {
+        style
+            .value(name)
+            .and_then(|value| value[0].maybe_percent_to_px(cb_length))
+            .or_else(|| {
+                style
+                    .node
+                    .attr(name)
+                    .and_then(|value| value.maybe_percent_to_px(cb_length))
+            })
+    };
+    let specified_width_px = specified_px("width", cb_width);
+    let specified_height_px = specified_px("height", cb_height);
+
+    let (width, height) = match *pixbuf {
+        Some(ref pixbuf) => {
+            let image_width = pixbuf.get_width() as f64;
+            let image_height = pixbuf.get_height() as f64;
+            match (specified_width_px, specified_height_px) {
+                (Some(width), Some(height)) => (width, height),
+                (Some(width), None) => (width, width * (image_height / image_width)),
+                (None, Some(height)) => (height * (image_width / image_height), height),
+                (None, None) => (image_width, image_height),
+            }
+        }
+        // An image with an empty alt is decoration, and takes no room if it fails to load.
+        None if style.image_failed(document)
+            && style.node.alt_text().map_or(false, |a| a.is_empty()) =>
+        {
+            (0.0, 0.0)
+        }
+        None => {
+            let (width, height) = if style.image_failed(document) {
+                broken_image_size(style)
+            } else {
+                (0.0, 0.0)
+            };
+            (
+                specified_width_px.unwrap_or(width),
+                specified_height_px.unwrap_or(height),
+            )
         }
     };
-
-    let specified_width_px = style
-        .node
-        .attr("width")
-        .and_then(|w| w.maybe_percent_to_px(cb_width));
-    // The same as above
-    let specified_height_px = style
-        .node
-        .attr("height")
-        .and_then(|h| h.maybe_percent_to_px(cb_height));
-
-    match (specified_width_px, specified_height_px) {
-        (Some(width), Some(height)) => (Au::from_f64_px(width), Au::from_f64_px(height)),
-        (Some(width), None) => (
-            Au::from_f64_px(width),
-            Au::from_f64_px(width * (pixbuf.get_height() as f64 / pixbuf.get_width() as f64)),
-        ),
-        (None, Some(height)) => (
-            Au::from_f64_px(height * (pixbuf.get_width() as f64 / pixbuf.get_height() as f64)),
-            Au::from_f64_px(height),
-        ),
-        (None, None) => (
-            Au::from_f64_px(pixbuf.get_width() as f64),
-            Au::from_f64_px(pixbuf.get_height() as f64),
-        ),
-    }
+    (Au::from_f64_px(width), Au::from_f64_px(height))
 }
 
-// TODO: Implement correctly
 impl<'a> LayoutBox<'a> {
-    /// Lay out a inline-block-level element and its descendants.
+    /// Lay out an inline-block element and its descendants. The contents are laid out as a block
+    /// in a new block formatting context, and the box is placed in the line as a whole.
     pub fn layout_inline_block(
         &mut self,
         _floats: &mut Floats,
         _last_margin_bottom: Au,
         containing_block: Dimensions,
-        _saved_block: Dimensions,
+        _containing_height: Option<Au>,
         viewport: Dimensions,
+        document: &Rc<Document>,
     ) {
-        // Child width can depend on parent width, so we need to calculate this box's width before
-        // laying out its children.
-        self.calculate_inline_block_width(containing_block);
-
-        self.assign_padding();
+        self.assign_padding(containing_block);
         self.assign_border_width();
         self.assign_margin();
-        // self.calculate_block_position(last_margin_bottom, containing_block);
 
-        self.layout_block_children(viewport);
+        // Child width can depend on parent width, so we need to calculate this box's width before
+        // laying out its children.
+        let width_not_specified = self.calculate_inline_block_width(containing_block);
+
+        if width_not_specified {
+            self.layout_children_shrink_to_fit(None, viewport, document);
+        } else {
+            self.layout_block_children(Au(0), None, viewport, document);
+        }
 
         // Parent height can depend on child height, so `calculate_height` must be called after the
         // children are laid out.
-        self.calculate_block_height();
+        let height = self.specified_height(None);
+        self.calculate_block_height(height);
     }
 
-    /// Calculate the width of a block-level non-replaced element in normal flow.
-    /// Sets the horizontal margin/padding/border dimensions, and the `width`.
+    /// Calculate the width of an inline-block non-replaced element. Sets the `width` to the
+    /// specified one, or to the available width if it's `auto`, in which case true is returned.
     /// ref. https://www.w3.org/TR/CSS2/visudet.html#inlineblock-width
-    pub fn calculate_inline_block_width(&mut self, _containing_block: Dimensions) {
+    pub fn calculate_inline_block_width(&mut self, containing_block: Dimensions) -> bool {
         let style = self.get_style_node();
+        let cb_width = containing_block.content.width.to_f64_px();
 
         // `width` has initial value `auto`.
-        // TODO: Implement calculating shrink-to-fit width
         let auto = Value::Keyword("auto".to_string());
-        let width = &style.value("width").unwrap_or(vec![auto.clone()])[0];
+        let width = style.value("width").unwrap_or(vec![auto])[0].clone();
+
+        let d = &mut self.dimensions;
+        let edges = d.padding.left + d.padding.right + d.border.left + d.border.right;
 
-        if width == &auto {
-            // TODO
-            panic!("calculating shrink-to-fit width is unsupported.");
+        match width.maybe_percent_to_px(cb_width) {
+            Some(width) => {
+                d.content.width = Au::from_f64_px(width);
+                // Under `box-sizing: border-box`, `width` includes the padding and border.
+                if style.box_sizing() == BoxSizing::BorderBox {
+                    d.content.width = max(d.content.width - edges, Au(0));
+                }
+                false
+            }
+            None => {
+                let available = containing_block.content.width - edges - d.margin.left
+                    - d.margin.right;
+                d.content.width = max(available, Au(0));
+                true
+            }
         }
+    }
 
-        self.dimensions.content.width = Au::from_f64_px(width.to_px().unwrap());
+    /// Lay out the children in the available width, which is the current `width`, and shrink the
+    /// width to what they actually take up. `height` is the height for percentages of the
+    /// children.
+    /// ref. https://www.w3.org/TR/CSS2/visudet.html#shrink-to-fit-float
+    pub fn layout_children_shrink_to_fit(
+        &mut self,
+        height: Option<Au>,
+        viewport: Dimensions,
+        document: &Rc<Document>,
+    ) {
+        let children = self.children.clone();
+        let floats = self.floats.clone();
+        self.layout_block_children(Au(0), height, viewport, document);
+
+        // Lay out again in the shrunk width.
+        let used_width = self.used_content_width();
+        if used_width < self.dimensions.content.width {
+            self.dimensions.content.width = used_width;
+            self.dimensions.content.height = Au(0);
+            self.floats = floats;
+            self.children = children;
+            self.layout_block_children(Au(0), height, viewport, document);
+        }
+    }
+
+    /// The width the children take up, in which block children of `width: auto` only take up
+    /// the width of their own contents.
+    fn used_content_width(&self) -> Au {
+        self.children.iter().fold(Au(0), |width, child| {
+            let d = &child.dimensions;
+            let auto_width = child.style.map_or(false, |style| {
+                style.value("width").map_or(true, |width| {
+                    width[0] == Value::Keyword("auto".to_string())
+                })
+            });
+            let child_width = if child.box_type == BoxType::BlockNode && auto_width {
+                child.used_content_width() + d.padding.left + d.padding.right + d.border.left
+                    + d.border.right + d.margin.left + d.margin.right
+            } else {
+                d.margin_box().width
+            };
+            max(width, child_width)
+        })
     }
 }
 
 use std::cell::RefCell;
+use std::rc::{Rc, Weak};
+use std::sync::mpsc;
+use std::thread;
+
+use gtk;
+use glib::Continue;
+
+use interface::reqwest::Url;
+use interface::{fetch, resolve_url, Document, LOAD_POLL_INTERVAL};
+
+/// The size of the glyph shown for an image that failed to load, and the gap to its alt text.
+pub const BROKEN_IMAGE_SIZE: f64 = 16.0;
+pub const BROKEN_IMAGE_GAP: f64 = 4.0;
+/// The width of the border around a broken image, and the padding inside it.
+pub const BROKEN_IMAGE_BORDER: f64 = 1.0;
+pub const BROKEN_IMAGE_PADDING: f64 = 2.0;
+
+type ImageKey = String; // Resolved URL
+
+/// An image in the cache.
+enum CachedImage {
+    /// Being loaded in the background. The documents are laid out again when it arrives.
+    Loading(Vec<Weak<Document>>),
+    Loaded(gdk_pixbuf::Pixbuf),
+    Failed,
+}
 
-type ImageKey = String; // URL
+/// The pixels of an image decoded on a worker thread. Unlike a Pixbuf, they can be sent to the
+/// GTK thread.
+struct DecodedImage {
+    pixels: Vec<u8>,
+    has_alpha: bool,
+    bits_per_sample: i32,
+    width: i32,
+    height: i32,
+    rowstride: i32,
+}
+
+impl DecodedImage {
+    fn new(pixbuf: &gdk_pixbuf::Pixbuf) -> DecodedImage {
+        DecodedImage {
+            pixels: unsafe { pixbuf.get_pixels() }.to_vec(),
+            has_alpha: pixbuf.get_has_alpha(),
+            bits_per_sample: pixbuf.get_bits_per_sample(),
+            width: pixbuf.get_width(),
+            height: pixbuf.get_height(),
+            rowstride: pixbuf.get_rowstride(),
+        }
+    }
+}
 
 thread_local!(
-    static IMG_CACHE: RefCell<HashMap<ImageKey, gdk_pixbuf::Pixbuf>> = {
+    static IMG_CACHE: RefCell<HashMap<ImageKey, CachedImage>> = {
         RefCell::new(HashMap::new())
     };
+    // Called when an image loaded in the background arrives. Images are loaded synchronously
+    // until it's set.
+    static ON_IMAGE_LOADED: RefCell<Option<Rc<Fn()>>> = { RefCell::new(None) };
 );
 
-use interface::download;
+/// Forget the loaded images, so that they are loaded again.
+pub fn clear_image_cache() {
+    IMG_CACHE.with(|c| c.borrow_mut().clear());
+}
+
+/// Load images on worker threads from now on, and call `on_loaded` whenever one arrives.
+/// Meanwhile, layout uses a placeholder.
+pub fn load_images_in_background<F: Fn() + 'static>(on_loaded: F) {
+    ON_IMAGE_LOADED.with(|f| *f.borrow_mut() = Some(Rc::new(on_loaded)));
+}
 
 impl<'a> StyledNode<'a> {
-    pub fn get_pixbuf(&self) -> gdk_pixbuf::Pixbuf {
-        IMG_CACHE.with(|c| {
-            let image_url = self.node.image_url().unwrap();
-            c.borrow_mut()
-                .entry(image_url.clone())
-                .or_insert_with(|| {
-                    let (cache_name, _) = download(image_url.as_str());
-                    gdk_pixbuf::Pixbuf::new_from_file(cache_name.as_str()).unwrap()
-                })
-                .clone()
-        })
+    /// The image of <img> in `document`. None if it's still loading or failed to load.
+    pub fn image(&self, document: &Rc<Document>) -> Option<gdk_pixbuf::Pixbuf> {
+        self.node
+            .image_url()
+            .and_then(|url| load_image(document, url.as_str()))
     }
+
+    /// Whether <img> has no usable image: it has no src, or the image failed to load.
+    pub fn image_failed(&self, document: &Document) -> bool {
+        self.node
+            .image_url()
+            .map_or(true, |url| image_failed(document, url.as_str()))
+    }
+
+    /// The image of `background-image: url(...)`. None if it's missing or can't be decoded.
+    pub fn background_image(&self, document: &Rc<Document>) -> Option<gdk_pixbuf::Pixbuf> {
+        self.background_image_url()
+            .and_then(|url| load_image(document, url.as_str()))
+    }
+}
+
+/// The font the alt text of a broken image is shown in.
+pub fn alt_text_font(style: &StyledNode) -> Font {
+    Font::new(
+        style.font_size(),
+        style.font_weight(),
+        style.font_style(),
+        resolve_font_family(&style.font_family()),
+    )
+}
+
+/// The size of the box shown instead of an image that failed to load, in px: the broken image
+/// glyph followed by the alt text, with padding and a border around them.
+fn broken_image_size(style: &StyledNode) -> (f64, f64) {
+    let (width, height) = match style.node.alt_text() {
+        Some(alt) if !alt.is_empty() => {
+            let font = alt_text_font(style);
+            let (ascent, descent) = font.get_ascent_descent();
+            (
+                BROKEN_IMAGE_SIZE + BROKEN_IMAGE_GAP + font.text_width(alt),
+                BROKEN_IMAGE_SIZE.max((ascent + descent).to_f64_px()),
+            )
+        }
+        _ => (BROKEN_IMAGE_SIZE, BROKEN_IMAGE_SIZE),
+    };
+    let edges = (BROKEN_IMAGE_BORDER + BROKEN_IMAGE_PADDING) * 2.0;
+    (width + edges, height + edges)
+}
+
+/// The image at `url`, resolved against `document`, which is laid out again when it arrives.
+/// Each URL is loaded only once, in the background if `load_images_in_background` was called.
+/// None until it's loaded, and if it failed to load.
+pub fn load_image(document: &Rc<Document>, url: &str) -> Option<gdk_pixbuf::Pixbuf> {
+    let url = match resolve_url(document, url) {
+        Ok(url) => url,
+        Err(e) => {
+            eprintln!("{}", e);
+            return None;
+        }
+    };
+    let key = url.as_str().to_string();
+    let background = ON_IMAGE_LOADED.with(|f| f.borrow().is_some());
+
+    IMG_CACHE.with(|c| {
+        let mut cache = c.borrow_mut();
+        match cache.get_mut(&key) {
+            Some(&mut CachedImage::Loaded(ref pixbuf)) => return Some(pixbuf.clone()),
+            Some(&mut CachedImage::Failed) => return None,
+            Some(&mut CachedImage::Loading(ref mut documents)) => {
+                if !documents
+                    .iter()
+                    .any(|d| d.upgrade().map_or(false, |d| Rc::ptr_eq(&d, document)))
+                {
+                    documents.push(Rc::downgrade(document));
+                }
+                return None;
+            }
+            None => {}
+        }
+
+        if background {
+            cache.insert(key.clone(), CachedImage::Loading(vec![Rc::downgrade(document)]));
+            start_loading(url, key);
+            return None;
+        }
+        match decode_image(&url) {
+            Ok(pixbuf) => {
+                cache.insert(key, CachedImage::Loaded(pixbuf.clone()));
+                Some(pixbuf)
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                cache.insert(key, CachedImage::Failed);
+                None
+            }
+        }
+    })
+}
+
+/// Whether the image at `url`, resolved against `document`, failed to load. False while it's
+/// loading.
+pub fn image_failed(document: &Document, url: &str) -> bool {
+    let key = match resolve_url(document, url) {
+        Ok(url) => url.into_string(),
+        Err(_) => return true,
+    };
+    IMG_CACHE.with(|c| match c.borrow().get(&key) {
+        Some(&CachedImage::Failed) => true,
+        _ => false,
+    })
+}
+
+fn decode_image(url: &Url) -> Result<gdk_pixbuf::Pixbuf, String> {
+    let (file_name, _) = try!(fetch(url));
+    gdk_pixbuf::Pixbuf::new_from_file(file_name.as_str())
+        .map_err(|e| format!("Failed to decode '{}': {}", url, e))
+}
+
+/// Fetch and decode the image at `url` on a worker thread, and put it in the cache as `key`
+/// when it arrives.
+fn start_loading(url: Url, key: ImageKey) {
+    let (sender, receiver) = mpsc::channel();
+    {
+        let url = url.clone();
+        thread::spawn(move || {
+            let image = decode_image(&url).map(|pixbuf| DecodedImage::new(&pixbuf));
+            // The receiver is gone if the window was closed.
+            let _ = sender.send(image);
+        });
+    }
+    gtk::timeout_add(LOAD_POLL_INTERVAL, move || {
+        let image = match receiver.try_recv() {
+            Ok(image) => image,
+            Err(mpsc::TryRecvError::Empty) => return Continue(true),
+            Err(mpsc::TryRecvError::Disconnected) => Err(format!("Failed to load '{}'", url)),
+        };
+        receive_image(key.clone(), image);
+        Continue(false)
+    });
+}
+
+/// Put an image loaded in the background in the cache, and lay out the documents using it
+/// again.
+fn receive_image(key: ImageKey, image: Result<DecodedImage, String>) {
+    let image = match image {
+        Ok(image) => CachedImage::Loaded(gdk_pixbuf::Pixbuf::new_from_vec(
+            image.pixels,
+            gdk_pixbuf::Colorspace::Rgb,
+            image.has_alpha,
+            image.bits_per_sample,
+            image.width,
+            image.height,
+            image.rowstride,
+        )),
+        Err(e) => {
+            eprintln!("{}", e);
+            CachedImage::Failed
+        }
+    };
+    let documents = IMG_CACHE.with(|c| match c.borrow_mut().insert(key, image) {
+        Some(CachedImage::Loading(documents)) => documents,
+        _ => vec![],
+    });
+    for document in documents.iter().filter_map(|d| d.upgrade()) {
+        document.invalidate();
+    }
+    let on_loaded = ON_IMAGE_LOADED.with(|f| f.borrow().clone());
+    if let Some(on_loaded) = on_loaded {
+        on_loaded();
+    }
+}
+
+#[test]
+fn test_receive_image() {
+    use interface::{update_html_tree_and_stylesheet, write_test_files};
+
+    let dir = write_test_files("naglfar_receive_image", &[("index.html", "")]);
+    let document = Rc::new(Document::new());
+    update_html_tree_and_stylesheet(&document, format!("{}index.html", dir)).unwrap();
+
+    let logo = format!("file://{}/example/logo.png", env!("CARGO_MANIFEST_DIR"));
+    let key = resolve_url(&document, &logo).unwrap().into_string();
+    IMG_CACHE.with(|c| {
+        c.borrow_mut().insert(
+            key.clone(),
+            CachedImage::Loading(vec![Rc::downgrade(&document)]),
+        )
+    });
+    // A placeholder is used while the image is loading.
+    assert!(load_image(&document, &logo).is_none());
+    assert!(!image_failed(&document, &logo));
+
+    // The image is decoded on a worker thread.
+    let url = Url::parse(&logo).unwrap();
+    let image = thread::spawn(move || DecodedImage::new(&decode_image(&url).unwrap()))
+        .join()
+        .unwrap();
+    let expected = gdk_pixbuf::Pixbuf::new_from_file(&format!(
+        "{}/example/logo.png",
+        env!("CARGO_MANIFEST_DIR")
+    )).unwrap();
+    receive_image(key.clone(), Ok(image));
+    let pixbuf = load_image(&document, &key).unwrap();
+    assert_eq!(
+        (pixbuf.get_width(), pixbuf.get_height()),
+        (expected.get_width(), expected.get_height())
+    );
+
+    let missing = format!("{}missing.png", dir);
+    let key = resolve_url(&document, &missing).unwrap().into_string();
+    receive_image(key, Err("Failed to load 'missing.png'".to_string()));
+    assert!(load_image(&document, &missing).is_none());
+    assert!(image_failed(&document, &missing));
+}
+
+#[test]
+fn test_image_placeholder() {
+    use painter::{display_list_for, DisplayCommand};
+
+    let url = "http://example.com/large.png";
+    IMG_CACHE.with(|c| {
+        c.borrow_mut()
+            .insert(url.to_string(), CachedImage::Loading(vec![]))
+    });
+    let html_src = format!(
+        "<html><body><img src='{0}' width='30' height='20' alt='large'><img src='{0}'>\
+         <span>after</span></body></html>",
+        url
+    );
+    let list = display_list_for(html_src.as_str(), "", 200.0);
+
+    // While the image loads, nothing is painted for it, and its box has the size of the
+    // attributes, or none without them.
+    assert!(!list.iter().any(|item| match item.command {
+        DisplayCommand::Image(_, _) | DisplayCommand::Border(..) => true,
+        _ => false,
+    }));
+    let texts: Vec<(String, Au)> = list
+        .iter()
+        .filter_map(|item| match item.command {
+            DisplayCommand::Text(ref text, rect, _, _, _) => Some((text.clone(), rect.x)),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(texts, vec![("after".to_string(), Au::from_f64_px(30.0))]);
+
+    // The document is laid out again when the image arrives, however many times it uses it.
+    IMG_CACHE.with(|c| match c.borrow().get(url) {
+        Some(&CachedImage::Loading(ref documents)) => assert_eq!(documents.len(), 1),
+        _ => panic!("The image isn't loading"),
+    });
 }
@@ -4,6 +4,7 @@ use css;
 use style;
 use layout;
 use painter;
+use inline;
 use window;
 use default_style;
 
@@ -13,12 +14,14 @@ use std::path::{Path, PathBuf};
 
 extern crate gtk;
 use gtk::WidgetExt;
+use glib::Continue;
 
 extern crate app_units;
 use app_units::Au;
 
 extern crate reqwest;
 use interface::reqwest::Url;
+use interface::reqwest::header::{Location, UserAgent};
 
 use std::fs;
 use std::io::{BufWriter, Write};
@@ -30,149 +33,926 @@ use self::rand::Rng;
 //  Returns (downloaded file name, file path(URL without ``http(s)://domain/``)).
 // If ``url_str`` starts with ``file://``, doesn't do anything special.
 //  Just returns (local file name, local file path).
-pub fn download(url_str: &str) -> (String, PathBuf) {
-    let url = HTML_SRC_URL.with(|a| {
-        let mut a = a.borrow_mut();
-        if let Some(ref mut a) = *a {
-            let mut url = Url::parse(a.as_str()).unwrap();
-            url.set_path(url_str);
-            return url;
-        }
-        *a = Some(url_str.to_string());
-        Url::parse(url_str).unwrap()
-    });
+// If ``url_str`` starts with ``data:``, writes the decoded data to a file:
+//  Returns (the file name, an empty path).
+// ``url_str`` is resolved against the URL of ``document``.
+pub fn download(document: &Document, url_str: &str) -> Result<(String, PathBuf), String> {
+    fetch(&try!(resolve_url(document, url_str)))
+}
+
+/// Resolve `url_str` against the base URL of `document`: the href of its <base>, or its URL.
+/// Links, images, stylesheets and `url()` in CSS are all resolved with this.
+pub fn resolve_url(document: &Document, url_str: &str) -> Result<Url, String> {
+    let base = document.base.borrow().clone().or_else(|| current_url(document));
+    resolve_against(base.as_ref().map(|base| base.as_str()), url_str)
+}
+
+/// Resolve `url_str` against `base` as RFC 3986 does: dot segments are removed, and an absolute
+/// path, a `//host` or a query replaces the part of `base` from there. Without `base`, only
+/// absolute URLs are valid.
+pub fn resolve_against(base: Option<&str>, url_str: &str) -> Result<Url, String> {
+    match base {
+        Some(base) => Url::parse(base).and_then(|base| base.join(url_str)),
+        None => Url::parse(url_str),
+    }.map_err(|e| format!("Invalid URL '{}': {}", url_str, e))
+}
+
+/// The URL relative URLs in `html_tree` at `url` resolve against: the href of its <base> if it's
+/// valid, or `url`.
+fn document_base(url: &Url, html_tree: &dom::Node) -> Url {
+    html_tree
+        .base_href()
+        .and_then(|href| url.join(href).ok())
+        .unwrap_or_else(|| url.clone())
+}
+
+/// The fragment of `url_str`, if any.
+pub fn url_fragment(document: &Document, url_str: &str) -> Option<String> {
+    resolve_url(document, url_str)
+        .ok()
+        .and_then(|url| url.fragment().map(|f| f.to_string()))
+}
+
+/// If `url_str` refers to a fragment of `document`, return the fragment.
+pub fn same_document_fragment(document: &Document, url_str: &str) -> Option<String> {
+    let mut url = resolve_url(document, url_str).ok()?;
+    let fragment = url.fragment()?.to_string();
+    let mut current = Url::parse(&current_url(document)?).ok()?;
+    url.set_fragment(None);
+    current.set_fragment(None);
+    if url == current {
+        Some(fragment)
+    } else {
+        None
+    }
+}
 
-    if url.scheme().to_ascii_lowercase() == "file" {
+/// The URL of `document`.
+pub fn current_url(document: &Document) -> Option<String> {
+    document.url.borrow().clone()
+}
+
+/// The HTML source of `document`, as it was parsed.
+pub fn current_source(document: &Document) -> Option<String> {
+    document.source.borrow().clone()
+}
+
+/// The title of `document` given by <title>.
+pub fn current_title(document: &Document) -> Option<String> {
+    document
+        .html_tree
+        .borrow()
+        .as_ref()
+        .and_then(|html_tree| html_tree.title())
+}
+
+/// The same as `download`, for a URL that is already resolved.
+pub fn fetch(url: &Url) -> Result<(String, PathBuf), String> {
+    match url.scheme().to_ascii_lowercase().as_str() {
+        "file" => {
+            let path = try!(file_path(url));
+            Ok((path.to_string_lossy().into_owned(), path))
+        }
+        "data" => {
+            let (content, media_type) = try!(decode_data_url(url));
+            // "image/png" is saved as ".png".
+            let extension = media_type
+                .split(|c| c == '/' || c == ';')
+                .nth(1)
+                .filter(|subtype| subtype.chars().all(|c| c.is_ascii_alphanumeric()))
+                .unwrap_or("data")
+                .to_string();
+            let file_name = try!(write_cache_file(&content, extension.as_str(), url));
+            Ok((file_name, PathBuf::new()))
+        }
+        _ => {
+            // http(s)://
+            let error = |e: &::std::fmt::Display| format!("Failed to download '{}': {}", url, e);
+
+            let mut content: Vec<u8> = vec![];
+            let (mut response, url) = try!(http_get(url));
+            try!(response.copy_to(&mut content).map_err(|e| error(&e)));
+            let path = Path::new(url.path());
+            let extension = match path.extension() {
+                Some(ext) => ext.to_str().unwrap(),
+                None => "html",
+            };
+            let file_name = try!(write_cache_file(&content, extension, &url));
+            Ok((file_name, path.to_path_buf()))
+        }
+    }
+}
+
+/// Write `content` fetched from `url` to a new file in the cache directory, and return its name.
+fn write_cache_file(content: &[u8], extension: &str, url: &Url) -> Result<String, String> {
+    let error = |e: &::std::fmt::Display| format!("Failed to download '{}': {}", url, e);
+
+    let tmpfile_name = format!(
+        "cache/{}.{}",
+        rand::thread_rng()
+            .gen_ascii_chars()
+            .take(8)
+            .collect::<String>(),
+        extension
+    );
+
+    let mut f = BufWriter::new(try!(
+        fs::File::create(tmpfile_name.as_str()).map_err(|e| error(&e))
+    ));
+    try!(f.write_all(content).map_err(|e| error(&e)));
+
+    Ok(tmpfile_name)
+}
+
+/// Decode a `data:` URL into its content and media type. The content is base64 if the media
+/// type is followed by `;base64`, and percent-encoded otherwise.
+/// ref. https://tools.ietf.org/html/rfc2397
+fn decode_data_url(url: &Url) -> Result<(Vec<u8>, String), String> {
+    let error = |e: &str| format!("Invalid data URL '{}': {}", url, e);
+
+    // The fragment isn't part of the data.
+    let data = url.as_str()["data:".len()..].split('#').next().unwrap();
+    let comma = try!(data.find(',').ok_or_else(|| error("No comma")));
+    let header = data[..comma].trim();
+    let content = percent_decode(&data[comma + 1..]);
+
+    let (media_type, is_base64) = match header.rfind(';') {
+        Some(i) if header[i + 1..].trim().eq_ignore_ascii_case("base64") => (&header[..i], true),
+        _ => (header, false),
+    };
+    // Only parameters, or nothing at all, means text.
+    let media_type = if media_type.is_empty() {
+        "text/plain;charset=US-ASCII".to_string()
+    } else if media_type.starts_with(';') {
+        format!("text/plain{}", media_type)
+    } else {
+        media_type.to_string()
+    };
+    let content = if is_base64 {
+        try!(decode_base64(&content).ok_or_else(|| error("Invalid base64")))
+    } else {
+        content
+    };
+    Ok((content, media_type))
+}
+
+/// Decode the `%XX` escapes in `s`. Malformed ones are left as they are.
+fn percent_decode(s: &str) -> Vec<u8> {
+    let bytes = s.as_bytes();
+    let hex = |i: usize| {
+        bytes
+            .get(i)
+            .and_then(|&b| (b as char).to_digit(16))
+            .map(|d| d as u8)
+    };
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match (bytes[i], hex(i + 1), hex(i + 2)) {
+            (b'%', Some(high), Some(low)) => {
+                decoded.push(high << 4 | low);
+                i += 3;
+            }
+            (b, _, _) => {
+                decoded.push(b);
+                i += 1;
+            }
+        }
+    }
+    decoded
+}
+
+/// Decode base64 with optional padding. Whitespace is ignored. None if it has other characters.
+fn decode_base64(input: &[u8]) -> Option<Vec<u8>> {
+    let mut decoded = Vec::with_capacity(input.len() * 3 / 4);
+    let (mut buffer, mut bits) = (0u32, 0);
+    for &c in input.iter().filter(|c| !(**c as char).is_whitespace()) {
+        let value = match c {
+            b'A'...b'Z' => c - b'A',
+            b'a'...b'z' => c - b'a' + 26,
+            b'0'...b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            b'=' => break,
+            _ => return None,
+        };
+        buffer = buffer << 6 | value as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            decoded.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+    Some(decoded)
+}
+
+/// Fetch the text of a document. Returns (text, the URL it's from after redirects).
+/// Text downloaded over HTTP(S) is decoded with the charset in its `Content-Type`.
+fn fetch_text(url: &Url) -> Result<(String, Url), String> {
+    let scheme = url.scheme().to_ascii_lowercase();
+    if scheme == "file" {
         // file://
-        (url.path().to_string(), Path::new(url.path()).to_path_buf())
+        Ok((try!(read_file(&try!(file_path(url)))), url.clone()))
+    } else if scheme == "data" {
+        // TODO: Decode with the charset in the media type.
+        let (content, _) = try!(decode_data_url(url));
+        Ok((String::from_utf8_lossy(&content).into_owned(), url.clone()))
     } else {
         // http(s)://
 
-        println!("download {}", url.as_str());
+        let error = |e: &::std::fmt::Display| format!("Failed to download '{}': {}", url, e);
 
-        let mut content: Vec<u8> = vec![];
-        reqwest::get(url.clone())
-            .unwrap()
-            .copy_to(&mut content)
-            .unwrap();
-        let path = Path::new(url.path());
-
-        let tmpfile_name = format!(
-            "cache/{}.{}",
-            rand::thread_rng()
-                .gen_ascii_chars()
-                .take(8)
-                .collect::<String>(),
-            if let Some(ext) = path.extension() {
-                ext.to_str().unwrap()
-            } else {
-                "html"
-            }
-        );
+        let (mut response, url) = try!(http_get(url));
+        Ok((try!(response.text().map_err(|e| error(&e))), url))
+    }
+}
+
+/// The User-Agent sent with HTTP requests.
+const USER_AGENT: &'static str = concat!("Naglfar/", env!("CARGO_PKG_VERSION"));
+/// How long an HTTP request may take before it fails, in seconds.
+const HTTP_TIMEOUT: u64 = 30;
+/// The most redirects followed for a request. Redirect loops give up after them too.
+const MAX_REDIRECTS: usize = 10;
+
+/// Send a GET request for `url`, following redirects. Returns the response and the URL it's from.
+/// Fails unless the final status is a success.
+fn http_get(url: &Url) -> Result<(reqwest::Response, Url), String> {
+    let error = |e: &::std::fmt::Display| format!("Failed to download '{}': {}", url, e);
 
-        let mut f = BufWriter::new(fs::File::create(tmpfile_name.as_str()).unwrap());
-        f.write_all(content.as_slice()).unwrap();
+    let client = try!(
+        reqwest::Client::builder()
+            .timeout(Duration::from_secs(HTTP_TIMEOUT))
+            .redirect(reqwest::RedirectPolicy::none())
+            .build()
+            .map_err(|e| error(&e))
+    );
+    let mut current = url.clone();
+    for _ in 0..MAX_REDIRECTS + 1 {
+        let response = try!(
+            client
+                .get(current.clone())
+                .header(UserAgent::new(USER_AGENT))
+                .send()
+                .map_err(|e| error(&e))
+        );
+        match response.status().as_u16() {
+            301 | 302 | 303 | 307 | 308 => {}
+            _ if response.status().is_success() => return Ok((response, current)),
+            _ => return Err(error(&response.status())),
+        }
 
-        (tmpfile_name, path.to_path_buf())
+        // The location is relative to the URL redirected from, and keeps its fragment unless it
+        // has one.
+        let location = try!(
+            response
+                .headers()
+                .get::<Location>()
+                .map(|location| location.to_string())
+                .ok_or_else(|| error(&"Redirected without a location"))
+        );
+        let mut next = try!(current.join(location.as_str()).map_err(|e| error(&e)));
+        if next.fragment().is_none() {
+            next.set_fragment(current.fragment());
+        }
+        if current.scheme() == "https" && next.scheme() == "http" {
+            eprintln!("Warning: '{}' redirects to insecure '{}'", current, next);
+        }
+        current = next;
     }
+    Err(error(&format!("More than {} redirects", MAX_REDIRECTS)))
 }
 
-use std::cell::RefCell;
+fn read_file(path: &Path) -> Result<String, String> {
+    let mut source = "".to_string();
+    try!(
+        OpenOptions::new()
+            .read(true)
+            .open(path)
+            .and_then(|mut f| f.read_to_string(&mut source))
+            .map_err(|e| format!("Cannot read '{}': {}", path.display(), e))
+    );
+    Ok(source)
+}
+
+/// The local path of the file URL `url`, with percent-encoded characters such as spaces decoded.
+fn file_path(url: &Url) -> Result<PathBuf, String> {
+    url.to_file_path().map_err(|_| format!("Cannot read '{}': Not a local file", url))
+}
+
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
 use std::rc::Rc;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// The state of the document shown in a window. Each window owns one, so that windows navigate
+/// independently.
+pub struct Document {
+    url: RefCell<Option<String>>,
+    // The href of <base>, resolved, if the document has one.
+    base: RefCell<Option<String>>,
+    // The HTML the tree was parsed from.
+    source: RefCell<Option<String>>,
+    html_tree: RefCell<Option<dom::Node>>,
+    stylesheet: RefCell<Option<css::Stylesheet>>,
+    history: RefCell<History>,
+    // Incremented whenever the document changes and has to be laid out again.
+    generation: Cell<usize>,
+    // The display list of the last layout, and what it was made for.
+    layout_saver: RefCell<Option<(LayoutKey, Rc<painter::DisplayList>)>>,
+    // The number of navigations started. A page loaded in the background by an older one is
+    // discarded.
+    navigation: Cell<usize>,
+    pub ankers: RefCell<HashMap<layout::Rect, window::AnkerKind>>,
+    // The border boxes of the elements URL fragments refer to, by the fragment.
+    pub url_fragments: RefCell<HashMap<String, layout::Rect>>,
+    // (Rect, title attribute) of the boxes with a title. Descendants come after their ancestors.
+    pub titles: RefCell<Vec<(layout::Rect, String)>>,
+    pub buttons: RefCell<HashMap<usize, gtk::Button>>,
+    // The boxes of the elements while the box model inspector is on. Empty otherwise.
+    pub inspected_boxes: RefCell<Vec<layout::InspectedBox>>,
+}
+
+impl Document {
+    pub fn new() -> Document {
+        Document {
+            url: RefCell::new(None),
+            base: RefCell::new(None),
+            source: RefCell::new(None),
+            html_tree: RefCell::new(None),
+            stylesheet: RefCell::new(None),
+            history: RefCell::new(History::new()),
+            generation: Cell::new(0),
+            layout_saver: RefCell::new(None),
+            navigation: Cell::new(0),
+            ankers: RefCell::new(HashMap::with_capacity(8)),
+            url_fragments: RefCell::new(HashMap::with_capacity(8)),
+            titles: RefCell::new(vec![]),
+            buttons: RefCell::new(HashMap::with_capacity(8)),
+            inspected_boxes: RefCell::new(vec![]),
+        }
+    }
+
+    /// Start a navigation, and return its id. Pages loaded in the background by the earlier
+    /// ones are discarded from now on.
+    pub fn begin_navigation(&self) -> usize {
+        let id = self.navigation.get() + 1;
+        self.navigation.set(id);
+        id
+    }
+
+    /// Mark the document as changed, so that it's laid out again when it's drawn next.
+    pub fn invalidate(&self) {
+        self.generation.set(self.generation.get() + 1);
+    }
+
+    /// The display list for a viewport of `width` x `height` at `zoom`. The last one is replayed
+    /// if neither the document nor the viewport changed since, and otherwise `build` makes it.
+    fn display_list<F>(
+        &self,
+        width: Au,
+        height: Au,
+        zoom: f64,
+        build: F,
+    ) -> Rc<painter::DisplayList>
+    where
+        F: FnOnce() -> painter::DisplayList,
+    {
+        let key = LayoutKey {
+            generation: self.generation.get(),
+            width: width,
+            height: height,
+            zoom: zoom,
+        };
+        let mut layout_saver = self.layout_saver.borrow_mut();
+        if let Some((ref last_key, ref items)) = *layout_saver {
+            if *last_key == key {
+                if TRACE_DISPLAY_LIST.with(|t| t.get()) {
+                    eprintln!("Replayed the display list ({} items)", items.len());
+                }
+                return items.clone();
+            }
+        }
+
+        let items = Rc::new(build());
+        *layout_saver = Some((key, items.clone()));
+        items
+    }
+}
+
+/// What a display list was made for: the generation of the document, the size of the viewport
+/// and the zoom.
+#[derive(Clone, Copy, PartialEq, Debug)]
+struct LayoutKey {
+    generation: usize,
+    width: Au,
+    height: Au,
+    zoom: f64,
+}
 
 thread_local!(
-    static LAYOUT_SAVER: RefCell<(Au, Au, painter::DisplayList)> = { RefCell::new((Au(0), Au(0), vec![])) };
-    static HTML_SRC_URL: RefCell<Option<String>> = { RefCell::new(None) };
-    static HTML_TREE: Rc<RefCell<Option<dom::Node>>> = { Rc::new(RefCell::new(None)) };
-    static STYLESHEET: Rc<RefCell<Option<css::Stylesheet>>> = { Rc::new(  RefCell::new(None)) };
+    // Whether to report on stderr when display lists are replayed or rebuilt.
+    static TRACE_DISPLAY_LIST: Cell<bool> = { Cell::new(false) };
 );
 
-static mut SRC_UPDATED: bool = false;
+/// Report on stderr whether each draw replays the display list or rebuilds it, and how long
+/// laying out the page and building the display list take.
+pub fn set_trace_display_list(trace: bool) {
+    TRACE_DISPLAY_LIST.with(|t| t.set(trace));
+}
 
-pub fn update_html_tree_and_stylesheet(html_src: String) {
-    let (html_src_cache_name, html_src_path) = download(html_src.as_str());
+/// URLs of the pages visited before and after the current page. The last ones are the nearest.
+#[derive(Clone, Debug, PartialEq)]
+struct History {
+    back: Vec<String>,
+    forward: Vec<String>,
+}
 
-    println!("HTML:");
-    let mut html_source = "".to_string();
-    OpenOptions::new()
-        .read(true)
-        .open(html_src_cache_name)
-        .unwrap()
-        .read_to_string(&mut html_source)
-        .ok()
-        .expect("cannot read file");
-    let html_tree = html::parse(html_source, html_src_path);
-    print!("{}", html_tree);
-
-    println!("CSS:");
-    let mut css_source = "".to_string();
-    if let Some(stylesheet_path) = html_tree.find_stylesheet_path() {
-        let (css_cache_name, _) = download(stylesheet_path.to_str().unwrap());
-        OpenOptions::new()
-            .read(true)
-            .open(css_cache_name)
-            .unwrap()
-            .read_to_string(&mut css_source)
-            .ok()
-            .expect("cannot read file");
-    } else {
-        println!("*** Not found any stylesheet but continue ***");
+impl History {
+    fn new() -> History {
+        History {
+            back: vec![],
+            forward: vec![],
+        }
+    }
+
+    /// Moved to a new page from `previous`. The forward history is truncated.
+    fn visited(&mut self, previous: String) {
+        self.back.push(previous);
+        self.forward.clear();
     }
-    let stylesheet = css::parse(css_source);
-    print!("{}", stylesheet);
 
-    HTML_TREE.with(|h| {
-        *h.borrow_mut() = Some(html_tree);
+    /// Moved back from `current` to `self.back.last()`.
+    fn went_back(&mut self, current: String) {
+        self.back.pop();
+        self.forward.push(current);
+    }
+
+    /// Moved forward from `current` to `self.forward.last()`.
+    fn went_forward(&mut self, current: String) {
+        self.forward.pop();
+        self.back.push(current);
+    }
+}
+
+/// Load the page at `html_src` into `document`, and add the current page to the history. If it
+/// can't be loaded, a page describing the error is shown instead. If `html_src` is not a valid
+/// URL, the current page is kept and the error is returned.
+pub fn update_html_tree_and_stylesheet(
+    document: &Document,
+    html_src: String,
+) -> Result<(), String> {
+    navigate_sync(document, Navigation::Visit(html_src)).map(|_| ())
+}
+
+/// Describe the element of `document` at `path`, the indices of the children from the root: its
+/// start tag, and the rules of the stylesheet and the style attribute applied to it.
+pub fn describe_element(document: &Document, path: &[usize]) -> Option<String> {
+    let html_tree = document.html_tree.borrow();
+    let stylesheet = document.stylesheet.borrow();
+    let (html_tree, stylesheet) = match (html_tree.as_ref(), stylesheet.as_ref()) {
+        (Some(html_tree), Some(stylesheet)) => (html_tree, stylesheet),
+        _ => return None,
+    };
+
+    let mut node = html_tree;
+    let mut ancestors = vec![];
+    for &i in path {
+        if let dom::NodeType::Element(ref elem) = node.data {
+            ancestors.push(elem);
+        }
+        node = node.children.get(i)?;
+    }
+    let elem = match node.data {
+        dom::NodeType::Element(ref elem) => elem,
+        dom::NodeType::Text(_) => return None,
+    };
+
+    let rules = style::matched_rules(elem, stylesheet, &ancestors);
+    let mut description = format!("{}\n", node.data);
+    description += &format!(
+        "{}",
+        css::Stylesheet {
+            rules: rules.into_iter().cloned().collect(),
+        }
+    );
+    if let Some(style) = elem.attrs.get("style") {
+        description += &format!("style attribute {{ {} }}\n", style);
+    }
+    Some(description)
+}
+
+/// Go back to the previous page of `document`. Returns whether there was one.
+pub fn history_back(document: &Document) -> Result<bool, String> {
+    navigate_sync(document, Navigation::Back)
+}
+
+/// Go forward to the next page of `document`. Returns whether there was one.
+pub fn history_forward(document: &Document) -> Result<bool, String> {
+    navigate_sync(document, Navigation::Forward)
+}
+
+/// Load the page of `document` again. Images are downloaded again too.
+pub fn reload(document: &Document) -> Result<(), String> {
+    navigate_sync(document, Navigation::Reload).map(|_| ())
+}
+
+/// Where a navigation goes.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Navigation {
+    /// Visit a URL, relative to the current document.
+    Visit(String),
+    Back,
+    Forward,
+    Reload,
+}
+
+/// A document fetched and parsed, ready to be shown.
+struct Page {
+    url: Url,
+    source: String,
+    html_tree: dom::Node,
+    stylesheet: css::Stylesheet,
+}
+
+/// How often a page or an image loaded in the background is checked for, in milliseconds.
+pub const LOAD_POLL_INTERVAL: u32 = 20;
+
+/// Navigate `document`, and wait until the page is shown. Returns whether there was anything to
+/// load.
+fn navigate_sync(document: &Document, navigation: Navigation) -> Result<bool, String> {
+    // A page still loading in the background is no longer wanted.
+    let id = document.begin_navigation();
+    let url = match try!(navigation_url(document, &navigation)) {
+        Some(url) => url,
+        None => return Ok(false),
+    };
+    // The page is shown like one loaded in the background. Nothing can start another navigation
+    // while it's fetched, so it's never discarded.
+    receive_page(document, id, &navigation, url.clone(), fetch_page(url)).unwrap_or(Ok(false))
+}
+
+/// Navigate `document` in the background: the page is fetched and parsed on a worker
+/// thread, and shown by the main loop once it's ready. `done` is then called with whether the
+/// document changed, or the error. A page is discarded if another navigation started since.
+/// Returns whether the page is being loaded; if not, `done` has been called with the error if
+/// there was one. Either way, a page still loading for an earlier navigation is discarded.
+pub fn navigate<F>(document: &Rc<Document>, navigation: Navigation, done: F) -> bool
+where
+    F: FnOnce(Result<bool, String>) + 'static,
+{
+    let id = document.begin_navigation();
+    let url = match navigation_url(document, &navigation) {
+        Ok(Some(url)) => url,
+        Ok(None) => return false,
+        Err(e) => {
+            done(Err(e));
+            return false;
+        }
+    };
+
+    let (sender, receiver) = mpsc::channel();
+    {
+        let url = url.clone();
+        thread::spawn(move || {
+            // The receiver is gone if the window was closed.
+            let _ = sender.send(fetch_page(url));
+        });
+    }
+    let document = document.clone();
+    let mut done = Some(done);
+    gtk::timeout_add(LOAD_POLL_INTERVAL, move || {
+        let page = match receiver.try_recv() {
+            Ok(page) => page,
+            Err(mpsc::TryRecvError::Empty) => return Continue(true),
+            Err(mpsc::TryRecvError::Disconnected) => Err(format!("Failed to load '{}'", url)),
+        };
+        if let Some(result) = receive_page(&document, id, &navigation, url.clone(), page) {
+            (done.take().unwrap())(result);
+        }
+        Continue(false)
     });
-    STYLESHEET.with(|s| *s.borrow_mut() = Some(stylesheet));
+    true
+}
 
-    unsafe {
-        SRC_UPDATED = true;
+/// Show `page`, loaded in the background by the navigation `id` of `document`. Returns None
+/// without showing it if another navigation started since.
+fn receive_page(
+    document: &Document,
+    id: usize,
+    navigation: &Navigation,
+    url: Url,
+    page: Result<Page, String>,
+) -> Option<Result<bool, String>> {
+    if document.navigation.get() != id {
+        eprintln!("Discarded '{}' loaded by a cancelled navigation", url);
+        return None;
     }
+    Some(finish_navigation(document, navigation, url, page))
 }
 
-pub fn run_with_url(html_src: String) {
-    update_html_tree_and_stylesheet(html_src);
+/// The URL to load for `navigation`, or None if there is nothing to go back or forward to.
+fn navigation_url(document: &Document, navigation: &Navigation) -> Result<Option<Url>, String> {
+    let url = match *navigation {
+        Navigation::Visit(ref html_src) => {
+            return resolve_url(document, html_src.as_str()).map(Some)
+        }
+        Navigation::Back => document.history.borrow().back.last().cloned(),
+        Navigation::Forward => document.history.borrow().forward.last().cloned(),
+        Navigation::Reload => {
+            Some(try!(current_url(document).ok_or("No document is loaded".to_string())))
+        }
+    };
+    match url {
+        Some(url) => Url::parse(url.as_str())
+            .map(Some)
+            .map_err(|e| format!("Invalid URL '{}': {}", url, e)),
+        None => Ok(None),
+    }
+}
 
-    window::render(move |widget| {
-        let mut viewport: layout::Dimensions = ::std::default::Default::default();
-        viewport.content.width = Au::from_f64_px(widget.get_allocated_width() as f64);
-        viewport.content.height = Au::from_f64_px(widget.get_allocated_height() as f64);
-
-        LAYOUT_SAVER.with(|x| {
-            let (ref mut last_width, ref mut last_height, ref mut last_displays) = *x.borrow_mut();
-            if *last_width == viewport.content.width && *last_height == viewport.content.height
-                && unsafe { !SRC_UPDATED }
-            {
-                last_displays.clone()
-            } else {
-                unsafe {
-                    SRC_UPDATED = false;
+/// Show `page`, loaded from `url` for `navigation`, in `document`, and update the history. If a
+/// visited page can't be loaded, a page describing the error is shown instead; otherwise the
+/// current page is kept and the error is returned.
+fn finish_navigation(
+    document: &Document,
+    navigation: &Navigation,
+    url: Url,
+    page: Result<Page, String>,
+) -> Result<bool, String> {
+    let previous = current_url(document);
+    match page {
+        Ok(page) => set_document(document, page.url, page.source, page.html_tree, page.stylesheet),
+        Err(e) => match *navigation {
+            Navigation::Visit(_) => {
+                eprintln!("{}", e);
+                load_error_page(document, url, e.as_str());
+            }
+            _ => return Err(e),
+        },
+    }
+
+    let mut history = document.history.borrow_mut();
+    match (navigation, previous) {
+        (&Navigation::Visit(_), Some(previous)) => history.visited(previous),
+        (&Navigation::Back, Some(previous)) => history.went_back(previous),
+        (&Navigation::Forward, Some(previous)) => history.went_forward(previous),
+        (&Navigation::Reload, _) => inline::clear_image_cache(),
+        _ => {}
+    }
+    Ok(true)
+}
+
+/// Fetch and parse the document at `url` and its linked stylesheet. The current document isn't
+/// touched, so that it can run on a worker thread.
+fn fetch_page(url: Url) -> Result<Page, String> {
+    // Relative URLs in the document resolve against where it's redirected to.
+    let (html_source, url) = try!(fetch_text(&url));
+
+    let html_tree = html::parse(html_source.clone());
+    // Linked stylesheets are relative to the new document.
+    let stylesheet = document_stylesheet(&document_base(&url, &html_tree), &html_tree);
+
+    Ok(Page {
+        url: url,
+        source: html_source,
+        html_tree: html_tree,
+        stylesheet: stylesheet,
+    })
+}
+
+/// The stylesheet of `html_tree`: the rules of its linked stylesheets and <style> elements in
+/// document order. Each is parsed on its own, so that an unclosed block doesn't swallow the next
+/// one. Linked stylesheets resolve against `base`, and those that can't be fetched are skipped.
+fn document_stylesheet(base: &Url, html_tree: &dom::Node) -> css::Stylesheet {
+    let mut stylesheet = css::Stylesheet { rules: vec![] };
+    for style_source in html_tree.style_sources() {
+        let rules = match style_source {
+            dom::StyleSource::Inline(source) => {
+                parse_stylesheet(source, base, &mut vec![base.clone()])
+            }
+            dom::StyleSource::Link(href) => {
+                let fetched = base.join(href.as_str())
+                    .map_err(|e| format!("Invalid URL '{}': {}", href, e))
+                    .and_then(|url| fetch_text(&url));
+                match fetched {
+                    Ok((source, url)) => parse_stylesheet(source, &url, &mut vec![url.clone()]),
+                    Err(e) => {
+                        eprintln!("Warning: Skipped a stylesheet: {}", e);
+                        continue;
+                    }
                 }
-                *last_width = viewport.content.width;
-                *last_height = viewport.content.height;
-
-                let html_tree = HTML_TREE.with(|h| (*h.borrow()).clone().unwrap());
-                let stylesheet = STYLESHEET.with(|s| (*s.borrow()).clone().unwrap());
-                let default_style = default_style::default_style();
-                let style_tree = style::style_tree(
-                    &html_tree,
-                    &stylesheet,
-                    &default_style,
-                    &style::PropertyMap::new(),
-                    &style::PropertyMap::new(),
-                    &vec![],
-                );
-                let layout_tree = layout::layout_tree(&style_tree, viewport);
-                print!("LAYOUT:\n{}", layout_tree);
-
-                let display_command = painter::build_display_list(&layout_tree);
-                println!("DISPLAY:\n{:?}", display_command);
-
-                *last_displays = display_command.clone();
-
-                display_command
             }
+        };
+        stylesheet.rules.extend(rules);
+    }
+    stylesheet
+}
+
+/// The most stylesheets nested by @import.
+const MAX_IMPORT_DEPTH: usize = 8;
+
+/// The rules of the stylesheet `source` at `url`, preceded by those of its @imports, which are
+/// fetched recursively. `importing` is the stylesheets importing this one and itself. An import
+/// of one of them would be a cycle, and is skipped like one deeper than `MAX_IMPORT_DEPTH`.
+fn parse_stylesheet(source: String, url: &Url, importing: &mut Vec<Url>) -> Vec<css::Rule> {
+    let (imports, stylesheet) = css::parse_with_imports(source);
+    let mut rules = vec![];
+    for href in imports {
+        let import_url = match url.join(href.as_str()) {
+            Ok(import_url) => import_url,
+            Err(e) => {
+                eprintln!("Warning: Skipped @import of invalid URL '{}': {}", href, e);
+                continue;
+            }
+        };
+        if importing.contains(&import_url) {
+            eprintln!("Warning: Skipped @import of '{}', which imports itself", import_url);
+            continue;
+        }
+        if importing.len() > MAX_IMPORT_DEPTH {
+            eprintln!("Warning: Skipped @import of '{}' nested too deeply", import_url);
+            continue;
+        }
+        match fetch_text(&import_url) {
+            Ok((source, import_url)) => {
+                importing.push(import_url.clone());
+                rules.extend(parse_stylesheet(source, &import_url, importing));
+                importing.pop();
+            }
+            Err(e) => eprintln!("Warning: Skipped @import: {}", e),
+        }
+    }
+    rules.extend(stylesheet.rules);
+    rules
+}
+
+/// Show a page describing `error` in `document` as the page at `url`, so that it can be reloaded.
+fn load_error_page(document: &Document, url: Url, error: &str) {
+    let escaped = error
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;");
+    let src = format!(
+        "<html><body><h1>Cannot load the page</h1><div>{}</div></body></html>",
+        escaped
+    );
+    let html_tree = html::parse(src.clone());
+    set_document(document, url, src, html_tree, css::Stylesheet { rules: vec![] });
+}
+
+fn set_document(
+    document: &Document,
+    url: Url,
+    source: String,
+    html_tree: dom::Node,
+    stylesheet: css::Stylesheet,
+) {
+    let base = document_base(&url, &html_tree);
+    *document.base.borrow_mut() = if base == url {
+        None
+    } else {
+        Some(base.into_string())
+    };
+    *document.url.borrow_mut() = Some(url.as_str().to_string());
+    *document.source.borrow_mut() = Some(source);
+    *document.html_tree.borrow_mut() = Some(html_tree);
+    *document.stylesheet.borrow_mut() = Some(stylesheet);
+
+    // Links of the previous page are no longer needed.
+    document.ankers.borrow_mut().clear();
+    document.url_fragments.borrow_mut().clear();
+    document.titles.borrow_mut().clear();
+    document.inspected_boxes.borrow_mut().clear();
+
+    document.invalidate();
+}
+
+/// The height of the viewport that pages are laid out in without a window.
+const SCREENSHOT_VIEWPORT_HEIGHT: f64 = 600.0;
+
+/// Render the whole page at `url`, laid out `width` px wide, to a PNG at `path` without opening a
+/// window. This works without a display. `url` may also be the path of a local file.
+pub fn screenshot(url: String, path: &Path, width: f64) -> Result<(), String> {
+    let display_list = try!(render_page(url, width));
+    window::write_png(path, &display_list, width)
+}
+
+/// Load the page at `url` and build its display list laid out `width` px wide, without a window.
+/// `url` may also be the path of a local file.
+pub fn render_page(url: String, width: f64) -> Result<painter::DisplayList, String> {
+    with_page_layout(url, width, |document, layout_root| {
+        painter::build_display_list(document, layout_root, false)
+    })
+}
+
+/// Load the page at `url` and return its layout tree laid out `width` px wide as text.
+pub fn dump_layout(url: String, width: f64) -> Result<String, String> {
+    with_page_layout(url, width, |_, layout_root| layout::dump_layout(layout_root))
+}
+
+/// Load the page at `url` into a new document, lay it out `width` px wide, and pass the document
+/// and the layout tree to `f`. `url` may also be the path of a local file.
+fn with_page_layout<T, F>(url: String, width: f64, f: F) -> Result<T, String>
+where
+    F: FnOnce(&Rc<Document>, &layout::LayoutBox) -> T,
+{
+    let url = match Url::parse(url.as_str()) {
+        Ok(url) => url,
+        Err(_) => try!(
+            fs::canonicalize(&url)
+                .ok()
+                .and_then(|path| Url::from_file_path(path).ok())
+                .ok_or_else(|| format!("Invalid URL or path '{}'", url))
+        ),
+    };
+    // Unlike in a window, a page that can't be loaded is an error.
+    let page = try!(fetch_page(url));
+    let document = Rc::new(Document::new());
+    set_document(&document, page.url, page.source, page.html_tree, page.stylesheet);
+
+    let mut viewport: layout::Dimensions = ::std::default::Default::default();
+    viewport.content.width = Au::from_f64_px(width);
+    viewport.content.height = Au::from_f64_px(SCREENSHOT_VIEWPORT_HEIGHT);
+
+    let html_tree = document.html_tree.borrow().clone().unwrap();
+    let stylesheet = document.stylesheet.borrow().clone().unwrap();
+    let default_style = default_style::default_style();
+    let style_tree = style::style_tree(
+        &html_tree,
+        &stylesheet,
+        &default_style,
+        &style::PropertyMap::new(),
+        &style::PropertyMap::new(),
+        &vec![],
+    );
+    let layout_tree = layout::layout_tree(&style_tree, viewport, &document);
+    Ok(f(&document, &layout_tree))
+}
+
+/// Lay out `document` in `viewport` and build its display list at `zoom`, registering the boxes
+/// for the box model inspector when `inspecting`.
+fn build_page_display_list(
+    document: &Rc<Document>,
+    viewport: layout::Dimensions,
+    zoom: f64,
+    inspecting: bool,
+) -> painter::DisplayList {
+    let html_tree = match document.html_tree.borrow().clone() {
+        Some(html_tree) => html_tree,
+        // A new window has nothing to paint until its first page arrives.
+        None => return vec![],
+    };
+    let stylesheet = document.stylesheet.borrow().clone().unwrap();
+    let default_style = default_style::default_style();
+
+    let start = Instant::now();
+    let style_tree = style::style_tree(
+        &html_tree,
+        &stylesheet,
+        &default_style,
+        &style::PropertyMap::new(),
+        &style::PropertyMap::new(),
+        &vec![],
+    );
+    let layout_tree = layout::layout_tree(&style_tree, viewport, document);
+    let display_command =
+        painter::build_zoomed_display_list(document, &layout_tree, zoom, inspecting);
+    let elapsed = start.elapsed();
+
+    if TRACE_DISPLAY_LIST.with(|t| t.get()) {
+        eprintln!(
+            "Rebuilt the display list ({} items) in {:.1} ms",
+            display_command.len(),
+            elapsed.as_secs() as f64 * 1000.0 + elapsed.subsec_nanos() as f64 / 1_000_000.0
+        );
+    }
+    display_command
+}
+
+/// Open a window showing the page at `html_src`, or return the error if it can't be loaded.
+pub fn run_with_url(html_src: String) -> Result<(), String> {
+    let document = Rc::new(Document::new());
+    try!(update_html_tree_and_stylesheet(&document, html_src));
+
+    // Every window lays out its own document, which it passes with its zoom and inspector.
+    window::render(document, move |document, widget, zoom, inspecting| {
+        let mut viewport: layout::Dimensions = ::std::default::Default::default();
+        // The page is laid out in CSS px, in which the zoomed window is narrower.
+        viewport.content.width = Au::from_f64_px(widget.get_allocated_width() as f64 / zoom);
+        // The drawing area is as tall as the page, so use the height of the visible area.
+        let visible_height = widget
+            .get_parent()
+            .and_then(|overlay| overlay.get_parent())
+            .map_or(widget.get_allocated_height(), |viewport| {
+                viewport.get_allocated_height()
+            });
+        viewport.content.height = Au::from_f64_px(visible_height as f64 / zoom);
+
+        document.display_list(viewport.content.width, viewport.content.height, zoom, || {
+            build_page_display_list(document, viewport, zoom, inspecting)
         })
     });
 
@@ -189,4 +969,853 @@ pub fn run_with_url(html_src: String) {
             };
         }
     }
+
+    Ok(())
+}
+
+#[test]
+fn test_describe_element() {
+    let dir = write_test_files(
+        "naglfar_describe_element",
+        &[(
+            "index.html",
+            "<html><head><style>p { color: red; } div p { margin: 0; } a { color: blue; }</style>\
+             </head><body><div><p style='padding: 1px'>a</p></div></body></html>",
+        )],
+    );
+    let document = Document::new();
+    update_html_tree_and_stylesheet(&document, format!("{}index.html", dir)).unwrap();
+
+    // The rules matching <p> through its ancestors, and its style attribute.
+    let description = describe_element(&document, &[1, 0, 0]).unwrap();
+    assert!(description.starts_with("<p style=\"padding: 1px\">\n"));
+    assert!(description.contains("p {"));
+    assert!(description.contains("div p {"));
+    assert!(!description.contains("a {"));
+    assert!(description.contains("style attribute { padding: 1px }"));
+    assert_eq!(describe_element(&document, &[1, 0, 0, 0]), None);
+    assert_eq!(describe_element(&document, &[5]), None);
+}
+
+#[test]
+fn test_history() {
+    let mut history = History::new();
+    history.visited("a".to_string());
+    history.visited("b".to_string());
+    // At c. Go back to b, and then to a.
+    history.went_back("c".to_string());
+    history.went_back("b".to_string());
+    assert_eq!(history.back, Vec::<String>::new());
+    assert_eq!(history.forward, vec!["c".to_string(), "b".to_string()]);
+    // Go forward to b.
+    history.went_forward("a".to_string());
+    assert_eq!(history.back, vec!["a".to_string()]);
+    assert_eq!(history.forward, vec!["c".to_string()]);
+    // Navigating to d from b truncates the forward history.
+    history.visited("b".to_string());
+    assert_eq!(history.back, vec!["a".to_string(), "b".to_string()]);
+    assert_eq!(history.forward, Vec::<String>::new());
+}
+
+/// Write `files`, pairs of a path and its content, to the directory `name` in the temporary
+/// directory. Returns the `file://` URL of the directory, ending with a slash. For tests.
+#[cfg(test)]
+pub fn write_test_files(name: &str, files: &[(&str, &str)]) -> String {
+    let dir = ::std::env::temp_dir().join(name);
+    for &(path, content) in files {
+        let path = dir.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::File::create(&path)
+            .unwrap()
+            .write_all(content.as_bytes())
+            .unwrap();
+    }
+    format!("file://{}/", dir.to_str().unwrap())
+}
+
+#[test]
+fn test_history_navigation() {
+    let dir = write_test_files(
+        "naglfar_history",
+        &[
+            ("a.html", "<html><body>a</body></html>"),
+            ("b.html", "<html><body>b</body></html>"),
+            ("c.html", "<html><body>c</body></html>"),
+            ("d.html", "<html><body>d</body></html>"),
+        ],
+    );
+    let page = |name: &str| format!("{}{}", dir, name);
+    let (a, b, c, d) = (page("a.html"), page("b.html"), page("c.html"), page("d.html"));
+
+    let document = Document::new();
+    update_html_tree_and_stylesheet(&document, a.clone()).unwrap();
+    // There is nothing to go back or forward to.
+    assert_eq!(history_back(&document), Ok(false));
+    assert_eq!(history_forward(&document), Ok(false));
+    assert_eq!(current_url(&document), Some(a.clone()));
+
+    update_html_tree_and_stylesheet(&document, b.clone()).unwrap();
+    update_html_tree_and_stylesheet(&document, c.clone()).unwrap();
+    assert_eq!(history_back(&document), Ok(true));
+    assert_eq!(current_url(&document), Some(b.clone()));
+    assert_eq!(history_back(&document), Ok(true));
+    assert_eq!(current_url(&document), Some(a.clone()));
+    assert_eq!(history_back(&document), Ok(false));
+    assert_eq!(history_forward(&document), Ok(true));
+    assert_eq!(current_url(&document), Some(b.clone()));
+
+    // A new navigation drops c from the forward history.
+    update_html_tree_and_stylesheet(&document, d.clone()).unwrap();
+    assert_eq!(history_forward(&document), Ok(false));
+    assert_eq!(history_back(&document), Ok(true));
+    assert_eq!(current_url(&document), Some(b.clone()));
+    assert_eq!(history_forward(&document), Ok(true));
+    assert_eq!(current_url(&document), Some(d.clone()));
+}
+
+#[test]
+fn test_independent_documents() {
+    use layout::Rect;
+
+    let dir = write_test_files(
+        "naglfar_documents",
+        &[
+            ("a.html", "<html><body><a href='b.html'>link</a></body></html>"),
+            ("b.html", "<html><body>b</body></html>"),
+        ],
+    );
+    let (a, b) = (format!("{}a.html", dir), format!("{}b.html", dir));
+
+    let first = Document::new();
+    update_html_tree_and_stylesheet(&first, a.clone()).unwrap();
+    first
+        .ankers
+        .borrow_mut()
+        .insert(Rect::default(), window::AnkerKind::URL(b.clone()));
+
+    // A second window starts without a document and a history.
+    let second = Document::new();
+    assert_eq!(current_url(&second), None);
+    update_html_tree_and_stylesheet(&second, b.clone()).unwrap();
+    assert_eq!(current_url(&second), Some(b.clone()));
+    assert_eq!(history_back(&second), Ok(false));
+
+    // Loading a page in the second window leaves the first one alone.
+    assert_eq!(first.ankers.borrow().len(), 1);
+    assert_eq!(current_url(&first), Some(a));
+}
+
+#[test]
+fn test_style_element() {
+    let dir = write_test_files(
+        "naglfar_style_element",
+        &[
+            (
+                "a.html",
+                "<html><head><style>p { color: red; }</style></head>\
+                 <body><p>text</p></body></html>",
+            ),
+        ],
+    );
+    let document = Document::new();
+    update_html_tree_and_stylesheet(&document, format!("{}a.html", dir)).unwrap();
+    // The source is kept as it was read.
+    assert!(current_source(&document).unwrap().starts_with("<html><head><style>"));
+    assert_eq!(
+        *document.stylesheet.borrow(),
+        Some(css::parse("p { color: red; }".to_string()))
+    );
+}
+
+#[test]
+fn test_style_elements_cascade() {
+    let dir = write_test_files(
+        "naglfar_style_elements",
+        &[
+            (
+                "a.html",
+                "<html><head><link rel='stylesheet' href='a.css'>\
+                 <style>p { color: red; } .b { color: blue; }</style></head>\
+                 <body><p class='a'>a</p><p class='b'>b</p><p class='c'>c</p>\
+                 <style>.c { color: lime; }</style></body></html>",
+            ),
+            ("a.css", "p { color: black; } .a { color: gray; }"),
+        ],
+    );
+    let document = Document::new();
+    update_html_tree_and_stylesheet(&document, format!("{}a.html", dir)).unwrap();
+
+    let html_tree = document.html_tree.borrow().clone().unwrap();
+    let stylesheet = document.stylesheet.borrow().clone().unwrap();
+    let default_style = default_style::default_style();
+    let style_tree = style::style_tree(
+        &html_tree,
+        &stylesheet,
+        &default_style,
+        &style::PropertyMap::new(),
+        &style::PropertyMap::new(),
+        &vec![],
+    );
+
+    fn p_colors(node: &style::StyledNode, found: &mut Vec<css::Value>) {
+        if let dom::NodeType::Element(ref e) = node.node.data {
+            if e.tag_name == "p" {
+                found.push(node.value("color").unwrap()[0].clone());
+            }
+        }
+        for child in &node.children {
+            p_colors(child, found);
+        }
+    }
+    let mut colors = vec![];
+    p_colors(&style_tree, &mut colors);
+    // <style> rules follow the linked ones, so `p` in <style> beats `p` in a.css. A more specific
+    // linked rule still wins, and every <style> element applies.
+    assert_eq!(
+        colors,
+        vec![
+            css::Value::Color(css::GRAY),
+            css::Value::Color(css::BLUE),
+            css::Value::Color(css::LIME),
+        ]
+    );
+}
+
+#[test]
+fn test_unsupported_selectors() {
+    let dir = write_test_files(
+        "naglfar_unsupported_selectors",
+        &[
+            (
+                "a.html",
+                "<html><head><style>a:hover { color: red; } p { color: blue; }\
+                 input[type=text] { color: red; } a::before { content: '}'; }\
+                 div > { color: red; } .b { color: lime; }</style></head></html>",
+            ),
+        ],
+    );
+    let document = Document::new();
+    update_html_tree_and_stylesheet(&document, format!("{}a.html", dir)).unwrap();
+
+    // Rules with selectors that aren't supported are skipped whole, and the others still apply.
+    assert_eq!(
+        *document.stylesheet.borrow(),
+        Some(css::parse("p { color: blue; } .b { color: lime; }".to_string()))
+    );
+}
+
+#[test]
+fn test_linked_stylesheets() {
+    let dir = write_test_files(
+        "naglfar_linked_stylesheets",
+        &[
+            (
+                "a.html",
+                "<html><head><link rel='stylesheet' href='a.css'>\
+                 <link rel='stylesheet' href='missing.css'>\
+                 <link rel='stylesheet' href='print.css' media='print'>\
+                 <style>div { color: blue; }</style>\
+                 <link rel='stylesheet' href='css/b.css'></head><body></body></html>",
+            ),
+            ("a.css", "p { color: red; }"),
+            ("print.css", "p { color: black; }"),
+            ("css/b.css", "a { color: lime; }"),
+        ],
+    );
+
+    // Every linked stylesheet applies in document order, except those that can't be fetched and
+    // those for print.
+    let document = Document::new();
+    update_html_tree_and_stylesheet(&document, format!("{}a.html", dir)).unwrap();
+    assert_eq!(
+        *document.stylesheet.borrow(),
+        Some(css::parse(
+            "p { color: red; } div { color: blue; } a { color: lime; }".to_string()
+        ))
+    );
+}
+
+#[test]
+fn test_file_names_with_spaces() {
+    let dir = write_test_files(
+        "naglfar_spaces",
+        &[
+            (
+                "my page.html",
+                "<html><head><link rel='stylesheet' href='my%20style.css'></head></html>",
+            ),
+            ("my style.css", "p { color: red; }"),
+        ],
+    );
+
+    // The page and its stylesheet are read from the files with the decoded names.
+    let document = Document::new();
+    update_html_tree_and_stylesheet(&document, format!("{}my page.html", dir)).unwrap();
+    assert_eq!(
+        *document.stylesheet.borrow(),
+        Some(css::parse("p { color: red; }".to_string()))
+    );
+    assert_eq!(
+        download(&document, "my style.css").unwrap().1,
+        ::std::env::temp_dir().join("naglfar_spaces/my style.css")
+    );
+}
+
+#[test]
+fn test_import() {
+    let mut files = vec![
+        (
+            "a.html".to_string(),
+            "<html><head><link rel='stylesheet' href='a.css'>\
+             <style>@import 'css/c.css'; div { color: red; }</style></head></html>"
+                .to_string(),
+        ),
+        (
+            "a.css".to_string(),
+            "@import url(css/b.css); @import 'missing.css'; a { color: red; }".to_string(),
+        ),
+        // b.css imports a.css back, and c.css imports itself.
+        (
+            "css/b.css".to_string(),
+            "@import '../a.css'; b { color: red; }".to_string(),
+        ),
+        ("css/c.css".to_string(), "@import 'c.css'; c { color: red; }".to_string()),
+        (
+            "deep.html".to_string(),
+            "<html><head><link rel='stylesheet' href='0.css'></head></html>".to_string(),
+        ),
+    ];
+    // A chain of stylesheets, each importing the next.
+    for i in 0..12 {
+        files.push((
+            format!("{}.css", i),
+            format!("@import '{}.css'; p{} {{ color: red; }}", i + 1, i),
+        ));
+    }
+    let files: Vec<(&str, &str)> = files
+        .iter()
+        .map(|&(ref name, ref content)| (name.as_str(), content.as_str()))
+        .collect();
+    let dir = write_test_files("naglfar_import", &files);
+
+    // The rules of an imported stylesheet come where it's imported. Cycles are broken, and missing
+    // stylesheets are skipped.
+    let document = Document::new();
+    update_html_tree_and_stylesheet(&document, format!("{}a.html", dir)).unwrap();
+    assert_eq!(
+        *document.stylesheet.borrow(),
+        Some(css::parse(
+            "b { color: red; } a { color: red; } c { color: red; } div { color: red; }"
+                .to_string()
+        ))
+    );
+
+    // Only 8 levels of imports are followed.
+    update_html_tree_and_stylesheet(&document, format!("{}deep.html", dir)).unwrap();
+    let rules = (0..MAX_IMPORT_DEPTH + 1)
+        .rev()
+        .map(|i| format!("p{} {{ color: red; }}", i))
+        .collect::<Vec<String>>()
+        .join(" ");
+    assert_eq!(*document.stylesheet.borrow(), Some(css::parse(rules)));
+}
+
+#[test]
+fn test_resolve_url() {
+    let document = Document::new();
+    let resolve = |url_str: &str| resolve_url(&document, url_str).unwrap().into_string();
+
+    // Without a current document, only absolute URLs are valid.
+    *document.url.borrow_mut() = None;
+    assert!(resolve_url(&document, "page2.html").is_err());
+    assert_eq!(resolve("file:///site/a.html"), "file:///site/a.html");
+
+    *document.url.borrow_mut() = Some("file:///site/dir/index.html".to_string());
+    assert_eq!(resolve("page2.html"), "file:///site/dir/page2.html");
+    assert_eq!(resolve("./page2.html"), "file:///site/dir/page2.html");
+    assert_eq!(resolve("../page2.html"), "file:///site/page2.html");
+    assert_eq!(resolve("/page2.html"), "file:///page2.html");
+    assert_eq!(resolve("http://example.com/"), "http://example.com/");
+
+    *document.url.borrow_mut() = Some("http://example.com/a/b.html".to_string());
+    assert_eq!(resolve("c.html"), "http://example.com/a/c.html");
+    assert_eq!(resolve("../c.html"), "http://example.com/c.html");
+    assert_eq!(resolve("/c.html"), "http://example.com/c.html");
+
+    // An absolute href in a parsed document is left as it is.
+    let html = "<html><body><a href='http://example.org/x.html'>x</a></body></html>";
+    let html_tree = html::parse(html.to_string());
+    let href = html_tree.find_first_node_by_tag_name("a").unwrap().anker_url().unwrap();
+    assert_eq!(resolve(href), "http://example.org/x.html");
+}
+
+#[test]
+fn test_same_document_fragment() {
+    let document = Document::new();
+    *document.url.borrow_mut() = Some("file:///site/a.html#top".to_string());
+    assert_eq!(same_document_fragment(&document, "#section2"), Some("section2".to_string()));
+    let fragment = |url_str: &str| same_document_fragment(&document, url_str);
+    assert_eq!(fragment("a.html#section2"), Some("section2".to_string()));
+    assert_eq!(fragment("a.html"), None);
+    assert_eq!(fragment("b.html#section2"), None);
+    assert_eq!(url_fragment(&document, "b.html#section2"), Some("section2".to_string()));
+    assert_eq!(url_fragment(&document, "b.html"), None);
+}
+
+#[test]
+fn test_navigation_clears_ankers() {
+    use layout::Rect;
+
+    let dir = write_test_files(
+        "naglfar_ankers",
+        &[
+            ("a.html", "<html><body><a href='b.html'>link</a></body></html>"),
+            ("b.html", "<html><body>no links</body></html>"),
+        ],
+    );
+
+    let document = Document::new();
+    update_html_tree_and_stylesheet(&document, format!("{}a.html", dir)).unwrap();
+    document
+        .ankers
+        .borrow_mut()
+        .insert(Rect::default(), window::AnkerKind::URL("b.html".to_string()));
+
+    // The links of page A are gone after navigating to page B.
+    update_html_tree_and_stylesheet(&document, format!("{}b.html", dir)).unwrap();
+    assert!(document.ankers.borrow().is_empty());
+}
+
+/// An HTTP response with `status` and `headers`, which are terminated with "\r\n". For tests.
+#[cfg(test)]
+fn http_response(status: &str, headers: &str, body: &[u8]) -> Vec<u8> {
+    let mut response = format!(
+        "HTTP/1.1 {}\r\n{}Content-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        headers,
+        body.len()
+    ).into_bytes();
+    response.extend_from_slice(body);
+    response
+}
+
+/// Start a mock HTTP server that returns `responses` in order. Returns its URL, ending with a
+/// slash, and the receiver of the requests, in lowercase. For tests.
+#[cfg(test)]
+fn mock_http_server(responses: Vec<Vec<u8>>) -> (String, mpsc::Receiver<String>) {
+    use std::net::TcpListener;
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/", listener.local_addr().unwrap());
+    let (sender, requests) = mpsc::channel();
+    thread::spawn(move || {
+        for response in responses {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0; 4096];
+            let len = stream.read(&mut request).unwrap();
+            let _ = sender.send(String::from_utf8_lossy(&request[..len]).to_lowercase());
+            stream.write_all(&response).unwrap();
+        }
+    });
+    (url, requests)
+}
+
+#[test]
+fn test_http() {
+    let (server, requests) = mock_http_server(vec![
+        http_response(
+            "200 OK",
+            "Content-Type: text/html; charset=iso-8859-1\r\n",
+            b"<html><head><title>caf\xe9</title></head></html>",
+        ),
+        http_response("404 Not Found", "Content-Type: text/html\r\n", b"not found"),
+        http_response("404 Not Found", "Content-Type: text/html\r\n", b"not found"),
+    ]);
+    let url = format!("{}index.html", server);
+
+    // The document is decoded with the charset.
+    let (html_source, final_url) = fetch_text(&Url::parse(url.as_str()).unwrap()).unwrap();
+    assert!(html_source.contains("café"));
+    assert_eq!(final_url.as_str(), url);
+    let request = requests.recv().unwrap();
+    assert!(request.starts_with("get /index.html "));
+    assert!(request.contains(&format!("user-agent: {}", USER_AGENT.to_lowercase())));
+
+    assert!(fetch_text(&Url::parse(url.as_str()).unwrap()).is_err());
+
+    // A page that can't be loaded is replaced with an error page.
+    let document = Document::new();
+    update_html_tree_and_stylesheet(&document, url.clone()).unwrap();
+    assert_eq!(current_url(&document), Some(url));
+    let html_tree = document.html_tree.borrow().clone().unwrap();
+    assert!(format!("{}", html_tree).contains("Cannot load the page"));
+}
+
+#[test]
+fn test_display_list_cache() {
+    let document = Document::new();
+    let builds = Cell::new(0);
+    let build = || {
+        builds.set(builds.get() + 1);
+        vec![]
+    };
+    let (width, height) = (Au::from_f64_px(800.0), Au::from_f64_px(600.0));
+
+    // Drawing the unchanged document again, e.g. when scrolling, replays the display list.
+    document.display_list(width, height, 1.0, &build);
+    document.display_list(width, height, 1.0, &build);
+    assert_eq!(builds.get(), 1);
+
+    // It's built again when the document, the viewport or the zoom changes.
+    document.invalidate();
+    document.display_list(width, height, 1.0, &build);
+    assert_eq!(builds.get(), 2);
+    document.display_list(width / 2, height, 1.0, &build);
+    assert_eq!(builds.get(), 3);
+    document.display_list(width / 2, height, 1.5, &build);
+    assert_eq!(builds.get(), 4);
+}
+
+#[test]
+fn test_background_navigation() {
+    let dir = write_test_files(
+        "naglfar_background",
+        &[
+            ("a.html", "<html><body>a</body></html>"),
+            ("b.html", "<html><body>b</body></html>"),
+            ("c.html", "<html><body>c</body></html>"),
+        ],
+    );
+    let page = |name: &str| format!("{}{}", dir, name);
+    let document = Rc::new(Document::new());
+    update_html_tree_and_stylesheet(&document, page("a.html")).unwrap();
+
+    // Pages are fetched and parsed on a worker thread.
+    let fetch = |name: &str| {
+        let url = Url::parse(&page(name)).unwrap();
+        thread::spawn(move || fetch_page(url)).join().unwrap()
+    };
+    let (b, c) = (fetch("b.html"), fetch("c.html"));
+    assert_eq!(current_url(&document), Some(page("a.html")));
+
+    // b is discarded, because the navigation to c started after it.
+    let b_id = document.begin_navigation();
+    let c_id = document.begin_navigation();
+    let b_url = Url::parse(&page("b.html")).unwrap();
+    let c_url = Url::parse(&page("c.html")).unwrap();
+    let visit_b = Navigation::Visit(page("b.html"));
+    let visit_c = Navigation::Visit(page("c.html"));
+    assert_eq!(receive_page(&document, b_id, &visit_b, b_url, b), None);
+    assert_eq!(current_url(&document), Some(page("a.html")));
+    assert_eq!(receive_page(&document, c_id, &visit_c, c_url, c), Some(Ok(true)));
+    assert_eq!(current_url(&document), Some(page("c.html")));
+    assert_eq!(document.history.borrow().back, vec![page("a.html")]);
+
+    // A page that can't be loaded is shown as an error page.
+    let missing = Url::parse(&page("missing.html")).unwrap();
+    let id = document.navigation.get();
+    let visit = Navigation::Visit(page("missing.html"));
+    let result = receive_page(&document, id, &visit, missing.clone(), fetch_page(missing));
+    assert_eq!(result, Some(Ok(true)));
+    assert_eq!(current_url(&document), Some(page("missing.html")));
+
+    // A navigation that fails to start discards the page loading for an earlier one too.
+    let a_id = document.begin_navigation();
+    let a_url = Url::parse(&page("a.html")).unwrap();
+    let visit_a = Navigation::Visit(page("a.html"));
+    let failed = Rc::new(Cell::new(false));
+    let started = {
+        let failed = failed.clone();
+        let visit = Navigation::Visit("http://[oops/".to_string());
+        navigate(&document, visit, move |result| failed.set(result.is_err()))
+    };
+    assert!(!started && failed.get());
+    assert_eq!(receive_page(&document, a_id, &visit_a, a_url, fetch("a.html")), None);
+    assert_eq!(current_url(&document), Some(page("missing.html")));
+}
+
+#[test]
+fn test_http_redirects() {
+    let redirect = |status: &str, location: &str| {
+        http_response(status, &format!("Location: {}\r\n", location), b"")
+    };
+    let mut responses = vec![
+        redirect("301 Moved Permanently", "/b/"),
+        redirect("307 Temporary Redirect", "c.html"),
+        http_response(
+            "200 OK",
+            "Content-Type: text/html\r\n",
+            b"<html><body><a href='d.html'>d</a></body></html>",
+        ),
+    ];
+    // A loop between a and b.
+    for i in 0..MAX_REDIRECTS + 1 {
+        responses.push(redirect("302 Found", if i % 2 == 0 { "/b" } else { "/a" }));
+    }
+    let (server, _requests) = mock_http_server(responses);
+
+    // The document is at the URL redirected to, and links resolve against it.
+    let document = Document::new();
+    update_html_tree_and_stylesheet(&document, format!("{}a.html#top", server)).unwrap();
+    assert_eq!(current_url(&document), Some(format!("{}b/c.html#top", server)));
+    assert_eq!(resolve_url(&document, "d.html").unwrap().as_str(), format!("{}b/d.html", server));
+
+    // A redirect loop gives up.
+    let error = fetch_text(&Url::parse(&format!("{}a", server)).unwrap()).unwrap_err();
+    assert!(error.contains("redirects"));
+}
+
+#[test]
+fn test_resolve_against() {
+    // The examples of RFC 3986, 5.4.
+    let base = Some("http://a/b/c/d;p?q");
+    let resolve = |url_str: &str| resolve_against(base, url_str).unwrap().into_string();
+    assert_eq!(resolve("g:h"), "g:h");
+    assert_eq!(resolve("g"), "http://a/b/c/g");
+    assert_eq!(resolve("./g"), "http://a/b/c/g");
+    assert_eq!(resolve("g/"), "http://a/b/c/g/");
+    assert_eq!(resolve("/g"), "http://a/g");
+    assert_eq!(resolve("//g"), "http://g/");
+    assert_eq!(resolve("?y"), "http://a/b/c/d;p?y");
+    assert_eq!(resolve("g?y"), "http://a/b/c/g?y");
+    assert_eq!(resolve("#s"), "http://a/b/c/d;p?q#s");
+    assert_eq!(resolve("g?y#s"), "http://a/b/c/g?y#s");
+    assert_eq!(resolve(";x"), "http://a/b/c/;x");
+    assert_eq!(resolve(""), "http://a/b/c/d;p?q");
+    assert_eq!(resolve("."), "http://a/b/c/");
+    assert_eq!(resolve(".."), "http://a/b/");
+    assert_eq!(resolve("../g"), "http://a/b/g");
+    assert_eq!(resolve("../.."), "http://a/");
+    assert_eq!(resolve("../../../g"), "http://a/g");
+    assert_eq!(resolve("g/../h"), "http://a/b/c/h");
+
+    let base = Some("file:///home/me/site/index.html");
+    assert_eq!(
+        resolve_against(base, "about.html").unwrap().as_str(),
+        "file:///home/me/site/about.html"
+    );
+    assert!(resolve_against(None, "about.html").is_err());
+}
+
+#[test]
+fn test_base_element() {
+    let dir = write_test_files(
+        "naglfar_base",
+        &[
+            (
+                "a.html",
+                "<html><head><base href='sub/'><link rel='stylesheet' href='a.css'></head>\
+                 <body><a href='b.html'>b</a></body></html>",
+            ),
+            ("sub/a.css", "p { color: red; }"),
+            ("c.html", "<html><body>c</body></html>"),
+        ],
+    );
+
+    // The stylesheet and the links resolve against <base>.
+    let document = Document::new();
+    update_html_tree_and_stylesheet(&document, format!("{}a.html", dir)).unwrap();
+    assert_eq!(current_url(&document), Some(format!("{}a.html", dir)));
+    let resolve = |url_str: &str| resolve_url(&document, url_str).unwrap().into_string();
+    assert_eq!(resolve("b.html"), format!("{}sub/b.html", dir));
+    assert_eq!(
+        *document.stylesheet.borrow(),
+        Some(css::parse("p { color: red; }".to_string()))
+    );
+
+    // Documents without <base> resolve against their URL.
+    update_html_tree_and_stylesheet(&document, format!("{}c.html", dir)).unwrap();
+    assert_eq!(resolve("b.html"), format!("{}b.html", dir));
+}
+
+#[test]
+fn test_anker_targets() {
+    use window::AnkerKind;
+
+    let dir = write_test_files(
+        "naglfar_anker_targets",
+        &[
+            (
+                "a.html",
+                "<html><head><base href='http://example.com/'></head>\
+                 <body><a href=''>self</a> <a href='#top'>top</a></body></html>",
+            ),
+        ],
+    );
+    let mut targets = with_page_layout(format!("{}a.html", dir), 300.0, |document, layout_root| {
+        painter::build_display_list(document, layout_root, false);
+        document
+            .ankers
+            .borrow()
+            .values()
+            .map(|anker| match *anker {
+                AnkerKind::URL(ref url) => url.clone(),
+                AnkerKind::URLFragment(ref id) => format!("#{}", id),
+            })
+            .collect::<Vec<String>>()
+    }).unwrap();
+    targets.sort();
+
+    // An empty href is the document itself rather than <base>.
+    assert_eq!(targets, vec!["#top".to_string(), format!("{}a.html", dir)]);
+}
+
+#[test]
+fn test_parsed_urls() {
+    // The src of <img> and the href of <a> in `html` at file:///site/dir/index.html, resolved.
+    let resolve_attrs = |html: &str| {
+        let url = Url::parse("file:///site/dir/index.html").unwrap();
+        let html_tree = html::parse(html.to_string());
+        let document = Document::new();
+        let stylesheet = css::Stylesheet { rules: vec![] };
+        set_document(&document, url, html.to_string(), html_tree, stylesheet);
+
+        let html_tree = document.html_tree.borrow();
+        let html_tree = html_tree.as_ref().unwrap();
+        let resolve = |url_str: Option<&String>| {
+            resolve_url(&document, url_str.unwrap()).unwrap().into_string()
+        };
+        (
+            resolve(html_tree.find_first_node_by_tag_name("img").unwrap().image_url()),
+            resolve(html_tree.find_first_node_by_tag_name("a").unwrap().anker_url()),
+        )
+    };
+
+    assert_eq!(
+        resolve_attrs(
+            "<html><body><img src='http://cdn.example.com/a.png'><a href='?q=1'>a</a></body></html>"
+        ),
+        (
+            "http://cdn.example.com/a.png".to_string(),
+            "file:///site/dir/index.html?q=1".to_string()
+        )
+    );
+    assert_eq!(
+        resolve_attrs(
+            "<html><head><base href='http://cdn.example.com/lib/'></head>\
+             <body><img src='a.png'><a href='?q=1'>a</a></body></html>"
+        ),
+        (
+            "http://cdn.example.com/lib/a.png".to_string(),
+            "http://cdn.example.com/lib/?q=1".to_string()
+        )
+    );
+}
+
+#[test]
+fn test_image_loading() {
+    use painter::DisplayCommand;
+
+    let png = include_bytes!("../example/image2.png");
+    let (server, requests) = mock_http_server(vec![
+        http_response("200 OK", "Content-Type: image/png\r\n", png),
+        http_response("200 OK", "Content-Type: image/png\r\n", png),
+    ]);
+    let html = format!(
+        "<html><body><img src='{0}a.png'><img src='{0}./a.png'>\
+         <img src='missing.png' alt='gone' width='100' height='20'></body></html>",
+        server
+    );
+    let dir = write_test_files("naglfar_images", &[("index.html", html.as_str())]);
+    let items = render_page(format!("{}index.html", dir), 400.0).unwrap();
+
+    // The absolute src is fetched from the server as it is, and the same image only once.
+    let requests: Vec<String> = requests.try_iter().collect();
+    assert_eq!(requests.len(), 1);
+    assert!(requests[0].starts_with("get /a.png "));
+    let images = items
+        .iter()
+        .filter(|item| match item.command {
+            DisplayCommand::Image(_, _) => true,
+            _ => false,
+        })
+        .count();
+    assert_eq!(images, 2);
+
+    // A missing image shows the alt text in the box of its size.
+    let alt = items.iter().find(|item| match item.command {
+        DisplayCommand::Text(ref text, _, _, _, _) => text == "gone",
+        _ => false,
+    });
+    assert!(alt.is_some());
+    assert!(items.iter().any(|item| match item.command {
+        DisplayCommand::ClipPush(rect, _) => {
+            rect.width == Au::from_f64_px(100.0) && rect.height == Au::from_f64_px(20.0)
+        }
+        _ => false,
+    }));
+}
+
+#[test]
+fn test_data_url() {
+    use gdk_pixbuf::{self, PixbufExt};
+
+    let decode = |url: &str| decode_data_url(&Url::parse(url).unwrap());
+
+    assert_eq!(
+        decode("data:,A%20brief%20note"),
+        Ok((
+            b"A brief note".to_vec(),
+            "text/plain;charset=US-ASCII".to_string()
+        ))
+    );
+    assert_eq!(
+        decode("data:text/css;charset=utf-8,p%7Bcolor:red%7D#fragment"),
+        Ok((b"p{color:red}".to_vec(), "text/css;charset=utf-8".to_string()))
+    );
+    assert_eq!(
+        decode("data:;charset=utf-8;base64,SGVs bG8="),
+        Ok((b"Hello".to_vec(), "text/plain;charset=utf-8".to_string()))
+    );
+    assert_eq!(
+        decode("data:image/png;BASE64,AAEC%2F%2Fw"),
+        Ok((vec![0, 1, 2, 255, 255], "image/png".to_string()))
+    );
+    assert!(decode("data:image/png;base64,A*==").is_err());
+    assert!(decode("data:text/plain").is_err());
+
+    // Images and stylesheets are read from the decoded data.
+    let css = Url::parse("data:text/css,p%20%7B%20color:%20red;%20%7D").unwrap();
+    assert_eq!(fetch_text(&css).unwrap().0, "p { color: red; }");
+    let png = Url::parse(
+        "data:image/png;base64,iVBORw0KGgoAAAANSUhEUgAAABQAAAAKCAIAAAA7N+mxAAAAFUlEQVR42mP4z8BANmI\
+         Y1TyqmRACADFExzlExWpRAAAAAElFTkSuQmCC",
+    ).unwrap();
+    let (file_name, _) = fetch(&png).unwrap();
+    assert!(file_name.ends_with(".png"));
+    let pixbuf = gdk_pixbuf::Pixbuf::new_from_file(file_name.as_str()).unwrap();
+    assert_eq!((pixbuf.get_width(), pixbuf.get_height()), (20, 10));
+}
+
+#[test]
+fn test_data_url_page() {
+    use painter::DisplayCommand;
+
+    // A stylesheet and an image given as data: URLs in the document.
+    let dir = write_test_files(
+        "naglfar_data_url",
+        &[
+            (
+                "index.html",
+                "<html><head><link rel='stylesheet' href='data:text/css,div%20%7B%20\
+                 height:%2010px;%20background-color:%20green;%20%7D'></head><body><div></div>\
+                 <img src='data:image/png;base64,iVBORw0KGgoAAAANSUhEUgAAABQAAAAKCAIAAAA7N+mxAAAA\
+                 FUlEQVR42mP4z8BANmIY1TyqmRACADFExzlExWpRAAAAAElFTkSuQmCC'></body></html>",
+            ),
+        ],
+    );
+    let items = render_page(format!("{}index.html", dir), 400.0).unwrap();
+
+    assert!(items.iter().any(|item| match item.command {
+        DisplayCommand::SolidColor(color, rect, _) => {
+            color == css::GREEN && rect.height == Au::from_f64_px(10.0)
+        }
+        _ => false,
+    }));
+    assert!(items.iter().any(|item| match item.command {
+        DisplayCommand::Image(_, rect) => {
+            rect.width == Au::from_f64_px(20.0) && rect.height == Au::from_f64_px(10.0)
+        }
+        _ => false,
+    }));
 }
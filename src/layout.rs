@@ -1,13 +1,18 @@
 use style::{Display, StyledNode};
-use dom::{LayoutType, NodeType};
+use css::Value;
+use dom::{LayoutType, Node, NodeType};
 use float::Floats;
 use font::{Font, FontSlant, FontWeight};
 use inline::LineMaker;
+use interface::Document;
 use style;
 
+use std::cmp::max;
+use std::collections::HashMap;
 use std::default::Default;
 use std::fmt;
 use std::ops::Range;
+use std::rc::Rc;
 
 use cairo;
 use pango;
@@ -44,6 +49,15 @@ pub struct EdgeSizes {
     pub bottom: Au,
 }
 
+/// The radii of the corners of a box. Zero means a square corner.
+#[derive(Clone, Copy, Default, Debug, PartialEq)]
+pub struct CornerRadii {
+    pub top_left: Au,
+    pub top_right: Au,
+    pub bottom_right: Au,
+    pub bottom_left: Au,
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub enum LayoutInfo {
     Generic,
@@ -53,12 +67,23 @@ pub enum LayoutInfo {
     Button(Option<gtk::Button>, usize),
 }
 
+// The marker of a list item (<li>).
+#[derive(Clone, Debug, PartialEq)]
+pub enum ListMarker {
+    Disc,
+    Circle,
+    Square,
+    Decimal(usize),
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub enum BoxType {
     BlockNode,
     InlineNode,
     InlineBlockNode,
     Float,
+    // An absolutely positioned box, which is out of the flow.
+    Absolute,
     TextNode(Text),
     AnonymousBlock,
 }
@@ -68,6 +93,9 @@ pub enum BoxType {
 pub struct LayoutBox<'a> {
     pub dimensions: Dimensions,
     pub z_index: i32,
+    // The opacity of the box and its descendants as a group.
+    pub opacity: f64,
+    pub marker: Option<ListMarker>,
     pub box_type: BoxType,
     pub info: LayoutInfo,
     pub floats: Floats,
@@ -94,6 +122,8 @@ impl<'a> LayoutBox<'a> {
             style: style,
             info: info,
             z_index: 0,
+            opacity: 1.0,
+            marker: None,
             floats: Floats::new(),
             dimensions: Default::default(),
             children: Vec::new(),
@@ -107,6 +137,33 @@ impl<'a> LayoutBox<'a> {
         }
     }
 
+    /// Whether the descendants of this box are clipped to its padding box.
+    pub fn clips_children(&self) -> bool {
+        match self.box_type {
+            BoxType::BlockNode | BoxType::InlineBlockNode | BoxType::Float | BoxType::Absolute => {
+                self.style
+                    .map_or(false, |style| style.overflow() == style::Overflow::Hidden)
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether the box is relatively or absolutely positioned. Positioned boxes are painted after
+    /// the in-flow content of their stacking context, ordered by z-index.
+    pub fn is_positioned(&self) -> bool {
+        match self.box_type {
+            BoxType::TextNode(_) | BoxType::AnonymousBlock => false,
+            _ => self.style
+                .map_or(false, |style| style.position() != style::Position::Static),
+        }
+    }
+
+    /// Whether the box paints its positioned descendants itself: positioned boxes, and boxes
+    /// composited as a group.
+    pub fn establishes_stacking_context(&self) -> bool {
+        self.is_positioned() || self.opacity < 1.0
+    }
+
     pub fn set_text_info(&mut self, font: Font, range: Range<usize>) {
         if let BoxType::TextNode(ref mut r) = self.box_type {
             r.font = font;
@@ -115,26 +172,45 @@ impl<'a> LayoutBox<'a> {
     }
 }
 
-/// Transform a style tree into a layout tree.
+/// Transform a style tree into a layout tree. Images and buttons are those of `document`.
 pub fn layout_tree<'a>(
     node: &'a StyledNode<'a>,
     mut containing_block: Dimensions,
+    document: &Rc<Document>,
 ) -> LayoutBox<'a> {
-    // Save the initial containing block height for calculating percent heights.
-    let saved_block = containing_block.clone();
+    // The initial containing block has the height of the viewport, against which percent heights
+    // are resolved.
+    let containing_height = Some(containing_block.content.height);
     let viewport = containing_block.clone();
     // The layout algorithm expects the container height to start at 0.
     containing_block.content.height = Au::from_f64_px(0.0);
 
     let mut id = 0;
     let mut root_box = build_layout_tree(node, &mut id);
+    // There is nothing for the root to be positioned in.
+    if root_box.box_type == BoxType::Absolute {
+        root_box.box_type = BoxType::BlockNode;
+    }
     root_box.layout(
         &mut Floats::new(),
         Au(0),
         containing_block,
-        saved_block,
+        containing_height,
         viewport,
+        document,
     );
+    // Absolutely positioned boxes are positioned in the viewport unless they have a positioned
+    // ancestor.
+    let initial_containing_block = Rect {
+        x: -root_box.dimensions.content.x,
+        y: -root_box.dimensions.content.y,
+        width: viewport.content.width,
+        height: viewport.content.height,
+    };
+    root_box.layout_absolute_boxes(initial_containing_block, viewport, document);
+    // The initial containing block keeps the height of the viewport for `top` and `bottom`.
+    containing_block.content.height = viewport.content.height;
+    root_box.apply_relative_offsets(containing_block.content, true);
     root_box
 }
 
@@ -163,22 +239,55 @@ fn build_layout_tree<'a>(style_node: &'a StyledNode<'a>, id: &mut usize) -> Layo
             LayoutType::Text => LayoutInfo::Text,
             LayoutType::Image => LayoutInfo::Image(None),
             LayoutType::Anker => LayoutInfo::Anker,
+            // Buttons are GTK widgets. Without GTK (e.g. in headless screenshots), their labels
+            // are laid out as text.
+            LayoutType::Button if !gtk::is_initialized() => LayoutInfo::Generic,
             LayoutType::Button => LayoutInfo::Button(None, *id),
         },
     );
 
+    root.z_index = style_node.z_index();
+    root.opacity = style_node.opacity();
+
     match style_node.float() {
         style::FloatType::None => {}
         style::FloatType::Left | style::FloatType::Right => root.box_type = BoxType::Float,
     }
+    // Absolute positioning wins over floating.
+    if is_absolutely_positioned(style_node) {
+        root.box_type = BoxType::Absolute;
+    }
 
     // Create the descendant boxes.
     let mut float_insert_point: Option<usize> = None;
+    let mut list_item_count = 0;
     for (i, child) in style_node.children.iter().enumerate() {
         *id += i;
         match (child.display(), child.float()) {
+            // Elements with `display: none` and their descendants generate no boxes, even if
+            // they are floated.
+            (Display::None, _) => {}
+            // Absolutely positioned boxes stay among the inline content around them, so that it
+            // isn't split into two anonymous blocks.
+            _ if is_absolutely_positioned(child) => {
+                let child_box = build_layout_tree(child, id);
+                let in_line = match root.children.last() {
+                    Some(&LayoutBox {
+                        box_type: BoxType::AnonymousBlock,
+                        ..
+                    }) => true,
+                    _ => root.box_type == BoxType::InlineNode,
+                };
+                if in_line {
+                    root.get_inline_container().children.push(child_box);
+                } else {
+                    root.children.push(child_box);
+                }
+            }
             (Display::Block, style::FloatType::None) => {
-                root.children.push(build_layout_tree(child, id));
+                let mut child_box = build_layout_tree(child, id);
+                child_box.marker = list_marker(child, &mut list_item_count);
+                root.children.push(child_box);
                 if float_insert_point.is_some() {
                     float_insert_point = None;
                 }
@@ -197,53 +306,88 @@ fn build_layout_tree<'a>(style_node: &'a StyledNode<'a>, id: &mut usize) -> Layo
                     root.children.push(build_layout_tree(child, id));
                 }
             }
-            (Display::None, _) => {} // Don't lay out nodes with `display: none;`
         }
     }
 
     root
 }
 
+/// Whether `style_node` is an absolutely positioned element. The text in an inline element has the
+/// same style, but isn't positioned by itself.
+fn is_absolutely_positioned(style_node: &StyledNode) -> bool {
+    match style_node.node.data {
+        NodeType::Element(_) => style_node.position() == style::Position::Absolute,
+        NodeType::Text(_) => false,
+    }
+}
+
+/// Return the marker of `style_node` if it's a list item. `count` is the number of the list items
+/// seen so far among its siblings, so each <ol> restarts numbering.
+fn list_marker(style_node: &StyledNode, count: &mut usize) -> Option<ListMarker> {
+    if !style_node.is_list_item() {
+        return None;
+    }
+
+    *count += 1;
+    match style_node.list_style_type() {
+        style::ListStyleType::None => None,
+        style::ListStyleType::Disc => Some(ListMarker::Disc),
+        style::ListStyleType::Circle => Some(ListMarker::Circle),
+        style::ListStyleType::Square => Some(ListMarker::Square),
+        style::ListStyleType::Decimal => Some(ListMarker::Decimal(*count)),
+    }
+}
+
 impl<'a> LayoutBox<'a> {
     /// Lay out a box and its descendants.
-    /// `saved_block` is used to know the maximum width/height of the box, calculate the percent
-    /// width/height and so on.
+    /// `containing_height` is the height of the containing block to resolve percent heights
+    /// against. None if it depends on the content, in which case percent heights behave as `auto`.
     pub fn layout(
         &mut self,
         floats: &mut Floats,
         last_margin_bottom: Au,
         containing_block: Dimensions,
-        saved_block: Dimensions,
+        containing_height: Option<Au>,
         viewport: Dimensions,
+        document: &Rc<Document>,
     ) {
         match self.box_type {
             BoxType::BlockNode => self.layout_block(
                 floats,
                 last_margin_bottom,
                 containing_block,
-                saved_block,
+                containing_height,
                 viewport,
+                document,
             ),
             BoxType::InlineBlockNode => self.layout_inline_block(
                 floats,
                 last_margin_bottom,
                 containing_block,
-                saved_block,
+                containing_height,
                 viewport,
+                document,
             ),
             BoxType::Float => self.layout_float(
                 floats,
                 last_margin_bottom,
                 containing_block,
-                saved_block,
+                containing_height,
                 viewport,
+                document,
             ),
+            // Out of the flow. Only the static position is noted here, and the box is laid out by
+            // `layout_absolute_boxes` after the flow.
+            BoxType::Absolute => {
+                self.dimensions.content.x = Au(0);
+                self.dimensions.content.y = containing_block.content.height;
+            }
             BoxType::AnonymousBlock => {
                 self.dimensions.content.x = Au::from_f64_px(0.0);
                 self.dimensions.content.y = containing_block.content.height;
 
                 let mut linemaker = LineMaker::new(self.children.clone(), floats.clone());
-                linemaker.run(containing_block.content.width, containing_block);
+                linemaker.run(containing_block.content.width, containing_block, document);
                 linemaker.end_of_lines();
                 linemaker.assign_position(containing_block.content.width);
 
@@ -256,11 +400,58 @@ impl<'a> LayoutBox<'a> {
         }
     }
 
+    /// Shift the relatively positioned boxes in the subtree by their offsets, after their space in
+    /// the flow has been taken up. Their descendants move with them, since they are relative to
+    /// the parent. `containing_block` is the content box of the containing block, whose height
+    /// is used for percentages only if `height_specified`.
+    /// ref. https://www.w3.org/TR/CSS2/visuren.html#relative-positioning
+    pub fn apply_relative_offsets(&mut self, containing_block: Rect, height_specified: bool) {
+        let relative = match self.box_type {
+            // The text inside an inline element has its style, but moves with it.
+            BoxType::TextNode(_) | BoxType::AnonymousBlock => None,
+            _ => self.style.filter(|style| style.position() == style::Position::Relative),
+        };
+        if let Some(style) = relative {
+            let width = Some(containing_block.width.to_f64_px());
+            let height = if height_specified {
+                Some(containing_block.height.to_f64_px())
+            } else {
+                None
+            };
+            // `left` wins over `right`, and `top` wins over `bottom`.
+            let dx = style
+                .offset("left", width)
+                .or_else(|| style.offset("right", width).map(|right| -right))
+                .unwrap_or(0.0);
+            let dy = style
+                .offset("top", height)
+                .or_else(|| style.offset("bottom", height).map(|bottom| -bottom))
+                .unwrap_or(0.0);
+            self.dimensions.content.x += Au::from_f64_px(dx);
+            self.dimensions.content.y += Au::from_f64_px(dy);
+        }
+
+        // Anonymous blocks and inline boxes aren't containing blocks.
+        let (containing_block, height_specified) = match self.box_type {
+            BoxType::BlockNode
+            | BoxType::InlineBlockNode
+            | BoxType::Float
+            | BoxType::Absolute => (
+                self.dimensions.content,
+                self.specified_height(None).is_some(),
+            ),
+            _ => (containing_block, height_specified),
+        };
+        for child in &mut self.children {
+            child.apply_relative_offsets(containing_block, height_specified);
+        }
+    }
+
     /// Where a new inline child should go.
     fn get_inline_container(&mut self) -> &mut LayoutBox<'a> {
         match self.box_type {
             BoxType::InlineNode | BoxType::AnonymousBlock => self,
-            BoxType::Float | BoxType::BlockNode | BoxType::InlineBlockNode => {
+            BoxType::Float | BoxType::BlockNode | BoxType::InlineBlockNode | BoxType::Absolute => {
                 match self.children.last() {
                     Some(&LayoutBox {
                         box_type: BoxType::AnonymousBlock,
@@ -278,15 +469,19 @@ impl<'a> LayoutBox<'a> {
         }
     }
 
-    pub fn assign_padding(&mut self) {
+    /// Percentages are resolved against the width of `containing_block`.
+    pub fn assign_padding(&mut self, containing_block: Dimensions) {
         let (padding_top, padding_right, padding_bottom, padding_left) =
             self.get_style_node().padding();
+        let cb_width = containing_block.content.width.to_f64_px();
 
         let d = &mut self.dimensions;
-        d.padding.left = Au::from_f64_px(padding_left.to_px().unwrap());
-        d.padding.top = Au::from_f64_px(padding_top.to_px().unwrap());
-        d.padding.bottom = Au::from_f64_px(padding_bottom.to_px().unwrap());
-        d.padding.right = Au::from_f64_px(padding_right.to_px().unwrap());
+        d.padding.left = Au::from_f64_px(padding_left.maybe_percent_to_px(cb_width).unwrap_or(0.0));
+        d.padding.top = Au::from_f64_px(padding_top.maybe_percent_to_px(cb_width).unwrap_or(0.0));
+        d.padding.bottom =
+            Au::from_f64_px(padding_bottom.maybe_percent_to_px(cb_width).unwrap_or(0.0));
+        d.padding.right =
+            Au::from_f64_px(padding_right.maybe_percent_to_px(cb_width).unwrap_or(0.0));
     }
 
     pub fn assign_margin(&mut self) {
@@ -358,6 +553,82 @@ impl Rect {
             height: self.height,
         }
     }
+    /// Scale the position and the size by `zoom`.
+    pub fn zoomed(self, zoom: f64) -> Rect {
+        Rect {
+            x: zoom_au(self.x, zoom),
+            y: zoom_au(self.y, zoom),
+            width: zoom_au(self.width, zoom),
+            height: zoom_au(self.height, zoom),
+        }
+    }
+}
+
+impl EdgeSizes {
+    pub fn zoomed(self, zoom: f64) -> EdgeSizes {
+        EdgeSizes {
+            left: zoom_au(self.left, zoom),
+            right: zoom_au(self.right, zoom),
+            top: zoom_au(self.top, zoom),
+            bottom: zoom_au(self.bottom, zoom),
+        }
+    }
+}
+
+impl CornerRadii {
+    /// The same radius at every corner.
+    pub fn uniform(radius: Au) -> CornerRadii {
+        CornerRadii {
+            top_left: radius,
+            top_right: radius,
+            bottom_right: radius,
+            bottom_left: radius,
+        }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        *self == CornerRadii::default()
+    }
+
+    /// Apply `f` to each radius.
+    pub fn map<F: Fn(Au) -> Au>(self, f: F) -> CornerRadii {
+        CornerRadii {
+            top_left: f(self.top_left),
+            top_right: f(self.top_right),
+            bottom_right: f(self.bottom_right),
+            bottom_left: f(self.bottom_left),
+        }
+    }
+
+    pub fn zoomed(self, zoom: f64) -> CornerRadii {
+        self.map(|radius| zoom_au(radius, zoom))
+    }
+
+    /// The radii of the inner edge of a border of `edges`, e.g. of the padding box.
+    pub fn inner(self, edges: EdgeSizes) -> CornerRadii {
+        let inner = |radius: Au, a: Au, b: Au| max(radius - max(a, b), Au(0));
+        CornerRadii {
+            top_left: inner(self.top_left, edges.top, edges.left),
+            top_right: inner(self.top_right, edges.top, edges.right),
+            bottom_right: inner(self.bottom_right, edges.bottom, edges.right),
+            bottom_left: inner(self.bottom_left, edges.bottom, edges.left),
+        }
+    }
+
+    /// The radii in px, clockwise from the top left.
+    pub fn to_f64_px(&self) -> [f64; 4] {
+        [
+            self.top_left.to_f64_px(),
+            self.top_right.to_f64_px(),
+            self.bottom_right.to_f64_px(),
+            self.bottom_left.to_f64_px(),
+        ]
+    }
+}
+
+/// Scale `au` by `zoom`.
+pub fn zoom_au(au: Au, zoom: f64) -> Au {
+    Au::from_f64_px(au.to_f64_px() * zoom)
 }
 
 impl Dimensions {
@@ -406,14 +677,355 @@ impl Dimensions {
 
 // Functions for displaying
 
-// TODO: Implement all features.
 impl<'a> fmt::Display for LayoutBox<'a> {
-    // TODO: Implement all features
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        writeln!(f, "{:?}", self.dimensions)?;
-        for child in &self.children {
-            write!(f, "{}", child)?;
+        write!(f, "{}", dump_layout(self))
+    }
+}
+
+/// The layout tree of `root` as text, one line per box indented by its depth: the type of the
+/// box, its node, the computed `display` and `position` of elements, and the content, padding,
+/// border and margin boxes in px relative to the document. The output is deterministic, so that
+/// it can be compared in tests.
+pub fn dump_layout(root: &LayoutBox) -> String {
+    let mut out = String::new();
+    dump_layout_box(&mut out, root, Au(0), Au(0), 0);
+    out
+}
+
+fn dump_layout_box(out: &mut String, layout_box: &LayoutBox, x: Au, y: Au, depth: usize) {
+    let rect = |rect: Rect| {
+        let rect = rect.add_parent_coordinate(x, y);
+        format!(
+            "({:.2}, {:.2}, {:.2}x{:.2})",
+            rect.x.to_f64_px(),
+            rect.y.to_f64_px(),
+            rect.width.to_f64_px(),
+            rect.height.to_f64_px()
+        )
+    };
+
+    let box_type = match layout_box.box_type {
+        BoxType::BlockNode => "block",
+        BoxType::InlineNode => "inline",
+        BoxType::InlineBlockNode => "inline-block",
+        BoxType::Float => "float",
+        BoxType::Absolute => "absolute",
+        BoxType::TextNode(_) => "text",
+        BoxType::AnonymousBlock => "anonymous",
+    };
+    let node = match (&layout_box.box_type, layout_box.style) {
+        (&BoxType::AnonymousBlock, _) | (_, None) => String::new(),
+        (&BoxType::TextNode(ref text), Some(style)) => match style.node.data {
+            NodeType::Text(ref body) => format!(" {:?}", &body[text.range.clone()]),
+            NodeType::Element(_) => String::new(),
+        },
+        (_, Some(style)) => match style.node.data {
+            NodeType::Element(ref e) => {
+                let display = match style.display() {
+                    Display::Block if style.is_list_item() => "list-item",
+                    Display::Inline => "inline",
+                    Display::Block => "block",
+                    Display::InlineBlock => "inline-block",
+                    Display::None => "none",
+                };
+                let position = match style.value("position") {
+                    Some(ref values) => match values[0] {
+                        Value::Keyword(ref k) => k.clone(),
+                        _ => "static".to_string(),
+                    },
+                    None => "static".to_string(),
+                };
+                format!(
+                    " <{}> display={} position={}",
+                    e.tag_name, display, position
+                )
+            }
+            NodeType::Text(_) => String::new(),
+        },
+    };
+
+    let d = layout_box.dimensions;
+    out.push_str(&format!(
+        "{}{}{} content={} padding={} border={} margin={}\n",
+        "  ".repeat(depth),
+        box_type,
+        node,
+        rect(d.content),
+        rect(d.padding_box()),
+        rect(d.border_box()),
+        rect(d.margin_box())
+    ));
+
+    for child in &layout_box.children {
+        dump_layout_box(out, child, x + d.content.x, y + d.content.y, depth + 1);
+    }
+}
+
+/// The boxes of an element in px relative to the document, shown by the box model inspector.
+#[derive(Clone, Debug, PartialEq)]
+pub struct InspectedBox {
+    pub content: Rect,
+    pub padding: Rect,
+    pub border: Rect,
+    pub margin: Rect,
+    /// The indices of the children from the root of the DOM tree down to the element.
+    pub path: Vec<usize>,
+}
+
+impl InspectedBox {
+    /// Scale the boxes by `zoom`.
+    pub fn zoomed(&self, zoom: f64) -> InspectedBox {
+        InspectedBox {
+            content: self.content.zoomed(zoom),
+            padding: self.padding.zoomed(zoom),
+            border: self.border.zoomed(zoom),
+            margin: self.margin.zoomed(zoom),
+            path: self.path.clone(),
+        }
+    }
+}
+
+/// The boxes of the elements in the layout tree of `root`, parents before their children. An
+/// inline element broken into lines has a box for each line.
+pub fn inspected_boxes(root: &LayoutBox) -> Vec<InspectedBox> {
+    fn node_paths(
+        node: &Node,
+        path: &mut Vec<usize>,
+        paths: &mut HashMap<*const Node, Vec<usize>>,
+    ) {
+        paths.insert(node as *const Node, path.clone());
+        for (i, child) in node.children.iter().enumerate() {
+            path.push(i);
+            node_paths(child, path, paths);
+            path.pop();
+        }
+    }
+
+    fn collect(
+        boxes: &mut Vec<InspectedBox>,
+        paths: &HashMap<*const Node, Vec<usize>>,
+        x: Au,
+        y: Au,
+        layout_box: &LayoutBox,
+    ) {
+        let d = layout_box.dimensions;
+        let element = match (&layout_box.box_type, layout_box.style) {
+            (&BoxType::AnonymousBlock, _) | (&BoxType::TextNode(_), _) | (_, None) => None,
+            (_, Some(style)) => match style.node.data {
+                NodeType::Element(_) => paths.get(&(style.node as *const Node)),
+                NodeType::Text(_) => None,
+            },
+        };
+        if let Some(path) = element {
+            boxes.push(InspectedBox {
+                content: d.content.add_parent_coordinate(x, y),
+                padding: d.padding_box().add_parent_coordinate(x, y),
+                border: d.border_box().add_parent_coordinate(x, y),
+                margin: d.margin_box().add_parent_coordinate(x, y),
+                path: path.clone(),
+            });
+        }
+        for child in &layout_box.children {
+            collect(boxes, paths, x + d.content.x, y + d.content.y, child);
         }
-        Ok(())
     }
+
+    let mut paths = HashMap::new();
+    if let Some(style) = root.style {
+        node_paths(style.node, &mut vec![], &mut paths);
+    }
+    let mut boxes = vec![];
+    collect(&mut boxes, &paths, Au(0), Au(0), root);
+    boxes
+}
+
+/// Style `html_src` with `css_src` and the default stylesheet, lay it out in a viewport `width`
+/// px wide, and pass the layout tree to `f`. For tests.
+#[cfg(test)]
+pub fn with_layout_tree<T, F>(html_src: &str, css_src: &str, width: f64, f: F) -> T
+where
+    F: FnOnce(&LayoutBox) -> T,
+{
+    let mut viewport: Dimensions = Default::default();
+    viewport.content.width = Au::from_f64_px(width);
+    with_layout_tree_in(html_src, css_src, viewport, &Rc::new(Document::new()), f)
+}
+
+/// The rect at (`x`, `y`) with the size, in px. For tests.
+#[cfg(test)]
+pub fn px_rect(x: f64, y: f64, width: f64, height: f64) -> Rect {
+    Rect {
+        x: Au::from_f64_px(x),
+        y: Au::from_f64_px(y),
+        width: Au::from_f64_px(width),
+        height: Au::from_f64_px(height),
+    }
+}
+
+/// Like `with_layout_tree`, but in `viewport`, with the images and buttons of `document`.
+#[cfg(test)]
+pub fn with_layout_tree_in<T, F>(
+    html_src: &str,
+    css_src: &str,
+    viewport: Dimensions,
+    document: &Rc<Document>,
+    f: F,
+) -> T
+where
+    F: FnOnce(&LayoutBox) -> T,
+{
+    style::with_style_tree(html_src, css_src, |style_tree| {
+        f(&layout_tree(style_tree, viewport, document))
+    })
+}
+
+#[test]
+fn test_dump_layout() {
+    let html_src = "<html><body><div></div><p style='position: relative'>text</p></body></html>";
+    let css_src = "body { margin: 0; padding: 1px; } \
+                   div { width: 100px; height: 20px; padding: 5px; border-width: 1px; \
+                         margin: 10px; }";
+    let dump = with_layout_tree(html_src, css_src, 400.0, |layout_tree| dump_layout(layout_tree));
+    let lines: Vec<&str> = dump.lines().collect();
+
+    assert!(lines[0].starts_with("block <html> display=block position=static content="));
+    // Children are indented, and the boxes are relative to the document.
+    assert!(lines.contains(
+        &"    block <div> display=block position=static content=(17.00, 17.00, 100.00x20.00) \
+          padding=(12.00, 12.00, 110.00x30.00) border=(11.00, 11.00, 112.00x32.00) \
+          margin=(1.00, 1.00, 132.00x52.00)"
+    ));
+    assert!(
+        lines
+            .iter()
+            .any(|line| line.starts_with("    block <p> display=block position=relative"))
+    );
+    assert!(lines.iter().any(|line| line.trim_left().starts_with("text \"text\" content=")));
+    // The output is deterministic.
+    assert_eq!(
+        dump,
+        with_layout_tree(html_src, css_src, 400.0, |layout_tree| dump_layout(layout_tree))
+    );
+}
+
+#[test]
+fn test_inspected_boxes() {
+    let html_src = "<html><body><div><p>a</p></div></body></html>";
+    let css_src = "body { margin: 0; } div { margin: 10px; padding: 5px; } p { margin: 0; }";
+    let boxes = with_layout_tree(html_src, css_src, 400.0, |layout_tree| {
+        inspected_boxes(layout_tree)
+    });
+
+    // <html>, <body>, <div> and <p>, parents first.
+    let paths: Vec<Vec<usize>> = boxes.iter().map(|b| b.path.clone()).collect();
+    assert_eq!(paths, vec![vec![], vec![0], vec![0, 0], vec![0, 0, 0]]);
+
+    let div = &boxes[2];
+    assert_eq!(div.margin.x, Au::from_f64_px(0.0));
+    assert_eq!(div.border.x, Au::from_f64_px(10.0));
+    assert_eq!(div.content.x, Au::from_f64_px(15.0));
+    assert_eq!(boxes[3].border, div.content);
+}
+
+#[test]
+fn test_inline_block() {
+    let html_src = "<html><body><p><div id='a'>a</div><div id='b'>b</div></p>\
+                    <p><div id='c'><div id='d'></div></div></p></body></html>";
+    let css_src = "body, p { margin: 0; } \
+                   #a, #b, #c { display: inline-block; padding: 5px; } \
+                   #a, #b { width: 50px; height: 20px; } \
+                   #d { width: 30px; height: 10px; }";
+    let boxes = with_layout_tree(html_src, css_src, 400.0, |layout_tree| {
+        inspected_boxes(layout_tree)
+    });
+    let find = |path: Vec<usize>| boxes.iter().find(|b| b.path == path).unwrap().clone();
+    let (a, b, c) = (find(vec![0, 0, 0]), find(vec![0, 0, 1]), find(vec![0, 1, 0]));
+
+    // The two boxes are side by side in the same line, with their own width, height and padding.
+    assert_eq!(a.content, px_rect(5.0, 5.0, 50.0, 20.0));
+    assert_eq!(b.content, px_rect(65.0, 5.0, 50.0, 20.0));
+    assert_eq!(b.padding, px_rect(60.0, 0.0, 60.0, 30.0));
+
+    // `width: auto` shrinks to fit the contents.
+    assert_eq!(c.content, px_rect(5.0, 35.0, 30.0, 10.0));
+
+    // The baseline of the last line in an inline-block is on that of the line around it, so the
+    // text in the same font is at the same height.
+    use painter::{display_list_for, DisplayCommand};
+    let html_src = "<html><body><p>x<span id='e'><div>top</div><div>bottom</div></span>y</p>\
+                    </body></html>";
+    let css_src = "body, p { margin: 0; } #e { display: inline-block; padding: 5px; }";
+    let items = display_list_for(html_src, css_src, 400.0);
+    let text_y = |text: &str| {
+        items
+            .iter()
+            .filter_map(|item| match item.command {
+                DisplayCommand::Text(ref t, rect, _, _, _) if t.trim() == text => Some(rect.y),
+                _ => None,
+            })
+            .next()
+            .unwrap()
+    };
+    assert_eq!(text_y("x"), text_y("bottom"));
+    assert_eq!(text_y("y"), text_y("bottom"));
+    assert!(text_y("top") < text_y("x"));
+}
+
+#[test]
+fn test_float_and_inline_block_height() {
+    let html_src = "<html><body><div id='a'>text</div><div id='b'></div>\
+                    <p><span id='c'>text</span></p></body></html>";
+    let css_src = "body, p { margin: 0; } \
+                   #a, #b { float: left; width: 50px; } \
+                   #a { height: 40px; } \
+                   #b { height: 50%; } \
+                   #c { display: inline-block; height: 30px; }";
+    let boxes = with_layout_tree(html_src, css_src, 400.0, |layout_tree| {
+        inspected_boxes(layout_tree)
+    });
+    let height = |path: Vec<usize>| {
+        let b = boxes.iter().find(|b| b.path == path).unwrap();
+        b.content.height
+    };
+
+    // The specified heights are used instead of those of the contents.
+    assert_eq!(height(vec![0, 0]), Au::from_f64_px(40.0));
+    assert_eq!(height(vec![0, 2, 0]), Au::from_f64_px(30.0));
+    // The height of the body depends on the content, so the percent height is `auto`.
+    assert_eq!(height(vec![0, 1]), Au(0));
+}
+
+#[test]
+fn test_absolute_position() {
+    let html_src = "<html><body><div id='a'></div>\
+                    <div id='parent'><div id='b'></div><div id='c'>c</div><div id='d'></div></div>\
+                    </body></html>";
+    let css_src = "body { margin: 0; } \
+                   #a { height: 30px; } \
+                   #parent { position: relative; margin-left: 10px; padding: 5px; width: 200px; \
+                             height: 100px; } \
+                   #b { position: absolute; top: 10px; left: 20px; width: 50px; height: 40px; \
+                        padding: 2px; } \
+                   #c { position: absolute; right: 0; bottom: 10%; } \
+                   #d { height: 15px; }";
+    let boxes = with_layout_tree(html_src, css_src, 400.0, |layout_tree| {
+        inspected_boxes(layout_tree)
+    });
+    let find = |path: Vec<usize>| boxes.iter().find(|b| b.path == path).unwrap().clone();
+
+    // The offsets are from the padding box of the positioned parent at (10, 30).
+    let b = find(vec![0, 1, 0]);
+    assert_eq!(b.border, px_rect(30.0, 40.0, 54.0, 44.0));
+    assert_eq!(b.content, px_rect(32.0, 42.0, 50.0, 40.0));
+
+    // Without width, the box shrinks to fit the text, and `bottom` is relative to the height.
+    let c = find(vec![0, 1, 1]);
+    assert_eq!(c.border.x + c.border.width, Au::from_f64_px(220.0));
+    assert!(c.border.width > Au(0) && c.border.width < Au::from_f64_px(50.0));
+    assert_eq!(c.border.y + c.border.height, Au::from_f64_px(30.0 + 110.0 - 11.0));
+
+    // The boxes are out of the flow.
+    let d = find(vec![0, 1, 2]);
+    assert_eq!(d.content, px_rect(15.0, 35.0, 200.0, 15.0));
 }
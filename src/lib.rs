@@ -7,8 +7,10 @@ pub mod font;
 pub mod inline;
 pub mod block;
 pub mod float;
+pub mod absolute;
 pub mod layout;
 pub mod painter;
+pub mod selection;
 pub mod window;
 pub mod interface;
 
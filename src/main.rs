@@ -1,9 +1,12 @@
 extern crate naglfar;
-use naglfar::interface;
+use naglfar::{interface, window};
 
 extern crate clap;
 use clap::{App, Arg};
 
+use std::path::Path;
+use std::process;
+
 const VERSION_STR: &'static str = env!("CARGO_PKG_VERSION");
 
 fn main() {
@@ -15,11 +18,67 @@ fn main() {
             Arg::with_name("URL")
                 .help("Set URL (starts with http(s):// or file://)")
                 .index(1),
+        )
+        .arg(
+            Arg::with_name("screenshot")
+                .long("screenshot")
+                .value_name("FILE")
+                .help("Render the page to a PNG file without opening a window"),
+        )
+        .arg(
+            Arg::with_name("dump-layout")
+                .long("dump-layout")
+                .conflicts_with("screenshot")
+                .help("Print the layout tree of the page without opening a window"),
+        )
+        .arg(
+            Arg::with_name("width")
+                .long("width")
+                .value_name("PX")
+                .default_value("800")
+                .help("Set the width of the page without a window"),
+        )
+        .arg(
+            Arg::with_name("wheel-step")
+                .long("wheel-step")
+                .value_name("PX")
+                .default_value("60")
+                .help("Set the distance to scroll by a notch of the mouse wheel"),
+        )
+        .arg(
+            Arg::with_name("trace-display-list")
+                .long("trace-display-list")
+                .help("Report whether each draw rebuilds the display list, and how long it takes"),
         );
     let app_matches = app.clone().get_matches();
 
     if let Some(url) = app_matches.value_of("URL") {
-        interface::run_with_url(url.to_string())
+        let width = match app_matches.value_of("width").unwrap().parse::<f64>() {
+            Ok(width) if width > 0.0 => width,
+            _ => {
+                eprintln!("The width must be a positive number");
+                process::exit(1)
+            }
+        };
+        match app_matches.value_of("wheel-step").unwrap().parse::<f64>() {
+            Ok(step) if step > 0.0 => window::set_wheel_step(step),
+            _ => {
+                eprintln!("The wheel step must be a positive number");
+                process::exit(1)
+            }
+        }
+        interface::set_trace_display_list(app_matches.is_present("trace-display-list"));
+        let result = if let Some(path) = app_matches.value_of("screenshot") {
+            interface::screenshot(url.to_string(), Path::new(path), width)
+        } else if app_matches.is_present("dump-layout") {
+            interface::dump_layout(url.to_string(), width).map(|dump| print!("{}", dump))
+        } else {
+            interface::run_with_url(url.to_string())
+        };
+        if let Err(e) = result {
+            eprintln!("{}", e);
+            process::exit(1)
+        }
     } else {
         app.print_help().unwrap();
         println!();
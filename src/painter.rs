@@ -1,39 +1,91 @@
-use layout::{BoxType, LayoutBox, LayoutInfo, Rect};
-use font::Font;
+use layout::{inspected_boxes, zoom_au, BoxType, CornerRadii, EdgeSizes, LayoutBox, LayoutInfo,
+             ListMarker, Rect};
+use font::{resolve_font_family, Font};
+use style::{BackgroundPosition, BackgroundRepeat, BackgroundSize, Visibility};
 use dom::{ElementData, LayoutType, NodeType};
-use css::{Color, TextDecoration, BLACK};
+use css::{BorderStyle, Color, GradientSpec, TextDecoration, BLACK, GRAY};
+use inline::{alt_text_font, BROKEN_IMAGE_BORDER, BROKEN_IMAGE_GAP, BROKEN_IMAGE_PADDING,
+             BROKEN_IMAGE_SIZE};
 use app_units::Au;
 
+use std::cmp::max;
+use std::rc::Rc;
+
 use gdk_pixbuf;
+use gdk_pixbuf::PixbufExt;
 use gtk;
 
-use window::{AnkerKind, ANKERS, URL_FRAGMENTS};
+use window::AnkerKind;
+use interface::{current_url, resolve_url, Document};
 
 #[derive(Debug, Clone)]
 pub enum DisplayCommand {
-    SolidColor(Color, Rect),
+    // The last fields of SolidColor and Border are the radii of the corners.
+    SolidColor(Color, Rect, CornerRadii),
+    // The shape of a shadow, with the offset and the spread applied, and the blur radius.
+    BoxShadow(Color, Rect, CornerRadii, Au),
+    Border(Color, Rect, EdgeSizes, BorderStyle, CornerRadii),
+    Gradient(GradientSpec, Rect),
+    // The image is scaled to the second rect and placed there, tiled along the repeated axes,
+    // and clipped to the first rect.
+    BackgroundImage(gdk_pixbuf::Pixbuf, Rect, Rect, BackgroundRepeat),
     Image(gdk_pixbuf::Pixbuf, Rect),
     Text(String, Rect, Color, Vec<TextDecoration>, Font),
     Button(gtk::Button, Rect),
+    // Clip the following commands to the rect with rounded corners until the corresponding
+    // ClipPop.
+    ClipPush(Rect, CornerRadii),
+    ClipPop,
+    // Paint the following commands into a group, composited with the alpha at GroupPop.
+    GroupPush,
+    GroupPop(f64),
 }
 
 #[derive(Debug, Clone)]
 pub struct DisplayCommandInfo {
     pub command: DisplayCommand,
+    // Whether the command paints the shadow of a text, which can't be selected or found.
+    pub text_shadow: bool,
 }
 
 impl DisplayCommandInfo {
     pub fn new(command: DisplayCommand) -> DisplayCommandInfo {
-        DisplayCommandInfo { command: command }
+        DisplayCommandInfo {
+            command: command,
+            text_shadow: false,
+        }
+    }
+    pub fn new_text_shadow(command: DisplayCommand) -> DisplayCommandInfo {
+        DisplayCommandInfo {
+            command: command,
+            text_shadow: true,
+        }
     }
 }
 
 pub type DisplayList = Vec<DisplayCommandInfo>;
 
-pub fn build_display_list(layout_root: &LayoutBox) -> DisplayList {
+/// Build the display list of the page of `document`. The boxes are registered for the box model
+/// inspector when `inspecting`.
+pub fn build_display_list(
+    document: &Rc<Document>,
+    layout_root: &LayoutBox,
+    inspecting: bool,
+) -> DisplayList {
+    // Ankers, fragments and titles are registered again at their current positions.
+    document.ankers.borrow_mut().clear();
+    document.url_fragments.borrow_mut().clear();
+    document.titles.borrow_mut().clear();
+    *document.inspected_boxes.borrow_mut() = if inspecting {
+        inspected_boxes(layout_root)
+    } else {
+        vec![]
+    };
+
     let mut list = Vec::new();
     render_layout_box(
         &mut list,
+        document,
         Au::from_f64_px(0.0),
         Au::from_f64_px(0.0),
         layout_root,
@@ -41,7 +93,174 @@ pub fn build_display_list(layout_root: &LayoutBox) -> DisplayList {
     list
 }
 
-fn render_layout_box(list: &mut DisplayList, x: Au, y: Au, layout_box: &LayoutBox) {
+/// Build the display list of the page zoomed by `zoom`. The page is laid out in CSS px, and the
+/// commands, ankers, fragments, titles and inspected boxes are scaled into the px of the zoomed
+/// page.
+pub fn build_zoomed_display_list(
+    document: &Rc<Document>,
+    layout_root: &LayoutBox,
+    zoom: f64,
+    inspecting: bool,
+) -> DisplayList {
+    let list = build_display_list(document, layout_root, inspecting);
+    if zoom == 1.0 {
+        return list;
+    }
+    {
+        let mut ankers = document.ankers.borrow_mut();
+        let zoomed = ankers
+            .drain()
+            .map(|(rect, ankerkind)| (rect.zoomed(zoom), ankerkind))
+            .collect();
+        *ankers = zoomed;
+    }
+    for rect in document.url_fragments.borrow_mut().values_mut() {
+        *rect = rect.zoomed(zoom);
+    }
+    for &mut (ref mut rect, _) in document.titles.borrow_mut().iter_mut() {
+        *rect = rect.zoomed(zoom);
+    }
+    for inspected_box in document.inspected_boxes.borrow_mut().iter_mut() {
+        *inspected_box = inspected_box.zoomed(zoom);
+    }
+    list.into_iter()
+        .map(|item| DisplayCommandInfo {
+            command: zoom_command(item.command, zoom),
+            ..item
+        })
+        .collect()
+}
+
+/// Scale `command` from CSS px into the px of the page zoomed by `zoom`. Fonts are scaled too, so
+/// that text is drawn at the zoomed size.
+fn zoom_command(command: DisplayCommand, zoom: f64) -> DisplayCommand {
+    match command {
+        DisplayCommand::SolidColor(color, rect, radius) => {
+            DisplayCommand::SolidColor(color, rect.zoomed(zoom), radius.zoomed(zoom))
+        }
+        DisplayCommand::BoxShadow(color, rect, radius, blur) => DisplayCommand::BoxShadow(
+            color,
+            rect.zoomed(zoom),
+            radius.zoomed(zoom),
+            zoom_au(blur, zoom),
+        ),
+        DisplayCommand::Border(color, rect, widths, style, radius) => DisplayCommand::Border(
+            color,
+            rect.zoomed(zoom),
+            widths.zoomed(zoom),
+            style,
+            radius.zoomed(zoom),
+        ),
+        DisplayCommand::Gradient(gradient, rect) => {
+            DisplayCommand::Gradient(gradient, rect.zoomed(zoom))
+        }
+        DisplayCommand::BackgroundImage(pixbuf, rect, tile, repeat) => {
+            DisplayCommand::BackgroundImage(pixbuf, rect.zoomed(zoom), tile.zoomed(zoom), repeat)
+        }
+        DisplayCommand::Image(pixbuf, rect) => DisplayCommand::Image(pixbuf, rect.zoomed(zoom)),
+        DisplayCommand::Text(text, rect, color, decorations, font) => {
+            let font = Font {
+                size: zoom_au(font.size, zoom),
+                letter_spacing: zoom_au(font.letter_spacing, zoom),
+                ..font
+            };
+            DisplayCommand::Text(text, rect.zoomed(zoom), color, decorations, font)
+        }
+        DisplayCommand::Button(button, rect) => DisplayCommand::Button(button, rect.zoomed(zoom)),
+        DisplayCommand::ClipPush(rect, radius) => {
+            DisplayCommand::ClipPush(rect.zoomed(zoom), radius.zoomed(zoom))
+        }
+        command => command,
+    }
+}
+
+/// A positioned box painted by the stacking context it belongs to: its z-index, the position of
+/// its parent's content box, and the clips of its ancestors in the stacking context.
+struct Positioned<'a, 'b: 'a> {
+    z_index: i32,
+    x: Au,
+    y: Au,
+    clips: Vec<(Rect, CornerRadii)>,
+    layout_box: &'a LayoutBox<'b>,
+}
+
+/// Paint `layout_box` as a stacking context: its background, the positioned descendants with
+/// negative z-index, the in-flow content, and then the other positioned descendants in ascending
+/// z-index. Ties are painted in tree order.
+fn render_layout_box(
+    list: &mut DisplayList,
+    document: &Rc<Document>,
+    x: Au,
+    y: Au,
+    layout_box: &LayoutBox,
+) {
+    let mut positioned = vec![];
+    collect_positioned(x, y, layout_box, &mut vec![], &mut positioned);
+    // The sort is stable.
+    positioned.sort_by_key(|positioned| positioned.z_index);
+    render_box(list, document, x, y, layout_box, &positioned);
+}
+
+/// Collect the positioned descendants of `layout_box` that belong to the stacking context being
+/// painted. Those inside another stacking context are painted by it.
+fn collect_positioned<'a, 'b>(
+    x: Au,
+    y: Au,
+    layout_box: &'a LayoutBox<'b>,
+    clips: &mut Vec<(Rect, CornerRadii)>,
+    positioned: &mut Vec<Positioned<'a, 'b>>,
+) {
+    let clip = clip_rect(x, y, layout_box);
+    clips.extend(clip);
+    let (child_x, child_y) = (
+        x + layout_box.dimensions.content.x,
+        y + layout_box.dimensions.content.y,
+    );
+    for child in &layout_box.children {
+        if child.is_positioned() {
+            positioned.push(Positioned {
+                z_index: child.z_index,
+                x: child_x,
+                y: child_y,
+                clips: clips.clone(),
+                layout_box: child,
+            });
+        } else if !child.establishes_stacking_context() {
+            collect_positioned(child_x, child_y, child, clips, positioned);
+        }
+    }
+    if clip.is_some() {
+        clips.pop();
+    }
+}
+
+/// Descendants of a box with `overflow: hidden` are clipped to its padding box, rounded along
+/// the inner edge of its border.
+fn clip_rect(x: Au, y: Au, layout_box: &LayoutBox) -> Option<(Rect, CornerRadii)> {
+    if layout_box.clips_children() {
+        let d = &layout_box.dimensions;
+        let radius = layout_box
+            .style
+            .map_or(CornerRadii::default(), |style| style.border_radius());
+        Some((
+            d.padding_box().add_parent_coordinate(x, y),
+            radius.inner(d.border),
+        ))
+    } else {
+        None
+    }
+}
+
+/// Paint `layout_box` and its in-flow descendants. If it's a stacking context, `positioned` are
+/// its positioned descendants, and otherwise empty.
+fn render_box(
+    list: &mut DisplayList,
+    document: &Rc<Document>,
+    x: Au,
+    y: Au,
+    layout_box: &LayoutBox,
+    positioned: &[Positioned],
+) {
     let is_input_elem = match layout_box.info {
         LayoutInfo::Button(_, _) => true,
         _ => false,
@@ -49,48 +268,102 @@ fn render_layout_box(list: &mut DisplayList, x: Au, y: Au, layout_box: &LayoutBo
 
     let mut buf = DisplayList::new();
 
-    render_background(&mut buf, x, y, layout_box);
-    render_borders(&mut buf, x, y, layout_box);
+    // In-flow children are painted in tree order, floats above the others (the sort is stable).
+    let mut children: Vec<&LayoutBox> = layout_box
+        .children
+        .iter()
+        .filter(|child| !child.is_positioned())
+        .collect();
+    children.sort_by_key(|child| match child.box_type {
+        BoxType::Float => 1,
+        _ => 0,
+    });
 
-    let mut children = layout_box.children.clone();
-    children.sort_by(|&LayoutBox { z_index: a, .. }, &LayoutBox { z_index: b, .. }| a.cmp(&b));
+    let (child_x, child_y) = (
+        x + layout_box.dimensions.content.x,
+        y + layout_box.dimensions.content.y,
+    );
+    let clip = clip_rect(x, y, layout_box);
 
-    for child in children
-        .iter()
-        .filter(|child| child.box_type != BoxType::Float)
-    {
-        render_layout_box(
-            &mut buf,
-            x + layout_box.dimensions.content.x,
-            y + layout_box.dimensions.content.y,
-            &child,
-        );
+    // A box with `visibility: hidden` takes up space but isn't painted. Its descendants can
+    // still be visible.
+    let visible = layout_box
+        .style
+        .map_or(true, |style| style.visibility() == Visibility::Visible);
+
+    if visible {
+        render_box_shadow(&mut buf, x, y, layout_box);
+        render_background(&mut buf, document, x, y, layout_box);
+        render_borders(&mut buf, x, y, layout_box);
+        render_list_marker(&mut buf, x, y, layout_box);
+        // Registered before the children, so that the innermost title comes last.
+        register_title(document, x, y, layout_box);
     }
-    for child in children
-        .iter()
-        .filter(|child| child.box_type == BoxType::Float)
-    {
-        render_layout_box(
-            &mut buf,
-            x + layout_box.dimensions.content.x,
-            y + layout_box.dimensions.content.y,
-            &child,
-        );
+
+    // Positioned descendants with negative z-index are painted behind the in-flow content.
+    for positioned in positioned.iter().filter(|positioned| positioned.z_index < 0) {
+        render_positioned(&mut buf, document, positioned);
     }
 
-    render_text(&mut buf, x, y, layout_box);
-    render_image(&mut buf, x, y, layout_box);
+    push_clip(&mut buf, clip);
+    for child in children {
+        if child.establishes_stacking_context() {
+            render_layout_box(&mut buf, document, child_x, child_y, child);
+        } else {
+            render_box(&mut buf, document, child_x, child_y, child, &[]);
+        }
+    }
+    pop_clip(&mut buf, clip);
 
-    register_anker(x, y, layout_box);
-    register_url_fragment(x, y, layout_box);
+    if visible {
+        render_text(&mut buf, x, y, layout_box);
+        render_image(&mut buf, document, x, y, layout_box);
+        // Hidden links must not be clickable.
+        register_anker(document, x, y, layout_box);
+    }
+    register_url_fragment(document, x, y, layout_box);
 
-    if is_input_elem {
+    for positioned in positioned.iter().filter(|positioned| positioned.z_index >= 0) {
+        render_positioned(&mut buf, document, positioned);
+    }
+    // Composite the box and its descendants as a whole. Opaque boxes don't need a group.
+    if layout_box.opacity < 1.0 && !buf.is_empty() {
+        buf.insert(0, DisplayCommandInfo::new(DisplayCommand::GroupPush));
+        buf.push(DisplayCommandInfo::new(DisplayCommand::GroupPop(
+            layout_box.opacity,
+        )));
+    }
+
+    if is_input_elem && visible {
         render_button(list, &mut buf, x, y, layout_box);
     } else {
         list.append(&mut buf);
     }
 }
 
+/// Paint a positioned box, clipped as its ancestors clip it.
+fn render_positioned(list: &mut DisplayList, document: &Rc<Document>, positioned: &Positioned) {
+    for &clip in &positioned.clips {
+        push_clip(list, Some(clip));
+    }
+    render_layout_box(list, document, positioned.x, positioned.y, positioned.layout_box);
+    for &clip in &positioned.clips {
+        pop_clip(list, Some(clip));
+    }
+}
+
+fn push_clip(list: &mut DisplayList, clip: Option<(Rect, CornerRadii)>) {
+    if let Some((rect, radius)) = clip {
+        list.push(DisplayCommandInfo::new(DisplayCommand::ClipPush(rect, radius)));
+    }
+}
+
+fn pop_clip(list: &mut DisplayList, clip: Option<(Rect, CornerRadii)>) {
+    if clip.is_some() {
+        list.push(DisplayCommandInfo::new(DisplayCommand::ClipPop));
+    }
+}
+
 fn render_button(
     list: &mut DisplayList,
     _children: &mut DisplayList,
@@ -106,6 +379,89 @@ fn render_button(
     }
 }
 
+/// Paint the marker of a list item to the left of its border box, vertically aligned with the
+/// first line.
+fn render_list_marker(list: &mut DisplayList, x: Au, y: Au, layout_box: &LayoutBox) {
+    let marker = match layout_box.marker {
+        Some(ref marker) => marker,
+        None => return,
+    };
+    let style = layout_box.get_style_node();
+
+    let font = Font::new(
+        style.font_size(),
+        style.font_weight(),
+        style.font_style(),
+        resolve_font_family(&style.font_family()),
+    );
+    let (ascent, descent) = font.get_ascent_descent();
+    let half_leading = (style.line_height() - (ascent + descent)) / 2;
+    let border_box = layout_box
+        .dimensions
+        .border_box()
+        .add_parent_coordinate(x, y);
+    let text_top = y + layout_box.dimensions.content.y + half_leading;
+    let gap = font.size / 2;
+    let color = get_color(layout_box, "color").unwrap_or(BLACK);
+
+    // Discs, circles and squares are centered at about the middle of lowercase letters.
+    let size = font.size * 35 / 100;
+    let bullet_rect = Rect {
+        x: border_box.x - gap - size,
+        y: text_top + ascent - ascent * 35 / 100 - size / 2,
+        width: size,
+        height: size,
+    };
+
+    match *marker {
+        ListMarker::Disc => {
+            list.push(DisplayCommandInfo::new(DisplayCommand::SolidColor(
+                color,
+                bullet_rect,
+                CornerRadii::uniform(size / 2),
+            )));
+        }
+        ListMarker::Circle => {
+            let width = max(size / 8, Au::from_f64_px(1.0));
+            list.push(DisplayCommandInfo::new(DisplayCommand::Border(
+                color,
+                bullet_rect,
+                EdgeSizes {
+                    left: width,
+                    right: width,
+                    top: width,
+                    bottom: width,
+                },
+                BorderStyle::Solid,
+                CornerRadii::uniform(size / 2),
+            )));
+        }
+        ListMarker::Square => {
+            list.push(DisplayCommandInfo::new(DisplayCommand::SolidColor(
+                color,
+                bullet_rect,
+                CornerRadii::default(),
+            )));
+        }
+        ListMarker::Decimal(n) => {
+            let text = format!("{}.", n);
+            let width = Au::from_f64_px(font.text_width(text.as_str()));
+            list.push(DisplayCommandInfo::new(DisplayCommand::Text(
+                text,
+                Rect {
+                    x: border_box.x - gap - width,
+                    y: text_top,
+                    width: width,
+                    height: ascent + descent,
+                },
+                color,
+                vec![],
+                font,
+            )));
+        }
+    }
+}
+
 fn render_text(list: &mut DisplayList, x: Au, y: Au, layout_box: &LayoutBox) {
     if let &BoxType::TextNode(ref text_info) = &layout_box.box_type {
         let text = if let NodeType::Text(ref text) = layout_box.style.unwrap().node.data {
@@ -113,35 +469,67 @@ fn render_text(list: &mut DisplayList, x: Au, y: Au, layout_box: &LayoutBox) {
         } else {
             unreachable!()
         };
+        let rect = layout_box.dimensions.content.add_parent_coordinate(x, y);
+        let text_decoration = match layout_box.style {
+            Some(style) => style.text_decoration(),
+            None => vec![],
+        };
+
+        // Shadows are painted back-to-front below the text. They don't affect layout.
+        // TODO: Support blur.
+        let shadows = match layout_box.style {
+            Some(style) => style.text_shadow(),
+            None => vec![],
+        };
+        for shadow in shadows.iter().rev() {
+            list.push(DisplayCommandInfo::new_text_shadow(DisplayCommand::Text(
+                text.to_string(),
+                Rect {
+                    x: rect.x + shadow.offset_x,
+                    y: rect.y + shadow.offset_y,
+                    ..rect
+                },
+                shadow.color,
+                text_decoration.clone(),
+                text_info.font.clone(),
+            )));
+        }
+
         list.push(DisplayCommandInfo::new(DisplayCommand::Text(
             text.to_string(),
-            layout_box.dimensions.content.add_parent_coordinate(x, y),
+            rect,
             get_color(layout_box, "color").unwrap_or(BLACK),
-            match layout_box.style {
-                Some(style) => style.text_decoration(),
-                None => vec![],
-            },
-            text_info.font,
+            text_decoration,
+            text_info.font.clone(),
         )));
     }
 }
 
-fn render_image(list: &mut DisplayList, x: Au, y: Au, layout_box: &LayoutBox) {
+fn render_image(
+    list: &mut DisplayList,
+    document: &Document,
+    x: Au,
+    y: Au,
+    layout_box: &LayoutBox,
+) {
     match layout_box.box_type {
-        BoxType::InlineNode | BoxType::Float => {
+        BoxType::InlineNode | BoxType::Float | BoxType::Absolute => {
             if let NodeType::Element(ElementData {
                 ref layout_type, ..
             }) = layout_box.style.unwrap().node.data
             {
                 if layout_type == &LayoutType::Image {
-                    list.push(DisplayCommandInfo::new(DisplayCommand::Image(
-                        if let &LayoutInfo::Image(ref pixbuf) = &layout_box.info {
-                            pixbuf.clone().unwrap()
-                        } else {
-                            panic!()
-                        },
-                        layout_box.dimensions.content.add_parent_coordinate(x, y),
-                    )))
+                    let rect = layout_box.dimensions.content.add_parent_coordinate(x, y);
+                    let style = layout_box.get_style_node();
+                    match layout_box.info {
+                        LayoutInfo::Image(Some(ref pixbuf)) => list.push(
+                            DisplayCommandInfo::new(DisplayCommand::Image(pixbuf.clone(), rect)),
+                        ),
+                        // The image is still loading.
+                        LayoutInfo::Image(None) if !style.image_failed(document) => {}
+                        LayoutInfo::Image(None) => render_broken_image(list, rect, layout_box),
+                        _ => panic!(),
+                    }
                 }
             }
         }
@@ -149,118 +537,379 @@ fn render_image(list: &mut DisplayList, x: Au, y: Au, layout_box: &LayoutBox) {
     }
 }
 
-fn register_anker(x: Au, y: Au, layout_box: &LayoutBox) {
+/// Paint the box of an image that failed to load: a border, and the broken image glyph followed
+/// by the alt text inside the padding. Nothing is painted if the box collapsed.
+fn render_broken_image(list: &mut DisplayList, rect: Rect, layout_box: &LayoutBox) {
+    if rect.width <= Au(0) || rect.height <= Au(0) {
+        return;
+    }
+    let style = layout_box.get_style_node();
+    let border = Au::from_f64_px(BROKEN_IMAGE_BORDER);
+    let inset = border + Au::from_f64_px(BROKEN_IMAGE_PADDING);
+    let glyph_size = Au::from_f64_px(BROKEN_IMAGE_SIZE);
+    let edges = |width: Au| EdgeSizes {
+        left: width,
+        right: width,
+        top: width,
+        bottom: width,
+    };
+
+    list.push(DisplayCommandInfo::new(DisplayCommand::ClipPush(
+        rect,
+        CornerRadii::default(),
+    )));
+    list.push(DisplayCommandInfo::new(DisplayCommand::Border(
+        GRAY,
+        rect,
+        edges(border),
+        BorderStyle::Solid,
+        CornerRadii::default(),
+    )));
+    list.push(DisplayCommandInfo::new(DisplayCommand::Border(
+        GRAY,
+        Rect {
+            x: rect.x + inset,
+            y: rect.y + inset,
+            width: glyph_size,
+            height: glyph_size,
+        },
+        edges(Au::from_f64_px(1.0)),
+        BorderStyle::Solid,
+        CornerRadii::default(),
+    )));
+    if let Some(alt) = style.node.alt_text() {
+        let font = alt_text_font(style);
+        let (ascent, descent) = font.get_ascent_descent();
+        let offset = inset + glyph_size + Au::from_f64_px(BROKEN_IMAGE_GAP);
+        list.push(DisplayCommandInfo::new(DisplayCommand::Text(
+            alt.clone(),
+            Rect {
+                x: rect.x + offset,
+                y: rect.y + inset,
+                width: max(rect.width - offset - inset, Au(0)),
+                height: ascent + descent,
+            },
+            get_color(layout_box, "color").unwrap_or(BLACK),
+            vec![],
+            font,
+        )));
+    }
+    list.push(DisplayCommandInfo::new(DisplayCommand::ClipPop));
+}
+
+fn register_anker(document: &Document, x: Au, y: Au, layout_box: &LayoutBox) {
     match layout_box.info {
         LayoutInfo::Anker => {
             if let Some(url) = layout_box.style.unwrap().node.anker_url() {
                 let rect = layout_box.dimensions.content.add_parent_coordinate(x, y);
-                ANKERS.with(|ankers| {
-                    ankers.borrow_mut().entry(rect).or_insert_with(|| {
-                        if url.chars().next().unwrap() == '#' {
-                            AnkerKind::URLFragment(url[1..].to_string())
-                        } else {
-                            AnkerKind::URL(url.to_string())
-                        }
-                    });
-                });
+                let kind = if url.starts_with('#') {
+                    AnkerKind::URLFragment(url[1..].to_string())
+                } else if url.is_empty() {
+                    // An empty href links to the document itself, even under <base>.
+                    AnkerKind::URL(current_url(document).unwrap_or_default())
+                } else {
+                    // Relative links are resolved against the document, so that they still
+                    // point to the right place after navigating.
+                    AnkerKind::URL(
+                        resolve_url(document, url)
+                            .map(|url| url.into_string())
+                            .unwrap_or(url.to_string()),
+                    )
+                };
+                document.ankers.borrow_mut().insert(rect, kind);
             }
         }
         _ => {}
     }
 }
 
-fn register_url_fragment(x: Au, y: Au, layout_box: &LayoutBox) {
+fn register_url_fragment(document: &Document, x: Au, y: Au, layout_box: &LayoutBox) {
     if let Some(style) = layout_box.style {
         if let NodeType::Element(ref e) = style.node.data {
-            if let Some(id) = e.id() {
-                URL_FRAGMENTS.with(|url_fragments| {
-                    url_fragments.borrow_mut().insert(
-                        id.to_string(),
-                        layout_box
-                            .dimensions
-                            .content
-                            .add_parent_coordinate(x, y)
-                            .y
-                            .to_f64_px(),
-                    )
-                });
+            if let Some(id) = e.fragment_name() {
+                let rect = layout_box
+                    .dimensions
+                    .border_box()
+                    .add_parent_coordinate(x, y);
+                // An element split into several boxes is referred to by the first one.
+                document
+                    .url_fragments
+                    .borrow_mut()
+                    .entry(id.to_string())
+                    .or_insert(rect);
             }
         }
     }
 }
 
-fn render_background(list: &mut DisplayList, x: Au, y: Au, layout_box: &LayoutBox) {
-    lookup_color(layout_box, "background-color", "background").map(|color| {
+fn register_title(document: &Document, x: Au, y: Au, layout_box: &LayoutBox) {
+    if let Some(style) = layout_box.style {
+        if let NodeType::Element(ref e) = style.node.data {
+            if let Some(title) = e.title() {
+                let rect = layout_box
+                    .dimensions
+                    .border_box()
+                    .add_parent_coordinate(x, y);
+                // An empty title is kept, since it hides the titles of the ancestors.
+                document.titles.borrow_mut().push((rect, title.to_string()));
+            }
+        }
+    }
+}
+
+/// Paint the outer shadow of the box under its background.
+/// TODO: Paint inset shadows.
+fn render_box_shadow(list: &mut DisplayList, x: Au, y: Au, layout_box: &LayoutBox) {
+    if let BoxType::TextNode(_) = layout_box.box_type {
+        return;
+    }
+
+    let style = match layout_box.style {
+        Some(style) => style,
+        None => return,
+    };
+
+    let shadow = match style.box_shadow() {
+        Some(shadow) => shadow,
+        None => return,
+    };
+    if shadow.inset || shadow.color.a == 0 {
+        return;
+    }
+
+    let spread = EdgeSizes {
+        left: shadow.spread,
+        right: shadow.spread,
+        top: shadow.spread,
+        bottom: shadow.spread,
+    };
+    let rect = layout_box
+        .dimensions
+        .border_box()
+        .add_parent_coordinate(x + shadow.offset_x, y + shadow.offset_y)
+        .expanded_by(spread);
+    if rect.width <= Au(0) || rect.height <= Au(0) {
+        return;
+    }
+    // Rounded corners grow and shrink with the spread, and square ones stay square.
+    let radius = style.border_radius().map(|radius| if radius > Au(0) {
+        max(radius + shadow.spread, Au(0))
+    } else {
+        Au(0)
+    });
+
+    list.push(DisplayCommandInfo::new(DisplayCommand::BoxShadow(
+        shadow.color,
+        rect,
+        radius,
+        shadow.blur,
+    )));
+}
+
+fn render_background(
+    list: &mut DisplayList,
+    document: &Rc<Document>,
+    x: Au,
+    y: Au,
+    layout_box: &LayoutBox,
+) {
+    // A text node under an inline element shares the element's properties, but its background
+    // is painted by the fragment of the inline element that contains it (one per line).
+    if let BoxType::TextNode(_) = layout_box.box_type {
+        return;
+    }
+
+    let style = match layout_box.style {
+        Some(style) => style,
+        None => return,
+    };
+
+    let border_box = layout_box
+        .dimensions
+        .border_box()
+        .add_parent_coordinate(x, y);
+
+    let radius = style.border_radius();
+    let color = style.background_color();
+    // Nothing to paint for transparent backgrounds.
+    if color.a != 0 {
         list.push(DisplayCommandInfo::new(DisplayCommand::SolidColor(
             color,
-            layout_box
-                .dimensions
-                .border_box()
-                .add_parent_coordinate(x, y),
-        )))
-    });
+            border_box,
+            radius,
+        )));
+    }
+
+    // A gradient or an image is painted over the background color, clipped to the rounded
+    // corners.
+    let gradient = style.background_gradient();
+    let pixbuf = style.background_image(document);
+    let clip = if radius.is_zero() || gradient.is_none() && pixbuf.is_none() {
+        None
+    } else {
+        Some((border_box, radius))
+    };
+    push_clip(list, clip);
+
+    if let Some(gradient) = gradient {
+        list.push(DisplayCommandInfo::new(DisplayCommand::Gradient(
+            gradient,
+            border_box,
+        )));
+    }
+
+    if let Some(pixbuf) = pixbuf {
+        // The image is sized and positioned in the padding box, and tiled over the border box.
+        let padding_box = layout_box
+            .dimensions
+            .padding_box()
+            .add_parent_coordinate(x, y);
+        let (width, height) = background_image_size(
+            style.background_size(),
+            (pixbuf.get_width() as f64, pixbuf.get_height() as f64),
+            padding_box,
+        );
+        let (position_x, position_y) = style.background_position();
+        let tile = Rect {
+            x: padding_box.x + background_offset(position_x, padding_box.width - width),
+            y: padding_box.y + background_offset(position_y, padding_box.height - height),
+            width: width,
+            height: height,
+        };
+        list.push(DisplayCommandInfo::new(DisplayCommand::BackgroundImage(
+            pixbuf,
+            border_box,
+            tile,
+            style.background_repeat(),
+        )));
+    }
+    pop_clip(list, clip);
+}
+
+/// The offset of a background image at `position` from the start of the box. `room` is how
+/// much larger the box is than the image.
+fn background_offset(position: BackgroundPosition, room: Au) -> Au {
+    match position {
+        BackgroundPosition::Length(length) => length,
+        BackgroundPosition::Percent(percent) => Au::from_f64_px(room.to_f64_px() * percent),
+    }
+}
+
+/// Return the size of a background image in the `rect`.
+fn background_image_size(size: BackgroundSize, image: (f64, f64), rect: Rect) -> (Au, Au) {
+    let (image_width, image_height) = image;
+    let (width, height) = (rect.width.to_f64_px(), rect.height.to_f64_px());
+    let scale = if image_width <= 0.0 || image_height <= 0.0 {
+        1.0
+    } else {
+        match size {
+            BackgroundSize::Auto => 1.0,
+            BackgroundSize::Cover => (width / image_width).max(height / image_height),
+            BackgroundSize::Contain => (width / image_width).min(height / image_height),
+        }
+    };
+    (
+        Au::from_f64_px(image_width * scale),
+        Au::from_f64_px(image_height * scale),
+    )
 }
 
 fn render_borders(list: &mut DisplayList, x: Au, y: Au, layout_box: &LayoutBox) {
     let d = &layout_box.dimensions;
     let border_box = d.border_box().add_parent_coordinate(x, y);
 
-    let (top_color, right_color, bottom_color, left_color) = match layout_box.style {
-        Some(style) => style.border_color(),
-        None => return,
-    };
+    let ((top_color, right_color, bottom_color, left_color), (top, right, bottom, left), radius) =
+        match layout_box.style {
+            Some(style) => (
+                style.border_color(),
+                style.border_style(),
+                style.border_radius(),
+            ),
+            None => return,
+        };
 
-    // Left border
-    if let Some(left_color) = left_color {
-        list.push(DisplayCommandInfo::new(DisplayCommand::SolidColor(
-            left_color,
+    // Sides sharing the same color and style are merged into a single command.
+    let mut borders: Vec<(Color, BorderStyle, EdgeSizes)> = vec![];
+    {
+        let mut add_side = |color: Option<Color>,
+                            style: BorderStyle,
+                            width: Au,
+                            set: &Fn(&mut EdgeSizes, Au)| {
+            if let Some(color) = color {
+                if width <= Au(0) || style == BorderStyle::None {
+                    return;
+                }
+                if let Some(&mut (_, _, ref mut edges)) = borders
+                    .iter_mut()
+                    .find(|&&mut (c, s, _)| c == color && s == style)
+                {
+                    set(edges, width);
+                    return;
+                }
+                let mut edges: EdgeSizes = Default::default();
+                set(&mut edges, width);
+                borders.push((color, style, edges));
+            }
+        };
+        add_side(top_color, top, d.border.top, &|e, w| e.top = w);
+        add_side(right_color, right, d.border.right, &|e, w| e.right = w);
+        add_side(bottom_color, bottom, d.border.bottom, &|e, w| e.bottom = w);
+        add_side(left_color, left, d.border.left, &|e, w| e.left = w);
+    }
+
+    for (color, style, edges) in borders {
+        list.push(DisplayCommandInfo::new(DisplayCommand::Border(
+            color, border_box, edges, style, radius,
+        )));
+    }
+}
+
+/// Return the rectangles of the non-zero edges of a border, in the order top, right, bottom
+/// and left.
+pub fn border_edge_rects(border_box: Rect, edges: &EdgeSizes) -> Vec<Rect> {
+    let rects = [
+        (
+            edges.top,
             Rect {
                 x: border_box.x,
                 y: border_box.y,
-                width: d.border.left,
-                height: border_box.height,
+                width: border_box.width,
+                height: edges.top,
             },
-        )));
-    }
-
-    // Right border
-    if let Some(right_color) = right_color {
-        list.push(DisplayCommandInfo::new(DisplayCommand::SolidColor(
-            right_color,
+        ),
+        (
+            edges.right,
             Rect {
-                x: border_box.x + border_box.width - d.border.right,
+                x: border_box.x + border_box.width - edges.right,
                 y: border_box.y,
-                width: d.border.right,
+                width: edges.right,
                 height: border_box.height,
             },
-        )));
-    }
-
-    // Top border
-    if let Some(top_color) = top_color {
-        list.push(DisplayCommandInfo::new(DisplayCommand::SolidColor(
-            top_color,
+        ),
+        (
+            edges.bottom,
             Rect {
                 x: border_box.x,
-                y: border_box.y,
+                y: border_box.y + border_box.height - edges.bottom,
                 width: border_box.width,
-                height: d.border.top,
+                height: edges.bottom,
             },
-        )));
-    }
-
-    // Bottom border
-    if let Some(bottom_color) = bottom_color {
-        list.push(DisplayCommandInfo::new(DisplayCommand::SolidColor(
-            bottom_color,
+        ),
+        (
+            edges.left,
             Rect {
                 x: border_box.x,
-                y: border_box.y + border_box.height - d.border.bottom,
-                width: border_box.width,
-                height: d.border.bottom,
+                y: border_box.y,
+                width: edges.left,
+                height: border_box.height,
             },
-        )));
-    }
+        ),
+    ];
+    rects
+        .iter()
+        .filter(|&&(width, _)| width > Au(0))
+        .map(|&(_, rect)| rect)
+        .collect()
 }
 
 /// Return the specified color for CSS property `name`, or None if no color was specified.
@@ -274,13 +923,1089 @@ fn get_color(layout_box: &LayoutBox, name: &str) -> Option<Color> {
     }
 }
 
-/// Return the specified color for CSS property `name` or `fallback_name`, or None if no color was specified.
-fn lookup_color(layout_box: &LayoutBox, name: &str, fallback_name: &str) -> Option<Color> {
-    match layout_box.style {
-        Some(style) => match style.lookup_without_default(name, fallback_name) {
-            Some(maybe_color) => maybe_color[0].to_color(),
+/// The display list of `html_src` styled by `css_src` in a viewport `width` px wide. For tests.
+#[cfg(test)]
+pub fn display_list_for(html_src: &str, css_src: &str, width: f64) -> DisplayList {
+    display_list_in(&Rc::new(Document::new()), html_src, css_src, width)
+}
+
+/// Like `display_list_for`, but the ankers, fragments and titles are registered in `document`.
+#[cfg(test)]
+pub fn display_list_in(
+    document: &Rc<Document>,
+    html_src: &str,
+    css_src: &str,
+    width: f64,
+) -> DisplayList {
+    use layout::{with_layout_tree_in, Dimensions};
+
+    let mut viewport: Dimensions = Default::default();
+    viewport.content.width = Au::from_f64_px(width);
+    with_layout_tree_in(html_src, css_src, viewport, document, |layout_tree| {
+        build_display_list(document, layout_tree, false)
+    })
+}
+
+#[test]
+fn test_background_color() {
+    use css::{RED, WHITE};
+
+    let html_src = "<html><body><div id='a'></div><div id='b'></div></body></html>";
+    let css_src = "#a { background-color: red; height: 10px; } #b { height: 10px; }";
+    let list = display_list_for(html_src, css_src, 100.0);
+
+    let solid_colors: Vec<(Color, Rect)> = list
+        .iter()
+        .filter_map(|item| match item.command {
+            DisplayCommand::SolidColor(color, rect, _) => Some((color, rect)),
             _ => None,
-        },
-        None => None,
+        })
+        .collect();
+
+    // <html> has a white background by default, and #b is transparent.
+    assert_eq!(
+        solid_colors,
+        vec![
+            (
+                WHITE,
+                Rect {
+                    x: Au(0),
+                    y: Au(0),
+                    width: Au::from_f64_px(100.0),
+                    height: Au::from_f64_px(20.0),
+                },
+            ),
+            (
+                RED,
+                Rect {
+                    x: Au(0),
+                    y: Au(0),
+                    width: Au::from_f64_px(100.0),
+                    height: Au::from_f64_px(10.0),
+                },
+            ),
+        ]
+    );
+}
+
+#[test]
+fn test_border() {
+    use css::BLACK;
+    use layout::px_rect;
+
+    let html_src = "<html><body><div></div></body></html>";
+    let css_src = "div { border: 2px solid black; height: 10px; }";
+    let list = display_list_for(html_src, css_src, 100.0);
+
+    let borders: Vec<(Color, Vec<Rect>)> = list
+        .iter()
+        .filter_map(|item| match item.command {
+            DisplayCommand::Border(color, rect, ref edges, BorderStyle::Solid, _) => {
+                Some((color, border_edge_rects(rect, edges)))
+            }
+            _ => None,
+        })
+        .collect();
+
+    assert_eq!(
+        borders,
+        vec![
+            (
+                BLACK,
+                vec![
+                    px_rect(0.0, 0.0, 100.0, 2.0),
+                    px_rect(98.0, 0.0, 2.0, 14.0),
+                    px_rect(0.0, 12.0, 100.0, 2.0),
+                    px_rect(0.0, 0.0, 2.0, 14.0),
+                ],
+            ),
+        ]
+    );
+}
+
+#[test]
+fn test_overflow_hidden() {
+    use css::{BLUE, RED};
+    use layout::px_rect;
+
+    let html_src = "<html><body><div id='a'><div id='b'>text</div></div><div id='c'></div>\
+                    </body></html>";
+    let css_src = "body { margin: 0; }
+                   #a { overflow: hidden; width: 50px; height: 20px; padding: 5px; }
+                   #b { width: 200px; height: 100px; background-color: blue; }
+                   #c { height: 10px; background-color: red; }";
+    let list = display_list_for(html_src, css_src, 100.0);
+
+    let position = |f: &Fn(&DisplayCommand) -> bool| {
+        list.iter().position(|item| f(&item.command)).unwrap()
+    };
+
+    // The descendants of #a are clipped to its padding box.
+    let push = position(&|command| match *command {
+        DisplayCommand::ClipPush(clip, _) => clip == px_rect(0.0, 0.0, 60.0, 30.0),
+        _ => false,
+    });
+    let pop = position(&|command| match *command {
+        DisplayCommand::ClipPop => true,
+        _ => false,
+    });
+    // The child and its text are painted within the clip, and the next box isn't clipped.
+    let blue = position(&|command| match *command {
+        DisplayCommand::SolidColor(color, _, _) => color == BLUE,
+        _ => false,
+    });
+    let text = position(&|command| match *command {
+        DisplayCommand::Text(..) => true,
+        _ => false,
+    });
+    let red = position(&|command| match *command {
+        DisplayCommand::SolidColor(color, _, _) => color == RED,
+        _ => false,
+    });
+    assert!(push < blue && blue < text && text < pop && pop < red);
+}
+
+#[test]
+fn test_z_index() {
+    use css::{BLUE, GREEN, RED, WHITE};
+
+    let html_src = "<html><body><div id='a'></div><div id='b'></div><div id='c'></div>\
+                    <div id='d'></div></body></html>";
+    let css_src = "div { height: 10px; }
+                   #a { background-color: red; position: relative; z-index: 2; }
+                   #b { background-color: green; }
+                   #c { background-color: blue; position: relative; z-index: -1; }
+                   #d { background-color: red; z-index: -5; }";
+    let list = display_list_for(html_src, css_src, 100.0);
+
+    let colors: Vec<Color> = list
+        .iter()
+        .filter_map(|item| match item.command {
+            DisplayCommand::SolidColor(color, _, _) => Some(color),
+            _ => None,
+        })
+        .collect();
+
+    // #c is painted above the background of the root, but behind the in-flow content. The
+    // z-index of #d, which isn't positioned, is ignored.
+    assert_eq!(colors, vec![WHITE, BLUE, GREEN, RED, RED]);
+}
+
+#[test]
+fn test_stacking_order() {
+    use css::{BLUE, GREEN, RED, WHITE};
+
+    // #a is nested in a box in the flow, and #b overlaps it.
+    let html_src = "<html><body><div><div id='a'></div></div><div id='b'></div>\
+                    <div id='c'></div></body></html>";
+    let css_src = "body { margin: 0; }
+                   #a { position: absolute; width: 50px; height: 50px; z-index: 2; \
+                        background-color: red; }
+                   #b { position: absolute; left: 20px; width: 50px; height: 50px; \
+                        z-index: 1; background-color: blue; }
+                   #c { height: 10px; background-color: green; }";
+    let list = display_list_for(html_src, css_src, 100.0);
+
+    let colors: Vec<Color> = list
+        .iter()
+        .filter_map(|item| match item.command {
+            DisplayCommand::SolidColor(color, _, _) if color != WHITE => Some(color),
+            _ => None,
+        })
+        .collect();
+
+    // The positioned boxes are painted after the in-flow content, and the higher z-index last.
+    assert_eq!(colors, vec![GREEN, BLUE, RED]);
+}
+
+#[test]
+fn test_nested_absolute_order() {
+    use css::{GREEN, RED, WHITE};
+
+    // #a is absolutely positioned without z-index, inside a box in the flow before #b.
+    let html_src = "<html><body><div><div id='a'></div></div><div id='b'></div></body></html>";
+    let css_src = "body { margin: 0; }
+                   #a { position: absolute; width: 50px; height: 50px; background-color: red; }
+                   #b { height: 10px; background-color: green; }";
+    let list = display_list_for(html_src, css_src, 100.0);
+
+    let colors: Vec<Color> = list
+        .iter()
+        .filter_map(|item| match item.command {
+            DisplayCommand::SolidColor(color, _, _) if color != WHITE => Some(color),
+            _ => None,
+        })
+        .collect();
+
+    // #a is painted above the later in-flow sibling of its parent.
+    assert_eq!(colors, vec![GREEN, RED]);
+}
+
+#[test]
+fn test_gradient() {
+    use css::GradientDirection;
+
+    let html_src = "<html><body><div id='a'></div><div id='b'></div></body></html>";
+    let css_src = "#a { background: linear-gradient(to right, red, blue); height: 10px; } \
+                   #b { background-image: linear-gradient(to nowhere, red, blue); height: 10px; }";
+    let list = display_list_for(html_src, css_src, 100.0);
+
+    let gradients: Vec<(GradientDirection, Rect)> = list
+        .iter()
+        .filter_map(|item| match item.command {
+            DisplayCommand::Gradient(ref gradient, rect) => Some((gradient.direction, rect)),
+            _ => None,
+        })
+        .collect();
+
+    // The invalid gradient on #b is ignored.
+    assert_eq!(
+        gradients,
+        vec![
+            (
+                GradientDirection::ToRight,
+                Rect {
+                    x: Au(0),
+                    y: Au(0),
+                    width: Au::from_f64_px(100.0),
+                    height: Au::from_f64_px(10.0),
+                },
+            ),
+        ]
+    );
+}
+
+#[test]
+fn test_box_shadow() {
+    use css::{RED, WHITE};
+    use layout::px_rect;
+
+    let html_src = "<html><body><div id='a'></div><div id='b'></div><div id='c'></div>\
+                    </body></html>";
+    let css_src = "div { height: 10px; width: 50px; }
+                   #a { box-shadow: 5px 5px 4px #f00, 1px 1px #00f; background-color: white; }
+                   #b { box-shadow: -1px 2px 0 3px red; border-radius: 2px; }
+                   #c { box-shadow: inset 1px 1px red; }";
+    let list = display_list_for(html_src, css_src, 100.0);
+
+    let px = Au::from_f64_px;
+    let commands: Vec<(Color, Rect, CornerRadii, Option<Au>)> = list
+        .iter()
+        .filter_map(|item| match item.command {
+            DisplayCommand::SolidColor(color, rect, radius) => Some((color, rect, radius, None)),
+            DisplayCommand::BoxShadow(color, rect, radius, blur) => {
+                Some((color, rect, radius, Some(blur)))
+            }
+            _ => None,
+        })
+        .collect();
+
+    // Only the first shadow is painted, underneath the background. The spread grows the shadow
+    // and its rounded corners. Inset shadows aren't painted.
+    assert_eq!(
+        commands[1..].to_vec(),
+        vec![
+            (RED, px_rect(5.0, 5.0, 50.0, 10.0), CornerRadii::default(), Some(px(4.0))),
+            (WHITE, px_rect(0.0, 0.0, 50.0, 10.0), CornerRadii::default(), None),
+            (RED, px_rect(-4.0, 9.0, 56.0, 16.0), CornerRadii::uniform(px(5.0)), Some(px(0.0))),
+        ]
+    );
+}
+
+#[test]
+fn test_border_radius() {
+    use css::RED;
+
+    let html_src = "<html><body><div id='a'></div><div id='b'></div><div id='c'></div>\
+                    </body></html>";
+    let css_src = "div { height: 10px; }
+                   #a { background-color: red; border-radius: 4px 2px; \
+                        border-bottom-left-radius: 1px; }
+                   #b { background-color: red; }
+                   #c { background: linear-gradient(red, blue); border-radius: 3px; \
+                        overflow: hidden; border: 1px solid black; }";
+    let list = display_list_for(html_src, css_src, 100.0);
+
+    let px = Au::from_f64_px;
+    let radii: Vec<CornerRadii> = list
+        .iter()
+        .filter_map(|item| match item.command {
+            DisplayCommand::SolidColor(color, _, radius) if color == RED => Some(radius),
+            _ => None,
+        })
+        .collect();
+    // The longhand overrides the shorthand, whose two values are for the opposite corners.
+    assert_eq!(
+        radii,
+        vec![
+            CornerRadii {
+                top_left: px(4.0),
+                top_right: px(2.0),
+                bottom_right: px(4.0),
+                bottom_left: px(1.0),
+            },
+            CornerRadii::default(),
+        ]
+    );
+
+    // The gradient is clipped to the rounded border box, and the descendants to the inner edge
+    // of the border.
+    let clips: Vec<CornerRadii> = list
+        .iter()
+        .filter_map(|item| match item.command {
+            DisplayCommand::ClipPush(_, radius) => Some(radius),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(clips, vec![CornerRadii::uniform(px(3.0)), CornerRadii::uniform(px(2.0))]);
+}
+
+#[test]
+fn test_list_marker() {
+    let html_src = "<html><body>\
+                    <ol><li>a</li><li>b<ol><li>c</li></ol></li><li>d</li></ol>\
+                    <ul><li>e</li><li style='list-style-type: none'>f</li></ul>\
+                    </body></html>";
+    let display_list = display_list_for(html_src, "", 300.0);
+
+    // Numbers are drawn to the left of the list items, and the nested list restarts at 1.
+    let numbers: Vec<(String, Rect)> = display_list
+        .iter()
+        .filter_map(|item| match item.command {
+            DisplayCommand::Text(ref text, rect, _, _, _) if text.ends_with('.') => {
+                Some((text.clone(), rect))
+            }
+            _ => None,
+        })
+        .collect();
+    assert_eq!(
+        numbers.iter().map(|n| n.0.as_str()).collect::<Vec<&str>>(),
+        vec!["1.", "2.", "1.", "3."]
+    );
+    let right_edge = |rect: Rect| rect.x + rect.width;
+    assert!(right_edge(numbers[0].1) < Au::from_f64_px(40.0));
+    assert_eq!(right_edge(numbers[0].1), right_edge(numbers[1].1));
+    assert_eq!(right_edge(numbers[1].1), right_edge(numbers[3].1));
+    assert!(right_edge(numbers[2].1) > Au::from_f64_px(40.0));
+    assert!(numbers[0].1.y < numbers[1].1.y && numbers[1].1.y < numbers[3].1.y);
+
+    // One disc for the <ul>. The second item has `list-style-type: none`.
+    let discs = display_list
+        .iter()
+        .filter(|item| match item.command {
+            DisplayCommand::SolidColor(color, _, radius) => color == BLACK && !radius.is_zero(),
+            _ => false,
+        })
+        .count();
+    assert_eq!(discs, 1);
+}
+
+#[test]
+fn test_list_style_types() {
+    let html_src = "<html><body><ul>\
+                    <li style='list-style-type: circle'>a</li>\
+                    <li style='list-style-type: square'>b</li>\
+                    <li style='list-style-type: decimal'>c</li>\
+                    </ul><div style='display: list-item; list-style-type: decimal'>d</div>\
+                    </body></html>";
+    let display_list = display_list_for(html_src, "", 300.0);
+
+    let circles: Vec<Rect> = display_list
+        .iter()
+        .filter_map(|item| match item.command {
+            DisplayCommand::Border(color, rect, _, BorderStyle::Solid, radius)
+                if color == BLACK && !radius.is_zero() =>
+            {
+                Some(rect)
+            }
+            _ => None,
+        })
+        .collect();
+    let squares: Vec<Rect> = display_list
+        .iter()
+        .filter_map(|item| match item.command {
+            DisplayCommand::SolidColor(color, rect, radius)
+                if color == BLACK && radius.is_zero() =>
+            {
+                Some(rect)
+            }
+            _ => None,
+        })
+        .collect();
+    assert_eq!(circles.len(), 1);
+    assert_eq!(squares.len(), 1);
+    assert_eq!(circles[0].width, squares[0].width);
+    assert!(circles[0].y < squares[0].y);
+
+    // The third item is the third in its list. Any element with `display: list-item` has a
+    // marker.
+    let numbers: Vec<String> = display_list
+        .iter()
+        .filter_map(|item| match item.command {
+            DisplayCommand::Text(ref text, _, _, _, _) if text.ends_with('.') => Some(text.clone()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(numbers, vec!["3.".to_string(), "1.".to_string()]);
+}
+
+#[test]
+fn test_hr() {
+    use css::GRAY;
+    use layout::px_rect;
+
+    let html_src = "<html><body><hr><hr class='half'></body></html>";
+    let css_src = ".half { width: 50%; }";
+    let list = display_list_for(html_src, css_src, 100.0);
+
+    let borders: Vec<(Color, Rect)> = list
+        .iter()
+        .filter_map(|item| match item.command {
+            DisplayCommand::Border(color, rect, _, _, _) => Some((color, rect)),
+            _ => None,
+        })
+        .collect();
+
+    // Each <hr> is a 2px gray line made of its top and bottom borders.
+    let rect = |y, width| px_rect(0.0, y, width, 2.0);
+    assert_eq!(borders, vec![(GRAY, rect(8.0, 100.0)), (GRAY, rect(18.0, 52.0))]);
+}
+
+#[test]
+fn test_visibility() {
+    use css::{BLUE, RED};
+
+    let html_src = "<html><body>\
+                    <div class='hidden'>a<a href='x'>link</a><span class='shown'>c</span></div>\
+                    <div class='none'>b</div>\
+                    <div id='after'></div>\
+                    </body></html>";
+    let css_src = "div { height: 10px; } \
+                   .hidden { visibility: hidden; background-color: red; } \
+                   .shown { visibility: visible; } \
+                   .none { display: none; } \
+                   #after { background-color: blue; }";
+    let document = Rc::new(Document::new());
+    let display_list = display_list_in(&document, html_src, css_src, 100.0);
+
+    // The hidden box keeps its space, but the box with `display: none` doesn't.
+    let solid_colors: Vec<(Color, Au)> = display_list
+        .iter()
+        .filter_map(|item| match item.command {
+            DisplayCommand::SolidColor(color, rect, _) => Some((color, rect.y)),
+            _ => None,
+        })
+        .collect();
+    assert!(!solid_colors.iter().any(|&(color, _)| color == RED));
+    assert!(solid_colors.contains(&(BLUE, Au::from_f64_px(10.0))));
+
+    // Only the descendant that overrides `visibility` is painted.
+    let texts: Vec<String> = display_list
+        .iter()
+        .filter_map(|item| match item.command {
+            DisplayCommand::Text(ref text, _, _, _, _) => Some(text.clone()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(texts, vec!["c".to_string()]);
+
+    // Hidden links can't be clicked.
+    assert!(document.ankers.borrow().is_empty());
+}
+
+#[test]
+fn test_relative_position() {
+    use css::{BLUE, RED};
+    use layout::px_rect;
+
+    let html_src = "<html><body><div id='a'><a href='x'>link</a></div><div id='b'></div>\
+                    </body></html>";
+    let css_src = "body { margin: 0; } \
+                   #a { position: relative; top: 5px; left: 10%; height: 20px; \
+                        background-color: red; } \
+                   #b { position: relative; bottom: 3px; right: 4px; height: 10px; \
+                        background-color: blue; }";
+    let document = Rc::new(Document::new());
+    let display_list = display_list_in(&document, html_src, css_src, 200.0);
+
+    let solid_colors: Vec<(Color, Rect)> = display_list
+        .iter()
+        .filter_map(|item| match item.command {
+            DisplayCommand::SolidColor(color, rect, _) if color == RED || color == BLUE => {
+                Some((color, rect))
+            }
+            _ => None,
+        })
+        .collect();
+    // The boxes are painted at the offsets, but the space in the flow is where they would be
+    // without them.
+    assert_eq!(
+        solid_colors,
+        vec![
+            (RED, px_rect(20.0, 5.0, 200.0, 20.0)),
+            (BLUE, px_rect(-4.0, 17.0, 200.0, 10.0)),
+        ]
+    );
+
+    // The link moves with its parent.
+    let ankers = document.ankers.borrow();
+    assert!(!ankers.is_empty());
+    assert!(
+        ankers
+            .keys()
+            .all(|rect| rect.x >= Au::from_f64_px(20.0) && rect.y >= Au::from_f64_px(5.0))
+    );
+}
+
+#[test]
+fn test_display_none() {
+    use css::{BLUE, RED};
+
+    let html_src = "<html><body>\
+                    <div id='a'></div>\
+                    <div class='none'>text<a href='x'>link</a><div>nested</div></div>\
+                    <div class='none' style='float: left'>float</div>\
+                    <span class='none'>inline</span>\
+                    <div id='b'></div>\
+                    </body></html>";
+    let css_src = "#a, #b { height: 10px; background-color: blue; } \
+                   #a { margin-bottom: 20px; } #b { margin-top: 10px; } \
+                   .none { display: none; height: 50px; margin: 40px; background-color: red; }";
+    let document = Rc::new(Document::new());
+    let display_list = display_list_in(&document, html_src, css_src, 100.0);
+
+    // Nothing of the elements and their descendants is painted, and the margins of the siblings
+    // collapse as if they weren't there.
+    let solid_colors: Vec<(Color, Au)> = display_list
+        .iter()
+        .filter_map(|item| match item.command {
+            DisplayCommand::SolidColor(color, rect, _) if color != WHITE => Some((color, rect.y)),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(
+        solid_colors,
+        vec![(BLUE, Au::from_f64_px(0.0)), (BLUE, Au::from_f64_px(30.0))]
+    );
+    assert!(!display_list.iter().any(|item| match item.command {
+        DisplayCommand::Text(_, _, _, _, _) => true,
+        DisplayCommand::SolidColor(color, _, _) => color == RED,
+        _ => false,
+    }));
+    assert!(document.ankers.borrow().is_empty());
+}
+
+#[test]
+fn test_script_and_style_not_rendered() {
+    let html_src = "<html><head><style>p { color: red; }</style></head><body>\
+                    <p>before</p><script>var s = 'script';</script><p>after</p></body></html>";
+    let texts: Vec<String> = display_list_for(html_src, "", 300.0)
+        .iter()
+        .filter_map(|item| match item.command {
+            DisplayCommand::Text(ref text, _, _, _, _) => Some(text.clone()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(texts, vec!["before".to_string(), "after".to_string()]);
+}
+
+#[test]
+fn test_image_size() {
+    // A 20x10 PNG.
+    let src = "data:image/png;base64,iVBORw0KGgoAAAANSUhEUgAAABQAAAAKCAIAAAA7N+mxAAAAFUlEQVR42mP4z8\
+               BANmIY1TyqmRACADFExzlExWpRAAAAAElFTkSuQmCC";
+    let html_src = format!(
+        "<html><body><img src='{0}'><img src='{0}' width='40'><img src='{0}' height='5'>\
+         <img src='{0}' width='30' height='30'><img id='a' src='{0}' width='30'>\
+         <img id='b' src='{0}' height='30'></body></html>",
+        src
+    );
+    let css_src = "#a { width: auto; } #b { width: 100%; }";
+    let list = display_list_for(html_src.as_str(), css_src, 100.0);
+
+    let sizes: Vec<(f64, f64)> = list
+        .iter()
+        .filter_map(|item| match item.command {
+            DisplayCommand::Image(_, rect) => {
+                Some((rect.width.to_f64_px(), rect.height.to_f64_px()))
+            }
+            _ => None,
+        })
+        .collect();
+    // The intrinsic size is scaled to one specified dimension keeping the aspect ratio. CSS wins
+    // over the attributes, unless it's `auto`.
+    assert_eq!(
+        sizes,
+        vec![
+            (20.0, 10.0),
+            (40.0, 20.0),
+            (10.0, 5.0),
+            (30.0, 30.0),
+            (30.0, 15.0),
+            (100.0, 30.0),
+        ]
+    );
+}
+
+#[test]
+fn test_broken_image() {
+    let html_src = "<html><body><img src='file:///nonexistent/a.png' alt='gone'>\
+                    <img src='file:///nonexistent/b.png' alt='' width='50' height='50'>\
+                    <img alt='sized' width='100' height='30'></body></html>";
+    let list = display_list_for(html_src, "", 400.0);
+
+    let clips: Vec<Rect> = list
+        .iter()
+        .filter_map(|item| match item.command {
+            DisplayCommand::ClipPush(rect, _) => Some(rect),
+            _ => None,
+        })
+        .collect();
+    let texts: Vec<(String, Rect)> = list
+        .iter()
+        .filter_map(|item| match item.command {
+            DisplayCommand::Text(ref text, rect, _, _, _) => Some((text.clone(), rect)),
+            _ => None,
+        })
+        .collect();
+
+    // The image with an empty alt collapses, and the others show the alt text in a box.
+    assert_eq!(clips.len(), 2);
+    assert_eq!(
+        texts.iter().map(|t| t.0.as_str()).collect::<Vec<&str>>(),
+        vec!["gone", "sized"]
+    );
+    // Without the size in the attributes, the box fits the glyph and the alt text.
+    let px = Au::from_f64_px;
+    let (alt, box_rect) = (texts[0].1, clips[0]);
+    let inset = px(BROKEN_IMAGE_BORDER + BROKEN_IMAGE_PADDING);
+    assert_eq!(alt.x, box_rect.x + inset + px(BROKEN_IMAGE_SIZE + BROKEN_IMAGE_GAP));
+    assert_eq!(alt.x + alt.width + inset, box_rect.x + box_rect.width);
+    assert!(box_rect.height >= px(BROKEN_IMAGE_SIZE) + inset * 2);
+    // Otherwise, the attributes win.
+    assert_eq!((clips[1].width, clips[1].height), (px(100.0), px(30.0)));
+}
+
+#[test]
+fn test_background_image() {
+    use css::RED;
+
+    let html_src = "<html><body><div id='a'></div></body></html>";
+    let css_src = "#a { background: red url('file:///nonexistent/image.png') no-repeat; \
+                   height: 10px; }";
+    let display_list = display_list_for(html_src, css_src, 100.0);
+
+    // A missing image falls back to the background color.
+    assert!(!display_list.iter().any(|item| match item.command {
+        DisplayCommand::BackgroundImage(_, _, _, _) => true,
+        _ => false,
+    }));
+    assert!(display_list.iter().any(|item| match item.command {
+        DisplayCommand::SolidColor(color, _, _) => color == RED,
+        _ => false,
+    }));
+
+    // A positioned image that isn't repeated is placed in the padding box, and painted in the
+    // border box.
+    let image = format!("{}/example/image2.png", env!("CARGO_MANIFEST_DIR"));
+    let (image_width, image_height) = {
+        let pixbuf = gdk_pixbuf::Pixbuf::new_from_file(image.as_str()).unwrap();
+        (pixbuf.get_width() as f64, pixbuf.get_height() as f64)
+    };
+    let css_src = format!(
+        "#a {{ background: url('file://{}') no-repeat right 10px; padding: 5px; \
+         border: 2px solid black; width: 1000px; height: 500px; }}",
+        image
+    );
+    let display_list = display_list_for(html_src, css_src.as_str(), 1100.0);
+    let px = Au::from_f64_px;
+    let backgrounds: Vec<(Rect, Rect, BackgroundRepeat)> = display_list
+        .iter()
+        .filter_map(|item| match item.command {
+            DisplayCommand::BackgroundImage(_, rect, tile, repeat) => Some((rect, tile, repeat)),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(
+        backgrounds,
+        vec![
+            (
+                Rect {
+                    x: px(0.0),
+                    y: px(0.0),
+                    width: px(1014.0),
+                    height: px(514.0),
+                },
+                Rect {
+                    x: px(2.0 + 1010.0 - image_width),
+                    y: px(2.0 + 10.0),
+                    width: px(image_width),
+                    height: px(image_height),
+                },
+                BackgroundRepeat::NoRepeat,
+            ),
+        ]
+    );
+
+    let rect = Rect {
+        x: Au(0),
+        y: Au(0),
+        width: Au::from_f64_px(100.0),
+        height: Au::from_f64_px(50.0),
+    };
+    assert_eq!(
+        background_image_size(BackgroundSize::Auto, (20.0, 10.0), rect),
+        (px(20.0), px(10.0))
+    );
+    assert_eq!(
+        background_image_size(BackgroundSize::Cover, (20.0, 20.0), rect),
+        (px(100.0), px(100.0))
+    );
+    assert_eq!(
+        background_image_size(BackgroundSize::Contain, (20.0, 20.0), rect),
+        (px(50.0), px(50.0))
+    );
+}
+
+#[test]
+fn test_opacity() {
+    let html_src = "<html><body><div id='a'><div id='b'></div></div><div id='c'></div>\
+                    </body></html>";
+    let css_src = "div { height: 10px; background-color: red; } \
+                   #a { opacity: 0.5; } #b { opacity: 2; } #c { opacity: 0.25; }";
+    let list = display_list_for(html_src, css_src, 100.0);
+
+    let commands: Vec<String> = list
+        .iter()
+        .map(|item| match item.command {
+            DisplayCommand::SolidColor(_, _, _) => "color".to_string(),
+            DisplayCommand::GroupPush => "push".to_string(),
+            DisplayCommand::GroupPop(alpha) => format!("pop {}", alpha),
+            _ => "other".to_string(),
+        })
+        .collect();
+
+    // #a and #b are composited as one group. #b's opacity is clamped to 1, which needs no group.
+    assert_eq!(
+        commands,
+        vec![
+            "color", "push", "color", "color", "pop 0.5", "push", "color", "pop 0.25"
+        ]
+    );
+}
+
+#[test]
+fn test_text_shadow() {
+    use css::{BLUE, RED};
+
+    let html_src = "<html><body><div>a<a href='x'>b</a></div></body></html>";
+    let css_src = "div { color: #000; text-shadow: 1px 2px red, 3px 4px blue; }";
+    let document = Rc::new(Document::new());
+    let display_list = display_list_in(&document, html_src, css_src, 100.0);
+
+    let texts: Vec<(String, Rect, Color)> = display_list
+        .iter()
+        .filter_map(|item| match item.command {
+            DisplayCommand::Text(ref text, rect, color, _, _) => {
+                Some((text.clone(), rect, color))
+            }
+            _ => None,
+        })
+        .collect();
+    assert_eq!(texts.len(), 6);
+
+    // The last shadow is painted first, and the text itself last.
+    let (_, rect, _) = texts[2];
+    let offset = |dx: f64, dy: f64| Rect {
+        x: rect.x + Au::from_f64_px(dx),
+        y: rect.y + Au::from_f64_px(dy),
+        ..rect
+    };
+    assert_eq!(texts[0], ("a".to_string(), offset(3.0, 4.0), BLUE));
+    assert_eq!(texts[1], ("a".to_string(), offset(1.0, 2.0), RED));
+    assert_eq!(texts[2].0, "a".to_string());
+    assert_eq!(texts[2].2, Color { r: 0, g: 0, b: 0, a: 255 });
+    assert_eq!(texts[5].0, "b".to_string());
+    // The shadows are marked, so that they can't be selected.
+    let shadows: Vec<bool> = display_list
+        .iter()
+        .filter_map(|item| match item.command {
+            DisplayCommand::Text(..) => Some(item.text_shadow),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(shadows, vec![true, true, false, true, true, false]);
+
+    // Only the link itself can be clicked.
+    assert_eq!(document.ankers.borrow().len(), 1);
+}
+
+#[test]
+fn test_stale_ankers() {
+    use window::hit_test_ankers;
+
+    let document = Rc::new(Document::new());
+    let display_list = |html_src: &str| display_list_in(&document, html_src, "", 300.0);
+    let clicked = || hit_test_ankers(1.0, 1.0, &*document.ankers.borrow()).cloned();
+
+    // Page A has a link at the top.
+    display_list("<html><body><a href='b.html'>link</a></body></html>");
+    match clicked() {
+        Some(AnkerKind::URL(ref url)) => assert_eq!(url, "b.html"),
+        _ => panic!(),
     }
+
+    // Page B has no links, so clicking the same place does nothing.
+    display_list("<html><body><div>no links</div></body></html>");
+    assert!(clicked().is_none());
+
+    // A link moved by a reflow is only registered at the new position.
+    display_list("<html><body><div>text</div><a href='b.html'>link</a></body></html>");
+    assert!(clicked().is_none());
+    assert_eq!(document.ankers.borrow().len(), 1);
+}
+
+#[test]
+fn test_text_wrapping() {
+    let paragraph = "the quick brown fox jumps over the lazy dog";
+    let html_src = format!("<html><body><div>{}</div></body></html>", paragraph);
+    let list = display_list_for(&html_src, "div { width: 100px; }", 300.0);
+
+    let texts: Vec<(String, Rect)> = list
+        .iter()
+        .filter_map(|item| match item.command {
+            DisplayCommand::Text(ref text, rect, _, _, _) => Some((text.clone(), rect)),
+            _ => None,
+        })
+        .collect();
+
+    // Each line is a Text command below the previous one, and fits in the container.
+    assert!(texts.len() > 1);
+    for (i, &(_, rect)) in texts.iter().enumerate() {
+        assert!(rect.width <= Au::from_f64_px(100.0));
+        if i > 0 {
+            assert!(rect.y > texts[i - 1].1.y);
+        }
+    }
+    // Lines are broken at word boundaries.
+    let words: Vec<&str> = texts.iter().flat_map(|t| t.0.split_whitespace()).collect();
+    assert_eq!(words, paragraph.split(' ').collect::<Vec<&str>>());
+}
+
+#[test]
+fn test_line_height() {
+    let line_advance = |css_src: &str| {
+        let html_src = "<html><body><div>first second</div></body></html>";
+        let list = display_list_for(html_src, css_src, 300.0);
+        let ys: Vec<Au> = list
+            .iter()
+            .filter_map(|item| match item.command {
+                DisplayCommand::Text(_, rect, _, _, _) => Some(rect.y),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(ys.len(), 2);
+        ys[1] - ys[0]
+    };
+    let css_src = |line_height: &str| {
+        format!(
+            "div {{ width: 1px; font-size: 20px; line-height: {}; }}",
+            line_height
+        )
+    };
+
+    // Each word is on its own line, which is as tall as the line height.
+    assert_eq!(line_advance(&css_src("2")), Au::from_f64_px(40.0));
+    assert_eq!(line_advance(&css_src("30px")), Au::from_f64_px(30.0));
+    assert_eq!(line_advance(&css_src("150%")), Au::from_f64_px(30.0));
+}
+
+#[test]
+fn test_letter_spacing() {
+    let text_width = |css_src: &str| {
+        let list = display_list_for("<html><body><h1>Heading</h1></body></html>", css_src, 800.0);
+        list.iter()
+            .filter_map(|item| match item.command {
+                DisplayCommand::Text(_, rect, _, _, _) => Some(rect.width),
+                _ => None,
+            })
+            .next()
+            .unwrap()
+    };
+    let width = text_width("");
+
+    assert!(text_width("h1 { letter-spacing: 4px; }") > width);
+    assert!(text_width("h1 { letter-spacing: -2px; }") < width);
+    // Tightening is limited, so the text doesn't collapse.
+    assert_eq!(
+        text_width("h1 { letter-spacing: -1000px; }"),
+        text_width("h1 { letter-spacing: -0.25em; }")
+    );
+}
+
+#[test]
+fn test_text_align() {
+    let paragraph = "the quick brown fox jumps over the lazy dog";
+    let lines = |text_align: &str| {
+        let html_src = format!("<html><body><div>{}</div></body></html>", paragraph);
+        let css_src = format!("div {{ width: 100px; text-align: {}; }}", text_align);
+
+        // (left edge, right edge) of each line
+        let mut lines: Vec<(Au, Au)> = vec![];
+        let mut last_y = None;
+        for item in display_list_for(&html_src, &css_src, 300.0) {
+            if let DisplayCommand::Text(_, rect, _, _, _) = item.command {
+                if last_y == Some(rect.y) {
+                    lines.last_mut().unwrap().1 = rect.x + rect.width;
+                } else {
+                    lines.push((rect.x, rect.x + rect.width));
+                }
+                last_y = Some(rect.y);
+            }
+        }
+        lines
+    };
+    let width = Au::from_f64_px(100.0);
+    let one_px = Au::from_f64_px(1.0);
+
+    let left = lines("left");
+    assert!(left.len() > 1);
+    assert!(left.iter().all(|&(l, _)| l == Au(0)));
+
+    let right = lines("right");
+    assert_eq!(right.len(), left.len());
+    assert!(right.iter().all(|&(_, r)| r == width));
+
+    let center = lines("center");
+    assert_eq!(center.len(), left.len());
+    for (&(l, r), &(left_l, left_r)) in center.iter().zip(left.iter()) {
+        assert!((l - (width - r)).0.abs() <= 1);
+        assert_eq!(r - l, left_r - left_l);
+    }
+
+    // All lines but the last one fill the width.
+    let justify = lines("justify");
+    assert_eq!(justify.len(), left.len());
+    for &(l, r) in &justify[..justify.len() - 1] {
+        assert_eq!(l, Au(0));
+        assert!(width - r < one_px);
+    }
+    let (l, r) = *justify.last().unwrap();
+    assert_eq!(l, Au(0));
+    assert!((r - left.last().unwrap().1).0.abs() < one_px.0);
+}
+
+#[test]
+fn test_text_decoration() {
+    let html_src = "<html><body>\
+                    <div class='u'>the quick brown fox jumps over the lazy dog</div>\
+                    <div class='s'>struck <span class='n'>through</span></div>\
+                    <div>plain</div>\
+                    </body></html>";
+    let css_src = "div { width: 100px; } \
+                   .u { text-decoration: underline; } \
+                   .s { text-decoration: line-through; } \
+                   .n { text-decoration: none; }";
+    let list = display_list_for(html_src, css_src, 300.0);
+
+    let texts: Vec<(String, Vec<TextDecoration>)> = list
+        .iter()
+        .filter_map(|item| match item.command {
+            DisplayCommand::Text(ref text, _, _, ref decorations, _) => {
+                Some((text.trim().to_string(), decorations.clone()))
+            }
+            _ => None,
+        })
+        .collect();
+
+    // Each line of the wrapped text is underlined.
+    let (underlined, rest): (Vec<_>, Vec<_>) = texts
+        .into_iter()
+        .partition(|&(_, ref d)| d == &vec![TextDecoration::Underline]);
+    assert!(underlined.len() > 1);
+    // `none` doesn't remove the decoration of the parent.
+    assert_eq!(
+        rest,
+        vec![
+            ("struck".to_string(), vec![TextDecoration::LineThrough]),
+            ("through".to_string(), vec![TextDecoration::LineThrough]),
+            ("plain".to_string(), vec![]),
+        ]
+    );
+}
+
+#[test]
+fn test_titles() {
+    let html_src = "<html><body><div title='outer' style='height: 100px'>\
+                    <div title='inner' style='height: 20px'></div>\
+                    <div title='' style='height: 20px'></div></div>\
+                    <div style='visibility: hidden' title='hidden'>text</div></body></html>";
+    let document = Rc::new(Document::new());
+    display_list_in(&document, html_src, "", 300.0);
+
+    let titles: Vec<String> = document
+        .titles
+        .borrow()
+        .iter()
+        .map(|&(_, ref title)| title.clone())
+        .collect();
+    // Ancestors come first. Hidden boxes have no tooltip.
+    assert_eq!(titles, vec!["outer".to_string(), "inner".to_string(), "".to_string()]);
+}
+
+#[test]
+fn test_url_fragments() {
+    let html_src = "<html><body><div style='height: 100px'></div>\
+                    <div id='section2' style='padding: 5px'>text</div>\
+                    <a name='named' style='display: block'>old style</a></body></html>";
+    let css_src = "body { margin: 0px; }";
+    let document = Rc::new(Document::new());
+    display_list_in(&document, html_src, css_src, 300.0);
+
+    // The top of the border box is registered.
+    let rect = document.url_fragments.borrow().get("section2").cloned();
+    assert_eq!(rect.map(|rect| rect.y), Some(Au::from_f64_px(100.0)));
+    assert!(!document.url_fragments.borrow().contains_key("missing"));
+    // <a name> is a target too.
+    assert!(document.url_fragments.borrow().contains_key("named"));
+}
+
+#[test]
+fn test_zoom() {
+    use layout::{with_layout_tree_in, Dimensions};
+
+    let html_src = "<html><body><p><a href='#p'>zoomed</a> text</p></body></html>";
+    // The window is 400px wide, which is narrower in CSS px when zoomed in.
+    let paint = |zoom: f64| {
+        let mut viewport: Dimensions = Default::default();
+        viewport.content.width = Au::from_f64_px(400.0 / zoom);
+        let document = Rc::new(Document::new());
+        let list = with_layout_tree_in(html_src, "", viewport, &document, |layout_tree| {
+            build_zoomed_display_list(&document, layout_tree, zoom, false)
+        });
+        let ankers = document.ankers.borrow().keys().cloned().collect::<Vec<_>>();
+        (list, ankers)
+    };
+    let text = |list: &DisplayList| -> Vec<(Rect, Au)> {
+        list.iter()
+            .filter_map(|item| match item.command {
+                DisplayCommand::Text(_, rect, _, _, ref font) => Some((rect, font.size)),
+                _ => None,
+            })
+            .collect()
+    };
+
+    // Doubling the zoom doubles the text and its font, and the links with it.
+    let ((list1, ankers1), (list2, ankers2)) = (paint(1.0), paint(2.0));
+    let doubled: Vec<_> = text(&list1)
+        .into_iter()
+        .map(|(rect, size)| (rect.zoomed(2.0), Au(size.0 * 2)))
+        .collect();
+    assert!(!doubled.is_empty());
+    assert_eq!(text(&list2), doubled);
+    assert_eq!(ankers1.len(), 1);
+    assert_eq!(
+        ankers2,
+        ankers1.into_iter().map(|rect| rect.zoomed(2.0)).collect::<Vec<_>>()
+    );
 }
@@ -0,0 +1,274 @@
+use painter::{DisplayCommand, DisplayList};
+use layout::Rect;
+use font::Font;
+
+use std::ops::Range;
+
+use app_units::Au;
+
+/// A text selection made by dragging from `anchor` to `focus`. The points are in the coordinates
+/// of the display list.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Selection {
+    pub anchor: (f64, f64),
+    pub focus: (f64, f64),
+}
+
+impl Selection {
+    pub fn new(x: f64, y: f64) -> Selection {
+        Selection {
+            anchor: (x, y),
+            focus: (x, y),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.anchor == self.focus
+    }
+
+    /// The anchor and the focus in the reading order.
+    fn ordered(&self) -> ((f64, f64), (f64, f64)) {
+        let (a, f) = (self.anchor, self.focus);
+        if (a.1, a.0) <= (f.1, f.0) {
+            (a, f)
+        } else {
+            (f, a)
+        }
+    }
+}
+
+/// Return the byte ranges of the selected text of each `DisplayCommand::Text` in `items`, keyed
+/// by the index of the item. `index_at(text, font, x)` returns the index in `text` nearest to `x`.
+pub fn selected_ranges<F>(
+    items: &DisplayList,
+    selection: &Selection,
+    index_at: F,
+) -> Vec<(usize, Range<usize>)>
+where
+    F: Fn(&str, &Font, f64) -> usize,
+{
+    let mut ranges = vec![];
+    if selection.is_empty() {
+        return ranges;
+    }
+    let ((start_x, start_y), (end_x, end_y)) = selection.ordered();
+
+    for (i, item) in items.iter().enumerate() {
+        if let DisplayCommand::Text(ref text, rect, _, _, ref font) = item.command {
+            if item.text_shadow {
+                continue;
+            }
+            let top = rect.y.to_f64_px();
+            let bottom = top + rect.height.to_f64_px();
+            if bottom <= start_y || end_y < top {
+                continue;
+            }
+            let left = rect.x.to_f64_px();
+            // The lines between the first and the last one are selected entirely.
+            let from = if start_y < top {
+                0
+            } else {
+                index_at(text, font, start_x - left)
+            };
+            let to = if bottom <= end_y {
+                text.len()
+            } else {
+                index_at(text, font, end_x - left)
+            };
+            if from < to {
+                ranges.push((i, from..to));
+            }
+        }
+    }
+    ranges
+}
+
+/// Concatenate the selected text. Fragments on different lines are separated by newlines.
+pub fn selected_text(items: &DisplayList, ranges: &[(usize, Range<usize>)]) -> String {
+    let mut selected = String::new();
+    let mut last_y = None;
+    for &(i, ref range) in ranges {
+        if let DisplayCommand::Text(ref text, rect, _, _, _) = items[i].command {
+            if last_y.map_or(false, |y| y != rect.y) {
+                selected.push('\n');
+            }
+            last_y = Some(rect.y);
+            selected.push_str(&text[range.clone()]);
+        }
+    }
+    selected
+}
+
+/// Return the byte ranges of the case-insensitive matches of `query` in each
+/// `DisplayCommand::Text` in `items`, keyed by the index of the item. Matches spanning several
+/// items are not found.
+pub fn find_matches(items: &DisplayList, query: &str) -> Vec<(usize, Range<usize>)> {
+    let mut matches = vec![];
+    if query.is_empty() {
+        return matches;
+    }
+    for (i, item) in items.iter().enumerate() {
+        if let DisplayCommand::Text(ref text, _, _, _, _) = item.command {
+            if item.text_shadow {
+                continue;
+            }
+            for range in find_ignore_case(text, query) {
+                matches.push((i, range));
+            }
+        }
+    }
+    matches
+}
+
+fn find_ignore_case(text: &str, query: &str) -> Vec<Range<usize>> {
+    let mut ranges = vec![];
+    let mut start = 0;
+    while start < text.len() {
+        // Compare character by character, since lowercasing may change the byte length.
+        let mut chars = text[start..].char_indices();
+        let mut end = None;
+        for q in query.chars() {
+            match chars.next() {
+                Some((pos, c)) if c.to_lowercase().eq(q.to_lowercase()) => {
+                    end = Some(start + pos + c.len_utf8())
+                }
+                _ => {
+                    end = None;
+                    break;
+                }
+            }
+        }
+        match end {
+            Some(end) => {
+                ranges.push(start..end);
+                start = end;
+            }
+            None => start += text[start..].chars().next().unwrap().len_utf8(),
+        }
+    }
+    ranges
+}
+
+/// The area covered by `range` of the text drawn in `rect`.
+pub fn range_rect(text: &str, rect: Rect, font: &Font, range: &Range<usize>) -> Rect {
+    let x = font.text_width(&text[..range.start]);
+    let width = font.text_width(&text[range.clone()]);
+    Rect {
+        x: rect.x + Au::from_f64_px(x),
+        y: rect.y,
+        width: Au::from_f64_px(width),
+        height: rect.height,
+    }
+}
+
+#[cfg(test)]
+fn test_display_list(texts: &[(&str, f64, f64)]) -> DisplayList {
+    use painter::DisplayCommandInfo;
+    use font::{FontSlant, FontWeight, DEFAULT_FONT_FAMILY};
+    use css::Color;
+
+    // Every character is 10px wide and every line is 20px high.
+    texts
+        .iter()
+        .map(|&(text, x, y)| {
+            DisplayCommandInfo::new(DisplayCommand::Text(
+                text.to_string(),
+                Rect {
+                    x: Au::from_f64_px(x),
+                    y: Au::from_f64_px(y),
+                    width: Au::from_f64_px(text.len() as f64 * 10.0),
+                    height: Au::from_f64_px(20.0),
+                },
+                Color::default(),
+                vec![],
+                Font::new(
+                    Au::from_f64_px(16.0),
+                    FontWeight::Normal,
+                    FontSlant::Normal,
+                    DEFAULT_FONT_FAMILY.to_string(),
+                ),
+            ))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+fn test_index_at(text: &str, _: &Font, x: f64) -> usize {
+    ((x / 10.0).round().max(0.0) as usize).min(text.len())
+}
+
+#[test]
+fn test_selected_ranges() {
+    let items = test_display_list(&[
+        ("hello ", 0.0, 0.0),
+        ("world", 60.0, 0.0),
+        ("next", 0.0, 20.0),
+    ]);
+    let select = |anchor: (f64, f64), focus: (f64, f64)| {
+        let selection = Selection {
+            anchor: anchor,
+            focus: focus,
+        };
+        let ranges = selected_ranges(&items, &selection, test_index_at);
+        (ranges.clone(), selected_text(&items, &ranges))
+    };
+
+    // Within a fragment.
+    assert_eq!(select((10.0, 5.0), (40.0, 5.0)), (vec![(0, 1..4)], "ell".to_string()));
+    // Across fragments on the same line, dragged backwards.
+    assert_eq!(
+        select((80.0, 5.0), (30.0, 5.0)),
+        (vec![(0, 3..6), (1, 0..2)], "lo wo".to_string())
+    );
+    // Across lines.
+    assert_eq!(
+        select((70.0, 5.0), (20.0, 25.0)),
+        (vec![(1, 1..5), (2, 0..2)], "orld\nne".to_string())
+    );
+    // A plain click selects nothing.
+    assert_eq!(select((30.0, 5.0), (30.0, 5.0)), (vec![], "".to_string()));
+}
+
+#[test]
+fn test_text_shadow_not_selected() {
+    // The shadow is drawn 2px below the text.
+    let mut items = test_display_list(&[("abc", 0.0, 2.0), ("abc", 0.0, 0.0)]);
+    items[0].text_shadow = true;
+    let selection = Selection {
+        anchor: (0.0, 5.0),
+        focus: (30.0, 5.0),
+    };
+    let ranges = selected_ranges(&items, &selection, test_index_at);
+    assert_eq!(ranges, vec![(1, 0..3)]);
+    assert_eq!(selected_text(&items, &ranges), "abc");
+
+    // The same word twice in a line isn't a shadow.
+    let items = test_display_list(&[("abc", 0.0, 0.0), ("abc", 30.0, 0.0)]);
+    let selection = Selection {
+        anchor: (0.0, 5.0),
+        focus: (60.0, 5.0),
+    };
+    let ranges = selected_ranges(&items, &selection, test_index_at);
+    assert_eq!(ranges, vec![(0, 0..3), (1, 0..3)]);
+}
+
+#[test]
+fn test_find_matches() {
+    let mut items = test_display_list(&[
+        ("Find me, find", 0.0, 0.0),
+        ("FIND", 0.0, 2.0),
+        ("FIND", 0.0, 0.0),
+        ("Straße", 0.0, 20.0),
+    ]);
+    items[1].text_shadow = true;
+    // The shadow of "FIND" is skipped.
+    assert_eq!(
+        find_matches(&items, "find"),
+        vec![(0, 0..4), (0, 9..13), (2, 0..4)]
+    );
+    assert_eq!(find_matches(&items, "STRASSE"), vec![]);
+    assert_eq!(find_matches(&items, "aße"), vec![(3, 3..7)]);
+    assert_eq!(find_matches(&items, "me, f"), vec![(0, 5..10)]);
+    assert_eq!(find_matches(&items, ""), vec![]);
+    assert_eq!(find_ignore_case("aaa", "aa"), vec![0..2]);
+}
@@ -1,9 +1,12 @@
 use dom::{ElementData, Node, NodeType};
-use css::{parse_attr_style, Color, Declaration, Rule, Selector, SimpleSelector, Specificity,
-          Stylesheet, TextDecoration, Unit, Value, pt2px};
+use css::{parse_attr_style, BorderStyle, Color, Declaration, GradientSpec, Rule, Selector,
+          SimpleSelector, Specificity, Stylesheet, TextDecoration, Unit, Value, pt2px, BLACK,
+          TRANSPARENT};
 use font::{FontSlant, FontWeight};
+use layout::CornerRadii;
 
 use std::collections::HashMap;
+use std::cmp::max;
 
 use app_units::Au;
 
@@ -38,6 +41,90 @@ pub enum ClearType {
     Both,
 }
 
+#[derive(Clone, PartialEq, Debug, Copy)]
+pub enum Position {
+    Static,
+    Relative,
+    Absolute,
+}
+
+#[derive(Clone, PartialEq, Debug, Copy)]
+pub enum Overflow {
+    Visible,
+    Hidden,
+}
+
+#[derive(Clone, PartialEq, Debug, Copy)]
+pub enum BackgroundRepeat {
+    Repeat,
+    RepeatX,
+    RepeatY,
+    NoRepeat,
+}
+
+/// An offset of a background image in `background-position`. A percentage aligns the point at
+/// that percentage of the image with the same point of the box.
+#[derive(Clone, PartialEq, Debug, Copy)]
+pub enum BackgroundPosition {
+    Length(Au),
+    Percent(f64),
+}
+
+#[derive(Clone, PartialEq, Debug, Copy)]
+pub enum BackgroundSize {
+    Auto,
+    Cover,
+    Contain,
+}
+
+#[derive(Clone, PartialEq, Debug, Copy)]
+pub enum Visibility {
+    Visible,
+    Hidden,
+}
+
+#[derive(Clone, PartialEq, Debug, Copy)]
+pub enum TextAlign {
+    Left,
+    Right,
+    Center,
+    Justify,
+}
+
+#[derive(Clone, PartialEq, Debug, Copy)]
+pub enum BoxSizing {
+    ContentBox,
+    BorderBox,
+}
+
+#[derive(Clone, PartialEq, Debug, Copy)]
+pub enum ListStyleType {
+    None,
+    Disc,
+    Circle,
+    Square,
+    Decimal,
+}
+
+#[derive(Clone, PartialEq, Debug, Copy)]
+pub struct BoxShadow {
+    pub offset_x: Au,
+    pub offset_y: Au,
+    pub blur: Au,
+    pub spread: Au,
+    pub color: Color,
+    // Whether the shadow is cast inside the padding box instead of outside the border box.
+    pub inset: bool,
+}
+
+#[derive(Clone, PartialEq, Debug, Copy)]
+pub struct TextShadow {
+    pub offset_x: Au,
+    pub offset_y: Au,
+    pub blur: Au,
+    pub color: Color,
+}
+
 pub const DEFAULT_FONT_SIZE: f64 = 16.0f64;
 pub const DEFAULT_LINE_HEIGHT_SCALE: f64 = 1.2f64;
 
@@ -59,11 +146,20 @@ impl<'a> StyledNode<'a> {
         self.value(name).or_else(|| self.value(fallback_name))
     }
 
+    /// Whether the node has `display: list-item`, which generates a list marker.
+    pub fn is_list_item(&self) -> bool {
+        match self.value("display") {
+            Some(x) => x[0] == Value::Keyword("list-item".to_string()),
+            None => false,
+        }
+    }
+
     pub fn display(&self) -> Display {
         match self.value("display") {
             Some(x) => match x[0] {
                 Value::Keyword(ref s) => match &**s {
-                    "block" => Display::Block,
+                    // A list item is a block with a marker.
+                    "block" | "list-item" => Display::Block,
                     "inline-block" => Display::InlineBlock,
                     "none" => Display::None,
                     "inline" | _ => Display::Inline,
@@ -104,6 +200,63 @@ impl<'a> StyledNode<'a> {
         }
     }
 
+    pub fn visibility(&self) -> Visibility {
+        match self.value("visibility") {
+            Some(x) => match x[0] {
+                Value::Keyword(ref k) if k == "hidden" || k == "collapse" => Visibility::Hidden,
+                _ => Visibility::Visible,
+            },
+            None => Visibility::Visible,
+        }
+    }
+
+    pub fn box_sizing(&self) -> BoxSizing {
+        match self.value("box-sizing") {
+            Some(x) => match x[0] {
+                Value::Keyword(ref k) if k == "border-box" => BoxSizing::BorderBox,
+                _ => BoxSizing::ContentBox,
+            },
+            None => BoxSizing::ContentBox,
+        }
+    }
+
+    pub fn position(&self) -> Position {
+        match self.value("position") {
+            Some(x) => match x[0] {
+                Value::Keyword(ref k) if k == "relative" => Position::Relative,
+                Value::Keyword(ref k) if k == "absolute" => Position::Absolute,
+                _ => Position::Static,
+            },
+            None => Position::Static,
+        }
+    }
+
+    /// The offset given by `top`, `right`, `bottom` or `left`, with percentages resolved against
+    /// `len`. None if it's `auto`.
+    pub fn offset(&self, name: &str, len: Option<f64>) -> Option<f64> {
+        match self.value(name) {
+            Some(x) => match x[0] {
+                Value::Length(f, Unit::Percent) => len.map(|len| len * f / 100.0),
+                ref value => value.to_px(),
+            },
+            None => None,
+        }
+    }
+
+    pub fn overflow(&self) -> Overflow {
+        match self.value("overflow") {
+            Some(x) => match x[0] {
+                // TODO: Scrollbars are not supported, so 'scroll' and 'auto' just clip.
+                Value::Keyword(ref s) => match &**s {
+                    "hidden" | "scroll" | "auto" => Overflow::Hidden,
+                    _ => Overflow::Visible,
+                },
+                _ => Overflow::Visible,
+            },
+            _ => Overflow::Visible,
+        }
+    }
+
     pub fn padding(&self) -> (Value, Value, Value, Value) {
         // padding has initial value 0.
         let zero = Value::Length(0.0, Unit::Px);
@@ -149,11 +302,18 @@ impl<'a> StyledNode<'a> {
         padding_bottom.get_or_insert_with(|| zero.clone());
         padding_left.get_or_insert_with(|| zero.clone());
 
+        // Negative values are not allowed for padding.
+        let non_negative = |v: Value| match v {
+            Value::Length(f, unit) => Value::Length(f.max(0.0), unit),
+            Value::Num(f) => Value::Num(f.max(0.0)),
+            v => v,
+        };
+
         (
-            padding_top.unwrap(),
-            padding_right.unwrap(),
-            padding_bottom.unwrap(),
-            padding_left.unwrap(),
+            non_negative(padding_top.unwrap()),
+            non_negative(padding_right.unwrap()),
+            non_negative(padding_bottom.unwrap()),
+            non_negative(padding_left.unwrap()),
         )
     }
 
@@ -209,18 +369,34 @@ impl<'a> StyledNode<'a> {
         )
     }
 
+    /// Look up `border-<side>-<kind>` (e.g. `border-top-color`), and then the value accepted by
+    /// `f` in the `border-<side>` shorthand.
+    fn border_side_value<T>(
+        &self,
+        side: &str,
+        kind: &str,
+        f: &Fn(&Value) -> Option<T>,
+    ) -> Option<T> {
+        self.value(format!("border-{}-{}", side, kind).as_str())
+            .and_then(|x| f(&x[0]))
+            .or_else(|| {
+                self.value(format!("border-{}", side).as_str())
+                    .and_then(|x| x.iter().filter_map(|v| f(v)).next())
+            })
+    }
+
     pub fn border_width(&self) -> (Value, Value, Value, Value) {
         // border has initial value 0.
         let zero = Value::Length(0.0, Unit::Px);
 
-        let mut border_top = self.value("border-top-width")
-            .and_then(|x| Some(x[0].clone()));
-        let mut border_bottom = self.value("border-bottom-width")
-            .and_then(|x| Some(x[0].clone()));
-        let mut border_left = self.value("border-left-width")
-            .and_then(|x| Some(x[0].clone()));
-        let mut border_right = self.value("border-right-width")
-            .and_then(|x| Some(x[0].clone()));
+        let width = |v: &Value| match *v {
+            Value::Length(_, _) | Value::Num(_) => Some(v.clone()),
+            _ => None,
+        };
+        let mut border_top = self.border_side_value("top", "width", &width);
+        let mut border_bottom = self.border_side_value("bottom", "width", &width);
+        let mut border_left = self.border_side_value("left", "width", &width);
+        let mut border_right = self.border_side_value("right", "width", &width);
 
         if let Some(border) = self.value("border-width") {
             match border.len() {
@@ -266,6 +442,21 @@ impl<'a> StyledNode<'a> {
             }
         }
 
+        // A side whose border-style is 'none' has no border width.
+        let (style_top, style_right, style_bottom, style_left) = self.border_style();
+        if style_top == BorderStyle::None {
+            border_top = Some(zero.clone());
+        }
+        if style_right == BorderStyle::None {
+            border_right = Some(zero.clone());
+        }
+        if style_bottom == BorderStyle::None {
+            border_bottom = Some(zero.clone());
+        }
+        if style_left == BorderStyle::None {
+            border_left = Some(zero.clone());
+        }
+
         border_top.get_or_insert_with(|| zero.clone());
         border_right.get_or_insert_with(|| zero.clone());
         border_bottom.get_or_insert_with(|| zero.clone());
@@ -280,13 +471,11 @@ impl<'a> StyledNode<'a> {
     }
 
     pub fn border_color(&self) -> (Option<Color>, Option<Color>, Option<Color>, Option<Color>) {
-        let mut border_top = self.value("border-top-color").and_then(|x| x[0].to_color());
-        let mut border_bottom = self.value("border-bottom-color")
-            .and_then(|x| x[0].to_color());
-        let mut border_left = self.value("border-left-color")
-            .and_then(|x| x[0].to_color());
-        let mut border_right = self.value("border-right-color")
-            .and_then(|x| x[0].to_color());
+        let color = |v: &Value| v.to_color();
+        let mut border_top = self.border_side_value("top", "color", &color);
+        let mut border_bottom = self.border_side_value("bottom", "color", &color);
+        let mut border_left = self.border_side_value("left", "color", &color);
+        let mut border_right = self.border_side_value("right", "color", &color);
 
         if let Some(border) = self.value("border-color") {
             match border.len() {
@@ -337,6 +526,281 @@ impl<'a> StyledNode<'a> {
         (border_top, border_right, border_bottom, border_left)
     }
 
+    /// Unlike the CSS spec, a border whose style is not specified is painted as solid.
+    pub fn border_style(&self) -> (BorderStyle, BorderStyle, BorderStyle, BorderStyle) {
+        let to_style = |v: &Value| v.to_border_style();
+        let mut border_top = self.border_side_value("top", "style", &to_style);
+        let mut border_bottom = self.border_side_value("bottom", "style", &to_style);
+        let mut border_left = self.border_side_value("left", "style", &to_style);
+        let mut border_right = self.border_side_value("right", "style", &to_style);
+
+        if let Some(border) = self.value("border-style") {
+            let style = |i: usize| border[i].to_border_style().unwrap_or(BorderStyle::Solid);
+            match border.len() {
+                1 => {
+                    border_top.get_or_insert_with(|| style(0));
+                    border_bottom.get_or_insert_with(|| style(0));
+                    border_left.get_or_insert_with(|| style(0));
+                    border_right.get_or_insert_with(|| style(0));
+                }
+                2 => {
+                    border_top.get_or_insert_with(|| style(0));
+                    border_bottom.get_or_insert_with(|| style(0));
+                    border_left.get_or_insert_with(|| style(1));
+                    border_right.get_or_insert_with(|| style(1));
+                }
+                3 => {
+                    border_top.get_or_insert_with(|| style(0));
+                    border_left.get_or_insert_with(|| style(1));
+                    border_right.get_or_insert_with(|| style(1));
+                    border_bottom.get_or_insert_with(|| style(2));
+                }
+                4 => {
+                    border_top.get_or_insert_with(|| style(0));
+                    border_right.get_or_insert_with(|| style(1));
+                    border_bottom.get_or_insert_with(|| style(2));
+                    border_left.get_or_insert_with(|| style(3));
+                }
+                0 | _ => unreachable!(),
+            }
+        } else if let Some(border_info) = self.value("border") {
+            if let Some(border_style) = border_info
+                .iter()
+                .filter_map(|v| v.to_border_style())
+                .next()
+            {
+                border_top.get_or_insert(border_style);
+                border_right.get_or_insert(border_style);
+                border_bottom.get_or_insert(border_style);
+                border_left.get_or_insert(border_style);
+            }
+        }
+
+        (
+            border_top.unwrap_or(BorderStyle::Solid),
+            border_right.unwrap_or(BorderStyle::Solid),
+            border_bottom.unwrap_or(BorderStyle::Solid),
+            border_left.unwrap_or(BorderStyle::Solid),
+        )
+    }
+
+    /// `background-color` has initial value `transparent`. The color in the `background`
+    /// shorthand is used if `background-color` is not specified.
+    pub fn background_color(&self) -> Color {
+        self.value("background-color")
+            .and_then(|x| x[0].to_color())
+            .or_else(|| {
+                self.value("background")
+                    .and_then(|x| x.iter().filter_map(|v| v.to_color()).next())
+            })
+            .unwrap_or(TRANSPARENT)
+    }
+
+    /// Return the gradient in `background-image` or the `background` shorthand.
+    pub fn background_gradient(&self) -> Option<GradientSpec> {
+        self.value("background-image")
+            .and_then(|x| x[0].to_gradient())
+            .or_else(|| {
+                self.value("background")
+                    .and_then(|x| x.iter().filter_map(|v| v.to_gradient()).next())
+            })
+    }
+
+    /// Return the first shadow in `box-shadow`.
+    /// TODO: Support multiple shadows.
+    pub fn box_shadow(&self) -> Option<BoxShadow> {
+        let values = self.value("box-shadow")?;
+        let mut lengths = vec![];
+        let mut color = None;
+        let mut inset = false;
+        for value in values.iter().take_while(|v| **v != Value::Comma) {
+            match value {
+                &Value::Keyword(ref k) if k == "none" => return None,
+                &Value::Keyword(ref k) if k == "inset" && !inset => inset = true,
+                &Value::Length(_, _) | &Value::Num(_) => lengths.push(value.to_px()?),
+                _ => color = Some(value.to_color()?),
+            }
+        }
+        if lengths.len() < 2 || lengths.len() > 4 {
+            return None;
+        }
+        let length = |i: usize| Au::from_f64_px(*lengths.get(i).unwrap_or(&0.0));
+        Some(BoxShadow {
+            offset_x: length(0),
+            offset_y: length(1),
+            blur: max(length(2), Au(0)),
+            spread: length(3),
+            // TODO: The default should be `currentColor`.
+            color: color.unwrap_or(BLACK),
+            inset: inset,
+        })
+    }
+
+    /// Return the shadows in `text-shadow`, with the topmost first.
+    /// An invalid shadow makes the whole declaration ignored.
+    pub fn text_shadow(&self) -> Vec<TextShadow> {
+        let values = match self.value("text-shadow") {
+            Some(values) => values,
+            None => return vec![],
+        };
+        values
+            .split(|v| *v == Value::Comma)
+            .map(|shadow| {
+                let mut lengths = vec![];
+                let mut color = None;
+                for value in shadow {
+                    match value {
+                        &Value::Length(_, _) | &Value::Num(_) => lengths.push(value.to_px()?),
+                        _ => color = Some(value.to_color()?),
+                    }
+                }
+                if lengths.len() < 2 || lengths.len() > 3 {
+                    return None;
+                }
+                let length = |i: usize| Au::from_f64_px(*lengths.get(i).unwrap_or(&0.0));
+                Some(TextShadow {
+                    offset_x: length(0),
+                    offset_y: length(1),
+                    blur: max(length(2), Au(0)),
+                    color: color.unwrap_or_else(|| {
+                        self.value("color")
+                            .and_then(|c| c[0].to_color())
+                            .unwrap_or(BLACK)
+                    }),
+                })
+            })
+            .collect::<Option<Vec<TextShadow>>>()
+            .unwrap_or(vec![])
+    }
+
+    pub fn list_style_type(&self) -> ListStyleType {
+        self.lookup("list-style-type", "list-style", &vec![])
+            .iter()
+            .filter_map(|value| match value {
+                &Value::Keyword(ref k) => match k.as_str() {
+                    "none" => Some(ListStyleType::None),
+                    "disc" => Some(ListStyleType::Disc),
+                    "circle" => Some(ListStyleType::Circle),
+                    "square" => Some(ListStyleType::Square),
+                    "decimal" => Some(ListStyleType::Decimal),
+                    _ => None,
+                },
+                _ => None,
+            })
+            .next()
+            .unwrap_or(ListStyleType::Disc)
+    }
+
+    /// Return the URL in `background-image` or the `background` shorthand.
+    pub fn background_image_url(&self) -> Option<String> {
+        self.lookup_without_default("background-image", "background")
+            .and_then(|x| x.iter().filter_map(|v| v.to_url()).next().cloned())
+    }
+
+    pub fn background_repeat(&self) -> BackgroundRepeat {
+        let keywords = self.lookup("background-repeat", "background", &vec![]);
+        keywords
+            .iter()
+            .filter_map(|keyword| match keyword {
+                &Value::Keyword(ref k) if k == "no-repeat" => Some(BackgroundRepeat::NoRepeat),
+                &Value::Keyword(ref k) if k == "repeat-x" => Some(BackgroundRepeat::RepeatX),
+                &Value::Keyword(ref k) if k == "repeat-y" => Some(BackgroundRepeat::RepeatY),
+                _ => None,
+            })
+            .next()
+            .unwrap_or(BackgroundRepeat::Repeat)
+    }
+
+    /// The horizontal and vertical offsets in `background-position`, given as one or two of
+    /// lengths, percentages and keywords. It's `0% 0%` by default.
+    pub fn background_position(&self) -> (BackgroundPosition, BackgroundPosition) {
+        #[derive(PartialEq)]
+        enum Axis {
+            X,
+            Y,
+            Either,
+        }
+        let values = self.lookup("background-position", "background", &vec![]);
+        let components: Vec<(Axis, BackgroundPosition)> = values
+            .iter()
+            .filter_map(|value| match value {
+                &Value::Keyword(ref k) => match k.as_str() {
+                    "left" => Some((Axis::X, BackgroundPosition::Percent(0.0))),
+                    "right" => Some((Axis::X, BackgroundPosition::Percent(1.0))),
+                    "top" => Some((Axis::Y, BackgroundPosition::Percent(0.0))),
+                    "bottom" => Some((Axis::Y, BackgroundPosition::Percent(1.0))),
+                    "center" => Some((Axis::Either, BackgroundPosition::Percent(0.5))),
+                    _ => None,
+                },
+                &Value::Length(f, Unit::Percent) => {
+                    Some((Axis::Either, BackgroundPosition::Percent(f / 100.0)))
+                }
+                &Value::Length(_, _) | &Value::Num(_) => value
+                    .to_px()
+                    .map(|px| (Axis::Either, BackgroundPosition::Length(Au::from_f64_px(px)))),
+                _ => None,
+            })
+            .collect();
+
+        let center = BackgroundPosition::Percent(0.5);
+        match components.len() {
+            1 if components[0].0 == Axis::Y => (center, components[0].1),
+            1 => (components[0].1, center),
+            // Keywords may come in either order, like `top left`.
+            2 if components[0].0 == Axis::Y || components[1].0 == Axis::X => {
+                (components[1].1, components[0].1)
+            }
+            2 => (components[0].1, components[1].1),
+            _ => (
+                BackgroundPosition::Percent(0.0),
+                BackgroundPosition::Percent(0.0),
+            ),
+        }
+    }
+
+    pub fn background_size(&self) -> BackgroundSize {
+        match self.value("background-size") {
+            Some(x) => match x[0] {
+                Value::Keyword(ref k) if k == "cover" => BackgroundSize::Cover,
+                Value::Keyword(ref k) if k == "contain" => BackgroundSize::Contain,
+                // TODO: Support lengths and percentages.
+                _ => BackgroundSize::Auto,
+            },
+            None => BackgroundSize::Auto,
+        }
+    }
+
+    /// The radii of the corners: `border-<corner>-radius`, and then the `border-radius` shorthand
+    /// with one to four values, clockwise from the top left.
+    pub fn border_radius(&self) -> CornerRadii {
+        let radius = |value: &Value| {
+            value
+                .to_px()
+                .map_or(Au(0), |r| Au::from_f64_px(r.max(0.0)))
+        };
+        let shorthand = self.value("border-radius").unwrap_or_default();
+        let corner = |name: &str, index: usize| match self.value(name) {
+            Some(value) => radius(&value[0]),
+            None => {
+                // Missing values are copied from the opposite corner.
+                let index = match shorthand.len() {
+                    0 => return Au(0),
+                    1 => 0,
+                    2 => index % 2,
+                    3 if index == 3 => 1,
+                    _ => index,
+                };
+                radius(&shorthand[index])
+            }
+        };
+        CornerRadii {
+            top_left: corner("border-top-left-radius", 0),
+            top_right: corner("border-top-right-radius", 1),
+            bottom_right: corner("border-bottom-right-radius", 2),
+            bottom_left: corner("border-bottom-left-radius", 3),
+        }
+    }
+
     pub fn text_decoration(&self) -> Vec<TextDecoration> {
         if let Some(text_decorations) = self.value("text-decoration") {
             let mut decorations = vec![];
@@ -370,6 +834,41 @@ impl<'a> StyledNode<'a> {
         self.lookup("font-style", "font-style", &vec![default_font_slant])[0].to_font_slant()
     }
 
+    /// `letter-spacing` in px. `normal` is 0. Negative spacing is limited to a quarter of the font
+    /// size so that glyphs don't pile up on each other.
+    pub fn letter_spacing(&self) -> Au {
+        let spacing = self.value("letter-spacing")
+            .and_then(|x| x[0].to_px())
+            .unwrap_or(0.0);
+        Au::from_f64_px(spacing.max(-self.font_size().to_f64_px() / 4.0))
+    }
+
+    /// The families in `font-family` in order of preference. Unquoted names may consist of
+    /// several identifiers separated by spaces.
+    pub fn font_family(&self) -> Vec<String> {
+        let values = match self.value("font-family") {
+            Some(values) => values,
+            None => return vec![],
+        };
+        values
+            .split(|v| *v == Value::Comma)
+            .filter_map(|family| {
+                let names: Vec<&str> = family
+                    .iter()
+                    .filter_map(|value| match value {
+                        &Value::Keyword(ref name) | &Value::Str(ref name) => Some(name.as_str()),
+                        _ => None,
+                    })
+                    .collect();
+                if names.is_empty() {
+                    None
+                } else {
+                    Some(names.join(" "))
+                }
+            })
+            .collect()
+    }
+
     pub fn line_height(&self) -> Au {
         let font_size = self.font_size().to_f64_px();
         let default_line_height = Value::Length(font_size * DEFAULT_LINE_HEIGHT_SCALE, Unit::Px);
@@ -378,14 +877,48 @@ impl<'a> StyledNode<'a> {
             &Value::Keyword(ref k) if k == "normal" => font_size * DEFAULT_LINE_HEIGHT_SCALE,
             &Value::Length(f, Unit::Px) => f,
             &Value::Length(f, Unit::Pt) => pt2px(f),
-            &Value::Length(_, _) => unimplemented!(),
             &Value::Num(f) => font_size * f,
-            _ => panic!(),
+            // Invalid values are ignored.
+            _ => font_size * DEFAULT_LINE_HEIGHT_SCALE,
         })
     }
 
-    pub fn text_align(&self) -> Value {
-        self.value_with_default("text-align", &vec![Value::Keyword("left".to_string())])[0].clone()
+    /// `opacity` clamped to 0.0-1.0. Percentages are also accepted.
+    pub fn opacity(&self) -> f64 {
+        match self.value("opacity") {
+            Some(x) => match x[0] {
+                Value::Num(f) => f.max(0.0).min(1.0),
+                Value::Length(f, Unit::Percent) => (f / 100.0).max(0.0).min(1.0),
+                _ => 1.0,
+            },
+            None => 1.0,
+        }
+    }
+
+    /// `z-index: auto` is treated as 0.
+    pub fn z_index(&self) -> i32 {
+        match self.value("z-index") {
+            Some(x) => match x[0] {
+                Value::Num(f) => f as i32,
+                _ => 0,
+            },
+            None => 0,
+        }
+    }
+
+    pub fn text_align(&self) -> TextAlign {
+        match self.value("text-align") {
+            Some(x) => match x[0] {
+                Value::Keyword(ref k) => match k.as_str() {
+                    "right" => TextAlign::Right,
+                    "center" => TextAlign::Center,
+                    "justify" => TextAlign::Justify,
+                    "left" | _ => TextAlign::Left,
+                },
+                _ => TextAlign::Left,
+            },
+            None => TextAlign::Left,
+        }
     }
 }
 
@@ -423,23 +956,48 @@ pub fn style_tree<'a>(
     inherited_property: &PropertyMap,
     parent_specified_values: &PropertyMap,
     appeared_elements: &Vec<SimpleSelector>,
+) -> StyledNode<'a> {
+    build_style_tree(
+        root,
+        stylesheet,
+        default_style,
+        inherited_property,
+        parent_specified_values,
+        appeared_elements,
+        None,
+    )
+}
+
+/// `root_font_size` is the font size of the root element in px, which `rem` is relative to. None
+/// means `root` is the root element.
+fn build_style_tree<'a>(
+    root: &'a Node,
+    stylesheet: &'a Stylesheet,
+    default_style: &Stylesheet,
+    inherited_property: &PropertyMap,
+    parent_specified_values: &PropertyMap,
+    appeared_elements: &Vec<SimpleSelector>,
+    root_font_size: Option<f64>,
 ) -> StyledNode<'a> {
     let mut appeared_elements = appeared_elements.clone();
 
     let specified_values = match root.data {
         NodeType::Element(ref elem) => {
-            let values = specified_values(
+            let mut values = specified_values(
                 elem,
                 default_style,
                 stylesheet,
                 inherited_property,
                 &appeared_elements,
             );
-            appeared_elements.push(SimpleSelector {
-                tag_name: Some(elem.tag_name.clone()),
-                id: elem.id().and_then(|id| Some(id.clone())),
-                class: elem.classes().iter().map(|x| x.to_string()).collect(),
-            });
+            resolve_css_wide_keywords(&mut values, parent_specified_values);
+            propagate_text_decoration(&mut values, parent_specified_values);
+            resolve_font_relative_lengths(
+                &mut values,
+                parent_specified_values,
+                root_font_size.unwrap_or(DEFAULT_FONT_SIZE),
+            );
+            appeared_elements.push(simple_selector(elem));
             values
         }
         NodeType::Text(_) => {
@@ -447,7 +1005,11 @@ pub fn style_tree<'a>(
                 match display[0] {
                     // If the parent element is an inline element, inherites the parent's properties.
                     Value::Keyword(ref k) if k == "inline" => parent_specified_values.clone(),
-                    _ => inherited_property.clone(),
+                    _ => {
+                        let mut values = inherited_property.clone();
+                        propagate_text_decoration(&mut values, parent_specified_values);
+                        values
+                    }
                 }
             } else {
                 inherited_property.clone()
@@ -455,15 +1017,29 @@ pub fn style_tree<'a>(
         }
     };
 
+    // The root element's font size is the base of `rem` in its descendants.
+    let root_font_size = Some(root_font_size.unwrap_or_else(|| {
+        specified_values
+            .get("font-size")
+            .and_then(|x| x[0].to_px())
+            .unwrap_or(DEFAULT_FONT_SIZE)
+    }));
+
     let inherited_property = inherit_peoperties(
         &specified_values,
         vec![
             "font-size",
+            "font-family",
             "line-height",
+            "letter-spacing",
             "font-weight",
             "font-style",
             "text-align",
             "color",
+            "list-style-type",
+            "list-style",
+            "visibility",
+            "text-shadow",
         ],
     );
 
@@ -472,13 +1048,14 @@ pub fn style_tree<'a>(
         children: root.children
             .iter()
             .map(|child| {
-                style_tree(
+                build_style_tree(
                     child,
                     stylesheet,
                     default_style,
                     &inherited_property,
                     &specified_values,
                     &appeared_elements,
+                    root_font_size,
                 )
             })
             .collect(),
@@ -486,6 +1063,144 @@ pub fn style_tree<'a>(
     }
 }
 
+/// Convert `em` and `rem` lengths into px. `em` in `font-size` is relative to the parent's font
+/// size, and `em` elsewhere is relative to the element's own font size. A percentage
+/// `line-height` is relative to the element's font size too, and is inherited as the length,
+/// whereas a unitless one is inherited as the factor.
+fn resolve_font_relative_lengths(
+    values: &mut PropertyMap,
+    parent_specified_values: &PropertyMap,
+    root_font_size: f64,
+) {
+    let parent_font_size = parent_specified_values
+        .get("font-size")
+        .and_then(|x| x[0].to_px())
+        .unwrap_or(DEFAULT_FONT_SIZE);
+
+    if let Some(font_size) = values.get_mut("font-size") {
+        for value in font_size.iter_mut() {
+            match *value {
+                Value::Length(f, Unit::Em) => {
+                    *value = Value::Length(f * parent_font_size, Unit::Px)
+                }
+                Value::Length(f, Unit::Rem) => {
+                    *value = Value::Length(f * root_font_size, Unit::Px)
+                }
+                Value::Length(f, Unit::Percent) => {
+                    *value = Value::Length(f / 100.0 * parent_font_size, Unit::Px)
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let font_size = values
+        .get("font-size")
+        .and_then(|x| x[0].to_px())
+        .unwrap_or(DEFAULT_FONT_SIZE);
+    for value in values.values_mut().flat_map(|values| values.iter_mut()) {
+        match *value {
+            Value::Length(f, Unit::Em) => *value = Value::Length(f * font_size, Unit::Px),
+            Value::Length(f, Unit::Rem) => *value = Value::Length(f * root_font_size, Unit::Px),
+            _ => {}
+        }
+    }
+    if let Some(line_height) = values.get_mut("line-height") {
+        for value in line_height.iter_mut() {
+            if let Value::Length(f, Unit::Percent) = *value {
+                *value = Value::Length(f / 100.0 * font_size, Unit::Px)
+            }
+        }
+    }
+}
+
+/// Add the text decorations of the parent to `values`. Decorations are drawn on all the descendant
+/// text, and `text-decoration: none` doesn't remove them.
+fn propagate_text_decoration(values: &mut PropertyMap, parent_specified_values: &PropertyMap) {
+    let mut decorations: Vec<Value> = vec![];
+    for value in parent_specified_values
+        .get("text-decoration")
+        .into_iter()
+        .chain(values.get("text-decoration").into_iter())
+        .flat_map(|v| v.iter())
+    {
+        match value.to_text_decoration() {
+            Some(TextDecoration::None) | None => {}
+            Some(_) => if !decorations.contains(value) {
+                decorations.push(value.clone())
+            },
+        }
+    }
+    if !decorations.is_empty() {
+        values.insert("text-decoration".to_string(), decorations);
+    }
+}
+
+/// Resolve `inherit` and `initial` on any property. `inherit` takes the parent's value, inherited
+/// or not. `initial` and `inherit` without a parent's value use the initial value.
+fn resolve_css_wide_keywords(values: &mut PropertyMap, parent_specified_values: &PropertyMap) {
+    let inherit = vec![Value::Keyword("inherit".to_string())];
+    let initial = vec![Value::Keyword("initial".to_string())];
+    let names: Vec<String> = values
+        .iter()
+        .filter(|&(_, value)| *value == inherit || *value == initial)
+        .map(|(name, _)| name.clone())
+        .collect();
+    for name in names {
+        let value = if values[&name] == inherit {
+            parent_specified_values
+                .get(&name)
+                .cloned()
+                .or_else(|| initial_value(name.as_str()))
+        } else {
+            initial_value(name.as_str())
+        };
+        match value {
+            Some(value) => {
+                values.insert(name, value);
+            }
+            // Getters fall back to the initial value when the property is missing.
+            None => {
+                values.remove(&name);
+            }
+        }
+    }
+}
+
+/// The initial value of each property.
+/// ref. https://www.w3.org/TR/CSS2/propidx.html
+fn initial_value(name: &str) -> Option<Vec<Value>> {
+    let keyword = |k: &str| Some(vec![Value::Keyword(k.to_string())]);
+    let zero = Some(vec![Value::Length(0.0, Unit::Px)]);
+    match name {
+        "display" => keyword("inline"),
+        "float" | "clear" | "text-decoration" | "box-shadow" | "text-shadow"
+        | "background-image" => keyword("none"),
+        "visibility" | "overflow" => keyword("visible"),
+        "position" => keyword("static"),
+        "top" | "right" | "bottom" | "left" => keyword("auto"),
+        "box-sizing" => keyword("content-box"),
+        "width" | "height" | "z-index" | "background-size" => keyword("auto"),
+        "max-width" | "max-height" => keyword("none"),
+        "min-width" | "min-height" => zero,
+        "margin" | "margin-top" | "margin-right" | "margin-bottom" | "margin-left" => zero,
+        "padding" | "padding-top" | "padding-right" | "padding-bottom" | "padding-left" => zero,
+        "border-radius" | "border-top-left-radius" | "border-top-right-radius"
+        | "border-bottom-right-radius" | "border-bottom-left-radius" => zero,
+        "color" => Some(vec![Value::Color(BLACK)]),
+        "background-color" => Some(vec![Value::Color(TRANSPARENT)]),
+        "background-repeat" => keyword("repeat"),
+        "font-size" => Some(vec![Value::Length(DEFAULT_FONT_SIZE, Unit::Px)]),
+        "font-weight" | "font-style" | "line-height" | "letter-spacing" => keyword("normal"),
+        "text-align" => keyword("left"),
+        "list-style-type" => keyword("disc"),
+        "opacity" => Some(vec![Value::Num(1.0)]),
+        // The getters of the other properties (e.g. borders) use their initial values when the
+        // property is missing.
+        _ => None,
+    }
+}
+
 fn specified_values(
     elem: &ElementData,
     default_style: &Stylesheet,
@@ -505,22 +1220,78 @@ fn specified_values(
 
     // Go through the rules from lowest to highest specificity.
     rules.sort_by(|&(a, _), &(b, _)| a.cmp(&b));
-    rules.iter().for_each(|&(_, rule)| {
-        rule.declarations.iter().for_each(|declaration| {
-            values.insert(declaration.name.clone(), declaration.values.clone());
-        })
-    });
 
-    if let Some(attr_style) = elem.attrs.get("style") {
-        let decls = parse_attr_style(attr_style.clone());
-        for Declaration { name, values: vals } in decls {
-            values.insert(name, vals);
-        }
+    let attr_style = elem.attrs
+        .get("style")
+        .map_or(vec![], |attr_style| parse_attr_style(attr_style.clone()));
+
+    // Important declarations override normal ones regardless of specificity. The inline style
+    // is more specific than any rule.
+    for &important in &[false, true] {
+        rules.iter().for_each(|&(_, rule)| {
+            rule.declarations
+                .iter()
+                .filter(|declaration| declaration.important == important && is_valid(declaration))
+                .for_each(|declaration| {
+                    values.insert(declaration.name.clone(), declaration.values.clone());
+                })
+        });
+
+        attr_style
+            .iter()
+            .filter(|declaration| declaration.important == important && is_valid(declaration))
+            .for_each(|declaration| {
+                values.insert(declaration.name.clone(), declaration.values.clone());
+            });
     }
 
     values
 }
 
+/// The checks of the values of properties. A declaration failing the check of its property is
+/// ignored, so that e.g. a misspelled `visibility` doesn't make descendants of a hidden element
+/// visible. Those of other properties are taken as they are.
+const VALUE_CHECKS: &[(&str, fn(&[Value]) -> bool)] = &[("visibility", is_visibility)];
+
+fn is_valid(declaration: &Declaration) -> bool {
+    VALUE_CHECKS
+        .iter()
+        .find(|&&(name, _)| name == declaration.name)
+        .map_or(true, |&(_, check)| check(&declaration.values))
+}
+
+fn is_visibility(values: &[Value]) -> bool {
+    match values {
+        &[Value::Keyword(ref k)] => match k.as_str() {
+            "visible" | "hidden" | "collapse" | "inherit" | "initial" => true,
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// The selector that matches `elem` itself, used to match it as an ancestor.
+fn simple_selector(elem: &ElementData) -> SimpleSelector {
+    SimpleSelector {
+        tag_name: Some(elem.tag_name.clone()),
+        id: elem.id().and_then(|id| Some(id.clone())),
+        class: elem.classes().iter().map(|x| x.to_string()).collect(),
+    }
+}
+
+/// The rules in `stylesheet` matching `elem`, whose ancestors from the root are `ancestors`, from
+/// lowest to highest specificity.
+pub fn matched_rules<'a>(
+    elem: &ElementData,
+    stylesheet: &'a Stylesheet,
+    ancestors: &[&ElementData],
+) -> Vec<&'a Rule> {
+    let ancestors: Vec<SimpleSelector> = ancestors.iter().map(|e| simple_selector(e)).collect();
+    let mut rules = matching_rules(elem, stylesheet, &ancestors);
+    rules.sort_by(|&(a, _), &(b, _)| a.cmp(&b));
+    rules.into_iter().map(|(_, rule)| rule).collect()
+}
+
 type MatchedRule<'a> = (Specificity, &'a Rule);
 
 fn matching_rules<'a>(
@@ -550,54 +1321,70 @@ fn match_rule<'a>(
         .map(|selector| (selector.specificity(), rule))
 }
 
-fn matches(
-    elem: &ElementData,
-    selector: &Selector,
-    appeared_elements: &Vec<SimpleSelector>,
-) -> bool {
+fn matches(elem: &ElementData, selector: &Selector, ancestors: &[SimpleSelector]) -> bool {
     match *selector {
         Selector::Simple(ref simple_selector) => matches_simple_selector(elem, simple_selector),
         Selector::Descendant(ref a, ref b) => {
-            matches_descendant_combinator(elem, &*a, &**b, appeared_elements)
-        }
-        Selector::Child(ref a, ref b) => {
-            matches_child_combinator(elem, &*a, &**b, appeared_elements)
+            matches_descendant_combinator(elem, &*a, &**b, ancestors)
         }
+        Selector::Child(ref a, ref b) => matches_child_combinator(elem, &*a, &**b, ancestors),
     }
 }
 
+/// `ancestors` is ordered from the root to the parent. The rest of the selector is matched only
+/// against the ancestors below the one matching `simple`, so `a b c` requires `a` to be an
+/// ancestor of `b`.
 fn matches_descendant_combinator(
     elem: &ElementData,
     simple: &SimpleSelector,
     selector_b: &Selector,
-    appeared_elements: &Vec<SimpleSelector>,
+    ancestors: &[SimpleSelector],
 ) -> bool {
-    appeared_elements.iter().any(|e| {
-        !((simple.tag_name.is_some() && e.tag_name != simple.tag_name)
-            || (simple.id.is_some() && e.id != simple.id)
-            || (!simple.class.iter().all(|class| e.class.contains(class))))
-    }) && matches(elem, selector_b, appeared_elements)
+    (0..ancestors.len()).any(|i| {
+        matches_ancestor(&ancestors[i], simple) && matches(elem, selector_b, &ancestors[i + 1..])
+    })
 }
 
 fn matches_child_combinator(
     elem: &ElementData,
     simple: &SimpleSelector,
     selector_b: &Selector,
-    appeared_elements: &Vec<SimpleSelector>,
+    ancestors: &[SimpleSelector],
 ) -> bool {
-    if let Some(ref last_elem) = appeared_elements.last() {
-        !((simple.tag_name.is_some() && last_elem.tag_name != simple.tag_name)
-            || (simple.id.is_some() && last_elem.id != simple.id)
-            || (!simple
-                .class
-                .iter()
-                .all(|class| last_elem.class.contains(class))))
-            && matches(elem, selector_b, appeared_elements)
-    } else {
-        false
+    (0..ancestors.len()).any(|i| {
+        matches_ancestor(&ancestors[i], simple)
+            && matches_first_ancestor(elem, selector_b, &ancestors[i + 1..])
+    })
+}
+
+/// Like `matches`, but the leftmost part of `selector` must match the first of `ancestors`, or
+/// `elem` itself if there are no ancestors left.
+fn matches_first_ancestor(
+    elem: &ElementData,
+    selector: &Selector,
+    ancestors: &[SimpleSelector],
+) -> bool {
+    match (selector, ancestors.split_first()) {
+        (&Selector::Simple(ref simple), None) => matches_simple_selector(elem, simple),
+        (&Selector::Descendant(ref a, ref b), Some((first, rest))) => {
+            matches_ancestor(first, a) && matches(elem, b, rest)
+        }
+        (&Selector::Child(ref a, ref b), Some((first, rest))) => {
+            matches_ancestor(first, a) && matches_first_ancestor(elem, b, rest)
+        }
+        _ => false,
     }
 }
 
+fn matches_ancestor(ancestor: &SimpleSelector, simple: &SimpleSelector) -> bool {
+    !((simple.tag_name.is_some() && ancestor.tag_name != simple.tag_name)
+        || (simple.id.is_some() && ancestor.id != simple.id)
+        || (!simple
+            .class
+            .iter()
+            .all(|class| ancestor.class.contains(class))))
+}
+
 fn matches_simple_selector(elem: &ElementData, selector: &SimpleSelector) -> bool {
     // Universal selector
     if selector.tag_name.is_none() && selector.id.is_none() && selector.class.is_empty() {
@@ -628,11 +1415,92 @@ fn matches_simple_selector(elem: &ElementData, selector: &SimpleSelector) -> boo
     true
 }
 
+/// A text node styled by the inline style `src`. For tests.
+#[cfg(test)]
+fn styled(src: &str) -> StyledNode<'static> {
+    use css::Declaration;
+
+    static TEXT: Node = Node {
+        data: NodeType::Text(String::new()),
+        children: Vec::new(),
+    };
+    StyledNode {
+        node: &TEXT,
+        specified_values: parse_attr_style(src.to_string())
+            .into_iter()
+            .map(|Declaration { name, values, .. }| (name, values))
+            .collect(),
+        children: vec![],
+    }
+}
+
+/// The data of an element `tag_name` with the attributes `attrs`. For tests.
+#[cfg(test)]
+fn elem(tag_name: &str, attrs: &[(&str, &str)]) -> ElementData {
+    let attrs = attrs
+        .iter()
+        .map(|&(name, value)| (name.to_string(), value.to_string()))
+        .collect();
+    match Node::elem(tag_name.to_string(), attrs, vec![]).data {
+        NodeType::Element(data) => data,
+        NodeType::Text(_) => unreachable!(),
+    }
+}
+
+/// The values specified for `elem` by the stylesheet `css_src` alone. For tests.
+#[cfg(test)]
+fn specified_values_for(elem: &ElementData, css_src: &str) -> PropertyMap {
+    use css;
+
+    specified_values(
+        elem,
+        &css::parse("".to_string()),
+        &css::parse(css_src.to_string()),
+        &PropertyMap::new(),
+        &vec![],
+    )
+}
+
+/// Style `html_src` with `css_src` and the default stylesheet, and pass the style tree to `f`.
+/// For tests.
+#[cfg(test)]
+pub fn with_style_tree<T, F>(html_src: &str, css_src: &str, f: F) -> T
+where
+    F: FnOnce(&StyledNode) -> T,
+{
+    use html;
+    use css;
+    use default_style::default_style;
+
+    let dom_node = html::parse(html_src.to_string());
+    let stylesheet = css::parse(css_src.to_string());
+    let default_style = default_style();
+    f(&style_tree(
+        &dom_node,
+        &stylesheet,
+        &default_style,
+        &PropertyMap::new(),
+        &PropertyMap::new(),
+        &vec![],
+    ))
+}
+
+/// The first node in the tree of `node` that is a `tag_name` element. For tests.
+#[cfg(test)]
+fn find<'a, 'b>(node: &'b StyledNode<'a>, tag_name: &str) -> Option<&'b StyledNode<'a>> {
+    match node.node.data {
+        NodeType::Element(ref e) if e.tag_name == tag_name => Some(node),
+        _ => node.children
+            .iter()
+            .filter_map(|child| find(child, tag_name))
+            .next(),
+    }
+}
+
 #[test]
 fn test1() {
     use html;
     use css;
-    use std::path::Path;
     use default_style::*;
 
     let src = "<html>
@@ -646,7 +1514,7 @@ fn test1() {
                    space
                  </body>
                </html>";
-    let dom_node = html::parse(src.to_string(), Path::new("a.html").to_path_buf());
+    let dom_node = html::parse(src.to_string());
 
     let src = "* { display: block; }
                div, body > div, body span { width: 100px; height: 50px; color: #ffffff; background-color: #003300; } 
@@ -663,3 +1531,451 @@ fn test1() {
         &vec![],
     );
 }
+
+#[test]
+fn test_padding() {
+    let padding = |src: &str| styled(src).padding();
+    let px = |f| Value::Length(f, Unit::Px);
+
+    assert_eq!(padding(""), (px(0.0), px(0.0), px(0.0), px(0.0)));
+    assert_eq!(padding("padding: 1px"), (px(1.0), px(1.0), px(1.0), px(1.0)));
+    assert_eq!(padding("padding: 1px 2px"), (px(1.0), px(2.0), px(1.0), px(2.0)));
+    assert_eq!(
+        padding("padding: 1px 2px 3px"),
+        (px(1.0), px(2.0), px(3.0), px(2.0))
+    );
+    assert_eq!(
+        padding("padding: 1px 2px 3px 4px"),
+        (px(1.0), px(2.0), px(3.0), px(4.0))
+    );
+    assert_eq!(
+        padding("padding: 1px; padding-left: 5px"),
+        (px(1.0), px(1.0), px(1.0), px(5.0))
+    );
+    assert_eq!(
+        padding("padding: -1px 2px"),
+        (px(0.0), px(2.0), px(0.0), px(2.0))
+    );
+
+    let (top, _, _, left) = padding("padding: 10% 5%");
+    assert_eq!(top.maybe_percent_to_px(200.0), Some(20.0));
+    assert_eq!(left.maybe_percent_to_px(200.0), Some(10.0));
+}
+
+#[test]
+fn test_class_selector() {
+    use css;
+
+    let classed = |tag_name: &str, class: &str| elem(tag_name, &[("class", class)]);
+    let matches_selector = |selector: &str, elem: &ElementData| {
+        let stylesheet = css::parse(format!("{} {{}}", selector));
+        matches(elem, &stylesheet.rules[0].selectors[0], &vec![])
+    };
+
+    // Single class
+    assert!(matches_selector(".highlight", &classed("p", "highlight")));
+    assert!(!matches_selector(".highlight", &classed("p", "highlighted")));
+
+    // Multiple classes separated by any whitespace
+    let multi = classed("p", " big\thighlight  note");
+    assert!(matches_selector(".highlight", &multi));
+    assert!(matches_selector(".big.note", &multi));
+    assert!(!matches_selector(".big.small", &multi));
+
+    // Compound selectors require both the tag and the class
+    assert!(matches_selector("p.highlight", &classed("p", "highlight")));
+    assert!(!matches_selector("p.highlight", &classed("div", "highlight")));
+    assert!(!matches_selector("p.highlight", &classed("p", "note")));
+
+    // A class rule beats a tag rule regardless of source order.
+    let values = specified_values_for(
+        &classed("p", "highlight"),
+        ".highlight { width: 2px; } p { width: 1px; }",
+    );
+    assert_eq!(values["width"], vec![Value::Length(2.0, Unit::Px)]);
+}
+
+#[test]
+fn test_id_selector_specificity() {
+    use css;
+
+    let elem = elem("p", &[("id", "main"), ("class", "note")]);
+    let color = |src: &str| specified_values_for(&elem, src)["color"].clone();
+
+    // An id rule overrides class and tag rules wherever it appears.
+    assert_eq!(
+        color("#main { color: #f00; } .note { color: #0f0; } p { color: #00f; }"),
+        vec![Value::Color(css::RED)]
+    );
+    assert_eq!(
+        color("p { color: #00f; } .note { color: #0f0; } #main { color: #f00; }"),
+        vec![Value::Color(css::RED)]
+    );
+    assert_eq!(
+        color("p#main { color: #f00; } #main.note { color: #0f0; }"),
+        vec![Value::Color(css::LIME)]
+    );
+
+    // Ties fall back to source order.
+    assert_eq!(
+        color("#main { color: #f00; } #main { color: #00f; }"),
+        vec![Value::Color(css::BLUE)]
+    );
+}
+
+#[test]
+fn test_background_position() {
+    use self::BackgroundPosition::{Length, Percent};
+
+    let position = |src: &str| styled(src).background_position();
+    let repeat = |src: &str| styled(src).background_repeat();
+    let px = Au::from_f64_px;
+
+    assert_eq!(position(""), (Percent(0.0), Percent(0.0)));
+    assert_eq!(
+        position("background-position: 10px 25%"),
+        (Length(px(10.0)), Percent(0.25))
+    );
+    assert_eq!(position("background-position: bottom"), (Percent(0.5), Percent(1.0)));
+    assert_eq!(position("background-position: right"), (Percent(1.0), Percent(0.5)));
+    assert_eq!(
+        position("background-position: top right"),
+        (Percent(1.0), Percent(0.0))
+    );
+    assert_eq!(
+        position("background: red url(a.png) center 5px no-repeat"),
+        (Percent(0.5), Length(px(5.0)))
+    );
+
+    assert_eq!(repeat(""), BackgroundRepeat::Repeat);
+    assert_eq!(repeat("background-repeat: repeat-x"), BackgroundRepeat::RepeatX);
+    assert_eq!(repeat("background: url(a.png) repeat-y"), BackgroundRepeat::RepeatY);
+    assert_eq!(repeat("background: url(a.png) no-repeat"), BackgroundRepeat::NoRepeat);
+}
+
+#[test]
+fn test_box_shadow() {
+    use css::{BLUE, RED};
+
+    let box_shadow = |src: &str| styled(src).box_shadow();
+    let px = Au::from_f64_px;
+
+    assert_eq!(
+        box_shadow("box-shadow: 1px 2px 3px #00f"),
+        Some(BoxShadow {
+            offset_x: px(1.0),
+            offset_y: px(2.0),
+            blur: px(3.0),
+            spread: px(0.0),
+            color: BLUE,
+            inset: false,
+        })
+    );
+    assert_eq!(
+        box_shadow("box-shadow: red -1px 2px 0 4px, 3px 4px blue"),
+        Some(BoxShadow {
+            offset_x: px(-1.0),
+            offset_y: px(2.0),
+            blur: px(0.0),
+            spread: px(4.0),
+            color: RED,
+            inset: false,
+        })
+    );
+    assert_eq!(box_shadow("box-shadow: 1px"), None);
+    assert_eq!(box_shadow("box-shadow: none"), None);
+    assert_eq!(
+        box_shadow("box-shadow: 1px 1px red inset"),
+        Some(BoxShadow {
+            offset_x: px(1.0),
+            offset_y: px(1.0),
+            blur: px(0.0),
+            spread: px(0.0),
+            color: RED,
+            inset: true,
+        })
+    );
+    assert_eq!(box_shadow("box-shadow: inset inset 1px 1px"), None);
+    assert_eq!(box_shadow(""), None);
+}
+
+#[test]
+fn test_text_shadow() {
+    use css::{BLUE, RED};
+
+    let text_shadow = |src: &str| styled(src).text_shadow();
+    let shadow = |x: f64, y: f64, blur: f64, color: Color| TextShadow {
+        offset_x: Au::from_f64_px(x),
+        offset_y: Au::from_f64_px(y),
+        blur: Au::from_f64_px(blur),
+        color: color,
+    };
+
+    assert_eq!(
+        text_shadow("text-shadow: 1px 2px #00f"),
+        vec![shadow(1.0, 2.0, 0.0, BLUE)]
+    );
+    assert_eq!(
+        text_shadow("text-shadow: red -1px 2px 3px, 4px 5px blue"),
+        vec![shadow(-1.0, 2.0, 3.0, RED), shadow(4.0, 5.0, 0.0, BLUE)]
+    );
+    // The color defaults to `color`.
+    assert_eq!(
+        text_shadow("color: red; text-shadow: 1px 1px"),
+        vec![shadow(1.0, 1.0, 0.0, RED)]
+    );
+    assert_eq!(text_shadow("text-shadow: 1px"), vec![]);
+    assert_eq!(text_shadow("text-shadow: 1px 1px red, 1px"), vec![]);
+    assert_eq!(text_shadow("text-shadow: none"), vec![]);
+}
+
+#[test]
+fn test_descendant_combinator() {
+    use css;
+
+    let elem = elem("p", &[]);
+    let ancestors = |tag_names: &[&str]| -> Vec<SimpleSelector> {
+        tag_names
+            .iter()
+            .map(|tag_name| SimpleSelector {
+                tag_name: Some(tag_name.to_string()),
+                id: None,
+                class: vec![format!("in-{}", tag_name)].into_iter().collect(),
+            })
+            .collect()
+    };
+    let matches_selector = |selector: &str, ancestors: &Vec<SimpleSelector>| {
+        let stylesheet = css::parse(format!("{} {{}}", selector));
+        matches(&elem, &stylesheet.rules[0].selectors[0], ancestors)
+    };
+
+    let nested = ancestors(&["html", "body", "article", "div"]);
+    // <article> is the grandparent.
+    assert!(matches_selector("article p", &nested));
+    assert!(matches_selector("article   p", &nested));
+    assert!(matches_selector("body .in-article p", &nested));
+    assert!(matches_selector("html * div p", &nested));
+    assert!(!matches_selector("section p", &nested));
+    assert!(!matches_selector("article span", &nested));
+    // Ancestors must appear in the same order as in the selector.
+    assert!(!matches_selector("div article p", &nested));
+    assert!(!matches_selector("article p", &vec![]));
+}
+
+#[test]
+fn test_child_combinator() {
+    use css;
+    use std::collections::HashSet;
+
+    let elem = elem("li", &[("class", "item")]);
+    let ancestor = |tag_name: &str, id: Option<&str>| SimpleSelector {
+        tag_name: Some(tag_name.to_string()),
+        id: id.map(|id| id.to_string()),
+        class: HashSet::new(),
+    };
+    let matches_selector = |selector: &str, ancestors: &Vec<SimpleSelector>| {
+        let stylesheet = css::parse(format!("{} {{}}", selector));
+        matches(&elem, &stylesheet.rules[0].selectors[0], ancestors)
+    };
+
+    // <ul id="menu"><li class="item">
+    let child = vec![ancestor("body", None), ancestor("ul", Some("menu"))];
+    assert!(matches_selector("ul > li", &child));
+    assert!(matches_selector("ul>li", &child));
+    assert!(matches_selector("#menu > li.item", &child));
+    assert!(matches_selector("ul#menu > .item", &child));
+    assert!(matches_selector("body > ul > li", &child));
+    assert!(!matches_selector("ul > li.other", &child));
+    assert!(!matches_selector("#other > li", &child));
+    assert!(!matches_selector("body > li", &child));
+
+    // <ul><li><div><li class="item">
+    let grandchild = vec![
+        ancestor("ul", None),
+        ancestor("li", None),
+        ancestor("div", None),
+    ];
+    assert!(!matches_selector("ul > li", &grandchild));
+    assert!(matches_selector("ul li", &grandchild));
+    assert!(matches_selector("ul > li li", &grandchild));
+    assert!(matches_selector("ul > li > div > li", &grandchild));
+    assert!(!matches_selector("ul > div li", &grandchild));
+}
+
+#[test]
+fn test_inline_style() {
+    use css;
+
+    let elem = elem("p", &[("class", "note"), ("style", "color: red; bogus; width: 10px")]);
+    let values = specified_values_for(&elem, ".note { color: blue; width: 20px; height: 5px; }");
+
+    // The inline style beats a matching class rule. Its malformed declaration is skipped.
+    assert_eq!(values["color"], vec![Value::Color(css::RED)]);
+    assert_eq!(values["width"], vec![Value::Length(10.0, Unit::Px)]);
+    assert_eq!(values["height"], vec![Value::Length(5.0, Unit::Px)]);
+}
+
+#[test]
+fn test_important() {
+    use css;
+
+    let elem = elem("p", &[("id", "main"), ("style", "color: #0f0")]);
+    let color = |src: &str| specified_values_for(&elem, src)["color"].clone();
+
+    // A low-specificity important declaration beats a high-specificity normal one, and the
+    // inline style.
+    assert_eq!(
+        color("p { color: #f00 !important; } #main { color: #00f; }"),
+        vec![Value::Color(css::RED)]
+    );
+    assert_eq!(
+        color("p { color: #f00 ! important } #main { color: #00f }"),
+        vec![Value::Color(css::RED)]
+    );
+    // Competing important declarations are resolved by specificity, then source order.
+    assert_eq!(
+        color("#main { color: #00f !important; } p { color: #f00 !important; }"),
+        vec![Value::Color(css::BLUE)]
+    );
+    assert_eq!(
+        color("p { color: #00f !important; } p { color: #f00 !important; }"),
+        vec![Value::Color(css::RED)]
+    );
+    // An unknown flag invalidates the declaration.
+    assert_eq!(
+        color("#main { color: #00f !bogus; }"),
+        vec![Value::Color(css::LIME)]
+    );
+}
+
+#[test]
+fn test_inheritance() {
+    use css;
+
+    let html_src = "<html><body><div><p><span>text</span><em>text</em></p></div></body></html>";
+    let css_src = "div { color: #f00; font-size: 20px; padding: 5px; width: 50px; } \
+                   p { line-height: 2; } \
+                   em { padding: inherit; color: inherit; width: inherit; }";
+    with_style_tree(html_src, css_src, |style_tree| {
+        // Inherited properties fall through <p> to the nested <span>.
+        let span = find(style_tree, "span").unwrap();
+        assert_eq!(span.value("color"), Some(vec![Value::Color(css::RED)]));
+        assert_eq!(span.font_size(), Au::from_f64_px(20.0));
+        assert_eq!(span.value("line-height"), Some(vec![Value::Num(2.0)]));
+        // Non-inherited properties don't leak.
+        assert_eq!(span.value("padding"), None);
+        assert_eq!(span.value("width"), None);
+
+        // `inherit` takes the parent's value. <p> doesn't have padding or width.
+        let em = find(style_tree, "em").unwrap();
+        assert_eq!(em.value("color"), Some(vec![Value::Color(css::RED)]));
+        assert_eq!(em.value("padding"), None);
+        assert_eq!(em.value("width"), None);
+    });
+}
+
+#[test]
+fn test_inherit_initial_keywords() {
+    use css;
+
+    let html_src = "<html><body><div><p><span>text</span></p></div></body></html>";
+    let css_src = "div { color: #f00; font-size: 20px; margin: 5px; } \
+                   p { margin: inherit; color: initial; font-size: initial; float: initial; }";
+    with_style_tree(html_src, css_src, |style_tree| {
+        // `inherit` works on the non-inherited `margin`.
+        let p = find(style_tree, "p").unwrap();
+        assert_eq!(p.value("margin"), Some(vec![Value::Length(5.0, Unit::Px)]));
+
+        // `initial` resets inherited properties, and the reset values are inherited.
+        assert_eq!(p.value("color"), Some(vec![Value::Color(css::BLACK)]));
+        assert_eq!(p.font_size(), Au::from_f64_px(DEFAULT_FONT_SIZE));
+        assert_eq!(p.float(), FloatType::None);
+        let span = find(style_tree, "span").unwrap();
+        assert_eq!(span.value("color"), Some(vec![Value::Color(css::BLACK)]));
+        assert_eq!(span.font_size(), Au::from_f64_px(DEFAULT_FONT_SIZE));
+    });
+}
+
+#[test]
+fn test_font_relative_lengths() {
+    let html_src = "<html><body><div><p><span>text</span></p></div></body></html>";
+    let css_src = "html { font-size: 10px; } \
+                   div { font-size: 16px; width: 2em; margin: 0em; } \
+                   p { font-size: 1.5em; padding: .5em; } \
+                   span { font-size: 30px; width: 2rem; line-height: 1.5rem; }";
+    with_style_tree(html_src, css_src, |style_tree| {
+        let px = |f| Some(vec![Value::Length(f, Unit::Px)]);
+
+        let div = find(style_tree, "div").unwrap();
+        assert_eq!(div.value("width"), px(32.0));
+        assert_eq!(div.value("margin"), px(0.0));
+
+        // `em` in font-size is relative to the parent's font size.
+        let p = find(style_tree, "p").unwrap();
+        assert_eq!(p.font_size(), Au::from_f64_px(24.0));
+        assert_eq!(p.value("padding"), px(12.0));
+
+        // `rem` ignores the local font size.
+        let span = find(style_tree, "span").unwrap();
+        assert_eq!(span.value("width"), px(20.0));
+        assert_eq!(span.line_height(), Au::from_f64_px(15.0));
+    });
+}
+
+#[test]
+fn test_line_height() {
+    let html_src = "<html><body>\
+                    <div><p>text</p></div>\
+                    <section><p>text</p></section>\
+                    <article><p>text</p></article>\
+                    </body></html>";
+    let css_src = "div, section, article { font-size: 10px; } p { font-size: 20px; } \
+                   div { line-height: 1.5; } \
+                   section { line-height: 30px; } \
+                   article { line-height: 150%; }";
+    with_style_tree(html_src, css_src, |style_tree| {
+        let line_heights = |tag_name: &str| {
+            let parent = find(style_tree, tag_name).unwrap();
+            (parent.line_height(), find(parent, "p").unwrap().line_height())
+        };
+        let px = Au::from_f64_px;
+
+        // A unitless value is inherited as the factor.
+        assert_eq!(line_heights("div"), (px(15.0), px(30.0)));
+        assert_eq!(line_heights("section"), (px(30.0), px(30.0)));
+        // A percentage is inherited as the length computed from the parent's font size.
+        assert_eq!(line_heights("article"), (px(15.0), px(15.0)));
+    });
+}
+
+#[test]
+fn test_font_family() {
+    let html_src = "<html><body><div><p>text</p></div></body></html>";
+    let css_src = "div { font-family: \"Noto Serif\", DejaVu Sans Mono, monospace; }";
+    with_style_tree(html_src, css_src, |style_tree| {
+        // Quoted and unquoted names with spaces. `font-family` is inherited.
+        let p = find(style_tree, "p").unwrap();
+        assert_eq!(
+            p.font_family(),
+            vec![
+                "Noto Serif".to_string(),
+                "DejaVu Sans Mono".to_string(),
+                "monospace".to_string(),
+            ]
+        );
+    });
+}
+
+#[test]
+fn test_visibility() {
+    let html_src = "<html><body><div><p>a</p><span>b</span><em>c</em></div></body></html>";
+    let css_src = "div { visibility: hidden; } p { visibility: visible; } \
+                   span { visibility: invisible; } em { visibility: 10px; }";
+    with_style_tree(html_src, css_src, |style_tree| {
+        assert_eq!(find(style_tree, "div").unwrap().visibility(), Visibility::Hidden);
+        assert_eq!(find(style_tree, "p").unwrap().visibility(), Visibility::Visible);
+        // Invalid values are ignored, and the hidden visibility is inherited.
+        assert_eq!(find(style_tree, "span").unwrap().visibility(), Visibility::Hidden);
+        assert_eq!(find(style_tree, "em").unwrap().visibility(), Visibility::Hidden);
+    });
+}
@@ -4,25 +4,32 @@ extern crate gtk;
 extern crate pango;
 extern crate pangocairo;
 
-use gtk::{Inhibit, ObjectExt, WidgetExt, traits::*};
-use gtk::ContainerExt;
+use gtk::{Inhibit, WidgetExt, traits::*};
+use gtk::{BoxExt, ButtonExt, ContainerExt, EntryExt, LabelExt};
 
 use glib::prelude::*; // or `use gtk::prelude::*;`
+use glib::Continue;
 
-use gdk::{ContextExt, Cursor, CursorType, Event, EventButton, EventMask, EventMotion, WindowExt,
-          RGBA};
+use gdk::{ContextExt, Cursor, CursorType, EventMask, ModifierType, ScrollDirection, WindowExt,
+          RGBA, SELECTION_CLIPBOARD};
+use gdk::enums::key;
 use gdk_pixbuf::{InterpType, PixbufExt};
 
-use cairo::Context;
+use cairo::{Context, Extend, Gradient, LinearGradient, PatternTrait};
 use pango::LayoutExt;
 
-use std::{cell::RefCell, cmp::{max, min}, collections::HashMap};
+use std::{cell::{Cell, RefCell}, cmp::{max, min}, collections::HashMap, fs::File, ops::Range,
+          path::Path, rc::Rc, time::Instant};
 
-use layout::Rect;
-use painter::{DisplayCommand, DisplayList};
+use layout::{EdgeSizes, InspectedBox, Rect};
+use painter::{border_edge_rects, DisplayCommand, DisplayList};
+use style::BackgroundRepeat;
 use font::FONT_DESC;
-use css::{TextDecoration, px2pt};
-use interface::update_html_tree_and_stylesheet;
+use inline::load_images_in_background;
+use selection::{find_matches, range_rect, selected_ranges, selected_text, Selection};
+use css::{BorderStyle, GradientDirection, TextDecoration};
+use interface::{current_source, current_title, current_url, describe_element, navigate,
+                resolve_url, same_document_fragment, url_fragment, Document, Navigation};
 
 #[derive(Clone, Debug)]
 pub enum AnkerKind {
@@ -31,24 +38,874 @@ pub enum AnkerKind {
 }
 
 thread_local!(
-    pub static ANKERS: RefCell<HashMap<Rect, AnkerKind>> = { RefCell::new(HashMap::with_capacity(8)) };
-    // HashMap<URL Fragment(id), y coordinate of the content>
-    pub static URL_FRAGMENTS: RefCell<HashMap<String, f64>> = { RefCell::new(HashMap::with_capacity(8)) };
-    pub static BUTTONS: RefCell<HashMap<usize, gtk::Button>> = { RefCell::new(HashMap::with_capacity(8)) };
+    // (Cursor over links, cursor elsewhere, cursor while loading). Created when first shown.
+    static CURSORS: RefCell<Option<(Cursor, Cursor, Cursor)>> = { RefCell::new(None) };
+    // The number of open windows. The app quits when the last one is closed.
+    static OPEN_WINDOWS: Cell<usize> = { Cell::new(0) };
+    // Distance to scroll by a notch of the mouse wheel, in px.
+    static WHEEL_STEP: Cell<f64> = { Cell::new(DEFAULT_WHEEL_STEP) };
 );
 
+/// The state of a window besides its document.
+struct View {
+    document: Rc<Document>,
+    // The URL bar and the page. Set in `RenderingWindow::new`.
+    url_entry: RefCell<Option<gtk::Entry>>,
+    drawing_area: RefCell<Option<gtk::DrawingArea>>,
+    // Whether a page is being loaded in the background.
+    loading: Cell<bool>,
+    // Scale of the page.
+    zoom: Cell<f64>,
+    // The text selection, and whether it's being dragged.
+    selection: Cell<Option<Selection>>,
+    selecting: Cell<bool>,
+    // The selected text as of the last draw, copied by Ctrl+C.
+    selected_text: RefCell<String>,
+    find: RefCell<Find>,
+    // The fragment to scroll to once the page is drawn.
+    pending_fragment: RefCell<Option<String>>,
+    // Whether the pointer is over a link.
+    over_anker: Cell<bool>,
+    // The target of the link under the pointer, shown in the status bar.
+    hovered_link: RefCell<Option<String>>,
+    // The images scaled for the page. Cleared when another page is shown.
+    scaled_images: RefCell<ScaledImageCache>,
+    // Whether the box model inspector is on, and the box under the pointer it highlights.
+    inspecting: Cell<bool>,
+    inspected: RefCell<Option<InspectedBox>>,
+    // The smooth scrolling in progress, vertically and horizontally.
+    vscroll: RefCell<Option<ScrollAnimation>>,
+    hscroll: RefCell<Option<ScrollAnimation>>,
+}
+
+impl View {
+    fn new(document: Rc<Document>) -> View {
+        View {
+            document: document,
+            url_entry: RefCell::new(None),
+            drawing_area: RefCell::new(None),
+            loading: Cell::new(false),
+            zoom: Cell::new(1.0),
+            selection: Cell::new(None),
+            selecting: Cell::new(false),
+            selected_text: RefCell::new(String::new()),
+            find: RefCell::new(Find::new()),
+            pending_fragment: RefCell::new(None),
+            over_anker: Cell::new(false),
+            hovered_link: RefCell::new(None),
+            scaled_images: RefCell::new(ScaledImageCache::new()),
+            inspecting: Cell::new(false),
+            inspected: RefCell::new(None),
+            vscroll: RefCell::new(None),
+            hscroll: RefCell::new(None),
+        }
+    }
+}
+
+/// Builds the display list of the document for the drawing area at the zoom, registering the
+/// boxes for the box model inspector if it's on.
+type Painter = Rc<Fn(&Rc<Document>, &gtk::DrawingArea, f64, bool) -> Rc<DisplayList>>;
+
+/// The start and end points of the gradient line of a gradient in `direction` painted in the rect.
+/// An angled line goes through the center, and is long enough for the corners to get the colors
+/// of the first and the last stops.
+/// ref. https://www.w3.org/TR/css-images-3/#linear-gradient-syntax
+fn gradient_line(
+    direction: GradientDirection,
+    x: f64,
+    y: f64,
+    width: f64,
+    height: f64,
+) -> (f64, f64, f64, f64) {
+    match direction {
+        GradientDirection::ToTop => (x, y + height, x, y),
+        GradientDirection::ToBottom => (x, y, x, y + height),
+        GradientDirection::ToLeft => (x + width, y, x, y),
+        GradientDirection::ToRight => (x, y, x + width, y),
+        GradientDirection::Angle(angle) => {
+            let (sin, cos) = angle.to_radians().sin_cos();
+            let half = (width * sin.abs() + height * cos.abs()) / 2.0;
+            let (center_x, center_y) = (x + width / 2.0, y + height / 2.0);
+            (
+                center_x - sin * half,
+                center_y + cos * half,
+                center_x + sin * half,
+                center_y - cos * half,
+            )
+        }
+    }
+}
+
+/// Return the anker at (x, y). Ankers are tried from top to bottom, and left to right, so that the
+/// result doesn't depend on the order of the HashMap.
+pub fn hit_test_ankers<T>(x: f64, y: f64, ankers: &HashMap<Rect, T>) -> Option<&T> {
+    let mut rects: Vec<&Rect> = ankers.keys().collect();
+    rects.sort_by_key(|rect| (rect.y, rect.x, rect.height, rect.width));
+    rects
+        .into_iter()
+        .find(|rect| {
+            rect.x.to_f64_px() <= x && x <= rect.x.to_f64_px() + rect.width.to_f64_px()
+                && rect.y.to_f64_px() <= y
+                && y <= rect.y.to_f64_px() + rect.height.to_f64_px()
+        })
+        .map(|rect| &ankers[rect])
+}
+
+/// Return the title of the innermost box at (x, y) that has one. An empty title hides the titles
+/// of the ancestors, so None is returned for it.
+pub fn hit_test_titles(x: f64, y: f64, titles: &[(Rect, String)]) -> Option<&str> {
+    titles
+        .iter()
+        .rev()
+        .find(|&&(ref rect, _)| {
+            rect.x.to_f64_px() <= x && x <= rect.x.to_f64_px() + rect.width.to_f64_px()
+                && rect.y.to_f64_px() <= y
+                && y <= rect.y.to_f64_px() + rect.height.to_f64_px()
+        })
+        .map(|&(_, ref title)| title.as_str())
+        .and_then(|title| if title.is_empty() { None } else { Some(title) })
+}
+
+/// Return the innermost element box whose border box contains (x, y).
+pub fn hit_test_inspected_boxes(x: f64, y: f64, boxes: &[InspectedBox]) -> Option<&InspectedBox> {
+    boxes.iter().rev().find(|inspected_box| {
+        let rect = inspected_box.border;
+        rect.x.to_f64_px() <= x && x <= rect.x.to_f64_px() + rect.width.to_f64_px()
+            && rect.y.to_f64_px() <= y
+            && y <= rect.y.to_f64_px() + rect.height.to_f64_px()
+    })
+}
+
+/// Turn the box model inspector on or off. The page is laid out again to register the boxes.
+fn toggle_inspector(view: &View, drawing_area: &gtk::DrawingArea) {
+    view.inspecting.set(!view.inspecting.get());
+    *view.inspected.borrow_mut() = None;
+    view.document.invalidate();
+    drawing_area.queue_draw();
+}
+
+/// Paint the margin of `inspected_box` in translucent orange, its padding in green and its
+/// content in blue, like the inspectors of browsers.
+fn paint_inspected_box(ctx: &Context, inspected_box: &InspectedBox) {
+    // Fill `outer` except `inner`.
+    let fill_between = |outer: Rect, inner: Rect, (r, g, b, a): (f64, f64, f64, f64)| {
+        ctx.set_source_rgba(r, g, b, a);
+        ctx.set_fill_rule(cairo::FillRule::EvenOdd);
+        for rect in &[outer, inner] {
+            ctx.rectangle(
+                rect.x.to_f64_px(),
+                rect.y.to_f64_px(),
+                rect.width.to_f64_px(),
+                rect.height.to_f64_px(),
+            );
+        }
+        ctx.fill();
+        ctx.set_fill_rule(cairo::FillRule::Winding);
+    };
+    fill_between(inspected_box.margin, inspected_box.border, INSPECTOR_MARGIN_COLOR);
+    fill_between(inspected_box.padding, inspected_box.content, INSPECTOR_PADDING_COLOR);
+    fill_highlight(ctx, inspected_box.content, INSPECTOR_CONTENT_COLOR);
+}
+
+/// Print the element of `document` in `inspected_box`, the rules applied to it and its boxes.
+fn print_inspected_box(document: &Document, inspected_box: &InspectedBox) {
+    if let Some(description) = describe_element(document, &inspected_box.path) {
+        print!("{}", description);
+    }
+    println!("content: {:?}", inspected_box.content);
+    println!("padding: {:?}", inspected_box.padding);
+    println!("border: {:?}", inspected_box.border);
+    println!("margin: {:?}", inspected_box.margin);
+}
+
+// Upper limit of the total size of the scaled images in bytes.
+const SCALED_IMG_CACHE_LIMIT: usize = 100 * 1024 * 1024;
+
+type ScaledImageKey = (gdk_pixbuf::Pixbuf, i32, i32);
+
+/// Scaled images keyed by (source image, width, height). Least recently used images are dropped
+/// when the total size exceeds `SCALED_IMG_CACHE_LIMIT`.
+pub struct ScaledImageCache {
+    // The value is (scaled image, last used time).
+    images: HashMap<ScaledImageKey, (gdk_pixbuf::Pixbuf, u64)>,
+    size: usize,
+    clock: u64,
+}
+
+impl ScaledImageCache {
+    fn new() -> ScaledImageCache {
+        ScaledImageCache {
+            images: HashMap::new(),
+            size: 0,
+            clock: 0,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.images.clear();
+        self.size = 0;
+    }
+
+    /// Return `pixbuf` scaled to `width` x `height`. None if the size is empty.
+    pub fn get(
+        &mut self,
+        pixbuf: &gdk_pixbuf::Pixbuf,
+        width: i32,
+        height: i32,
+    ) -> Option<gdk_pixbuf::Pixbuf> {
+        self.clock += 1;
+        let key = (pixbuf.clone(), width, height);
+
+        if let Some(&mut (ref scaled, ref mut last_used)) = self.images.get_mut(&key) {
+            *last_used = self.clock;
+            return Some(scaled.clone());
+        }
+
+        let scaled = pixbuf.scale_simple(width, height, InterpType::Hyper)?;
+        self.size += image_size(&scaled);
+        self.images.insert(key, (scaled.clone(), self.clock));
+
+        // Keep at least the image just inserted.
+        while self.size > SCALED_IMG_CACHE_LIMIT && self.images.len() > 1 {
+            let lru_key = self.images
+                .iter()
+                .min_by_key(|&(_, &(_, last_used))| last_used)
+                .map(|(key, _)| key.clone())
+                .unwrap();
+            let (lru, _) = self.images.remove(&lru_key).unwrap();
+            self.size -= image_size(&lru);
+        }
+
+        Some(scaled)
+    }
+}
+
+// Distance to scroll by an arrow key, and the overlap of pages scrolled by PageUp/PageDown, in px.
+// Used when the adjustment has no increments.
+const SCROLL_STEP: f64 = 40.0;
+const PAGE_OVERLAP: f64 = 40.0;
+
+pub const DEFAULT_WHEEL_STEP: f64 = 60.0;
+// How long smooth scrolling takes, and the interval of its frames, in ms.
+const SCROLL_DURATION: f64 = 150.0;
+const SCROLL_FRAME_INTERVAL: u32 = 16;
+
+/// Set the distance to scroll by a notch of the mouse wheel, in px.
+pub fn set_wheel_step(step: f64) {
+    WHEEL_STEP.with(|s| s.set(step));
+}
+
+/// The scrolling of an adjustment from `from` to `to`, which started at `start`.
+#[derive(Clone, Copy, Debug)]
+struct ScrollAnimation {
+    from: f64,
+    to: f64,
+    start: Instant,
+}
+
+impl ScrollAnimation {
+    /// The position `elapsed` ms after the start, and whether the animation is over.
+    fn position(&self, elapsed: f64) -> (f64, bool) {
+        let t = (elapsed / SCROLL_DURATION).min(1.0).max(0.0);
+        // Ease out: fast at first, and slowing down toward the target.
+        let eased = 1.0 - (1.0 - t).powi(3);
+        (self.from + (self.to - self.from) * eased, t >= 1.0)
+    }
+}
+
+/// The distance to scroll by a wheel event, in px horizontally and vertically. Shift turns
+/// vertical scrolling into horizontal one.
+fn wheel_delta(event: &gdk::EventScroll, step: f64) -> (f64, f64) {
+    let (dx, dy) = match event.get_direction() {
+        ScrollDirection::Up => (0.0, -1.0),
+        ScrollDirection::Down => (0.0, 1.0),
+        ScrollDirection::Left => (-1.0, 0.0),
+        ScrollDirection::Right => (1.0, 0.0),
+        ScrollDirection::Smooth => event.get_delta(),
+        _ => (0.0, 0.0),
+    };
+    if event.get_state().contains(ModifierType::SHIFT_MASK) {
+        ((dx + dy) * step, 0.0)
+    } else {
+        (dx * step, dy * step)
+    }
+}
+
+/// The target of smooth scrolling by `delta` from `target`, within the scrollable range.
+fn scroll_target(target: f64, delta: f64, lower: f64, upper: f64, page: f64) -> f64 {
+    let bottom = (upper - page).max(lower);
+    (target + delta).max(lower).min(bottom)
+}
+
+/// Scroll `adjustment` by `delta` px smoothly. Scrolling again during the animation retargets it
+/// from the current position, so that it doesn't jump.
+fn smooth_scroll_by(view: &Rc<View>, adjustment: gtk::Adjustment, horizontal: bool, delta: f64) {
+    fn scroll_of(view: &View, horizontal: bool) -> &RefCell<Option<ScrollAnimation>> {
+        if horizontal {
+            &view.hscroll
+        } else {
+            &view.vscroll
+        }
+    }
+
+    {
+        let mut animation = scroll_of(view, horizontal).borrow_mut();
+        let running = animation.is_some();
+        let target = animation.map_or(adjustment.get_value(), |animation| animation.to);
+        *animation = Some(ScrollAnimation {
+            from: adjustment.get_value(),
+            to: scroll_target(
+                target,
+                delta,
+                adjustment.get_lower(),
+                adjustment.get_upper(),
+                adjustment.get_page_size(),
+            ),
+            start: Instant::now(),
+        });
+        // The running timer picks up the new target.
+        if running {
+            return;
+        }
+    }
+
+    let view = view.clone();
+    gtk::timeout_add(SCROLL_FRAME_INTERVAL, move || {
+        let mut animation = scroll_of(&view, horizontal).borrow_mut();
+        let done = match *animation {
+            Some(ref scroll) => {
+                let elapsed = scroll.start.elapsed();
+                let elapsed =
+                    elapsed.as_secs() as f64 * 1000.0 + elapsed.subsec_nanos() as f64 / 1_000_000.0;
+                let (value, done) = scroll.position(elapsed);
+                adjustment.set_value(value);
+                done
+            }
+            None => true,
+        };
+        if done {
+            *animation = None;
+        }
+        Continue(!done)
+    });
+}
+
+/// Return the position of `adjustment` after scrolling by the key, or None if the key doesn't
+/// scroll.
+fn scroll_position(keyval: u32, adjustment: &gtk::Adjustment) -> Option<f64> {
+    scroll_position_in(
+        keyval,
+        adjustment.get_value(),
+        adjustment.get_lower(),
+        adjustment.get_upper(),
+        adjustment.get_page_size(),
+        (adjustment.get_step_increment(), adjustment.get_page_increment()),
+    )
+}
+
+fn scroll_position_in(
+    keyval: u32,
+    value: f64,
+    lower: f64,
+    upper: f64,
+    page: f64,
+    (step, page_step): (f64, f64),
+) -> Option<f64> {
+    let bottom = (upper - page).max(lower);
+    let step = if step > 0.0 { step } else { SCROLL_STEP };
+    let page_step = if page_step > 0.0 {
+        page_step
+    } else {
+        (page - PAGE_OVERLAP).max(SCROLL_STEP)
+    };
+    let value = match keyval {
+        key::Up | key::KP_Up => value - step,
+        key::Down | key::KP_Down => value + step,
+        key::Page_Up | key::KP_Page_Up => value - page_step,
+        key::Page_Down | key::KP_Page_Down | key::space => value + page_step,
+        key::Home | key::KP_Home => lower,
+        key::End | key::KP_End => bottom,
+        _ => return None,
+    };
+    Some(value.max(lower).min(bottom))
+}
+
+/// Scroll to the element with the id at the next draw, when the page has been laid out.
+fn scroll_to_fragment(view: &View, id: String) {
+    *view.pending_fragment.borrow_mut() = Some(id);
+}
+
+/// Return the position of `adjustment` that puts the top of the element with the id at the top of
+/// the viewport. Unknown fragments scroll to the top of the page.
+fn fragment_scroll_position(
+    id: &str,
+    url_fragments: &HashMap<String, Rect>,
+    adjustment: &gtk::Adjustment,
+) -> f64 {
+    fragment_scroll_position_in(
+        id,
+        url_fragments,
+        adjustment.get_lower(),
+        adjustment.get_upper(),
+        adjustment.get_page_size(),
+    )
+}
+
+fn fragment_scroll_position_in(
+    id: &str,
+    url_fragments: &HashMap<String, Rect>,
+    lower: f64,
+    upper: f64,
+    page: f64,
+) -> f64 {
+    url_fragments.get(id).map_or(lower, |rect| {
+        let top = rect.y.to_f64_px();
+        top.max(lower).min((upper - page).max(lower))
+    })
+}
+
+const MIN_ZOOM: f64 = 0.5;
+const MAX_ZOOM: f64 = 3.0;
+
+/// Change the zoom by `steps` of 10%. Zero steps resets it. The layout is done in CSS px, and the
+/// display list is scaled by the zoom.
+fn zoom_by(view: &View, steps: i32, drawing_area: &gtk::DrawingArea) {
+    view.zoom.set(zoom_step(view.zoom.get(), steps));
+    drawing_area.queue_draw();
+}
+
+fn zoom_step(zoom: f64, steps: i32) -> f64 {
+    if steps == 0 {
+        return 1.0;
+    }
+    let zoom = ((zoom * 10.0).round() + steps as f64) / 10.0;
+    zoom.max(MIN_ZOOM).min(MAX_ZOOM)
+}
+
+/// The state of the find bar.
+struct Find {
+    query: String,
+    // The index of the current match.
+    current: usize,
+    // The number of matches as of the last draw.
+    count: usize,
+    // Whether to scroll to the current match at the next draw.
+    scroll: bool,
+}
+
+impl Find {
+    fn new() -> Find {
+        Find {
+            query: String::new(),
+            current: 0,
+            count: 0,
+            scroll: false,
+        }
+    }
+}
+
+fn set_find_query(view: &View, query: String, drawing_area: &gtk::DrawingArea) {
+    {
+        let mut find = view.find.borrow_mut();
+        find.query = query;
+        find.current = 0;
+        find.scroll = true;
+    }
+    drawing_area.queue_draw();
+}
+
+/// Move to the next match, or the previous one if `step` is negative.
+fn find_next(view: &View, step: i32, drawing_area: &gtk::DrawingArea) {
+    {
+        let mut find = view.find.borrow_mut();
+        if find.count > 0 {
+            let count = find.count as i32;
+            find.current = ((find.current as i32 + step) % count + count) as usize % find.count;
+            find.scroll = true;
+        }
+    }
+    drawing_area.queue_draw();
+}
+
+/// Return the position of the scrolled window that shows the area from `top` to `bottom` in the
+/// middle, or None if it's already visible.
+fn scroll_into_view(
+    value: f64,
+    lower: f64,
+    upper: f64,
+    page: f64,
+    (top, bottom): (f64, f64),
+) -> Option<f64> {
+    if value <= top && bottom <= value + page {
+        return None;
+    }
+    let value = top - (page - (bottom - top)) / 2.0;
+    Some(value.max(lower).min((upper - page).max(lower)))
+}
+
+/// Clear the text selection, e.g. when navigating to another page.
+fn clear_selection(view: &View) {
+    view.selection.set(None);
+    view.selecting.set(false);
+    view.selected_text.borrow_mut().clear();
+}
+
+fn copy_selection(view: &View) {
+    let text = view.selected_text.borrow();
+    if !text.is_empty() {
+        gtk::Clipboard::get(&SELECTION_CLIPBOARD).set_text(&text);
+    }
+}
+
+/// Record whether the pointer is over a link. Returns whether it changed since the last motion.
+fn update_hover_state(view: &View, over_anker: bool) -> bool {
+    view.over_anker.replace(over_anker) != over_anker
+}
+
+/// Show the busy cursor while a page is loading, and otherwise the one for whether the pointer is
+/// over a link.
+fn update_cursor(view: &View) {
+    let window = match view.drawing_area
+        .borrow()
+        .as_ref()
+        .and_then(|drawing_area| drawing_area.get_parent())
+        .and_then(|overlay| overlay.get_window())
+    {
+        Some(window) => window,
+        None => return,
+    };
+    CURSORS.with(|cursors| {
+        let mut cursors = cursors.borrow_mut();
+        let &mut (ref hand, ref arrow, ref busy) = cursors.get_or_insert_with(|| {
+            (
+                Cursor::new(CursorType::Hand1),
+                Cursor::new(CursorType::LeftPtr),
+                Cursor::new(CursorType::Watch),
+            )
+        });
+        window.set_cursor(Some(if view.loading.get() {
+            busy
+        } else if view.over_anker.get() {
+            hand
+        } else {
+            arrow
+        }));
+    });
+}
+
+/// Record the target of the link under the pointer. Returns whether it changed since the last
+/// motion.
+fn update_hovered_link(view: &View, target: Option<String>) -> bool {
+    let changed = *view.hovered_link.borrow() != target;
+    *view.hovered_link.borrow_mut() = target;
+    changed
+}
+
+/// The URL `anker` leads to, resolved against `document`.
+fn link_target(document: &Document, anker: &AnkerKind) -> String {
+    match anker {
+        // Registered already resolved.
+        &AnkerKind::URL(ref url) => url.clone(),
+        &AnkerKind::URLFragment(ref id) => {
+            let fragment = format!("#{}", id);
+            resolve_url(document, &fragment)
+                .map(|url| url.into_string())
+                .unwrap_or(fragment)
+        }
+    }
+}
+
+/// Show the URL of the new page of `view` in the URL bar, or the error in its tooltip if the page
+/// couldn't be loaded. `result` is whether a page was loaded, in which case the selection and the
+/// scaled images of the previous one are dropped. Returns whether the page needs to be redrawn.
+fn navigated(view: &View, result: Result<bool, String>) -> bool {
+    let entry = view.url_entry.borrow();
+    let entry = entry.as_ref().unwrap();
+    match result {
+        Ok(loaded) => {
+            if loaded {
+                clear_selection(view);
+                view.scaled_images.borrow_mut().clear();
+                entry.set_text(current_url(&view.document).unwrap_or_default().as_str());
+                if let Some(window) = entry.get_toplevel() {
+                    set_window_title(&view.document, &window.downcast::<gtk::Window>().unwrap());
+                }
+            }
+            entry.set_tooltip_text(None);
+            loaded
+        }
+        Err(e) => {
+            entry.set_tooltip_text(Some(e.as_str()));
+            false
+        }
+    }
+}
+
+/// Show the title of `document`, or its file name if it has no title.
+fn set_window_title(document: &Document, window: &gtk::Window) {
+    let title = current_title(document).or_else(|| {
+        current_url(document).map(|url| match url.trim_right_matches('/').rsplit('/').next() {
+            Some(file_name) if !file_name.is_empty() => file_name.to_string(),
+            _ => url,
+        })
+    });
+    match title {
+        Some(title) => window.set_title(&format!("{} - Naglfar", title)),
+        None => window.set_title("Naglfar"),
+    }
+}
+
+/// Navigate `view` to `url`. A fragment of its document is scrolled to without reloading, and
+/// other pages are loaded in the background. Returns whether the page needs to be redrawn now.
+fn open_url(view: &Rc<View>, url: String) -> bool {
+    if let Some(id) = same_document_fragment(&view.document, &url) {
+        // A page still loading would replace the one scrolled in.
+        view.document.begin_navigation();
+        view.loading.set(false);
+        update_cursor(view);
+        scroll_to_fragment(view, id);
+        return true;
+    }
+    // Resolve the fragment against the current page before leaving it.
+    let fragment = url_fragment(&view.document, &url);
+    start_navigation(view, Navigation::Visit(url), fragment);
+    false
+}
+
+/// Start `navigation` of `view` in the background. The current page stays interactive with the
+/// busy cursor until the new one is shown, and then `fragment` is scrolled to. Starting another
+/// navigation, even one that fails, first discards the page.
+fn start_navigation(view: &Rc<View>, navigation: Navigation, fragment: Option<String>) {
+    let started = navigate(&view.document, navigation, {
+        let view = view.clone();
+        move |result| {
+            view.loading.set(false);
+            update_cursor(&view);
+            if navigated(&view, result) {
+                if let Some(id) = fragment {
+                    scroll_to_fragment(&view, id);
+                }
+                if let Some(ref drawing_area) = *view.drawing_area.borrow() {
+                    drawing_area.queue_draw();
+                }
+            }
+        }
+    });
+    // A page loading for an earlier navigation was discarded even if this one didn't start.
+    view.loading.set(started);
+    update_cursor(view);
+}
+
+/// Whether a click with `button` and the modifier keys in `state` opens a link in a new window.
+/// That's the middle button, or the left one with Ctrl.
+fn opens_new_window(button: u32, state: ModifierType) -> bool {
+    button == 2 || button == 1 && state.contains(ModifierType::CONTROL_MASK)
+}
+
+/// Open `url` in a new window with its own document and history. The URL is resolved against
+/// `document`, that of the window it's opened from.
+fn open_in_new_window(document: &Document, url: String, painter: Painter) {
+    let url = match resolve_url(document, &url) {
+        Ok(url) => url.into_string(),
+        Err(e) => {
+            eprintln!("{}", e);
+            return;
+        }
+    };
+    let fragment = url_fragment(document, &url);
+
+    let view = Rc::new(View::new(Rc::new(Document::new())));
+    let window = RenderingWindow::new(800, 520, view.clone(), painter);
+    window.quit_on_last_close();
+    // The page is loaded in the background like any other, and the fragment is scrolled to once
+    // it's shown.
+    start_navigation(&view, Navigation::Visit(url), fragment);
+}
+
+/// Add an item labeled `label` to `menu`, which calls `action` when it's chosen.
+fn add_menu_item<F: Fn() + 'static>(menu: &gtk::Menu, label: &str, action: F) {
+    let item = gtk::MenuItem::new_with_label(label);
+    item.connect_activate(move |_| action());
+    menu.append(&item);
+}
+
+/// The context menu of the page in `view`. If a link was clicked, the menu is for `anker`.
+fn context_menu(
+    anker: Option<AnkerKind>,
+    view: &Rc<View>,
+    painter: &Painter,
+    drawing_area: &gtk::Widget,
+) -> gtk::Menu {
+    let menu = gtk::Menu::new();
+    match anker {
+        Some(anker) => {
+            // The URL is resolved now, so that the items open the link that was clicked even if
+            // the page changes before one is chosen.
+            let target = link_target(&view.document, &anker);
+            {
+                let view = view.clone();
+                let drawing_area = drawing_area.clone();
+                let target = target.clone();
+                add_menu_item(&menu, "Open Link", move || {
+                    if open_url(&view, target.clone()) {
+                        drawing_area.queue_draw();
+                    }
+                });
+            }
+            {
+                let view = view.clone();
+                let painter = painter.clone();
+                let target = target.clone();
+                add_menu_item(&menu, "Open Link in New Window", move || {
+                    open_in_new_window(&view.document, target.clone(), painter.clone());
+                });
+            }
+            add_menu_item(&menu, "Copy Link Address", move || {
+                gtk::Clipboard::get(&SELECTION_CLIPBOARD).set_text(&target);
+            });
+        }
+        None => {
+            {
+                let view = view.clone();
+                add_menu_item(&menu, "Reload", move || {
+                    start_navigation(&view, Navigation::Reload, None);
+                });
+            }
+            {
+                let view = view.clone();
+                add_menu_item(&menu, "View Source", move || {
+                    open_source_window(&view.document);
+                });
+            }
+        }
+    }
+    menu
+}
+
+/// Open a window showing the HTML source of `document`.
+fn open_source_window(document: &Document) {
+    let source = match current_source(document) {
+        Some(source) => source,
+        None => return,
+    };
+    let font = pango::FontDescription::from_string("monospace");
+
+    let text_view = gtk::TextView::new();
+    text_view.set_editable(false);
+    text_view.override_font(&font);
+    let buffer = text_view.get_buffer().unwrap();
+    buffer.set_text(source.as_str());
+    let highlights = source_highlights(&source);
+    for &(kind, color) in &[(SourceKind::Tag, "#881280"), (SourceKind::Comment, "#236e25")] {
+        let tag = gtk::TextTag::new(None);
+        tag.set_property_foreground(Some(color));
+        buffer.get_tag_table().unwrap().add(&tag);
+        for &(ref range, _) in highlights.iter().filter(|h| h.1 == kind) {
+            buffer.apply_tag(
+                &tag,
+                &buffer.get_iter_at_offset(range.start as i32),
+                &buffer.get_iter_at_offset(range.end as i32),
+            );
+        }
+    }
+
+    let line_numbers = gtk::TextView::new();
+    line_numbers.set_editable(false);
+    line_numbers.set_cursor_visible(false);
+    line_numbers.set_sensitive(false);
+    line_numbers.override_font(&font);
+    line_numbers
+        .get_buffer()
+        .unwrap()
+        .set_text(source_line_numbers(&source).as_str());
+
+    // Both views are in the same scrolled window, so the numbers scroll with the source.
+    let hbox = gtk::Box::new(gtk::Orientation::Horizontal, 4);
+    hbox.pack_start(&line_numbers, false, false, 0);
+    hbox.pack_start(&text_view, true, true, 0);
+    let scrolled_window = gtk::ScrolledWindow::new(None, None);
+    scrolled_window.add(&hbox);
+
+    let window = gtk::Window::new(gtk::WindowType::Toplevel);
+    window.set_title(&format!(
+        "Source of {} - Naglfar",
+        current_url(document).unwrap_or_default()
+    ));
+    window.set_default_size(800, 520);
+    window.add(&scrolled_window);
+    window.show_all();
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum SourceKind {
+    Tag,
+    Comment,
+}
+
+/// The ranges of tags and comments in the HTML `source`, in characters (not bytes), to color.
+fn source_highlights(source: &str) -> Vec<(Range<usize>, SourceKind)> {
+    let chars: Vec<char> = source.chars().collect();
+    let starts_with = |pos: usize, s: &str| {
+        s.chars()
+            .enumerate()
+            .all(|(i, c)| chars.get(pos + i) == Some(&c))
+    };
+    let mut highlights = vec![];
+    let mut pos = 0;
+    while pos < chars.len() {
+        if starts_with(pos, "<!--") {
+            let end = (pos + 4..chars.len())
+                .find(|&end| starts_with(end, "-->"))
+                .map_or(chars.len(), |end| end + 3);
+            highlights.push((pos..end, SourceKind::Comment));
+            pos = end;
+        } else if chars[pos] == '<' {
+            let end = (pos + 1..chars.len())
+                .find(|&end| chars[end] == '>')
+                .map_or(chars.len(), |end| end + 1);
+            highlights.push((pos..end, SourceKind::Tag));
+            pos = end;
+        } else {
+            pos += 1;
+        }
+    }
+    highlights
+}
+
+/// The line numbers of `source`, right-aligned, one per line.
+fn source_line_numbers(source: &str) -> String {
+    let lines = source.matches('\n').count() + 1;
+    let width = lines.to_string().len();
+    (1..lines + 1)
+        .map(|n| format!("{:>1$}", n, width))
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// The vertical adjustment of the scrolled window containing the drawing area.
+fn vadjustment(drawing_area: &gtk::DrawingArea) -> Option<gtk::Adjustment> {
+    drawing_area
+        .get_parent()
+        .and_then(|overlay| overlay.get_parent())
+        .and_then(|viewport| viewport.get_parent())
+        .and_then(|w| w.downcast::<gtk::ScrolledWindow>().ok())
+        .and_then(|scrolled_window| scrolled_window.get_vadjustment())
+}
+
+fn image_size(pixbuf: &gdk_pixbuf::Pixbuf) -> usize {
+    (pixbuf.get_rowstride() * pixbuf.get_height()) as usize
+}
+
 struct RenderingWindow {
     window: gtk::Window,
     drawing_area: gtk::DrawingArea,
 }
 
 impl RenderingWindow {
-    fn new<F: 'static>(width: i32, height: i32, f: F) -> RenderingWindow
-    where
-        F: Fn(&gtk::DrawingArea) -> DisplayList,
-    {
+    /// Create a window showing the document of `view`.
+    fn new(width: i32, height: i32, view: Rc<View>, painter: Painter) -> RenderingWindow {
         let window = gtk::Window::new(gtk::WindowType::Toplevel);
-        window.set_title("Naglfar");
+        set_window_title(&view.document, &window);
         window.set_default_size(width, height);
         window.override_background_color(
             gtk::StateFlags::from_bits(gtk::StateFlags::NORMAL.bits()).unwrap(),
@@ -60,8 +917,18 @@ impl RenderingWindow {
             }),
         );
 
+        // The width follows the window, so that the page is laid out again when it's resized.
         let drawing_area = gtk::DrawingArea::new();
-        drawing_area.set_size_request(width, height);
+        drawing_area.set_size_request(-1, height);
+        {
+            let last_width = Cell::new(-1);
+            drawing_area.connect_size_allocate(move |drawing_area, allocation| {
+                if last_width.get() != allocation.width {
+                    last_width.set(allocation.width);
+                    drawing_area.queue_draw();
+                }
+            });
+        }
 
         let layout = gtk::Layout::new(None, None);
 
@@ -74,114 +941,395 @@ impl RenderingWindow {
             overlay.set_child_index(&layout, 1);
         }
 
-        let scrolled_window = gtk::ScrolledWindow::new(None, None);
-        scrolled_window.add(&overlay);
+        let scrolled_window = gtk::ScrolledWindow::new(None, None);
+        scrolled_window.add(&overlay);
+
+        // Toolbar
+        let back_button = gtk::Button::new_with_label("Back");
+        {
+            let view = view.clone();
+            back_button.connect_clicked(move |_| {
+                start_navigation(&view, Navigation::Back, None);
+            });
+        }
+        let forward_button = gtk::Button::new_with_label("Forward");
+        {
+            let view = view.clone();
+            forward_button.connect_clicked(move |_| {
+                start_navigation(&view, Navigation::Forward, None);
+            });
+        }
+
+        let reload_button = gtk::Button::new_with_label("Reload");
+        {
+            let view = view.clone();
+            reload_button.connect_clicked(move |_| {
+                start_navigation(&view, Navigation::Reload, None);
+            });
+        }
+
+        let url_entry = gtk::Entry::new();
+        if let Some(url) = current_url(&view.document) {
+            url_entry.set_text(url.as_str());
+        }
+        {
+            let view = view.clone();
+            let drawing_area = drawing_area.clone();
+            url_entry.connect_activate(move |entry| {
+                if let Some(url) = entry.get_text() {
+                    if open_url(&view, url) {
+                        drawing_area.queue_draw();
+                    }
+                }
+            });
+        }
+        *view.url_entry.borrow_mut() = Some(url_entry.clone());
+        *view.drawing_area.borrow_mut() = Some(drawing_area.clone());
+
+        // The find bar, shown by Ctrl+F.
+        let find_entry = gtk::Entry::new();
+        {
+            let view = view.clone();
+            let drawing_area = drawing_area.clone();
+            find_entry.connect_changed(move |entry| {
+                set_find_query(&view, entry.get_text().unwrap_or_default(), &drawing_area);
+            });
+        }
+        // The status bar, showing the target of the link under the pointer.
+        let status_label = gtk::Label::new(None);
+        status_label.set_halign(gtk::Align::Start);
+        status_label.set_ellipsize(pango::EllipsizeMode::Middle);
+
+        let find_bar = gtk::Box::new(gtk::Orientation::Horizontal, 0);
+        find_bar.pack_start(&gtk::Label::new("Find:"), false, false, 4);
+        find_bar.pack_start(&find_entry, true, true, 0);
+        find_bar.set_no_show_all(true);
+        find_entry.show();
+        for child in find_bar.get_children() {
+            child.show();
+        }
+
+        let toolbar = gtk::Box::new(gtk::Orientation::Horizontal, 0);
+        toolbar.pack_start(&back_button, false, false, 0);
+        toolbar.pack_start(&forward_button, false, false, 0);
+        toolbar.pack_start(&reload_button, false, false, 0);
+        toolbar.pack_start(&url_entry, true, true, 0);
+
+        let file_menu = gtk::Menu::new();
+        {
+            let view = view.clone();
+            let window = window.clone();
+            let drawing_area = drawing_area.clone();
+            let painter = painter.clone();
+            add_menu_item(&file_menu, "Export PDF...", move || {
+                export_pdf_dialog(&view, &window, &drawing_area, &painter);
+            });
+        }
+        {
+            let view = view.clone();
+            add_menu_item(&file_menu, "View Source", move || {
+                open_source_window(&view.document);
+            });
+        }
+        let file_item = gtk::MenuItem::new_with_label("File");
+        file_item.set_submenu(Some(&file_menu));
+        let menu_bar = gtk::MenuBar::new();
+        menu_bar.append(&file_item);
+
+        // Alt+Left and Alt+Right go back and forward. F5 and Ctrl+R reload. Ctrl+Plus, Ctrl+Minus
+        // and Ctrl+0 zoom. Ctrl+C copies the selected text. Ctrl+P exports the page to PDF. Ctrl+U
+        // shows the source of the page. Ctrl+Shift+I toggles the box model inspector. Ctrl+F opens
+        // the find bar, in which Enter and Shift+Enter move between matches and Escape closes it.
+        // Other keys scroll the page unless the URL bar or the find bar is focused.
+        {
+            let view = view.clone();
+            let drawing_area = drawing_area.clone();
+            let painter = painter.clone();
+            let url_entry = url_entry.clone();
+            let find_bar = find_bar.clone();
+            let find_entry = find_entry.clone();
+            let scrolled_window = scrolled_window.clone();
+            window.connect_key_press_event(move |window, event| {
+                let alt = event.get_state().contains(ModifierType::MOD1_MASK);
+                let ctrl = event.get_state().contains(ModifierType::CONTROL_MASK);
+                let shift = event.get_state().contains(ModifierType::SHIFT_MASK);
+                let editing = url_entry.has_focus() || find_entry.has_focus();
+                let navigation = match event.get_keyval() {
+                    key::Left if alt => Navigation::Back,
+                    key::Right if alt => Navigation::Forward,
+                    key::F5 => Navigation::Reload,
+                    key::r | key::R if ctrl => Navigation::Reload,
+                    key::c | key::C if ctrl && !editing => {
+                        copy_selection(&view);
+                        return Inhibit(true);
+                    }
+                    key::p | key::P if ctrl => {
+                        export_pdf_dialog(&view, window, &drawing_area, &painter);
+                        return Inhibit(true);
+                    }
+                    key::i | key::I if ctrl && shift => {
+                        toggle_inspector(&view, &drawing_area);
+                        return Inhibit(true);
+                    }
+                    key::u | key::U if ctrl => {
+                        open_source_window(&view.document);
+                        return Inhibit(true);
+                    }
+                    key::f | key::F if ctrl => {
+                        find_bar.show();
+                        find_entry.grab_focus();
+                        return Inhibit(true);
+                    }
+                    key::Return | key::KP_Enter if find_entry.has_focus() => {
+                        find_next(&view, if shift { -1 } else { 1 }, &drawing_area);
+                        return Inhibit(true);
+                    }
+                    key::Escape if find_bar.is_visible() => {
+                        find_bar.hide();
+                        find_entry.set_text("");
+                        return Inhibit(true);
+                    }
+                    key::plus | key::equal | key::KP_Add if ctrl => {
+                        zoom_by(&view, 1, &drawing_area);
+                        return Inhibit(true);
+                    }
+                    key::minus | key::KP_Subtract if ctrl => {
+                        zoom_by(&view, -1, &drawing_area);
+                        return Inhibit(true);
+                    }
+                    key::_0 | key::KP_0 if ctrl => {
+                        zoom_by(&view, 0, &drawing_area);
+                        return Inhibit(true);
+                    }
+                    _ if editing || alt || ctrl => return Inhibit(false),
+                    keyval => {
+                        let adjustment = scrolled_window.get_vadjustment().unwrap();
+                        return match scroll_position(keyval, &adjustment) {
+                            Some(value) => {
+                                adjustment.set_value(value);
+                                Inhibit(true)
+                            }
+                            None => Inhibit(false),
+                        };
+                    }
+                };
+                start_navigation(&view, navigation, None);
+                Inhibit(true)
+            });
+        }
+
+        // The wheel scrolls smoothly. Ctrl+wheel is left to the window, which zooms.
+        {
+            let view = view.clone();
+            let scrolled_window = scrolled_window.clone();
+            overlay.connect_scroll_event(move |_, event| {
+                if event.get_state().contains(ModifierType::CONTROL_MASK) {
+                    return Inhibit(false);
+                }
+                let (dx, dy) = wheel_delta(event, WHEEL_STEP.with(|s| s.get()));
+                if let Some(adjustment) = scrolled_window.get_vadjustment() {
+                    if dy != 0.0 {
+                        smooth_scroll_by(&view, adjustment, false, dy);
+                    }
+                }
+                if let Some(adjustment) = scrolled_window.get_hadjustment() {
+                    // Only if the page is wider than the window.
+                    let width = adjustment.get_upper() - adjustment.get_lower();
+                    if dx != 0.0 && width > adjustment.get_page_size() {
+                        smooth_scroll_by(&view, adjustment, true, dx);
+                    }
+                }
+                Inhibit(true)
+            });
+        }
+
+        // Ctrl+wheel zooms.
+        {
+            let view = view.clone();
+            let drawing_area = drawing_area.clone();
+            window.connect_scroll_event(move |_, event| {
+                if !event.get_state().contains(ModifierType::CONTROL_MASK) {
+                    return Inhibit(false);
+                }
+                match event.get_direction() {
+                    ScrollDirection::Up => zoom_by(&view, 1, &drawing_area),
+                    ScrollDirection::Down => zoom_by(&view, -1, &drawing_area),
+                    ScrollDirection::Smooth => {
+                        let (_, dy) = event.get_delta();
+                        if dy < 0.0 {
+                            zoom_by(&view, 1, &drawing_area)
+                        } else if dy > 0.0 {
+                            zoom_by(&view, -1, &drawing_area)
+                        }
+                    }
+                    _ => {}
+                }
+                Inhibit(true)
+            });
+        }
+
+        let vbox = gtk::Box::new(gtk::Orientation::Vertical, 0);
+        vbox.pack_start(&menu_bar, false, false, 0);
+        vbox.pack_start(&toolbar, false, false, 0);
+        vbox.pack_start(&scrolled_window, true, true, 0);
+        vbox.pack_start(&find_bar, false, false, 0);
+        vbox.pack_start(&status_label, false, false, 0);
+
+        window.add(&vbox);
+        overlay.add_events(
+            EventMask::POINTER_MOTION_MASK.bits() as i32
+                | EventMask::BUTTON_PRESS_MASK.bits() as i32
+                | EventMask::BUTTON_RELEASE_MASK.bits() as i32
+                | EventMask::SCROLL_MASK.bits() as i32
+                | EventMask::SMOOTH_SCROLL_MASK.bits() as i32,
+        );
+        window.add_events(EventMask::SCROLL_MASK.bits() as i32);
+        // Boxes with a title attribute show it as a tooltip.
+        overlay.set_has_tooltip(true);
+        {
+            let view = view.clone();
+            overlay.connect_query_tooltip(move |_, x, y, _, tooltip| {
+                match hit_test_titles(x as f64, y as f64, &*view.document.titles.borrow()) {
+                    Some(title) => {
+                        tooltip.set_text(Some(title));
+                        true
+                    }
+                    None => false,
+                }
+            });
+        }
+
+        {
+            let view = view.clone();
+            let status_label = status_label.clone();
+            overlay.connect_motion_notify_event(move |overlay, event| {
+                let (x, y) = event.get_position();
+
+                if view.selecting.get() {
+                    if let Some(mut selection) = view.selection.get() {
+                        selection.focus = (x, y);
+                        view.selection.set(Some(selection));
+                    }
+                    overlay.get_children()[0].queue_draw(); // [0] is DrawingArea
+                }
+
+                if view.inspecting.get() {
+                    let inspected =
+                        hit_test_inspected_boxes(x, y, &*view.document.inspected_boxes.borrow())
+                            .cloned();
+                    if *view.inspected.borrow() != inspected {
+                        *view.inspected.borrow_mut() = inspected;
+                        overlay.get_children()[0].queue_draw(); // [0] is DrawingArea
+                    }
+                }
+
+                let anker = hit_test_ankers(x, y, &*view.document.ankers.borrow()).cloned();
+                let over_anker = anker.is_some();
+
+                // Update the status bar only when the pointer moves to another link.
+                let target = anker.map(|anker| link_target(&view.document, &anker));
+                if update_hovered_link(&view, target.clone()) {
+                    status_label.set_text(target.as_ref().map_or("", |target| target.as_str()));
+                }
+
+                // Change the cursor only when the pointer enters or leaves a link.
+                if update_hover_state(&view, over_anker) {
+                    update_cursor(&view);
+                }
+                Inhibit(true)
+            });
+        }
+
+        // A click on a link opens it. The middle button and Ctrl+click open it in a new window.
+        // The right button pops up the context menu.
+        {
+            let view = view.clone();
+            let painter = painter.clone();
+            overlay.connect_button_press_event(move |overlay, event| {
+                let (clicked_x, clicked_y) = event.get_position();
 
-        window.add(&scrolled_window);
-        overlay.add_events(
-            EventMask::POINTER_MOTION_MASK.bits() as i32
-                | EventMask::BUTTON_PRESS_MASK.bits() as i32,
-        );
-        overlay
-            .connect("motion-notify-event", false, |args| {
-                let overlay = args[0]
-                    .clone()
-                    .downcast::<gtk::Overlay>()
-                    .unwrap()
-                    .get()
-                    .unwrap();
-                let (x, y) = args[1]
-                    .clone()
-                    .downcast::<Event>()
-                    .unwrap()
-                    .get()
-                    .unwrap()
-                    .downcast::<EventMotion>()
-                    .unwrap()
-                    .get_position();
-
-                ANKERS.with(|ankers| {
-                    let window = overlay.get_window().unwrap();
-                    if (&*ankers.borrow()).iter().any(|(rect, _)| {
-                        rect.x.to_f64_px() <= x && x <= rect.x.to_f64_px() + rect.width.to_f64_px()
-                            && rect.y.to_f64_px() <= y
-                            && y <= rect.y.to_f64_px() + rect.height.to_f64_px()
-                    }) {
-                        window.set_cursor(Some(&Cursor::new(CursorType::Hand1)));
-                    } else {
-                        // TODO: This is executed many times. It's inefficient.
-                        window.set_cursor(Some(&Cursor::new(CursorType::LeftPtr)));
+                if event.get_button() == 3 {
+                    let anker =
+                        hit_test_ankers(clicked_x, clicked_y, &*view.document.ankers.borrow())
+                            .cloned();
+                    let menu = context_menu(anker, &view, &painter, &overlay.get_children()[0]);
+                    menu.set_attach_widget(Some(overlay));
+                    menu.show_all();
+                    menu.popup_easy(event.get_button(), event.get_time());
+                    return Inhibit(true);
+                }
+
+                // While inspecting, a click prints the element instead of following links.
+                if view.inspecting.get() && event.get_button() == 1 {
+                    if let Some(inspected_box) = hit_test_inspected_boxes(
+                        clicked_x,
+                        clicked_y,
+                        &*view.document.inspected_boxes.borrow(),
+                    ) {
+                        print_inspected_box(&view.document, inspected_box);
                     }
-                });
-                Some(true.to_value())
-            })
-            .unwrap();
+                    return Inhibit(true);
+                }
 
-        overlay
-            .connect("button-press-event", false, |args| {
-                let overlay = args[0]
-                    .clone()
-                    .downcast::<gtk::Overlay>()
-                    .unwrap()
-                    .get()
-                    .unwrap();
-                let (clicked_x, clicked_y) = args[1]
-                    .clone()
-                    .downcast::<Event>()
-                    .unwrap()
-                    .get()
-                    .unwrap()
-                    .downcast::<EventButton>()
-                    .unwrap()
-                    .get_position();
-
-                ANKERS.with(|ankers| {
-                    // TODO: Makes no sense.
-                    let mut ankers = ankers.borrow_mut();
-                    let mut anker_clicked = false;
-                    if let Some((_, ankerkind)) = ankers.iter().find(|&(rect, _)| {
-                        rect.x.to_f64_px() <= clicked_x
-                            && clicked_x <= rect.x.to_f64_px() + rect.width.to_f64_px()
-                            && rect.y.to_f64_px() <= clicked_y
-                            && clicked_y <= rect.y.to_f64_px() + rect.height.to_f64_px()
-                    }) {
-                        match ankerkind {
-                            &AnkerKind::URL(ref url) => {
-                                anker_clicked = true;
-                                update_html_tree_and_stylesheet(url.to_string());
+                let new_window = opens_new_window(event.get_button(), event.get_state());
+
+                if !new_window {
+                    // Start a new selection, which also clears the previous one.
+                    view.selection
+                        .set(Some(Selection::new(clicked_x, clicked_y)));
+                    view.selecting.set(true);
+                    overlay.get_children()[0].queue_draw(); // [0] is DrawingArea
+                }
+
+                // Don't keep the ankers borrowed, since loading a new page clears them.
+                let ankerkind =
+                    hit_test_ankers(clicked_x, clicked_y, &*view.document.ankers.borrow()).cloned();
+                if let Some(ankerkind) = ankerkind {
+                    match ankerkind {
+                        AnkerKind::URL(ref url) if new_window => {
+                            open_in_new_window(&view.document, url.to_string(), painter.clone())
+                        }
+                        AnkerKind::URLFragment(ref id) if new_window => {
+                            let url = format!("#{}", id);
+                            open_in_new_window(&view.document, url, painter.clone())
+                        }
+                        AnkerKind::URL(ref url) => {
+                            if open_url(&view, url.to_string()) {
                                 overlay.get_children()[0].queue_draw(); // [0] is DrawingArea
                             }
-                            &AnkerKind::URLFragment(ref id) => {
-                                URL_FRAGMENTS.with(|ufs| {
-                                    if let Some(content_y) = ufs.borrow().get(id) {
-                                        let mut adjustment = overlay
-                                            .get_parent()
-                                            .unwrap()
-                                            .get_parent()
-                                            .unwrap()
-                                            .downcast::<gtk::ScrolledWindow>()
-                                            .unwrap()
-                                            .get_vadjustment()
-                                            .unwrap();
-                                        adjustment.set_value(*content_y);
-                                    }
-                                });
-                            }
-                        };
-                    }
-                    if anker_clicked {
-                        ankers.clear()
-                    }
-                });
-                Some(true.to_value())
-            })
-            .unwrap();
+                        }
+                        AnkerKind::URLFragment(ref id) => {
+                            scroll_to_fragment(&view, id.to_string());
+                            overlay.get_children()[0].queue_draw(); // [0] is DrawingArea
+                        }
+                    };
+                }
+                Inhibit(true)
+            });
+        }
+
+        {
+            let view = view.clone();
+            overlay.connect_button_release_event(move |_, _| {
+                view.selecting.set(false);
+                // A plain click leaves no selection.
+                if view.selection
+                    .get()
+                    .map_or(false, |selection| selection.is_empty())
+                {
+                    view.selection.set(None)
+                }
+                Inhibit(true)
+            });
+        }
 
         let instance = RenderingWindow {
             window: window,
             drawing_area: drawing_area,
         };
 
+        // The index of the display list drawn last, rebuilt with the display list.
+        let index_cache: RefCell<Option<(Rc<DisplayList>, Rc<DisplayIndex>)>> = RefCell::new(None);
         instance
             .drawing_area
             .connect_draw(move |widget, cairo_context| {
@@ -189,17 +1337,78 @@ impl RenderingWindow {
                 let pango_ctx = widget.create_pango_context().unwrap();
                 let mut pango_layout = pango::Layout::new(&pango_ctx);
 
-                let items = f(widget);
+                let items = painter(&view.document, widget, view.zoom.get(), view.inspecting.get());
+
+                let selected = view.selection.get().map_or(vec![], |selection| {
+                    selected_ranges(&items, &selection, |text, font, x| font.index_at(text, x))
+                });
+                *view.selected_text.borrow_mut() = selected_text(&items, &selected);
+                let selected: HashMap<_, _> = selected.into_iter().collect();
+
+                // Matches of the find bar, grouped by item. The bool tells the current match.
+                let mut found: HashMap<usize, Vec<(::std::ops::Range<usize>, bool)>> =
+                    HashMap::new();
+                let mut current_match = None;
+                {
+                    let mut find = view.find.borrow_mut();
+                    let matches = find_matches(&items, &find.query);
+                    find.count = matches.len();
+                    if find.current >= find.count {
+                        find.current = 0;
+                    }
+                    for (n, (i, range)) in matches.into_iter().enumerate() {
+                        let is_current = n == find.current;
+                        if is_current && find.scroll {
+                            if let DisplayCommand::Text(ref text, rect, _, _, ref font) =
+                                items[i].command
+                            {
+                                current_match = Some(range_rect(text, rect, font, &range));
+                            }
+                        }
+                        found.entry(i).or_insert(vec![]).push((range, is_current));
+                    }
+                    find.scroll = false;
+                }
+                if let Some(rect) = current_match {
+                    if let Some(adjustment) = vadjustment(widget) {
+                        let top = rect.y.to_f64_px();
+                        let bottom = (rect.y + rect.height).to_f64_px();
+                        if let Some(value) = scroll_into_view(
+                            adjustment.get_value(),
+                            adjustment.get_lower(),
+                            adjustment.get_upper(),
+                            adjustment.get_page_size(),
+                            (top, bottom),
+                        ) {
+                            adjustment.set_value(value);
+                        }
+                    }
+                }
 
-                if let DisplayCommand::SolidColor(_, rect) = items[0].command {
-                    if widget.get_size_request().1 != rect.height.ceil_to_px() {
+                if let DisplayCommand::SolidColor(_, rect, _) = items[0].command {
+                    let height = rect.height.ceil_to_px();
+                    if widget.get_size_request().1 != height {
                         widget
                             .get_parent()
                             .unwrap()
                             .downcast::<gtk::Overlay>()
                             .unwrap()
-                            .set_size_request(-1, rect.height.ceil_to_px());
-                        widget.set_size_request(-1, rect.height.ceil_to_px())
+                            .set_size_request(-1, height);
+                        widget.set_size_request(-1, height)
+                    }
+                }
+
+                let pending_fragment = view.pending_fragment.borrow().clone();
+                if let (Some(id), Some(adjustment)) = (pending_fragment, vadjustment(widget)) {
+                    // Wait until the scrolled window has grown to the height of the page.
+                    if adjustment.get_upper() >= widget.get_size_request().1 as f64 {
+                        *view.pending_fragment.borrow_mut() = None;
+                        let value = fragment_scroll_position(
+                            &id,
+                            &*view.document.url_fragments.borrow(),
+                            &adjustment,
+                        );
+                        adjustment.set_value(value);
                     }
                 }
                 let overlay = widget
@@ -212,20 +1421,38 @@ impl RenderingWindow {
                     .downcast::<gtk::Layout>()
                     .unwrap(); // [1] is Layout
 
-                for item in &items {
-                    if match &item.command {
-                        &DisplayCommand::SolidColor(_, rect)
-                        | &DisplayCommand::Image(_, rect)
-                        | &DisplayCommand::Text(_, rect, _, _, _)
-                        | &DisplayCommand::Button(_, rect) => {
-                            let rect_y = rect.y.to_px();
-                            let rect_height = rect.height.to_px();
-                            let sy = max(rect_y, redraw_start_y as i32);
-                            let ey = min(rect_y + rect_height, redraw_end_y as i32);
-                            ey - sy > 0
+                let index = cached_display_index(&index_cache, &items);
+                let mut scaled_images = view.scaled_images.borrow_mut();
+                for i in index.query(redraw_start_y.floor() as i32, redraw_end_y.ceil() as i32) {
+                    let item = &items[i];
+                    // Matches and the selection are painted behind the text.
+                    if let &DisplayCommand::Text(ref text, rect, _, _, ref font) = &item.command {
+                        for &(ref range, is_current) in found.get(&i).unwrap_or(&vec![]) {
+                            let highlight = range_rect(text, rect, font, range);
+                            let color = if is_current {
+                                CURRENT_MATCH_COLOR
+                            } else {
+                                MATCH_COLOR
+                            };
+                            fill_highlight(cairo_context, highlight, color);
+                        }
+                        if let Some(range) = selected.get(&i) {
+                            let highlight = range_rect(text, rect, font, range);
+                            fill_highlight(cairo_context, highlight, SELECTION_COLOR);
                         }
-                    } {
-                        render_item(cairo_context, &mut pango_layout, layout, &item.command);
+                    }
+                    render_item(
+                        cairo_context,
+                        &mut pango_layout,
+                        Some(layout),
+                        &mut scaled_images,
+                        &item.command,
+                    );
+                }
+
+                if view.inspecting.get() {
+                    if let Some(ref inspected_box) = *view.inspected.borrow() {
+                        paint_inspected_box(cairo_context, inspected_box);
                     }
                 }
 
@@ -238,27 +1465,350 @@ impl RenderingWindow {
         instance
     }
 
-    fn exit_on_close(&self) {
+    /// Quit the app when this window is closed, unless other windows are still open.
+    fn quit_on_last_close(&self) {
+        OPEN_WINDOWS.with(|n| n.set(n.get() + 1));
         self.window.connect_delete_event(|_, _| {
-            gtk::main_quit();
-            Inhibit(true)
+            let remaining = OPEN_WINDOWS.with(|n| {
+                n.set(n.get() - 1);
+                n.get()
+            });
+            if remaining == 0 {
+                gtk::main_quit();
+            }
+            Inhibit(false)
         });
     }
 }
 
+// Translucent blue.
+const SELECTION_COLOR: (f64, f64, f64, f64) = (0.2, 0.5, 1.0, 0.4);
+// Yellow, and orange for the current match.
+const MATCH_COLOR: (f64, f64, f64, f64) = (1.0, 1.0, 0.0, 1.0);
+const CURRENT_MATCH_COLOR: (f64, f64, f64, f64) = (1.0, 0.6, 0.0, 1.0);
+// The margin, padding and content of the box highlighted by the box model inspector.
+const INSPECTOR_MARGIN_COLOR: (f64, f64, f64, f64) = (1.0, 0.6, 0.2, 0.4);
+const INSPECTOR_PADDING_COLOR: (f64, f64, f64, f64) = (0.4, 0.8, 0.3, 0.4);
+const INSPECTOR_CONTENT_COLOR: (f64, f64, f64, f64) = (0.3, 0.5, 1.0, 0.4);
+
+fn fill_highlight(ctx: &Context, rect: Rect, (r, g, b, a): (f64, f64, f64, f64)) {
+    ctx.set_source_rgba(r, g, b, a);
+    ctx.rectangle(
+        rect.x.to_f64_px(),
+        rect.y.to_f64_px(),
+        rect.width.to_f64_px(),
+        rect.height.to_f64_px(),
+    );
+    ctx.fill();
+}
+
+// The height of a page of exported PDFs: A4 at 96 dpi, in px.
+const PDF_PAGE_HEIGHT: f64 = 1122.0;
+
+/// The height of the bands of `DisplayIndex`.
+const DISPLAY_INDEX_BAND: i32 = 512;
+
+/// An index of display items by their vertical extent, so that an expose only visits the items
+/// in the redraw area instead of the whole list.
+#[derive(Debug)]
+pub struct DisplayIndex {
+    // The items overlapping each band of `DISPLAY_INDEX_BAND` px from the top, with their extent.
+    bands: Vec<Vec<(i32, i32, usize)>>,
+    // Clips and groups, which must always be balanced. They are visited on every expose.
+    always: Vec<usize>,
+}
+
+impl DisplayIndex {
+    pub fn new(items: &DisplayList) -> DisplayIndex {
+        let mut index = DisplayIndex {
+            bands: vec![],
+            always: vec![],
+        };
+        for (i, item) in items.iter().enumerate() {
+            let rect = match painted_rect(&item.command) {
+                Some(rect) => rect,
+                None => {
+                    index.always.push(i);
+                    continue;
+                }
+            };
+            // Items without height are indexed at the px they're at.
+            let top = rect.y.to_px();
+            let bottom = max((rect.y + rect.height).to_px(), top + 1);
+            let first_band = max(top, 0) / DISPLAY_INDEX_BAND;
+            let last_band = max(bottom - 1, 0) / DISPLAY_INDEX_BAND;
+            if index.bands.len() <= last_band as usize {
+                index.bands.resize(last_band as usize + 1, vec![]);
+            }
+            for band in first_band..last_band + 1 {
+                index.bands[band as usize].push((top, bottom, i));
+            }
+        }
+        index
+    }
+
+    /// The indices of the items to draw for the redraw area from `start_y` to `end_y`, in the
+    /// order of the display list.
+    pub fn query(&self, start_y: i32, end_y: i32) -> Vec<usize> {
+        let mut indices = self.always.clone();
+        if end_y > start_y && end_y > 0 {
+            let first_band = max(start_y, 0) / DISPLAY_INDEX_BAND;
+            let last_band = min((end_y - 1) / DISPLAY_INDEX_BAND, self.bands.len() as i32 - 1);
+            for band in first_band..last_band + 1 {
+                indices.extend(
+                    self.bands[band as usize]
+                        .iter()
+                        .filter(|&&(top, bottom, _)| top < end_y && start_y < bottom)
+                        .map(|&(_, _, i)| i),
+                );
+            }
+        }
+        // Items spanning several bands are found more than once.
+        indices.sort();
+        indices.dedup();
+        indices
+    }
+}
+
+/// The area `command` paints in. None for clips and groups.
+fn painted_rect(command: &DisplayCommand) -> Option<Rect> {
+    match *command {
+        DisplayCommand::SolidColor(_, rect, _)
+        | DisplayCommand::Border(_, rect, _, _, _)
+        | DisplayCommand::Gradient(_, rect)
+        | DisplayCommand::BackgroundImage(_, rect, _, _)
+        | DisplayCommand::Image(_, rect)
+        | DisplayCommand::Text(_, rect, _, _, _)
+        | DisplayCommand::Button(_, rect) => Some(rect),
+        // The blur reaches out of the shadow's rect.
+        DisplayCommand::BoxShadow(_, rect, _, blur) => Some(Rect {
+            y: rect.y - blur,
+            height: rect.height + blur * 2,
+            ..rect
+        }),
+        DisplayCommand::ClipPush(..)
+        | DisplayCommand::ClipPop
+        | DisplayCommand::GroupPush
+        | DisplayCommand::GroupPop(_) => None,
+    }
+}
+
+/// The index of `items`, which is built again only when the display list was rebuilt.
+fn cached_display_index(
+    cache: &RefCell<Option<(Rc<DisplayList>, Rc<DisplayIndex>)>>,
+    items: &Rc<DisplayList>,
+) -> Rc<DisplayIndex> {
+    let mut cache = cache.borrow_mut();
+    if let Some((ref last_items, ref index)) = *cache {
+        if Rc::ptr_eq(last_items, items) {
+            return index.clone();
+        }
+    }
+    let index = Rc::new(DisplayIndex::new(items));
+    *cache = Some((items.clone(), index.clone()));
+    index
+}
+
+/// The height of the page painted by `items`: the bottom of the lowest item. Clipped items only
+/// count up to the bottom of the clip.
+fn display_list_height(items: &DisplayList) -> f64 {
+    let mut clips: Vec<f64> = vec![];
+    let mut height = 0.0f64;
+    for item in items {
+        match item.command {
+            DisplayCommand::ClipPush(rect, _) => {
+                let bottom = (rect.y + rect.height).to_f64_px();
+                clips.push(clips.last().map_or(bottom, |clip| clip.min(bottom)));
+            }
+            DisplayCommand::ClipPop => {
+                clips.pop();
+            }
+            ref command => if let Some(rect) = painted_rect(command) {
+                let bottom = (rect.y + rect.height).to_f64_px();
+                height = height.max(clips.last().map_or(bottom, |clip| clip.min(bottom)));
+            },
+        }
+    }
+    height
+}
+
+/// The number of PDF pages a page `height` px tall is split into. There is at least one.
+fn pdf_page_count(height: f64) -> usize {
+    max(1, (height / PDF_PAGE_HEIGHT).ceil() as usize)
+}
+
+/// Paint all of `items` with `ctx`, whether or not they are in the clip. Buttons are skipped,
+/// since they are widgets on the window.
+fn render_display_list(ctx: &Context, items: &DisplayList) {
+    let mut pango_layout = pangocairo::functions::create_layout(ctx).unwrap();
+    let mut scaled_images = ScaledImageCache::new();
+    for item in items {
+        render_item(ctx, &mut pango_layout, None, &mut scaled_images, &item.command);
+    }
+}
+
+/// Write the whole page painted by `items`, `width` px wide, to a PDF at `path`, split into A4
+/// pages.
+fn export_pdf(path: &Path, items: &DisplayList, width: f64) -> Result<(), String> {
+    let surface = cairo::PDFSurface::create(path, width, PDF_PAGE_HEIGHT);
+    let ctx = Context::new(&surface);
+    for page in 0..pdf_page_count(display_list_height(items)) {
+        ctx.save();
+        ctx.translate(0.0, -(page as f64) * PDF_PAGE_HEIGHT);
+        render_display_list(&ctx, items);
+        ctx.restore();
+        ctx.show_page();
+    }
+    surface.finish();
+    // A surface that can't be written puts the context in an error state.
+    match ctx.status() {
+        cairo::Status::Success => Ok(()),
+        status => Err(format!("Failed to write {}: {:?}", path.display(), status)),
+    }
+}
+
+/// Paint the whole page painted by `items`, `width` px wide, onto an image as tall as the page.
+pub fn render_to_image(items: &DisplayList, width: f64) -> Result<cairo::ImageSurface, String> {
+    let height = display_list_height(items).max(1.0);
+    let surface = try!(
+        cairo::ImageSurface::create(
+            cairo::Format::Rgb24,
+            width.ceil() as i32,
+            height.ceil() as i32
+        ).map_err(|e| format!("Failed to create an image surface: {:?}", e))
+    );
+    render_display_list(&Context::new(&surface), items);
+    Ok(surface)
+}
+
+/// Write the whole page painted by `items`, `width` px wide, to a PNG at `path`.
+pub fn write_png(path: &Path, items: &DisplayList, width: f64) -> Result<(), String> {
+    let surface = try!(render_to_image(items, width));
+    let mut file = try!(File::create(path).map_err(|e| format!("{}: {}", path.display(), e)));
+    surface
+        .write_to_png(&mut file)
+        .map_err(|e| format!("Failed to write {}: {:?}", path.display(), e))
+}
+
+/// Ask for a file name, and export the page of `view` in `drawing_area` to it as a PDF.
+fn export_pdf_dialog(
+    view: &View,
+    window: &gtk::Window,
+    drawing_area: &gtk::DrawingArea,
+    painter: &Painter,
+) {
+    let dialog = gtk::FileChooserDialog::new(
+        Some("Export PDF"),
+        Some(window),
+        gtk::FileChooserAction::Save,
+    );
+    dialog.add_button("_Cancel", gtk::ResponseType::Cancel.into());
+    dialog.add_button("_Export", gtk::ResponseType::Accept.into());
+    dialog.set_do_overwrite_confirmation(true);
+    dialog.set_current_name("page.pdf");
+    let response = dialog.run();
+    let path = dialog.get_filename();
+    dialog.destroy();
+
+    if response == gtk::ResponseType::Accept.into() {
+        if let Some(path) = path {
+            let items = painter(
+                &view.document,
+                drawing_area,
+                view.zoom.get(),
+                view.inspecting.get(),
+            );
+            if let Err(e) = export_pdf(&path, &items, drawing_area.get_allocated_width() as f64) {
+                let dialog = gtk::MessageDialog::new(
+                    Some(window),
+                    gtk::DialogFlags::MODAL,
+                    gtk::MessageType::Error,
+                    gtk::ButtonsType::Close,
+                    e.as_str(),
+                );
+                dialog.run();
+                dialog.destroy();
+            }
+        }
+    }
+}
+
+/// Paint `item` with `ctx`. Buttons are put on `layout`, and skipped if there is none. Images are
+/// scaled through `scaled_images`.
 fn render_item(
     ctx: &Context,
     pango_layout: &mut pango::Layout,
-    layout: &gtk::Layout,
+    layout: Option<&gtk::Layout>,
+    scaled_images: &mut ScaledImageCache,
     item: &DisplayCommand,
 ) {
     match item {
-        &DisplayCommand::SolidColor(ref color, rect) => {
-            ctx.rectangle(
+        &DisplayCommand::SolidColor(ref color, rect, radius) => {
+            rounded_rectangle(
+                ctx,
                 rect.x.to_f64_px(),
                 rect.y.to_f64_px(),
                 rect.width.to_f64_px(),
                 rect.height.to_f64_px(),
+                radius.to_f64_px(),
+            );
+            ctx.set_source_rgba(
+                color.r as f64 / 255.0,
+                color.g as f64 / 255.0,
+                color.b as f64 / 255.0,
+                color.a as f64 / 255.0,
+            );
+            ctx.fill();
+        }
+        &DisplayCommand::BoxShadow(ref color, rect, radius, blur) => {
+            // Approximate the blur with a stack of shapes getting smaller towards the center.
+            // Their alpha adds up to the shadow color's alpha in the middle.
+            const MAX_BLUR_STEPS: i32 = 8;
+            let blur = blur.to_f64_px();
+            let steps = min(blur.round() as i32, MAX_BLUR_STEPS);
+            let alpha = color.a as f64 / 255.0;
+            ctx.set_source_rgba(
+                color.r as f64 / 255.0,
+                color.g as f64 / 255.0,
+                color.b as f64 / 255.0,
+                1.0 - (1.0 - alpha).powf(1.0 / (steps + 1) as f64),
+            );
+            for i in 0..(steps + 1) {
+                // The blur spreads half outside and half inside the shadow's edge.
+                let inflate = if steps == 0 {
+                    0.0
+                } else {
+                    blur / 2.0 - blur * i as f64 / steps as f64
+                };
+                let width = rect.width.to_f64_px() + inflate * 2.0;
+                let height = rect.height.to_f64_px() + inflate * 2.0;
+                if width <= 0.0 || height <= 0.0 {
+                    continue;
+                }
+                let mut radius = radius.to_f64_px();
+                for r in radius.iter_mut().filter(|r| **r > 0.0) {
+                    *r = (*r + inflate).max(0.0);
+                }
+                rounded_rectangle(
+                    ctx,
+                    rect.x.to_f64_px() - inflate,
+                    rect.y.to_f64_px() - inflate,
+                    width,
+                    height,
+                    radius,
+                );
+                ctx.fill();
+            }
+        }
+        &DisplayCommand::Border(ref color, rect, ref edges, style, radius) => {
+            let rounded = !radius.is_zero();
+            let (x, y) = (rect.x.to_f64_px(), rect.y.to_f64_px());
+            let (width, height) = (rect.width.to_f64_px(), rect.height.to_f64_px());
+            let (top, right, bottom, left) = (
+                edges.top.to_f64_px(),
+                edges.right.to_f64_px(),
+                edges.bottom.to_f64_px(),
+                edges.left.to_f64_px(),
             );
             ctx.set_source_rgba(
                 color.r as f64 / 255.0,
@@ -266,48 +1816,144 @@ fn render_item(
                 color.b as f64 / 255.0,
                 color.a as f64 / 255.0,
             );
+            // The rounded paths follow the inner edge only when this command has all four sides,
+            // and a dashed stroke only when they are as wide. Otherwise each side is painted
+            // straight, clipped to the outer rounded edge.
+            let all_sides = top > 0.0 && right > 0.0 && bottom > 0.0 && left > 0.0;
+            let same_width = top == right && right == bottom && bottom == left;
+            match style {
+                BorderStyle::Solid if rounded && all_sides => {
+                    // Fill the area between the outer and the inner rounded rectangles.
+                    ctx.save();
+                    ctx.set_fill_rule(cairo::FillRule::EvenOdd);
+                    rounded_rectangle(ctx, x, y, width, height, radius.to_f64_px());
+                    rounded_rectangle(
+                        ctx,
+                        x + left,
+                        y + top,
+                        width - left - right,
+                        height - top - bottom,
+                        radius.inner(*edges).to_f64_px(),
+                    );
+                    ctx.fill();
+                    ctx.restore();
+                }
+                BorderStyle::Dashed | BorderStyle::Dotted if rounded && all_sides && same_width => {
+                    // Stroke along the center line of the edges.
+                    let w = top;
+                    let mut radius = radius.to_f64_px();
+                    for r in radius.iter_mut() {
+                        *r = (*r - w / 2.0).max(0.0);
+                    }
+                    ctx.save();
+                    set_border_dash(ctx, style, w);
+                    ctx.set_line_width(w);
+                    rounded_rectangle(
+                        ctx,
+                        x + w / 2.0,
+                        y + w / 2.0,
+                        width - w,
+                        height - w,
+                        radius,
+                    );
+                    ctx.stroke();
+                    ctx.restore();
+                }
+                _ => {
+                    ctx.save();
+                    if rounded {
+                        rounded_rectangle(ctx, x, y, width, height, radius.to_f64_px());
+                        ctx.clip();
+                    }
+                    render_straight_border(ctx, style, rect, edges);
+                    ctx.restore();
+                }
+            }
+        }
+        &DisplayCommand::Gradient(ref gradient, rect) => {
+            let (x, y) = (rect.x.to_f64_px(), rect.y.to_f64_px());
+            let (width, height) = (rect.width.to_f64_px(), rect.height.to_f64_px());
+            let (x0, y0, x1, y1) = gradient_line(gradient.direction, x, y, width, height);
+            let linear = LinearGradient::new(x0, y0, x1, y1);
+            for (offset, color) in gradient.resolved_stops() {
+                linear.add_color_stop_rgba(
+                    offset,
+                    color.r as f64 / 255.0,
+                    color.g as f64 / 255.0,
+                    color.b as f64 / 255.0,
+                    color.a as f64 / 255.0,
+                );
+            }
+            ctx.rectangle(x, y, width, height);
+            ctx.set_source(&linear);
             ctx.fill();
         }
+        &DisplayCommand::BackgroundImage(ref pixbuf, rect, tile, repeat) => {
+            let scaled = scaled_images.get(
+                pixbuf,
+                tile.width.to_f64_px() as i32,
+                tile.height.to_f64_px() as i32,
+            );
+            if let Some(scaled) = scaled {
+                let (x, y) = (rect.x.to_f64_px(), rect.y.to_f64_px());
+                let (width, height) = (rect.width.to_f64_px(), rect.height.to_f64_px());
+                let (tile_x, tile_y) = (tile.x.to_f64_px(), tile.y.to_f64_px());
+                let (tile_width, tile_height) = (tile.width.to_f64_px(), tile.height.to_f64_px());
+                ctx.save();
+                ctx.rectangle(x, y, width, height);
+                ctx.clip();
+                // The pattern repeats in both directions, so an axis that doesn't repeat is
+                // limited to the tile.
+                ctx.set_source_pixbuf(&scaled, tile_x, tile_y);
+                if repeat != BackgroundRepeat::NoRepeat {
+                    ctx.get_source().set_extend(Extend::Repeat);
+                }
+                match repeat {
+                    BackgroundRepeat::Repeat => ctx.rectangle(x, y, width, height),
+                    BackgroundRepeat::RepeatX => ctx.rectangle(x, tile_y, width, tile_height),
+                    BackgroundRepeat::RepeatY => ctx.rectangle(tile_x, y, tile_width, height),
+                    BackgroundRepeat::NoRepeat => {
+                        ctx.rectangle(tile_x, tile_y, tile_width, tile_height)
+                    }
+                }
+                ctx.fill();
+                ctx.restore();
+            }
+        }
         &DisplayCommand::Image(ref pixbuf, rect) => {
-            ctx.set_source_pixbuf(
-                &pixbuf
-                    .scale_simple(
-                        rect.width.to_f64_px() as i32,
-                        rect.height.to_f64_px() as i32,
-                        InterpType::Hyper,
-                    )
-                    .unwrap(),
-                rect.x.to_f64_px(),
-                rect.y.to_f64_px(),
+            let scaled = scaled_images.get(
+                pixbuf,
+                rect.width.to_f64_px() as i32,
+                rect.height.to_f64_px() as i32,
             );
-            ctx.paint();
+            if let Some(scaled) = scaled {
+                ctx.set_source_pixbuf(&scaled, rect.x.to_f64_px(), rect.y.to_f64_px());
+                ctx.paint();
+            }
         }
         &DisplayCommand::Text(ref text, rect, ref color, ref decorations, ref font) => {
-            FONT_DESC.with(|font_desc| {
+            // (underline position, strikethrough position, ascent). Each is the distance above
+            // the baseline.
+            let (underline_pos, strikethrough_pos, ascent) = FONT_DESC.with(|font_desc| {
+                use pango::ContextExt as PangoContextExt;
+
                 let mut font_desc = font_desc.borrow_mut();
-                font_desc.set_size(pango::units_from_double(px2pt(font.size.to_f64_px())));
-                font_desc.set_style(font.slant.to_pango_font_slant());
-                font_desc.set_weight(font.weight.to_pango_font_weight());
-
-                let attr_list = pango::AttrList::new();
-                for decoration in decorations {
-                    match decoration {
-                        &TextDecoration::Underline => {
-                            attr_list.insert(
-                                pango::Attribute::new_underline(pango::Underline::Single).unwrap(),
-                            );
-                        }
-                        &TextDecoration::Overline => unimplemented!(),
-                        &TextDecoration::LineThrough => {
-                            attr_list.insert(pango::Attribute::new_strikethrough(true).unwrap());
-                        }
-                        &TextDecoration::None => {}
-                    }
-                }
+                font.set_font_description(&mut font_desc);
 
                 pango_layout.set_text(text.as_str());
-                pango_layout.set_attributes(Some(&attr_list));
                 pango_layout.set_font_description(Some(&*font_desc));
+                font.set_layout_attributes(pango_layout);
+
+                let metrics = pango_layout
+                    .get_context()
+                    .unwrap()
+                    .get_metrics(Some(&*font_desc), Some(&pango::Language::from_string("")))
+                    .unwrap();
+                (
+                    pango::units_to_double(metrics.get_underline_position()),
+                    pango::units_to_double(metrics.get_strikethrough_position()),
+                    pango::units_to_double(metrics.get_ascent()),
+                )
             });
 
             ctx.set_source_rgba(
@@ -319,22 +1965,570 @@ fn render_item(
             ctx.move_to(rect.x.to_f64_px(), rect.y.to_f64_px());
 
             pangocairo::functions::show_layout(ctx, &pango_layout);
+
+            let baseline = rect.y.to_f64_px() + pango::units_to_double(pango_layout.get_baseline());
+            for decoration in decorations {
+                let line_y = match decoration {
+                    &TextDecoration::Underline => baseline - underline_pos,
+                    &TextDecoration::Overline => baseline - ascent,
+                    &TextDecoration::LineThrough => baseline - strikethrough_pos,
+                    &TextDecoration::None => continue,
+                };
+                ctx.rectangle(rect.x.to_f64_px(), line_y, rect.width.to_f64_px(), 1.0);
+                ctx.fill();
+            }
+        }
+        &DisplayCommand::ClipPush(rect, radius) => {
+            ctx.save();
+            rounded_rectangle(
+                ctx,
+                rect.x.to_f64_px(),
+                rect.y.to_f64_px(),
+                rect.width.to_f64_px(),
+                rect.height.to_f64_px(),
+                radius.to_f64_px(),
+            );
+            ctx.clip();
+        }
+        &DisplayCommand::ClipPop => ctx.restore(),
+        &DisplayCommand::GroupPush => ctx.push_group(),
+        &DisplayCommand::GroupPop(alpha) => {
+            ctx.pop_group_to_source();
+            ctx.paint_with_alpha(alpha);
         }
         &DisplayCommand::Button(ref btn, rect) => {
             use gtk::LayoutExt;
-            layout.put(btn, rect.x.ceil_to_px(), rect.y.ceil_to_px());
+            if let Some(layout) = layout {
+                layout.put(btn, rect.x.ceil_to_px(), rect.y.ceil_to_px());
+            }
+        }
+    }
+}
+
+/// Add a rectangle path whose corners are rounded by `radius`, clockwise from the top left.
+fn rounded_rectangle(ctx: &Context, x: f64, y: f64, width: f64, height: f64, radius: [f64; 4]) {
+    use std::f64::consts::PI;
+
+    let radius = clamp_radii(radius, width, height);
+    let (top_left, top_right, bottom_right, bottom_left) =
+        (radius[0], radius[1], radius[2], radius[3]);
+    if top_left <= 0.0 && top_right <= 0.0 && bottom_right <= 0.0 && bottom_left <= 0.0 {
+        ctx.rectangle(x, y, width, height);
+        return;
+    }
+
+    ctx.new_sub_path();
+    ctx.arc(x + width - top_right, y + top_right, top_right, -PI / 2.0, 0.0);
+    ctx.arc(x + width - bottom_right, y + height - bottom_right, bottom_right, 0.0, PI / 2.0);
+    ctx.arc(x + bottom_left, y + height - bottom_left, bottom_left, PI / 2.0, PI);
+    ctx.arc(x + top_left, y + top_left, top_left, PI, 3.0 * PI / 2.0);
+    ctx.close_path();
+}
+
+/// Scale down `radius`, clockwise from the top left, so that the corners on each side fit in it
+/// without overlapping. Then each radius is cut to half of the shorter side.
+/// ref. https://www.w3.org/TR/css-backgrounds-3/#corner-overlap
+fn clamp_radii(radius: [f64; 4], width: f64, height: f64) -> [f64; 4] {
+    let (top_left, top_right, bottom_right, bottom_left) =
+        (radius[0], radius[1], radius[2], radius[3]);
+    let sides = [
+        (width, top_left + top_right),
+        (height, top_right + bottom_right),
+        (width, bottom_right + bottom_left),
+        (height, bottom_left + top_left),
+    ];
+    let scale = sides
+        .iter()
+        .filter(|&&(_, sum)| sum > 0.0)
+        .fold(1.0f64, |scale, &(len, sum)| scale.min(len.max(0.0) / sum));
+    let max_radius = (width.min(height) / 2.0).max(0.0);
+    let clamp = |radius: f64| (radius * scale).min(max_radius);
+    [
+        clamp(top_left),
+        clamp(top_right),
+        clamp(bottom_right),
+        clamp(bottom_left),
+    ]
+}
+
+/// Paint the sides of a border in `rect` with square corners, with the current source.
+fn render_straight_border(ctx: &Context, style: BorderStyle, rect: Rect, edges: &EdgeSizes) {
+    let (x, y) = (rect.x.to_f64_px(), rect.y.to_f64_px());
+    let (width, height) = (rect.width.to_f64_px(), rect.height.to_f64_px());
+    let (top, right, bottom, left) = (
+        edges.top.to_f64_px(),
+        edges.right.to_f64_px(),
+        edges.bottom.to_f64_px(),
+        edges.left.to_f64_px(),
+    );
+    match style {
+        BorderStyle::Solid => {
+            for edge in border_edge_rects(rect, edges) {
+                ctx.rectangle(
+                    edge.x.to_f64_px(),
+                    edge.y.to_f64_px(),
+                    edge.width.to_f64_px(),
+                    edge.height.to_f64_px(),
+                );
+            }
+            ctx.fill();
+        }
+        BorderStyle::Dashed | BorderStyle::Dotted => {
+            // Each edge is stroked along its center line. The dash pattern is scaled by the
+            // edge's width.
+            let edges = [
+                (top, (x, y + top / 2.0), (x + width, y + top / 2.0)),
+                (
+                    right,
+                    (x + width - right / 2.0, y),
+                    (x + width - right / 2.0, y + height),
+                ),
+                (
+                    bottom,
+                    (x, y + height - bottom / 2.0),
+                    (x + width, y + height - bottom / 2.0),
+                ),
+                (left, (x + left / 2.0, y), (x + left / 2.0, y + height)),
+            ];
+            for &(w, (sx, sy), (ex, ey)) in edges.iter() {
+                if w <= 0.0 {
+                    continue;
+                }
+                set_border_dash(ctx, style, w);
+                ctx.set_line_width(w);
+                ctx.move_to(sx, sy);
+                ctx.line_to(ex, ey);
+                ctx.stroke();
+            }
+        }
+        BorderStyle::None => {}
+    }
+}
+
+/// Set the dash pattern for a border of `style`. The pattern is scaled by the border `width`.
+fn set_border_dash(ctx: &Context, style: BorderStyle, width: f64) {
+    match style {
+        BorderStyle::Dashed => {
+            ctx.set_line_cap(cairo::LineCap::Butt);
+            ctx.set_dash(&[width * 3.0, width * 3.0], 0.0);
         }
+        BorderStyle::Dotted => {
+            // Zero-length dashes with round caps are drawn as dots.
+            ctx.set_line_cap(cairo::LineCap::Round);
+            ctx.set_dash(&[0.0, width * 2.0], width / 2.0);
+        }
+        BorderStyle::Solid | BorderStyle::None => {}
     }
 }
 
-pub fn render<F: 'static>(f: F)
+pub fn render<F: 'static>(document: Rc<Document>, f: F)
 where
-    F: Fn(&gtk::DrawingArea) -> DisplayList,
+    F: Fn(&Rc<Document>, &gtk::DrawingArea, f64, bool) -> Rc<DisplayList>,
 {
     gtk::init().unwrap_or_else(|_| panic!("Failed to initialize GTK."));
 
-    let window = RenderingWindow::new(800, 520, f);
-    window.exit_on_close();
+    // The pages are laid out again with the images that arrive, so redraw the windows.
+    load_images_in_background(|| {
+        for window in gtk::Window::list_toplevels() {
+            window.queue_draw();
+        }
+    });
+
+    let window = RenderingWindow::new(800, 520, Rc::new(View::new(document)), Rc::new(f));
+    window.quit_on_last_close();
 
     gtk::main();
 }
+
+#[test]
+fn test_hit_test_ankers() {
+    use layout::px_rect;
+
+    let mut ankers = HashMap::new();
+    ankers.insert(px_rect(10.0, 10.0, 50.0, 20.0), "a".to_string());
+    ankers.insert(px_rect(40.0, 20.0, 50.0, 20.0), "b".to_string());
+    ankers.insert(px_rect(0.0, 100.0, 10.0, 10.0), "c".to_string());
+
+    let hit = |x: f64, y: f64| hit_test_ankers(x, y, &ankers).map(|s| s.as_str());
+    assert_eq!(hit(10.0, 10.0), Some("a"));
+    assert_eq!(hit(60.0, 30.0), Some("a"));
+    assert_eq!(hit(80.0, 35.0), Some("b"));
+    assert_eq!(hit(5.0, 105.0), Some("c"));
+    assert_eq!(hit(5.0, 5.0), None);
+    assert_eq!(hit(61.0, 15.0), None);
+    // Overlapping ankers always resolve to the upper one.
+    for _ in 0..10 {
+        assert_eq!(hit(45.0, 25.0), Some("a"));
+    }
+}
+
+#[test]
+fn test_hit_test_inspected_boxes() {
+    use layout::px_rect;
+
+    let square = |x: f64, size: f64, path: Vec<usize>| {
+        let rect = px_rect(x, x, size, size);
+        InspectedBox {
+            content: rect,
+            padding: rect,
+            border: rect,
+            margin: rect,
+            path: path,
+        }
+    };
+    let boxes = vec![square(0.0, 100.0, vec![]), square(10.0, 20.0, vec![0])];
+    let path = |x: f64| hit_test_inspected_boxes(x, x, &boxes).map(|b| b.path.clone());
+
+    // The innermost box wins.
+    assert_eq!(path(5.0), Some(vec![]));
+    assert_eq!(path(15.0), Some(vec![0]));
+    assert_eq!(path(150.0), None);
+}
+
+#[test]
+fn test_hit_test_titles() {
+    use layout::px_rect;
+
+    let titles = vec![
+        (px_rect(0.0, 0.0, 100.0, 100.0), "outer".to_string()),
+        (px_rect(10.0, 10.0, 20.0, 20.0), "inner".to_string()),
+        (px_rect(50.0, 50.0, 20.0, 20.0), "".to_string()),
+    ];
+    assert_eq!(hit_test_titles(5.0, 5.0, &titles), Some("outer"));
+    // The innermost title wins.
+    assert_eq!(hit_test_titles(15.0, 15.0, &titles), Some("inner"));
+    // An empty title shows nothing.
+    assert_eq!(hit_test_titles(55.0, 55.0, &titles), None);
+    assert_eq!(hit_test_titles(150.0, 150.0, &titles), None);
+}
+
+/// Two 50x20px links side by side, to a.html at x=100 and to b.html at x=200. For tests.
+#[cfg(test)]
+fn test_ankers() -> HashMap<Rect, AnkerKind> {
+    use layout::px_rect;
+
+    let mut ankers = HashMap::new();
+    ankers.insert(px_rect(100.0, 0.0, 50.0, 20.0), AnkerKind::URL("a.html".to_string()));
+    ankers.insert(px_rect(200.0, 0.0, 50.0, 20.0), AnkerKind::URL("b.html".to_string()));
+    ankers
+}
+
+#[test]
+fn test_hover_state() {
+    let ankers = test_ankers();
+    let view = View::new(Rc::new(Document::new()));
+
+    // Move the pointer horizontally over the two links, and back.
+    let path = (0..300).chain((0..300).rev()).map(|x| x as f64);
+    let cursor_changes = path
+        .filter(|&x| update_hover_state(&view, hit_test_ankers(x, 10.0, &ankers).is_some()))
+        .count();
+    // The cursor changes only when entering or leaving a link.
+    assert_eq!(cursor_changes, 8);
+}
+
+#[test]
+fn test_hovered_link() {
+    use interface::{update_html_tree_and_stylesheet, write_test_files};
+
+    let ankers = test_ankers();
+    let view = View::new(Rc::new(Document::new()));
+
+    // Move the pointer horizontally over the two links.
+    let status_changes = (0..300)
+        .map(|x| hit_test_ankers(x as f64, 10.0, &ankers).map(|a| link_target(&view.document, a)))
+        .filter(|target| update_hovered_link(&view, target.clone()))
+        .count();
+    // The status changes only when entering or leaving a link.
+    assert_eq!(status_changes, 4);
+
+    // Fragments are shown as the URL of the current document.
+    let dir = write_test_files("naglfar_hovered_link", &[("a.html", "<html></html>")]);
+    update_html_tree_and_stylesheet(&view.document, format!("{}a.html", dir)).unwrap();
+    assert_eq!(
+        link_target(&view.document, &AnkerKind::URLFragment("top".to_string())),
+        format!("{}a.html#top", dir)
+    );
+}
+
+#[test]
+fn test_scroll_position() {
+    // A 1000px page in a 300px viewport, scrolled to 100px.
+    let scroll =
+        |keyval: u32, value: f64| scroll_position_in(keyval, value, 0.0, 1000.0, 300.0, (0.0, 0.0));
+    assert_eq!(scroll(key::Down, 100.0), Some(140.0));
+    assert_eq!(scroll(key::Up, 100.0), Some(60.0));
+    assert_eq!(scroll(key::Up, 10.0), Some(0.0));
+    assert_eq!(scroll(key::Page_Down, 100.0), Some(360.0));
+    assert_eq!(scroll(key::space, 100.0), Some(360.0));
+    assert_eq!(scroll(key::Page_Up, 100.0), Some(0.0));
+    assert_eq!(scroll(key::Home, 100.0), Some(0.0));
+    assert_eq!(scroll(key::End, 100.0), Some(700.0));
+    assert_eq!(scroll(key::Page_Down, 600.0), Some(700.0));
+    assert_eq!(scroll(key::a, 100.0), None);
+    // The page fits in the viewport.
+    assert_eq!(scroll_position_in(key::End, 0.0, 0.0, 200.0, 300.0, (0.0, 0.0)), Some(0.0));
+    // The increments of the adjustment are used if it has them.
+    let scroll = |keyval: u32, value: f64| {
+        scroll_position_in(keyval, value, 0.0, 1000.0, 300.0, (30.0, 270.0))
+    };
+    assert_eq!(scroll(key::Down, 100.0), Some(130.0));
+    assert_eq!(scroll(key::KP_Up, 100.0), Some(70.0));
+    assert_eq!(scroll(key::Page_Down, 100.0), Some(370.0));
+    assert_eq!(scroll(key::KP_Page_Up, 300.0), Some(30.0));
+    assert_eq!(scroll(key::KP_End, 100.0), Some(700.0));
+}
+
+#[test]
+fn test_opens_new_window() {
+    assert!(opens_new_window(2, ModifierType::empty()));
+    assert!(opens_new_window(1, ModifierType::CONTROL_MASK));
+    assert!(!opens_new_window(1, ModifierType::empty()));
+    assert!(!opens_new_window(1, ModifierType::SHIFT_MASK));
+    assert!(!opens_new_window(3, ModifierType::CONTROL_MASK));
+}
+
+#[test]
+fn test_pdf_pages() {
+    assert_eq!(pdf_page_count(0.0), 1);
+    assert_eq!(pdf_page_count(PDF_PAGE_HEIGHT), 1);
+    assert_eq!(pdf_page_count(PDF_PAGE_HEIGHT + 1.0), 2);
+    assert_eq!(pdf_page_count(PDF_PAGE_HEIGHT * 3.5), 4);
+}
+
+#[test]
+fn test_export_pdf() {
+    use painter::display_list_for;
+    use std::io::Read;
+
+    let items = display_list_for(
+        "<html><body><div>first</div><div style='margin-top: 2000px'>last</div></body></html>",
+        "",
+        400.0,
+    );
+    assert!(display_list_height(&items) > PDF_PAGE_HEIGHT);
+
+    let path = ::std::env::temp_dir().join(format!("naglfar_export_{}.pdf", ::std::process::id()));
+    export_pdf(&path, &items, 400.0).unwrap();
+    let mut pdf = vec![];
+    ::std::fs::File::open(&path)
+        .unwrap()
+        .read_to_end(&mut pdf)
+        .unwrap();
+    ::std::fs::remove_file(&path).unwrap();
+    assert!(pdf.starts_with(b"%PDF"));
+
+    // A file that can't be created is an error.
+    let path = ::std::env::temp_dir().join("naglfar_missing_dir").join("a.pdf");
+    assert!(export_pdf(&path, &items, 400.0).is_err());
+}
+
+#[test]
+fn test_display_list_height() {
+    use painter::display_list_for;
+
+    // Boxes out of the root count, but not the parts of boxes clipped away.
+    let items = display_list_for(
+        "<html><body><div style='height: 100px'></div>\
+         <div style='position: absolute; top: 300px; width: 10px; height: 50px'></div>\
+         <div style='overflow: hidden; height: 20px'><div style='height: 1000px'></div></div>\
+         </body></html>",
+        "body { margin: 0px; } div { background-color: red; }",
+        400.0,
+    );
+    assert_eq!(display_list_height(&items), 350.0);
+}
+
+#[test]
+fn test_write_png() {
+    use painter::display_list_for;
+
+    let items = display_list_for(
+        "<html><body><div style='height: 300px; background-color: red'>a</div></body></html>",
+        "",
+        200.0,
+    );
+    let path = ::std::env::temp_dir().join("naglfar_screenshot.png");
+    write_png(&path, &items, 200.0).unwrap();
+    let mut file = File::open(&path).unwrap();
+    let surface = cairo::ImageSurface::create_from_png(&mut file).unwrap();
+    assert_eq!(surface.get_width(), 200);
+    assert_eq!(surface.get_height() as f64, display_list_height(&items).ceil());
+}
+
+#[test]
+fn test_rounded_border_sides() {
+    use painter::display_list_for;
+
+    let items = display_list_for(
+        "<html><body><div></div></body></html>",
+        "body { margin: 0px; } \
+         div { width: 60px; height: 60px; border: 10px solid blue; border-top-color: red; \
+               border-radius: 20px; }",
+        100.0,
+    );
+    let mut surface = render_to_image(&items, 100.0).unwrap();
+    let stride = surface.get_stride() as usize;
+    let data = surface.get_data().unwrap();
+    let pixel = |x: usize, y: usize| {
+        let i = y * stride + x * 4;
+        (data[i + 2], data[i + 1], data[i])
+    };
+    // The corners are rounded, and each side keeps its color up to them.
+    assert_eq!(pixel(1, 1), (255, 255, 255));
+    assert_eq!(pixel(13, 2), (255, 0, 0));
+    assert_eq!(pixel(2, 30), (0, 0, 255));
+    assert_eq!(pixel(30, 30), (255, 255, 255));
+}
+
+#[test]
+fn test_source_highlights() {
+    let source = "<p class=\"a\">\u{e9}t\u{e9}<!-- <b> -->x</p";
+    assert_eq!(
+        source_highlights(source),
+        vec![
+            (0..13, SourceKind::Tag),
+            (16..28, SourceKind::Comment),
+            (29..32, SourceKind::Tag),
+        ]
+    );
+    assert_eq!(source_line_numbers("a"), "1");
+    assert_eq!(
+        source_line_numbers("1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n"),
+        " 1\n 2\n 3\n 4\n 5\n 6\n 7\n 8\n 9\n10\n11"
+    );
+}
+
+#[test]
+fn test_zoom_step() {
+    assert_eq!(zoom_step(1.0, 1), 1.1);
+    assert_eq!(zoom_step(1.0, -1), 0.9);
+    assert_eq!(zoom_step(1.5, 0), 1.0);
+    assert_eq!(zoom_step(0.5, -1), MIN_ZOOM);
+    assert_eq!(zoom_step(3.0, 1), MAX_ZOOM);
+}
+
+#[test]
+fn test_scroll_into_view() {
+    // A 1000px page in a 300px viewport, scrolled to 100px.
+    let scroll = |top: f64, bottom: f64| scroll_into_view(100.0, 0.0, 1000.0, 300.0, (top, bottom));
+    assert_eq!(scroll(150.0, 170.0), None);
+    // The area is centered.
+    assert_eq!(scroll(600.0, 620.0), Some(460.0));
+    assert_eq!(scroll(50.0, 70.0), Some(0.0));
+    assert_eq!(scroll(990.0, 1000.0), Some(700.0));
+}
+
+#[test]
+fn test_fragment_scroll_position() {
+    use layout::px_rect;
+
+    let mut url_fragments = HashMap::new();
+    let rect = |y| px_rect(0.0, y, 100.0, 20.0);
+    url_fragments.insert("section2".to_string(), rect(400.0));
+    url_fragments.insert("footer".to_string(), rect(950.0));
+
+    // A 1000px page in a 300px viewport.
+    let scroll = |id: &str| fragment_scroll_position_in(id, &url_fragments, 0.0, 1000.0, 300.0);
+    assert_eq!(scroll("section2"), 400.0);
+    // Can't scroll past the end of the page.
+    assert_eq!(scroll("footer"), 700.0);
+    // Unknown fragments scroll to the top.
+    assert_eq!(scroll("missing"), 0.0);
+}
+
+#[test]
+fn test_display_index() {
+    use css::{BLACK, WHITE};
+    use layout::px_rect;
+    use painter::DisplayCommandInfo;
+
+    let rect = |y, height| px_rect(0.0, y, 100.0, height);
+    let solid = |y: f64, height: f64| {
+        DisplayCommandInfo::new(DisplayCommand::SolidColor(
+            BLACK,
+            rect(y, height),
+            Default::default(),
+        ))
+    };
+    let items = vec![
+        DisplayCommandInfo::new(DisplayCommand::SolidColor(
+            WHITE,
+            rect(0.0, 3000.0),
+            Default::default(),
+        )),
+        solid(10.0, 20.0),
+        DisplayCommandInfo::new(DisplayCommand::ClipPush(rect(1000.0, 100.0), Default::default())),
+        solid(1000.0, 50.0),
+        solid(1020.0, 0.0),
+        DisplayCommandInfo::new(DisplayCommand::ClipPop),
+        solid(2500.0, 600.0),
+    ];
+    let index = DisplayIndex::new(&items);
+
+    // The page background spans all the bands, but is visited once. Clips are always visited.
+    assert_eq!(index.query(0, 300), vec![0, 1, 2, 5]);
+    assert_eq!(index.query(1040, 1300), vec![0, 2, 3, 5]);
+    assert_eq!(index.query(1050, 1300), vec![0, 2, 5]);
+    assert_eq!(index.query(2900, 3200), vec![0, 2, 5, 6]);
+    assert_eq!(index.query(5000, 5300), vec![2, 5]);
+    // Items without height are visited only around where they are.
+    assert_eq!(index.query(1010, 1030), vec![0, 2, 3, 4, 5]);
+}
+
+#[test]
+fn test_smooth_scroll() {
+    let scroll = ScrollAnimation {
+        from: 100.0,
+        to: 220.0,
+        start: Instant::now(),
+    };
+    assert_eq!(scroll.position(0.0), (100.0, false));
+    assert_eq!(scroll.position(SCROLL_DURATION), (220.0, true));
+    assert_eq!(scroll.position(SCROLL_DURATION * 2.0), (220.0, true));
+    // Eased out: more than half of the way in the first half of the time.
+    let (half, _) = scroll.position(SCROLL_DURATION / 2.0);
+    assert!(half > 160.0 && half < 220.0);
+
+    // Wheel events during an animation add up on its target, within the page.
+    let target = |target: f64, delta: f64| scroll_target(target, delta, 0.0, 1000.0, 300.0);
+    assert_eq!(target(100.0, DEFAULT_WHEEL_STEP), 160.0);
+    assert_eq!(target(160.0, DEFAULT_WHEEL_STEP), 220.0);
+    assert_eq!(target(680.0, DEFAULT_WHEEL_STEP), 700.0);
+    assert_eq!(target(30.0, -DEFAULT_WHEEL_STEP), 0.0);
+}
+
+#[test]
+fn test_gradient_line() {
+    let line = |direction| gradient_line(direction, 10.0, 20.0, 100.0, 50.0);
+    let assert_close = |(x0, y0, x1, y1): (f64, f64, f64, f64), expected: (f64, f64, f64, f64)| {
+        let (ex0, ey0, ex1, ey1) = expected;
+        for &(a, b) in &[(x0, ex0), (y0, ey0), (x1, ex1), (y1, ey1)] {
+            assert!((a - b).abs() < 1e-9, "{:?} != {:?}", (x0, y0, x1, y1), expected);
+        }
+    };
+
+    assert_eq!(line(GradientDirection::ToRight), (10.0, 20.0, 110.0, 20.0));
+    // 0deg points to the top, and angles turn clockwise through the center.
+    assert_close(line(GradientDirection::Angle(0.0)), (60.0, 70.0, 60.0, 20.0));
+    assert_close(line(GradientDirection::Angle(90.0)), (10.0, 45.0, 110.0, 45.0));
+    assert_close(line(GradientDirection::Angle(-90.0)), (110.0, 45.0, 10.0, 45.0));
+    // A diagonal line is longer than the sides, so that the corners get the end colors.
+    let half = (100.0 + 50.0) / 2.0 * 0.5f64.sqrt();
+    assert_close(
+        line(GradientDirection::Angle(45.0)),
+        (60.0 - half, 45.0 + half, 60.0 + half, 45.0 - half),
+    );
+}
+
+#[test]
+fn test_clamp_radii() {
+    // Radii fitting in the sides are kept.
+    assert_eq!(clamp_radii([10.0, 0.0, 5.0, 0.0], 100.0, 50.0), [10.0, 0.0, 5.0, 0.0]);
+    // A single radius is at most half of the shorter side.
+    assert_eq!(clamp_radii([100.0; 4], 100.0, 50.0), [25.0; 4]);
+    assert_eq!(clamp_radii([60.0, 0.0, 0.0, 0.0], 100.0, 50.0), [25.0, 0.0, 0.0, 0.0]);
+    // Corners sharing a side are scaled down together, and then cut to half of the shorter side.
+    assert_eq!(clamp_radii([60.0, 20.0, 0.0, 0.0], 40.0, 100.0), [20.0, 10.0, 0.0, 0.0]);
+    assert_eq!(clamp_radii([100.0, 20.0, 10.0, 0.0], 60.0, 200.0), [30.0, 10.0, 5.0, 0.0]);
+}
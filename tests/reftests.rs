@@ -0,0 +1,151 @@
+//! Reference tests. Each `tests/reftests/NAME.html` has a reference `NAME-ref.html`, and both must
+//! render to the same image. When they don't, the two images and their difference are written to
+//! `target/reftest-failures/`.
+
+extern crate cairo;
+extern crate naglfar;
+
+use naglfar::{interface, window};
+
+use std::cmp::max;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+
+/// The width the pages are laid out in.
+const WIDTH: f64 = 400.0;
+/// The largest difference of a channel regarded as the same, to allow for antialiasing.
+const TOLERANCE: u8 = 2;
+
+fn reftest_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/reftests")
+}
+
+fn failure_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("target/reftest-failures")
+}
+
+/// The pairs of a test and its reference, sorted by name.
+fn reftests() -> Vec<(String, PathBuf, PathBuf)> {
+    let mut tests: Vec<(String, PathBuf, PathBuf)> = fs::read_dir(reftest_dir())
+        .unwrap()
+        .filter_map(|entry| {
+            let path = entry.unwrap().path();
+            let name = path.file_name()?.to_str()?.to_string();
+            if !name.ends_with(".html") || name.ends_with("-ref.html") {
+                return None;
+            }
+            let name = name.trim_right_matches(".html").to_string();
+            let reference = path.with_file_name(format!("{}-ref.html", name));
+            if !reference.exists() {
+                panic!("{} has no reference {}", path.display(), reference.display());
+            }
+            Some((name, path, reference))
+        })
+        .collect();
+    tests.sort();
+    tests
+}
+
+fn render(path: &Path) -> Result<cairo::ImageSurface, String> {
+    let items = try!(interface::render_page(path.to_str().unwrap().to_string(), WIDTH));
+    window::render_to_image(&items, WIDTH)
+}
+
+/// The pixels of `surface`, which is `get_stride()` bytes wide and has 4 bytes per pixel.
+fn pixels(surface: &mut cairo::ImageSurface) -> Vec<u8> {
+    surface.flush();
+    surface.get_data().unwrap().to_vec()
+}
+
+/// The pixel of an image at (x, y), or None if it's out of the image.
+fn pixel(image: &(Vec<u8>, usize, i32, i32), x: i32, y: i32) -> Option<&[u8]> {
+    let &(ref pixels, stride, width, height) = image;
+    if x < width && y < height {
+        let offset = y as usize * stride + x as usize * 4;
+        Some(&pixels[offset..offset + 4])
+    } else {
+        None
+    }
+}
+
+/// Compare the images, and return an image where differing pixels are red, or None if they are
+/// the same.
+fn diff(
+    test: &mut cairo::ImageSurface,
+    reference: &mut cairo::ImageSurface,
+) -> Option<cairo::ImageSurface> {
+    let test = (
+        pixels(test),
+        test.get_stride() as usize,
+        test.get_width(),
+        test.get_height(),
+    );
+    let reference = (
+        pixels(reference),
+        reference.get_stride() as usize,
+        reference.get_width(),
+        reference.get_height(),
+    );
+    let width = max(test.2, reference.2);
+    let height = max(test.3, reference.3);
+
+    let mut image = cairo::ImageSurface::create(cairo::Format::Rgb24, width, height).unwrap();
+    let stride = image.get_stride() as usize;
+    let mut differs = false;
+    {
+        let mut data = image.get_data().unwrap();
+        for y in 0..height {
+            for x in 0..width {
+                // The 4th byte of Rgb24 pixels is unused.
+                let same = match (pixel(&test, x, y), pixel(&reference, x, y)) {
+                    (Some(a), Some(b)) => a.iter()
+                        .zip(b.iter())
+                        .take(3)
+                        .all(|(a, b)| (*a as i16 - *b as i16).abs() <= TOLERANCE as i16),
+                    _ => false,
+                };
+                differs |= !same;
+                // Pixels are stored as BGRx in native-endian words.
+                let (blue, green, red) = if same { (224, 224, 224) } else { (0, 0, 255) };
+                let offset = y as usize * stride + x as usize * 4;
+                data[offset] = blue;
+                data[offset + 1] = green;
+                data[offset + 2] = red;
+            }
+        }
+    }
+    image.mark_dirty();
+
+    if differs {
+        Some(image)
+    } else {
+        None
+    }
+}
+
+fn write_png(surface: &cairo::ImageSurface, path: &Path) {
+    surface.write_to_png(&mut File::create(path).unwrap()).unwrap();
+}
+
+#[test]
+fn reftest() {
+    let mut failures = vec![];
+    for (name, test_path, reference_path) in reftests() {
+        let mut test = render(&test_path).unwrap_or_else(|e| panic!("{}: {}", name, e));
+        let mut reference = render(&reference_path).unwrap_or_else(|e| panic!("{}: {}", name, e));
+        if let Some(image) = diff(&mut test, &mut reference) {
+            let dir = failure_dir();
+            fs::create_dir_all(&dir).unwrap();
+            write_png(&test, &dir.join(format!("{}.png", name)));
+            write_png(&reference, &dir.join(format!("{}-ref.png", name)));
+            write_png(&image, &dir.join(format!("{}-diff.png", name)));
+            failures.push(name);
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "Reftests failed: {}. See {}",
+        failures.join(", "),
+        failure_dir().display()
+    );
+}